@@ -0,0 +1,35 @@
+//! Compare the ways `stamp` reads files for hashing
+//!
+//! Run with `cargo bench --bench hash`, adding `--features mmap` to include
+//! memory-mapped hashing. The file size in MiB can be given as an argument.
+
+use ots::commands::stamp::{hash_file_with, ReadMode};
+use ots::ots::DigestType;
+use ots::progress::BatchProgress;
+use std::time::Instant;
+
+/// Size of the hashed file in MiB, unless given on the command line
+const DEFAULT_MIB: usize = 512;
+
+fn main() {
+    let mib = std::env::args().skip(1).find_map(|arg| arg.parse().ok()).unwrap_or(DEFAULT_MIB);
+    let path = std::env::temp_dir().join(format!("ots-bench-{}", std::process::id()));
+    std::fs::write(&path, vec![0x5a; mib << 20]).expect("cannot write the test file");
+
+    let modes = [
+        ("8 KiB reads", ReadMode::Buffered(8 << 10)),
+        ("1 MiB reads", ReadMode::Buffered(1 << 20)),
+        ("8 MiB reads", ReadMode::Buffered(8 << 20)),
+        #[cfg(feature = "mmap")]
+        ("mmap", ReadMode::Mapped),
+    ];
+    let progress = BatchProgress::hidden();
+    for (name, mode) in modes {
+        let started = Instant::now();
+        hash_file_with(&path, DigestType::Sha256, mode, &progress).expect("cannot hash");
+        let elapsed = started.elapsed();
+        let rate = mib as f64 / elapsed.as_secs_f64();
+        println!("{name:>12}: {:>6} ms, {rate:>7.0} MiB/s", elapsed.as_millis());
+    }
+    std::fs::remove_file(&path).expect("cannot remove the test file");
+}
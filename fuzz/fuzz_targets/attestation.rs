@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ots::ots::{Attestation, Serializer};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(attestation) = Attestation::from_bytes(data) else {
+        return;
+    };
+    // Anything that parses must display and survive a round trip
+    let _ = attestation.to_string();
+    let mut ser = Serializer::new(Vec::new());
+    attestation.serialize(&mut ser).expect("serializing to memory cannot fail");
+    let reparsed =
+        Attestation::from_bytes(&ser.into_inner()).expect("serialized attestation must parse");
+    assert_eq!(attestation, reparsed);
+});
@@ -0,0 +1,16 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ots::ots::DetachedTimestampFile;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(proof) = DetachedTimestampFile::from_bytes(data) else {
+        return;
+    };
+    // Anything that parses must display and survive a round trip
+    let _ = proof.to_string();
+    let mut buf = Vec::new();
+    proof.to_writer(&mut buf).expect("serializing to memory cannot fail");
+    let reparsed = DetachedTimestampFile::from_bytes(&buf).expect("serialized proof must parse");
+    assert_eq!(proof, reparsed);
+});
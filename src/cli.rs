@@ -1,4 +1,11 @@
+use crate::commands::convert::ConvertFormat;
+use crate::ots::{DigestType, Metadata};
+use crate::time_style::TimeStyle;
 use clap::{Parser, Subcommand};
+use glob::Pattern;
+#[cfg(feature = "api")]
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
 
 /// `OpenTimestamps` command-line interface
@@ -7,59 +14,725 @@ use std::path::PathBuf;
 #[command(about = "OpenTimestamps client", long_about = None)]
 #[command(version)]
 pub struct Cli {
-    /// Verbose output
+    /// Print more: -v for details, -vv for HTTP requests and digests, -vvv for everything
+    #[arg(short, long, global = true, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    pub verbose: u8,
+
+    /// Print nothing but errors
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+
+    /// Route calendar traffic through Tor
+    #[arg(long, global = true)]
+    pub use_tor: bool,
+
+    /// Tor SOCKS5 proxy address [default: 127.0.0.1:9050]
+    #[arg(long, global = true, value_name = "ADDR")]
+    pub tor_proxy: Option<String>,
+
+    /// Use a separate Tor circuit for every calendar request
+    #[arg(long, global = true)]
+    pub tor_isolate: bool,
+
+    /// Configuration file to use instead of the default one
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Named profile from the configuration file to take defaults from
+    #[arg(long, global = true, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Answer yes to every prompt, e.g. to overwrite existing files
     #[arg(short, long, global = true)]
-    pub verbose: bool,
+    pub yes: bool,
+
+    /// How to print attested times: iso8601, unix or human; iso8601 also
+    /// gives JSON reports strings instead of Unix times
+    #[arg(
+        long,
+        global = true,
+        value_name = "STYLE",
+        value_parser = parse_time_style,
+        default_value = "human"
+    )]
+    pub time_style: TimeStyle,
 
     #[command(subcommand)]
     pub command: Command,
 }
 
 /// Available commands
+// Parsed once per run, so the size of the `Stamp` variant doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 pub enum Command {
     /// Create timestamp for file(s)
     Stamp {
-        /// Files to timestamp
-        #[arg(required = true)]
+        /// Files to timestamp, or http(s) URLs to download; directories are walked recursively
+        #[arg(required_unless_present_any = ["digest", "files_from"], conflicts_with = "digest")]
         files: Vec<PathBuf>,
 
+        /// Also stamp the paths listed in this file, one per line, or - to read them from stdin
+        #[arg(long, value_name = "PATH", conflicts_with = "digest")]
+        files_from: Option<PathBuf>,
+
+        /// Only stamp files in directories matching this glob, e.g. '*.csv' (can specify multiple)
+        #[arg(long, value_name = "GLOB", value_parser = parse_glob)]
+        include: Vec<Pattern>,
+
+        /// Skip files and directories matching this glob (can specify multiple)
+        #[arg(long, value_name = "GLOB", value_parser = parse_glob)]
+        exclude: Vec<Pattern>,
+
+        /// Timestamp this hex-encoded SHA256 digest instead of files
+        #[arg(
+            long,
+            value_name = "HEX",
+            value_parser = parse_digest,
+            conflicts_with_all = ["attach", "merkle"]
+        )]
+        digest: Option<[u8; 32]>,
+
+        /// Hash algorithm for the file digest: sha1, sha256, ripemd160 or keccak256
+        #[arg(
+            long,
+            value_name = "ALGORITHM",
+            value_parser = parse_algorithm,
+            default_value = "sha256",
+            conflicts_with = "digest"
+        )]
+        algorithm: DigestType,
+
+        /// Append this hex-encoded nonce to every digest instead of 16 random bytes,
+        /// for reproducible commitments
+        #[arg(long, value_name = "HEX", value_parser = parse_nonce)]
+        nonce: Option<Vec<u8>>,
+
+        /// Send the digest itself to the calendars, without a privacy nonce
+        #[arg(long, conflicts_with = "nonce")]
+        no_nonce: bool,
+
+        /// Send every calendar a commitment with its own random nonce, so submissions
+        /// cannot be linked across calendars
+        #[arg(long, conflicts_with_all = ["nonce", "no_nonce", "merkle"])]
+        nonce_per_calendar: bool,
+
+        /// Directory to write the proofs to, or the proof path when stamping one file
+        /// or --digest [default: next to each file, or <HEX>.ots]
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+
         /// Calendar server URLs (can specify multiple)
         #[arg(short, long)]
         calendar: Option<Vec<String>>,
 
-        /// Timeout in seconds
-        #[arg(short, long, default_value = "30")]
-        timeout: u64,
+        /// File listing calendar server URLs, one per line
+        #[arg(long, value_name = "PATH")]
+        calendars_file: Option<PathBuf>,
+
+        /// Timeout in seconds [default: 30]
+        #[arg(short, long)]
+        timeout: Option<u64>,
+
+        /// Delay each calendar submission by a random 0..N seconds
+        #[arg(long, value_name = "SECONDS")]
+        jitter: Option<u64>,
+
+        /// Hold submissions until the next N-second window boundary
+        #[arg(long, value_name = "SECONDS")]
+        batch_window: Option<u64>,
+
+        /// Fail unless at least N distinct calendars accept each file's commitment
+        #[arg(long, value_name = "N", default_value_t = 1)]
+        min_responses: usize,
+
+        /// Write a single .otsa file containing both the data and its timestamp
+        #[arg(long)]
+        attach: bool,
+
+        /// Write zstd-compressed .ots.zst proofs
+        #[arg(long, conflicts_with = "attach")]
+        compress: bool,
+
+        /// Submit one Merkle root for all files instead of one digest per file
+        /// (automatic from 100 files)
+        #[arg(long)]
+        merkle: bool,
+
+        /// Submit one digest per file even when stamping many files
+        #[arg(long, conflicts_with = "merkle")]
+        no_merkle: bool,
+
+        /// Write a JSON audit trail of every file's path to the Merkle root
+        #[arg(long, value_name = "FILE", requires = "merkle")]
+        audit: Option<PathBuf>,
+
+        /// Label the proof with metadata, e.g. doc-id=INV-42 (can specify multiple)
+        #[arg(long = "meta", value_name = "KEY=VALUE", value_parser = parse_metadata_entry)]
+        metadata: Vec<(String, String)>,
+
+        /// Overwrite existing output files without asking
+        #[arg(short, long)]
+        force: bool,
+
+        /// Print a JSON summary of every file to stdout when done
+        #[arg(long)]
+        json: bool,
+
+        /// Write a JSON summary of every file to this path when done
+        #[arg(long, value_name = "PATH")]
+        report: Option<PathBuf>,
+
+        /// Also write each proof's digest and attestations to <proof>.json
+        #[arg(long)]
+        emit_json_summary: bool,
+
+        /// Also commit to this digest of each file, saved as <file>.<algorithm>.ots
+        /// (can specify multiple)
+        #[arg(
+            long,
+            value_name = "ALGORITHM",
+            value_parser = parse_algorithm,
+            conflicts_with_all = ["digest", "attach", "nonce_per_calendar"]
+        )]
+        extra_digest: Vec<DigestType>,
+
+        /// Read files for hashing in chunks of this size, e.g. 64K or 4M
+        #[arg(long, value_name = "BYTES", value_parser = parse_size, default_value = "1M")]
+        buffer_size: usize,
+
+        /// Hash files through a memory map instead of reads (fastest for huge files)
+        #[cfg(feature = "mmap")]
+        #[arg(long, conflicts_with = "buffer_size")]
+        mmap: bool,
+
+        /// Hash up to N files at the same time
+        #[arg(short, long, value_name = "N", default_value = "1")]
+        jobs: NonZeroUsize,
+
+        /// Skip the files an interrupted batch run from this directory already stamped
+        #[arg(long, conflicts_with = "digest")]
+        resume: bool,
+
+        /// Do not draw progress bars while hashing and submitting
+        #[arg(long)]
+        no_progress: bool,
     },
 
-    /// Verify a timestamp
+    /// Verify timestamps
     Verify {
-        /// OTS file to verify
-        file: PathBuf,
+        /// OTS files to verify, or - to read one from stdin
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
 
         /// Original file (optional, derived from .ots filename if not provided)
         #[arg(short, long)]
         target: Option<PathBuf>,
+
+        /// Hex-encoded digest of the original file, to verify without the file itself
+        #[arg(long, value_name = "HEX", value_parser = parse_hex, conflicts_with = "target")]
+        digest: Option<Vec<u8>>,
+
+        /// Require a signed .otss file from this signer (hex-encoded ed25519 public key)
+        #[arg(long, value_name = "PUBKEY")]
+        signer: Option<String>,
+
+        /// Verify offline against this hex-encoded 80-byte Bitcoin block header, or a
+        /// file holding the hex or a JSON header from Bitcoin Core or Esplora
+        #[arg(long, value_name = "HEX|FILE")]
+        block_header: Option<String>,
+
+        /// Read Bitcoin block headers from this file of concatenated 80-byte headers
+        /// instead of a network backend
+        #[arg(long, value_name = "PATH", conflicts_with = "block_header")]
+        headers_file: Option<PathBuf>,
+
+        /// Height of the first header in --headers-file
+        #[arg(long, value_name = "HEIGHT", default_value_t = 0, requires = "headers_file")]
+        headers_start: u32,
+
+        /// Also check the header chain from a built-in checkpoint up to each Bitcoin
+        /// block: linkage, proof of work and difficulty changes (SPV-grade, slow)
+        #[arg(long, conflicts_with = "block_header")]
+        strict: bool,
+
+        /// Upgrade pending attestations in memory before verifying, for fresh stamps
+        #[arg(long)]
+        upgrade: bool,
+
+        /// Write the upgraded proof back to its file
+        #[arg(long, requires = "upgrade", conflicts_with = "signer")]
+        save: bool,
+
+        /// Print a JSON summary of every file to stdout when done
+        #[arg(long)]
+        json: bool,
+
+        /// Write a JSON summary of every file and each of its block attestations
+        /// (status, height, block time, backend, error) to this path when done
+        #[arg(long, value_name = "PATH")]
+        report: Option<PathBuf>,
     },
 
-    /// Upgrade pending timestamp to Bitcoin attestation
+    /// Upgrade pending timestamps to Bitcoin attestations
     Upgrade {
-        /// OTS file to upgrade
-        file: PathBuf,
+        /// OTS files to upgrade, or - to read one from stdin and write it to stdout
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
 
         /// Dry run, don't modify file
         #[arg(short, long)]
         dry_run: bool,
+
+        /// Write what each calendar said about its pending attestations as JSON to
+        /// this path, or - for stdout
+        #[arg(long, value_name = "PATH", requires = "dry_run", conflicts_with = "merge_response")]
+        readiness: Option<PathBuf>,
+
+        /// Consider unknown attestations complete instead of waiting for Bitcoin
+        #[arg(long)]
+        accept_unknown: bool,
+
+        /// Remove the pending attestations once the proof has a Bitcoin attestation
+        #[arg(long)]
+        prune_pending: bool,
+
+        /// Directory to write the proofs to, or the proof path when upgrading one
+        /// file [default: upgrade in place]
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+
+        /// Overwrite existing output files without asking
+        #[arg(short, long, requires = "output")]
+        force: bool,
+
+        /// Only query the calendars listed in this file, one URL per line
+        #[arg(long, value_name = "PATH")]
+        calendars_file: Option<PathBuf>,
+
+        /// Query NEW for pending attestations naming the calendar OLD, e.g. a mirror
+        /// of a dead calendar (can specify multiple)
+        #[arg(long, value_name = "OLD=NEW", value_parser = parse_calendar_mapping)]
+        calendar_map: Vec<(String, String)>,
+
+        /// Print a JSON summary of every file to stdout when done
+        #[arg(long)]
+        json: bool,
+
+        /// Write a JSON summary of every file to this path when done
+        #[arg(long, value_name = "PATH")]
+        report: Option<PathBuf>,
+
+        /// Merge this saved calendar response instead of querying calendars
+        #[arg(long, value_name = "PATH", requires = "uri")]
+        merge_response: Option<PathBuf>,
+
+        /// Calendar the merged response came from
+        #[arg(long, value_name = "URL", requires = "merge_response")]
+        uri: Option<String>,
+
+        /// Also write each proof's digest and attestations to <proof>.json
+        #[arg(long)]
+        emit_json_summary: bool,
     },
 
     /// Show timestamp information
     Info {
-        /// OTS file to inspect
+        /// OTS file to inspect, or - to read it from stdin
         file: PathBuf,
 
         /// Show detailed output
         #[arg(short, long)]
         detailed: bool,
+
+        /// Show proofs with trailing data, unknown ops or a newer version
+        /// instead of rejecting them
+        #[arg(long)]
+        lenient: bool,
     },
+
+    /// Convert a proof between the binary .ots format and JSON
+    Convert {
+        /// Proof to convert, binary or JSON, or - to read it from stdin
+        file: PathBuf,
+
+        /// Format to write: ots, armor, json, or cbor with the cbor feature
+        #[arg(long, value_name = "FORMAT", value_parser = parse_convert_format)]
+        to: ConvertFormat,
+
+        /// Write the converted proof here instead of to stdout
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+
+        /// Sort attestations and ops into canonical order, so the same proof
+        /// always converts to the same bytes
+        #[arg(long)]
+        canonical: bool,
+
+        /// Overwrite existing output files without asking
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Show what changed between two versions of a proof
+    Diff {
+        /// Old version of the proof, or - to read it from stdin
+        old: PathBuf,
+
+        /// New version of the proof, or - to read it from stdin
+        new: PathBuf,
+    },
+
+    /// Check the configuration, calendars, verifiers and Tor, with hints for failures
+    Doctor {
+        /// Timeout in seconds for each network check [default: 30]
+        #[arg(short, long)]
+        timeout: Option<u64>,
+    },
+
+    /// Show aggregate statistics for a directory of timestamps
+    Stats {
+        /// Directory to scan recursively for .ots files
+        dir: PathBuf,
+    },
+
+    /// List stamps recorded in the state database
+    Status {
+        /// Only list stamps with this status (pending, confirmed or verified)
+        #[arg(long)]
+        status: Option<String>,
+
+        /// Only list stamps whose file path contains this text
+        #[arg(long)]
+        file: Option<String>,
+
+        /// Re-read pending proofs first, marking upgraded ones confirmed
+        #[arg(long)]
+        refresh: bool,
+    },
+
+    /// Write a minimal proof for a single attestation
+    Extract {
+        /// OTS file to extract from
+        file: PathBuf,
+
+        /// Output file for the extracted proof
+        output: PathBuf,
+
+        /// Keep the Bitcoin attestation at this block height
+        #[arg(
+            long,
+            value_name = "HEIGHT",
+            required_unless_present = "pending",
+            conflicts_with = "pending"
+        )]
+        bitcoin: Option<usize>,
+
+        /// Keep the pending attestation with this calendar URI
+        #[arg(long, value_name = "URI")]
+        pending: Option<String>,
+
+        /// Overwrite existing output files without asking
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Sign a proof, writing a .otss file next to it
+    Sign {
+        /// OTS file to sign
+        file: PathBuf,
+
+        /// File holding the hex-encoded ed25519 secret key
+        #[arg(short, long)]
+        key: PathBuf,
+
+        /// Overwrite existing output files without asking
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Stamp a file again every time its content changes
+    Rolling {
+        /// File to watch
+        file: PathBuf,
+
+        /// Directory for the stamped digests and their proofs
+        #[arg(long, value_name = "DIR")]
+        history: PathBuf,
+
+        /// Check the file every N seconds (checks once if not given)
+        #[arg(long, value_name = "SECONDS")]
+        interval: Option<u64>,
+
+        /// Calendar server URLs (can specify multiple)
+        #[arg(short, long)]
+        calendar: Option<Vec<String>>,
+
+        /// File listing calendar server URLs, one per line
+        #[arg(long, value_name = "PATH")]
+        calendars_file: Option<PathBuf>,
+
+        /// Timeout in seconds [default: 30]
+        #[arg(short, long)]
+        timeout: Option<u64>,
+    },
+
+    /// Anchor a confirmed timestamp again through fresh calendar submissions
+    Reanchor {
+        /// Confirmed OTS file to update
+        file: PathBuf,
+
+        /// Calendar server URLs (can specify multiple)
+        #[arg(short, long)]
+        calendar: Option<Vec<String>>,
+
+        /// File listing calendar server URLs, one per line
+        #[arg(long, value_name = "PATH")]
+        calendars_file: Option<PathBuf>,
+
+        /// Timeout in seconds [default: 30]
+        #[arg(short, long)]
+        timeout: Option<u64>,
+    },
+
+    /// Maintain a tamper-evident, hash-chained log
+    Log {
+        #[command(subcommand)]
+        command: LogCommand,
+    },
+
+    /// Package many proofs into a single .otsz bundle
+    Bundle {
+        #[command(subcommand)]
+        command: BundleCommand,
+    },
+
+    /// Generate valid and malformed .ots files for interop testing
+    GenVectors {
+        /// Directory to write the vectors to
+        output: PathBuf,
+
+        /// Overwrite existing output files without asking
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Serve stamp, verify, upgrade and info over HTTP
+    #[cfg(feature = "api")]
+    Api {
+        /// Address to listen on
+        #[arg(long, value_name = "ADDR", default_value = crate::commands::api::DEFAULT_API_ADDR)]
+        listen: SocketAddr,
+
+        /// Calendar server URLs (can specify multiple)
+        #[arg(short, long)]
+        calendar: Option<Vec<String>>,
+
+        /// Timeout in seconds [default: 30]
+        #[arg(short, long)]
+        timeout: Option<u64>,
+    },
+
+    /// Split an attached .otsa file into the original data and its .ots proof
+    Unpack {
+        /// Attached timestamp file to unpack
+        file: PathBuf,
+
+        /// Overwrite existing output files without asking
+        #[arg(short, long)]
+        force: bool,
+    },
+}
+
+/// Proof bundle commands
+#[derive(Subcommand)]
+pub enum BundleCommand {
+    /// Create a bundle from .ots proofs
+    Create {
+        /// Bundle file to create
+        bundle: PathBuf,
+
+        /// Proofs to include
+        #[arg(required = true)]
+        proofs: Vec<PathBuf>,
+
+        /// Overwrite existing output files without asking
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Extract the proofs and manifest of a bundle
+    Extract {
+        /// Bundle file
+        bundle: PathBuf,
+
+        /// Directory to extract into
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+
+        /// Overwrite existing output files without asking
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Verify every proof in a bundle
+    Verify {
+        /// Bundle file
+        bundle: PathBuf,
+
+        /// Directory holding the stamped files, to check their hashes too
+        #[arg(long, value_name = "DIR")]
+        data: Option<PathBuf>,
+    },
+}
+
+/// Hash-chained log commands
+#[derive(Subcommand)]
+pub enum LogCommand {
+    /// Append entries to a log, extending its hash chain
+    Append {
+        /// Log file
+        logfile: PathBuf,
+
+        /// Entries to append, one line each
+        #[arg(required = true)]
+        entries: Vec<String>,
+
+        /// Stamp the chain head every N entries
+        #[arg(long, value_name = "N")]
+        stamp_every: Option<usize>,
+
+        /// Calendar server URLs (can specify multiple)
+        #[arg(short, long)]
+        calendar: Option<Vec<String>>,
+
+        /// Timeout in seconds [default: 30]
+        #[arg(short, long)]
+        timeout: Option<u64>,
+    },
+
+    /// Stamp the current chain head of a log
+    Stamp {
+        /// Log file
+        logfile: PathBuf,
+
+        /// Calendar server URLs (can specify multiple)
+        #[arg(short, long)]
+        calendar: Option<Vec<String>>,
+
+        /// Timeout in seconds [default: 30]
+        #[arg(short, long)]
+        timeout: Option<u64>,
+    },
+
+    /// Prove that a log entry existed by the time of a Bitcoin block
+    Verify {
+        /// Log file
+        logfile: PathBuf,
+
+        /// Line number of the entry (1-based)
+        line: usize,
+    },
+}
+
+/// Parse the style of attested times for `--time-style`
+fn parse_time_style(style: &str) -> Result<TimeStyle, String> {
+    match style.to_ascii_lowercase().as_str() {
+        "iso8601" => Ok(TimeStyle::Iso8601),
+        "unix" => Ok(TimeStyle::Unix),
+        "human" => Ok(TimeStyle::Human),
+        _ => Err("expected iso8601, unix or human".to_string()),
+    }
+}
+
+/// Parse a glob pattern for `--include` and `--exclude`
+fn parse_glob(pattern: &str) -> Result<Pattern, String> {
+    Pattern::new(pattern).map_err(|e| e.to_string())
+}
+
+/// Parse a hash algorithm for `stamp --algorithm`
+fn parse_algorithm(algorithm: &str) -> Result<DigestType, String> {
+    match algorithm.to_ascii_lowercase().as_str() {
+        "sha1" => Ok(DigestType::Sha1),
+        "sha256" => Ok(DigestType::Sha256),
+        "ripemd160" => Ok(DigestType::Ripemd160),
+        "keccak256" => Ok(DigestType::Keccak256),
+        "sha3" | "sha3-256" => {
+            Err("OpenTimestamps has no SHA3-256 op (keccak256 is Ethereum's variant)".to_string())
+        }
+        _ => Err("expected sha1, sha256, ripemd160 or keccak256".to_string()),
+    }
+}
+
+/// Parse the output format of `convert --to`
+fn parse_convert_format(format: &str) -> Result<ConvertFormat, String> {
+    match format.to_ascii_lowercase().as_str() {
+        "ots" => Ok(ConvertFormat::Ots),
+        "armor" => Ok(ConvertFormat::Armor),
+        "json" => Ok(ConvertFormat::Json),
+        #[cfg(feature = "cbor")]
+        "cbor" => Ok(ConvertFormat::Cbor),
+        _ if cfg!(feature = "cbor") => Err("expected ots, armor, json or cbor".to_string()),
+        _ => Err("expected ots, armor or json".to_string()),
+    }
+}
+
+/// Parse a hex-encoded SHA256 digest
+fn parse_digest(digest: &str) -> Result<[u8; 32], String> {
+    let mut bytes = [0u8; 32];
+    hex::decode_to_slice(digest, &mut bytes)
+        .map_err(|e| format!("expected 64 hex characters: {e}"))?;
+    Ok(bytes)
+}
+
+/// Parse a hex-encoded digest of any length
+fn parse_hex(digest: &str) -> Result<Vec<u8>, String> {
+    hex::decode(digest.trim()).map_err(|e| format!("expected hex: {e}"))
+}
+
+/// Parse a size in bytes with an optional K, M or G suffix
+fn parse_size(size: &str) -> Result<usize, String> {
+    let (number, unit) = match size.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((i, _)) => size.split_at(i),
+        None => (size, ""),
+    };
+    let shift = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 0,
+        "K" | "KB" | "KIB" => 10,
+        "M" | "MB" | "MIB" => 20,
+        "G" | "GB" | "GIB" => 30,
+        _ => return Err(format!("unknown unit {unit}, expected K, M or G")),
+    };
+    let number: usize = number.parse().map_err(|_| "expected a number of bytes".to_string())?;
+    number
+        .checked_shl(shift)
+        .filter(|bytes| *bytes > 0 && bytes >> shift == number)
+        .ok_or_else(|| "expected a size between 1 byte and the address space".to_string())
+}
+
+/// Parse an `OLD=NEW` calendar mapping for `upgrade --calendar-map`
+fn parse_calendar_mapping(mapping: &str) -> Result<(String, String), String> {
+    let (old, new) = mapping.split_once('=').ok_or("expected OLD=NEW")?;
+    for url in [old, new] {
+        if !reqwest::Url::parse(url).is_ok_and(|u| u.host_str().is_some()) {
+            return Err(format!("invalid calendar URL {url}"));
+        }
+    }
+    Ok((old.to_string(), new.to_string()))
+}
+
+/// Parse a hex-encoded nonce for `stamp --nonce`
+fn parse_nonce(nonce: &str) -> Result<Vec<u8>, String> {
+    match hex::decode(nonce) {
+        Ok(bytes) if bytes.is_empty() => Err("use --no-nonce for an empty nonce".to_string()),
+        Ok(bytes) => Ok(bytes),
+        Err(e) => Err(format!("expected hex: {e}")),
+    }
+}
+
+/// Parse a `KEY=VALUE` metadata entry, checking it is valid metadata
+fn parse_metadata_entry(entry: &str) -> Result<(String, String), String> {
+    let (key, value) = entry.split_once('=').ok_or("expected KEY=VALUE")?;
+    Metadata::default().insert(key, value).map_err(|e| e.to_string())?;
+    Ok((key.to_string(), value.to_string()))
 }
@@ -0,0 +1,251 @@
+use crate::calendar::CalendarClient;
+use crate::commands::decompress_proof;
+use crate::commands::info::collect_attestations;
+use crate::commands::stamp::stamp_digest;
+use crate::commands::upgrade::upgrade_timestamp;
+use crate::commands::verify::verify_timestamp;
+use crate::error::{Error, Result};
+use crate::ots::DetachedTimestampFile;
+use crate::verbosity::progress;
+use crate::verifier::ChainVerifiers;
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// Default address the API server listens on
+pub const DEFAULT_API_ADDR: &str = "127.0.0.1:8080";
+
+/// Largest request body accepted, in bytes
+pub const MAX_BODY_SIZE: usize = 64 * 1024 * 1024;
+
+/// Header telling whether `/upgrade` changed the proof
+pub const UPGRADED_HEADER: &str = "x-ots-upgraded";
+
+/// State shared by all requests
+struct ApiState {
+    client: CalendarClient,
+    verifiers: ChainVerifiers,
+    calendar_groups: Vec<Vec<String>>,
+}
+
+/// Execute the api command
+///
+/// Serves the stamp, verify, upgrade and info operations over HTTP until
+/// the process is stopped. Every endpoint takes a `POST` with a raw body:
+///
+/// - `/stamp`: the document; returns its `.ots` proof
+/// - `/verify`: an `.ots` proof; returns the verification result as JSON
+/// - `/upgrade`: an `.ots` proof; returns the upgraded proof, with the
+///   `x-ots-upgraded` header telling whether anything changed
+/// - `/info`: an `.ots` proof; returns its digest and attestations as JSON
+///
+/// # Arguments
+///
+/// * `addr` - Address to listen on
+/// * `client` - Calendar client used for stamping and upgrading
+/// * `verifiers` - Backends used to verify block attestations
+/// * `calendar_groups` - Calendar server URLs per chain used for stamping
+///
+/// # Errors
+///
+/// Returns error if the address cannot be bound or the server fails
+pub async fn execute(
+    addr: SocketAddr,
+    client: CalendarClient,
+    verifiers: ChainVerifiers,
+    calendar_groups: Vec<Vec<String>>,
+) -> Result<()> {
+    let state = Arc::new(ApiState { client, verifiers, calendar_groups });
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    progress!("Listening on http://{}", listener.local_addr()?);
+    axum::serve(listener, router(state)).await?;
+    Ok(())
+}
+
+/// Build the routes of the API
+fn router(state: Arc<ApiState>) -> Router {
+    Router::new()
+        .route("/stamp", post(stamp))
+        .route("/verify", post(verify))
+        .route("/upgrade", post(upgrade))
+        .route("/info", post(info))
+        .layer(DefaultBodyLimit::max(MAX_BODY_SIZE))
+        .with_state(state)
+}
+
+/// Stamp the SHA256 digest of the request body
+async fn stamp(State(state): State<Arc<ApiState>>, body: Bytes) -> ApiResult<Response> {
+    let digest: [u8; 32] = Sha256::digest(&body).into();
+    let ots = stamp_digest(digest, &state.calendar_groups, &state.client).await?;
+    Ok(proof_response(&ots, &[])?)
+}
+
+/// Result of `/verify`
+#[derive(Serialize)]
+struct VerifyResponse {
+    verified: bool,
+    digest: String,
+}
+
+/// Verify the block attestations of the proof in the request body
+async fn verify(
+    State(state): State<Arc<ApiState>>,
+    body: Bytes,
+) -> ApiResult<Json<VerifyResponse>> {
+    let ots = parse_proof(&body)?;
+    verify_timestamp(&ots.timestamp, &state.verifiers).await?;
+    Ok(Json(VerifyResponse { verified: true, digest: hex::encode(ots.timestamp.start_digest()) }))
+}
+
+/// Upgrade the pending attestations of the proof in the request body
+async fn upgrade(State(state): State<Arc<ApiState>>, body: Bytes) -> ApiResult<Response> {
+    let mut ots = parse_proof(&body)?;
+    let upgraded = upgrade_timestamp(&mut ots.timestamp, &state.client).await?;
+    let upgraded = if upgraded { "true" } else { "false" };
+    Ok(proof_response(&ots, &[(UPGRADED_HEADER, upgraded)])?)
+}
+
+/// Result of `/info`
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct InfoResponse {
+    digest_type: String,
+    digest: String,
+    attestations: Vec<String>,
+}
+
+/// Parse the proof in a request body, which may be ASCII-armored or
+/// zstd-compressed like a proof file
+fn parse_proof(body: &Bytes) -> Result<DetachedTimestampFile> {
+    Ok(DetachedTimestampFile::from_reader(decompress_proof(body.to_vec())?.as_slice())?)
+}
+
+/// Describe the proof in the request body
+async fn info(body: Bytes) -> ApiResult<Json<InfoResponse>> {
+    let ots = parse_proof(&body)?;
+    let mut attestations = vec![];
+    collect_attestations(&ots.timestamp.first_step(), &mut attestations);
+    Ok(Json(InfoResponse {
+        digest_type: ots.digest_type().to_string(),
+        digest: hex::encode(ots.timestamp.start_digest()),
+        attestations: attestations.iter().map(ToString::to_string).collect(),
+    }))
+}
+
+/// Serialize a proof as a binary response
+fn proof_response(
+    ots: &DetachedTimestampFile,
+    headers: &[(&'static str, &str)],
+) -> Result<Response> {
+    let mut bytes = vec![];
+    ots.to_writer(&mut bytes)?;
+    let mut response =
+        ([(header::CONTENT_TYPE, "application/octet-stream")], bytes).into_response();
+    for (name, value) in headers {
+        if let Ok(value) = value.parse() {
+            response.headers_mut().insert(*name, value);
+        }
+    }
+    Ok(response)
+}
+
+type ApiResult<T> = std::result::Result<T, ApiError>;
+
+/// An error returned to the client as JSON with a matching status code
+#[derive(Debug)]
+struct ApiError(Error);
+
+impl From<Error> for ApiError {
+    fn from(error: Error) -> Self {
+        Self(error)
+    }
+}
+
+impl From<crate::ots::OtsError> for ApiError {
+    fn from(error: crate::ots::OtsError) -> Self {
+        Self(error.into())
+    }
+}
+
+impl ApiError {
+    /// The status code for the error: bad input, failed verification, or an upstream failure
+    fn status(&self) -> StatusCode {
+        match self.0 {
+            Error::InvalidOts(_) => StatusCode::BAD_REQUEST,
+            Error::Verification(_) | Error::NoBitcoinAttestation | Error::PendingTimestamp => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            Error::Http(_)
+            | Error::Calendar(_)
+            | Error::CalendarUnavailable { .. }
+            | Error::CalendarRateLimited { .. }
+            | Error::CalendarRejected { .. }
+            | Error::InvalidCalendarResponse(_)
+            | Error::BlockedCalendar(_) => StatusCode::BAD_GATEWAY,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = serde_json::json!({ "error": self.0.to_string() });
+        (self.status(), Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ots::{armor, Attestation, Digest, Step, StepData, Timestamp};
+
+    #[tokio::test]
+    async fn test_info() {
+        let ots = DetachedTimestampFile {
+            timestamp: Timestamp::new(
+                Digest::sha256([0xab; 32]),
+                &Step {
+                    data: StepData::Attestation(Attestation::Bitcoin { height: 100 }),
+                    output: vec![0xab; 32],
+                    next: vec![],
+                },
+            )
+            .unwrap(),
+        };
+        let mut bytes = vec![];
+        ots.to_writer(&mut bytes).unwrap();
+
+        let Json(response) = info(Bytes::from(bytes.clone())).await.unwrap();
+        assert_eq!(
+            response,
+            InfoResponse {
+                digest_type: "SHA256".to_string(),
+                digest: hex::encode([0xab; 32]),
+                attestations: vec!["Bitcoin block 100".to_string()],
+            }
+        );
+
+        // An armored proof pasted into the body reads the same
+        let Json(armored) = info(Bytes::from(armor(&bytes))).await.unwrap();
+        assert_eq!(armored, response);
+
+        let error = info(Bytes::from_static(b"not a proof")).await.unwrap_err();
+        assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_error_status() {
+        assert_eq!(
+            ApiError(Error::NoBitcoinAttestation).status(),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(ApiError(Error::Calendar("down".into())).status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(ApiError(Error::Log("x".into())).status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
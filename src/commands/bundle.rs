@@ -0,0 +1,298 @@
+use crate::commands::verify::verify_timestamp;
+use crate::commands::{decompress_proof, without_compression};
+use crate::error::{Error, Result};
+use crate::ots::DetachedTimestampFile;
+use crate::output::{create_file, Overwrite};
+use crate::verbosity::progress;
+use crate::verifier::ChainVerifiers;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// Name of the manifest inside a bundle
+pub const MANIFEST_NAME: &str = "manifest.json";
+
+/// Directory holding the proofs inside a bundle
+pub const PROOFS_DIR: &str = "proofs";
+
+/// Version of the manifest format written by this client
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// Manifest listing every proof in a bundle
+///
+/// A bundle (`.otsz`) is a tar archive holding `manifest.json` and one
+/// `proofs/<file>.ots` per stamped file, so a whole dataset's proofs can be
+/// shipped as one artifact.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    entries: Vec<ManifestEntry>,
+}
+
+/// A single stamped file in a bundle manifest
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct ManifestEntry {
+    /// Name of the stamped file
+    file: String,
+    /// Path of its proof inside the bundle
+    proof: String,
+    /// Hash function used for the file, e.g. `SHA256`
+    digest_type: String,
+    /// Hex-encoded digest of the file
+    digest: String,
+}
+
+/// Execute the bundle create command
+///
+/// Packages the given `.ots` proofs and a manifest describing them into
+/// `bundle`. Each proof is stored under its file name, so names must be
+/// unique.
+///
+/// # Errors
+///
+/// Returns error if:
+/// - A proof cannot be read or parsed, or lacks the .ots extension
+/// - Two proofs have the same file name
+/// - The bundle exists and may not be overwritten, or cannot be written
+pub fn create(bundle: &Path, proofs: &[impl AsRef<Path>], overwrite: Overwrite) -> Result<()> {
+    let mut manifest = Manifest { version: MANIFEST_VERSION, entries: vec![] };
+    let mut contents = BTreeMap::new();
+    for proof_path in proofs {
+        let proof_path = proof_path.as_ref();
+        let name = without_compression(proof_path);
+        let name = name
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".ots"))
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| {
+                Error::Bundle(format!("{} is not an .ots file", proof_path.display()))
+            })?;
+
+        // Bundles hold plain proofs, so compressed ones are stored decompressed
+        let bytes = decompress_proof(std::fs::read(proof_path)?)?;
+        let ots = DetachedTimestampFile::from_reader(bytes.as_slice())?;
+        let proof = format!("{PROOFS_DIR}/{name}.ots");
+        if contents.insert(proof.clone(), bytes).is_some() {
+            return Err(Error::Bundle(format!("Duplicate proof name: {name}.ots")));
+        }
+        manifest.entries.push(ManifestEntry {
+            file: name.to_string(),
+            proof,
+            digest_type: ots.digest_type().to_string(),
+            digest: hex::encode(ots.timestamp.start_digest()),
+        });
+    }
+
+    let f = create_file(bundle, overwrite)?;
+    let mut builder = tar::Builder::new(BufWriter::new(f));
+    let manifest_json = serde_json::to_vec_pretty(&manifest).map_err(std::io::Error::from)?;
+    append(&mut builder, MANIFEST_NAME, &manifest_json)?;
+    for (path, bytes) in &contents {
+        append(&mut builder, path, bytes)?;
+    }
+    builder.into_inner()?.flush()?;
+
+    progress!("Created bundle with {} proofs: {}", manifest.entries.len(), bundle.display());
+    Ok(())
+}
+
+/// Execute the bundle extract command
+///
+/// Writes every proof in `bundle` to `output` as `<file>.ots`, along with the
+/// manifest.
+///
+/// # Errors
+///
+/// Returns error if the bundle is invalid or an output file exists and may
+/// not be overwritten, or cannot be written
+pub fn extract(bundle: &Path, output: &Path, overwrite: Overwrite) -> Result<()> {
+    let (manifest, proofs) = read_bundle(bundle)?;
+    std::fs::create_dir_all(output)?;
+
+    for entry in &manifest.entries {
+        let path = output.join(format!("{}.ots", entry.file));
+        create_file(&path, overwrite)?.write_all(&proofs[&entry.proof])?;
+        progress!("Extracted timestamp: {}", path.display());
+    }
+    let manifest_path = output.join(MANIFEST_NAME);
+    let manifest_json = serde_json::to_vec_pretty(&manifest).map_err(std::io::Error::from)?;
+    create_file(&manifest_path, overwrite)?.write_all(&manifest_json)?;
+
+    progress!("Extracted {} proofs from {}", manifest.entries.len(), bundle.display());
+    Ok(())
+}
+
+/// Execute the bundle verify command
+///
+/// Checks every proof against the manifest and its block attestations
+/// against the blockchain. With `data` set, the stamped files are also
+/// looked up in that directory and hashed.
+///
+/// # Errors
+///
+/// Returns error if the bundle is invalid or any proof fails verification
+pub async fn verify(bundle: &Path, data: Option<&Path>, verifiers: &ChainVerifiers) -> Result<()> {
+    let (manifest, proofs) = read_bundle(bundle)?;
+
+    let mut failed = 0;
+    for entry in &manifest.entries {
+        progress!("Verifying {}", entry.file);
+        if let Err(e) = verify_entry(entry, &proofs[&entry.proof], data, verifiers).await {
+            println!("{}: {e}", entry.file);
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(Error::Verification(format!(
+            "{failed} of {} proofs in {} failed",
+            manifest.entries.len(),
+            bundle.display()
+        )));
+    }
+    progress!("All {} proofs in {} verified", manifest.entries.len(), bundle.display());
+    Ok(())
+}
+
+/// Verify one proof of a bundle
+async fn verify_entry(
+    entry: &ManifestEntry,
+    proof: &[u8],
+    data: Option<&Path>,
+    verifiers: &ChainVerifiers,
+) -> Result<()> {
+    let ots = DetachedTimestampFile::from_reader(proof)?;
+    if ots.digest_type().to_string() != entry.digest_type
+        || hex::encode(ots.timestamp.start_digest()) != entry.digest.to_lowercase()
+    {
+        return Err(Error::Bundle(format!("{} does not match the manifest", entry.proof)));
+    }
+
+    if let Some(dir) = data {
+        let path = dir.join(&entry.file);
+        let digest = ots.digest_type().digest_reader(BufReader::new(File::open(&path)?))?;
+        if &digest != ots.timestamp.start_digest() {
+            return Err(Error::Verification(format!(
+                "File hash mismatch for {}. Expected {}, got {}",
+                path.display(),
+                entry.digest,
+                hex::encode(&digest)
+            )));
+        }
+    }
+
+    verify_timestamp(&ots.timestamp, verifiers).await
+}
+
+/// Read the manifest and proofs of a bundle
+///
+/// Every manifest entry must name a plain file and have its proof in the
+/// bundle, so extracting cannot write outside the output directory.
+fn read_bundle(bundle: &Path) -> Result<(Manifest, BTreeMap<String, Vec<u8>>)> {
+    let mut archive = tar::Archive::new(BufReader::new(File::open(bundle)?));
+    let mut manifest = None;
+    let mut proofs = BTreeMap::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().into_owned();
+        let mut bytes = vec![];
+        entry.read_to_end(&mut bytes)?;
+        if path == MANIFEST_NAME {
+            manifest = Some(
+                serde_json::from_slice::<Manifest>(&bytes)
+                    .map_err(|e| Error::Bundle(format!("Invalid manifest: {e}")))?,
+            );
+        } else {
+            proofs.insert(path, bytes);
+        }
+    }
+
+    let manifest = manifest.ok_or_else(|| Error::Bundle(format!("{MANIFEST_NAME} missing")))?;
+    if manifest.version != MANIFEST_VERSION {
+        return Err(Error::Bundle(format!("Unsupported manifest version {}", manifest.version)));
+    }
+    for entry in &manifest.entries {
+        if !is_plain_name(&entry.file) {
+            return Err(Error::Bundle(format!("Invalid file name: {}", entry.file)));
+        }
+        if !proofs.contains_key(&entry.proof) {
+            return Err(Error::Bundle(format!("Proof {} missing", entry.proof)));
+        }
+    }
+    Ok((manifest, proofs))
+}
+
+/// Whether `name` is a single path component that stays inside a directory
+fn is_plain_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\'])
+}
+
+/// Add a file to a tar archive
+fn append<W: Write>(builder: &mut tar::Builder<W>, path: &str, bytes: &[u8]) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(bytes.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, path, bytes)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ots::{Attestation, Digest, DigestType, Step, StepData, Timestamp};
+
+    fn write_proof(path: &Path, digest: &[u8]) {
+        let ots = DetachedTimestampFile {
+            timestamp: Timestamp::new(
+                Digest::new(DigestType::Sha256, digest.to_vec()).unwrap(),
+                &Step {
+                    data: StepData::Attestation(Attestation::Bitcoin { height: 1 }),
+                    output: digest.to_vec(),
+                    next: vec![],
+                },
+            )
+            .unwrap(),
+        };
+        ots.to_writer(File::create(path).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_create_and_read() {
+        let dir = std::env::temp_dir().join(format!("ots_bundle_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let first = dir.join("a.txt.ots");
+        let second = dir.join("b.txt.ots");
+        write_proof(&first, &[1; 32]);
+        write_proof(&second, &[2; 32]);
+
+        let bundle = dir.join("data.otsz");
+        create(&bundle, &[&first, &second], Overwrite::Never).unwrap();
+        let (manifest, proofs) = read_bundle(&bundle).unwrap();
+        assert_eq!(manifest.entries.len(), 2);
+        assert_eq!(manifest.entries[0].file, "a.txt");
+        assert_eq!(manifest.entries[0].digest_type, "SHA256");
+        assert_eq!(manifest.entries[1].digest, hex::encode([2; 32]));
+        assert_eq!(proofs["proofs/b.txt.ots"], std::fs::read(&second).unwrap());
+
+        // The bundle is only overwritten when allowed, and names must be unique
+        assert!(create(&bundle, &[&first], Overwrite::Never).is_err());
+        create(&bundle, &[&first], Overwrite::Always).unwrap();
+        assert_eq!(read_bundle(&bundle).unwrap().0.entries.len(), 1);
+        assert!(create(&dir.join("dup.otsz"), &[&first, &first], Overwrite::Never).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_is_plain_name() {
+        assert!(is_plain_name("file.txt"));
+        assert!(!is_plain_name("../file.txt"));
+        assert!(!is_plain_name("dir/file.txt"));
+        assert!(!is_plain_name(".."));
+        assert!(!is_plain_name(""));
+    }
+}
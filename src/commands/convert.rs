@@ -0,0 +1,240 @@
+use crate::commands::{is_compressed, is_stdio, open_proof, write_canonical_proof, write_proof};
+use crate::error::Result;
+use crate::ots::{DetachedTimestampFile, ProofDocument};
+use crate::output::{create_file, Overwrite};
+use crate::verbosity::progress;
+#[cfg(feature = "cbor")]
+use std::io::Read;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+/// First byte of a CBOR proof, the header of an array of 4 items
+#[cfg(feature = "cbor")]
+const CBOR_PROOF_HEADER: u8 = 0x84;
+
+/// Format `convert` writes a proof in
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ConvertFormat {
+    /// The binary `.ots` format, zstd-compressed if the output ends in `.zst`
+    Ots,
+    /// The binary format in base64 between armor lines, see `ots::armor`
+    Armor,
+    /// The JSON form of `ProofDocument`
+    Json,
+    /// The compact CBOR encoding of `DetachedTimestampFile::to_cbor`
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+/// Execute the convert command
+///
+/// Reads a proof in binary form, compressed, armored or not, or as a JSON document,
+/// and writes it in the format `to`, to standard output unless `output` is
+/// given. The layout of the proof is kept unless `canonical` is set, which
+/// sorts its attestations and ops as `Timestamp::normalize` does, so the same
+/// proof always converts to the same bytes.
+///
+/// # Arguments
+///
+/// * `file` - Proof to convert, or `-` for standard input
+/// * `to` - Format to write
+/// * `output` - Path of the converted proof
+/// * `overwrite` - What to do when `output` already exists
+/// * `canonical` - Write the proof in canonical form
+///
+/// # Errors
+///
+/// Returns error if:
+/// - The file cannot be read, or is neither a valid proof nor a valid document
+/// - The proof holds an op this client does not know, which JSON cannot describe
+/// - The output file already exists or cannot be written
+pub fn execute(
+    file: &Path,
+    to: ConvertFormat,
+    output: Option<&Path>,
+    overwrite: Overwrite,
+    canonical: bool,
+) -> Result<()> {
+    let mut ots = read_proof_or_document(file)?;
+    let output = output.filter(|path| !is_stdio(path));
+    if canonical && to != ConvertFormat::Ots {
+        // The other formats are written from the sorted proof
+        ots.timestamp.normalize();
+    }
+    match to {
+        ConvertFormat::Json => {
+            let document = ProofDocument::from_file(&ots)?;
+            let json = serde_json::to_string_pretty(&document).map_err(std::io::Error::from)?;
+            let mut writer = open_output(output, overwrite)?;
+            writeln!(writer, "{json}")?;
+            writer.flush()?;
+        }
+        ConvertFormat::Armor => {
+            let armored = ots.to_armored()?;
+            let mut writer = open_output(output, overwrite)?;
+            writer.write_all(armored.as_bytes())?;
+            writer.flush()?;
+        }
+        ConvertFormat::Ots => {
+            let compress = output.is_some_and(is_compressed);
+            let writer = open_output(output, overwrite)?;
+            if canonical {
+                write_canonical_proof(&ots, writer, compress)?;
+            } else {
+                write_proof(&ots, writer, compress)?;
+            }
+        }
+        #[cfg(feature = "cbor")]
+        ConvertFormat::Cbor => {
+            let cbor = ots.to_cbor()?;
+            let mut writer = open_output(output, overwrite)?;
+            writer.write_all(&cbor)?;
+            writer.flush()?;
+        }
+    }
+
+    if let Some(path) = output {
+        progress!("Converted {} to {}", file.display(), path.display());
+    }
+    Ok(())
+}
+
+/// The file at `output`, or standard output
+fn open_output(output: Option<&Path>, overwrite: Overwrite) -> Result<Box<dyn Write>> {
+    let writer: Box<dyn Write> = match output {
+        Some(path) => Box::new(create_file(path, overwrite)?),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    Ok(writer)
+}
+
+/// Read a proof in binary form, or as a JSON `ProofDocument`
+///
+/// A document is recognized by its opening brace, and with the `cbor`
+/// feature a CBOR proof by the header of its 4-item array; binary proofs
+/// start with neither.
+///
+/// # Errors
+/// Returns error if the file cannot be read or does not hold a valid proof
+pub fn read_proof_or_document(file: &Path) -> Result<DetachedTimestampFile> {
+    let mut reader = open_proof(file)?;
+    let first = reader.fill_buf()?.iter().find(|byte| !byte.is_ascii_whitespace()).copied();
+    #[cfg(feature = "cbor")]
+    if first == Some(CBOR_PROOF_HEADER) {
+        let mut cbor = Vec::new();
+        reader.read_to_end(&mut cbor)?;
+        return Ok(DetachedTimestampFile::from_cbor(&cbor)?);
+    }
+    if first == Some(b'{') {
+        let document: ProofDocument =
+            serde_json::from_reader(reader).map_err(std::io::Error::from)?;
+        Ok(document.to_file()?)
+    } else {
+        Ok(DetachedTimestampFile::from_reader(reader)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ots::{Attestation, Digest, DigestType, Op, Step, StepData, Timestamp};
+
+    #[test]
+    fn test_round_trip() {
+        let start = vec![0xab; 32];
+        let output = Op::Sha256.execute(&start);
+        let ots = DetachedTimestampFile {
+            timestamp: Timestamp::new(
+                Digest::new(DigestType::Sha256, start).unwrap(),
+                &Step {
+                    data: StepData::Op(Op::Sha256),
+                    output: output.clone(),
+                    next: vec![Step {
+                        data: StepData::Attestation(Attestation::Bitcoin { height: 100 }),
+                        output,
+                        next: vec![],
+                    }],
+                },
+            )
+            .unwrap(),
+        };
+        let dir = std::env::temp_dir().join(format!("ots-convert-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let proof = dir.join("file.txt.ots");
+        write_proof(&ots, std::fs::File::create(&proof).unwrap(), false).unwrap();
+
+        let json = dir.join("file.txt.json");
+        execute(&proof, ConvertFormat::Json, Some(&json), Overwrite::Never, false).unwrap();
+        let text = std::fs::read_to_string(&json).unwrap();
+        assert!(text.contains("\"height\": 100"));
+        assert_eq!(read_proof_or_document(&json).unwrap(), ots);
+
+        let armored = dir.join("file.txt.ots.asc");
+        execute(&json, ConvertFormat::Armor, Some(&armored), Overwrite::Never, false).unwrap();
+        assert_eq!(read_proof_or_document(&armored).unwrap(), ots);
+
+        let back = dir.join("back.ots.zst");
+        execute(&json, ConvertFormat::Ots, Some(&back), Overwrite::Never, false).unwrap();
+        assert_eq!(read_proof_or_document(&back).unwrap(), ots);
+        assert!(execute(&json, ConvertFormat::Ots, Some(&back), Overwrite::Never, false).is_err());
+
+        #[cfg(feature = "cbor")]
+        {
+            let cbor = dir.join("file.txt.cbor");
+            execute(&proof, ConvertFormat::Cbor, Some(&cbor), Overwrite::Never, false).unwrap();
+            assert_eq!(std::fs::read(&cbor).unwrap()[0], CBOR_PROOF_HEADER);
+            assert_eq!(read_proof_or_document(&cbor).unwrap(), ots);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_canonical() {
+        let start = vec![0xcd; 32];
+        let attested = |attestation: Attestation| Step {
+            data: StepData::Attestation(attestation),
+            output: start.clone(),
+            next: vec![],
+        };
+        let ots = DetachedTimestampFile {
+            timestamp: Timestamp::new(
+                Digest::new(DigestType::Sha256, start.clone()).unwrap(),
+                &Step {
+                    data: StepData::Fork,
+                    output: start.clone(),
+                    next: vec![
+                        attested(Attestation::Pending { uri: "https://b.example".into() }),
+                        attested(Attestation::Bitcoin { height: 100 }),
+                        attested(Attestation::Pending { uri: "https://a.example".into() }),
+                    ],
+                },
+            )
+            .unwrap(),
+        };
+        let mut normalized = ots.clone();
+        normalized.timestamp.normalize();
+        assert_ne!(normalized, ots);
+
+        let dir =
+            std::env::temp_dir().join(format!("ots-convert-canonical-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let proof = dir.join("file.txt.ots");
+        write_proof(&ots, std::fs::File::create(&proof).unwrap(), false).unwrap();
+
+        let canonical = dir.join("canonical.ots");
+        execute(&proof, ConvertFormat::Ots, Some(&canonical), Overwrite::Never, true).unwrap();
+        let mut expected = vec![];
+        normalized.to_writer(&mut expected).unwrap();
+        assert_eq!(std::fs::read(&canonical).unwrap(), expected);
+
+        let json = dir.join("file.txt.json");
+        execute(&proof, ConvertFormat::Json, Some(&json), Overwrite::Never, true).unwrap();
+        assert_eq!(read_proof_or_document(&json).unwrap(), normalized);
+
+        // Without the flag the layout is kept
+        let kept = dir.join("kept.ots");
+        execute(&proof, ConvertFormat::Ots, Some(&kept), Overwrite::Never, false).unwrap();
+        assert_eq!(std::fs::read(&kept).unwrap(), std::fs::read(&proof).unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,37 @@
+use crate::commands::open_proof;
+use crate::error::Result;
+use crate::ots::{self, DetachedTimestampFile};
+use std::path::Path;
+
+/// Execute the diff command
+///
+/// Compares two versions of a proof, e.g. before and after an upgrade, and
+/// prints the attestations added and removed and where the trees diverge.
+/// Proofs that only order their attestations and ops differently are
+/// reported as equivalent. Either file may be `-` to read it from stdin.
+///
+/// # Errors
+/// Returns error if either file cannot be read or parsed
+pub fn execute(old: &Path, new: &Path) -> Result<()> {
+    let old_ots = DetachedTimestampFile::from_reader(open_proof(old)?)?;
+    let new_ots = DetachedTimestampFile::from_reader(open_proof(new)?)?;
+
+    if old_ots.digest_type() != new_ots.digest_type() {
+        println!("Digest type: {} -> {}", old_ots.digest_type(), new_ots.digest_type());
+    }
+    if old_ots.timestamp.equivalent(&new_ots.timestamp) {
+        if old_ots.timestamp == new_ots.timestamp {
+            println!("No differences");
+        } else {
+            println!("No differences, except in the order of attestations and ops");
+        }
+        return Ok(());
+    }
+    let changes = ots::diff(&old_ots.timestamp, &new_ots.timestamp);
+    if changes.is_empty() {
+        println!("No differences");
+    } else {
+        print!("{changes}");
+    }
+    Ok(())
+}
@@ -0,0 +1,270 @@
+use crate::calendar::{CalendarClient, TorConfig, DEFAULT_CALENDARS};
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::report;
+use crate::verifier::{self, BlockVerifier, ChainVerifiers};
+use futures::future::join_all;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::future::Future;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+
+/// Optional features and whether this binary was built with them
+const FEATURES: &[(&str, bool)] = &[
+    ("electrum", cfg!(feature = "electrum")),
+    ("esplora", cfg!(feature = "esplora")),
+    ("rpc", cfg!(feature = "rpc")),
+    ("api", cfg!(feature = "api")),
+];
+
+/// Hint for a Tor proxy that does not accept connections
+const TOR_HINT: &str = "start Tor, or point --tor-proxy at its SOCKS port";
+
+/// Hint for a block verifier that cannot fetch blocks
+const VERIFIER_HINT: &str =
+    "check your network, or pick another server with `backend` in a profile";
+
+/// Everything `ots doctor` checks, as set up from the command line and configuration
+pub struct Environment<'a> {
+    /// The configuration file in use, `None` for the defaults, or why it is invalid
+    pub config_source: Result<Option<PathBuf>>,
+    /// The configuration, the defaults if the file is invalid
+    pub config: &'a Config,
+    /// Calendar server URLs per chain, an empty group meaning the defaults
+    pub calendar_groups: Vec<Vec<String>>,
+    /// Client for the calendars, or why it cannot be built
+    pub client: Result<CalendarClient>,
+    /// Tor settings, if Tor is in use
+    pub tor: Option<&'a TorConfig>,
+    /// Server overriding the default of the Bitcoin backend
+    pub backend: Option<&'a str>,
+    /// Server overriding the default of the Litecoin backend
+    pub litecoin_backend: Option<&'a str>,
+    /// How long to wait for each network check
+    pub timeout: Duration,
+}
+
+/// Outcome of one check
+pub struct Check {
+    /// What was checked
+    pub name: String,
+    /// What was found, or why the check failed
+    pub outcome: Result<String>,
+    /// What to do on failure, instead of the generic hint for the error
+    hint: Option<&'static str>,
+}
+
+impl Check {
+    fn new(name: impl Into<String>, outcome: Result<String>) -> Self {
+        Self { name: name.into(), outcome, hint: None }
+    }
+
+    const fn with_hint(mut self, hint: &'static str) -> Self {
+        self.hint = Some(hint);
+        self
+    }
+
+    /// Whether the check passed
+    #[must_use]
+    pub const fn passed(&self) -> bool {
+        self.outcome.is_ok()
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.outcome {
+            Ok(found) => write!(f, "[ ok ] {}: {found}", self.name),
+            Err(e) => {
+                let explanation = report::explain(e);
+                write!(f, "[FAIL] {}: {}", self.name, explanation.summary)?;
+                match self.hint.or(explanation.hint) {
+                    Some(hint) => write!(f, "\n       hint: {hint}"),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+/// Execute the doctor command
+///
+/// Checks the configuration, compiled features, state directory, Tor proxy,
+/// every calendar and the block verifiers, printing one line per check and
+/// a hint under each failure. Network checks run concurrently.
+///
+/// # Errors
+/// Returns `Error::ChecksFailed` if any check failed
+pub async fn execute(env: Environment<'_>) -> Result<()> {
+    let mut checks = vec![
+        check_config(env.config_source),
+        check_features(),
+        check_state_dir(env.config.state.path().as_deref()),
+    ];
+    if let Some(tor) = env.tor {
+        checks.push(check_tor(tor, env.timeout).await);
+    }
+    checks.extend(check_calendars(env.client, &env.calendar_groups, env.timeout).await);
+    match ChainVerifiers::new(env.tor, env.config, env.backend, env.litecoin_backend) {
+        Ok(verifiers) => {
+            let bitcoin = format!("Bitcoin verifier ({})", verifier::BACKEND_NAME);
+            let check = check_verifier(bitcoin, verifiers.bitcoin.as_ref(), env.timeout).await;
+            checks.push(check.with_hint(VERIFIER_HINT));
+            let litecoin = format!("Litecoin verifier ({})", verifiers.litecoin_backend);
+            checks.push(check_verifier(litecoin, verifiers.litecoin.as_ref(), env.timeout).await);
+        }
+        Err(e) => checks.push(Check::new("Block verifiers", Err(e)).with_hint(VERIFIER_HINT)),
+    }
+
+    for check in &checks {
+        println!("{check}");
+    }
+    let failed = checks.iter().filter(|check| !check.passed()).count();
+    if failed > 0 {
+        return Err(Error::ChecksFailed { failed, total: checks.len() });
+    }
+    println!("All {} checks passed", checks.len());
+    Ok(())
+}
+
+/// Report which configuration file is in use
+fn check_config(source: Result<Option<PathBuf>>) -> Check {
+    let outcome = source.map(|path| match path {
+        Some(path) => path.display().to_string(),
+        None => "no file, using defaults".to_string(),
+    });
+    Check::new("Configuration", outcome)
+}
+
+/// List the optional features this binary was built with
+fn check_features() -> Check {
+    let features: Vec<&str> =
+        FEATURES.iter().filter(|(_, enabled)| *enabled).map(|(name, _)| *name).collect();
+    Check::new("Features", Ok(features.join(", ")))
+}
+
+/// Check that the state database can be created or updated
+///
+/// Probes the nearest existing directory on the way to the database with a
+/// temporary file, since missing directories are created on first use.
+fn check_state_dir(path: Option<&Path>) -> Check {
+    let Some(path) = path else {
+        return Check::new("State directory", Ok("recording disabled".to_string()));
+    };
+    let outcome = path
+        .ancestors()
+        .skip(1)
+        .find(|dir| dir.is_dir())
+        .ok_or_else(|| std::io::Error::from(ErrorKind::NotFound).into())
+        .and_then(probe_writable)
+        .map(|()| format!("{} is writable", path.display()));
+    Check::new("State directory", outcome)
+}
+
+/// Create and remove a file in `dir`
+fn probe_writable(dir: &Path) -> Result<()> {
+    let probe = dir.join(format!(".ots-doctor-{}", std::process::id()));
+    OpenOptions::new().write(true).create_new(true).open(&probe)?;
+    std::fs::remove_file(&probe)?;
+    Ok(())
+}
+
+/// Check that the Tor SOCKS proxy accepts connections
+async fn check_tor(tor: &TorConfig, timeout: Duration) -> Check {
+    let outcome = with_timeout(timeout, async {
+        TcpStream::connect(&tor.proxy).await?;
+        Ok::<_, Error>(format!("proxy {} accepts connections", tor.proxy))
+    })
+    .await;
+    Check::new("Tor", outcome).with_hint(TOR_HINT)
+}
+
+/// Check every calendar, reporting how long each took to answer
+async fn check_calendars(
+    client: Result<CalendarClient>,
+    calendar_groups: &[Vec<String>],
+    timeout: Duration,
+) -> Vec<Check> {
+    let client = match client {
+        Ok(client) => client,
+        Err(e) => return vec![Check::new("Calendar client", Err(e))],
+    };
+    let client = &client;
+    let mut calendars: Vec<String> = vec![];
+    for group in calendar_groups {
+        let group = if group.is_empty() {
+            DEFAULT_CALENDARS.iter().map(ToString::to_string).collect()
+        } else {
+            group.clone()
+        };
+        for calendar in group {
+            if !calendars.contains(&calendar) {
+                calendars.push(calendar);
+            }
+        }
+    }
+
+    join_all(calendars.into_iter().map(|calendar| async move {
+        let outcome = with_timeout(timeout, client.ping(&calendar))
+            .await
+            .map(|latency| format!("answered in {} ms", latency.as_millis()));
+        Check::new(format!("Calendar {calendar}"), outcome)
+    }))
+    .await
+}
+
+/// Check that a block verifier can fetch the genesis block
+async fn check_verifier(name: String, verifier: &dyn BlockVerifier, timeout: Duration) -> Check {
+    let started = Instant::now();
+    let outcome = with_timeout(timeout, verifier.get_block_header(0))
+        .await
+        .map(|_| format!("fetched the genesis block in {} ms", started.elapsed().as_millis()));
+    Check::new(name, outcome)
+}
+
+/// Run a check, failing it if it takes longer than `timeout`
+async fn with_timeout<T>(timeout: Duration, check: impl Future<Output = Result<T>>) -> Result<T> {
+    tokio::time::timeout(timeout, check)
+        .await
+        .unwrap_or_else(|_| Err(std::io::Error::from(ErrorKind::TimedOut).into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let passed = Check::new("Features", Ok("electrum".into()));
+        assert!(passed.passed());
+        assert_eq!(passed.to_string(), "[ ok ] Features: electrum");
+
+        let failed = Check::new("Configuration", Err(Error::Config("bad key".into())));
+        assert!(!failed.passed());
+        assert_eq!(
+            failed.to_string(),
+            "[FAIL] Configuration: invalid configuration: bad key\n       \
+             hint: fix the configuration file (see --config and OTS_CONFIG)"
+        );
+
+        let tor = Check::new("Tor", Err(std::io::Error::from(ErrorKind::TimedOut).into()))
+            .with_hint(TOR_HINT);
+        assert!(tor.to_string().ends_with(TOR_HINT));
+    }
+
+    #[test]
+    fn test_state_dir() {
+        assert!(check_state_dir(None).passed());
+
+        // Missing directories are fine as long as an ancestor is writable
+        let dir = std::env::temp_dir().join(format!("ots-doctor-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let check = check_state_dir(Some(&dir.join("missing").join("state.db")));
+        assert!(check.passed(), "{check}");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
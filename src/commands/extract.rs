@@ -0,0 +1,43 @@
+use crate::commands::{is_compressed, open_proof, write_proof};
+use crate::error::{Error, Result};
+use crate::ots::{Attestation, DetachedTimestampFile};
+use crate::output::{create_file, Overwrite};
+use crate::verbosity::progress;
+use std::path::Path;
+
+/// Execute the extract command
+///
+/// Reads an `.ots` file and writes a new proof containing only the shortest
+/// path to a single attestation, dropping every other branch.
+///
+/// # Arguments
+///
+/// * `file` - Path to the .ots file to extract from
+/// * `attestation` - The attestation to keep
+/// * `output` - Path of the new .ots file, zstd-compressed if it ends in `.zst`
+/// * `overwrite` - What to do when `output` already exists
+///
+/// # Errors
+///
+/// Returns error if:
+/// - File cannot be read or parsed
+/// - The timestamp does not contain the attestation
+/// - The output file already exists or cannot be written
+pub fn execute(
+    file: &Path,
+    attestation: &Attestation,
+    output: &Path,
+    overwrite: Overwrite,
+) -> Result<()> {
+    let ots = DetachedTimestampFile::from_reader(open_proof(file)?)?;
+
+    let timestamp = ots.timestamp.extract_path(attestation).ok_or_else(|| {
+        Error::Verification(format!("Timestamp has no {attestation} attestation"))
+    })?;
+    let extracted = DetachedTimestampFile { timestamp };
+
+    write_proof(&extracted, create_file(output, overwrite)?, is_compressed(output))?;
+
+    progress!("Extracted {attestation} proof: {}", output.display());
+    Ok(())
+}
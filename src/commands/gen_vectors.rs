@@ -0,0 +1,293 @@
+use crate::error::Result;
+use crate::ots::{
+    Attestation, Digest, DigestType, Op, OtsError, Serializer, TimestampWriter, MAX_OP_LENGTH,
+    MAX_RESULT_LENGTH, RECURSION_LIMIT,
+};
+use crate::output::{create_file, Overwrite};
+use crate::verbosity::progress;
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+
+/// Name of the file describing every generated vector
+pub const VECTORS_MANIFEST: &str = "vectors.json";
+
+/// Message whose digests start the generated timestamps
+const MESSAGE: &[u8] = b"OpenTimestamps interop test vector\n";
+
+/// Calendar URI used by pending attestations
+const PENDING_URI: &str = "https://alice.btc.calendar.opentimestamps.org";
+
+/// A generated test vector
+#[derive(Serialize)]
+struct Vector {
+    /// File name of the vector
+    file: String,
+    /// Whether a conforming client must accept the file
+    valid: bool,
+    /// What the vector exercises
+    description: String,
+    #[serde(skip)]
+    bytes: Vec<u8>,
+}
+
+impl Vector {
+    fn valid(file: &str, description: &str, bytes: Vec<u8>) -> Self {
+        Self { file: file.to_string(), valid: true, description: description.to_string(), bytes }
+    }
+
+    fn invalid(file: &str, description: &str, bytes: Vec<u8>) -> Self {
+        Self { file: file.to_string(), valid: false, description: description.to_string(), bytes }
+    }
+}
+
+/// Execute the gen-vectors command
+///
+/// Writes a suite of valid and deliberately malformed `.ots` files into
+/// `output`, for checking that other implementations accept and reject the
+/// same proofs as this one. `vectors.json` lists every file with whether it
+/// must be accepted and what it exercises.
+///
+/// # Errors
+///
+/// Returns error if `output` contains files from an earlier run that may not
+/// be overwritten, or cannot be written
+pub fn execute(output: &Path, overwrite: Overwrite) -> Result<()> {
+    let vectors = vectors()?;
+    std::fs::create_dir_all(output)?;
+
+    for vector in &vectors {
+        create_file(&output.join(&vector.file), overwrite)?.write_all(&vector.bytes)?;
+    }
+    let manifest = serde_json::to_vec_pretty(&vectors).map_err(std::io::Error::from)?;
+    create_file(&output.join(VECTORS_MANIFEST), overwrite)?.write_all(&manifest)?;
+
+    let valid = vectors.iter().filter(|vector| vector.valid).count();
+    progress!(
+        "Wrote {valid} valid and {} invalid vectors to {}",
+        vectors.len() - valid,
+        output.display()
+    );
+    Ok(())
+}
+
+/// Build every test vector
+fn vectors() -> Result<Vec<Vector>> {
+    let sha256 = DigestType::Sha256.digest_reader(MESSAGE)?;
+    let bitcoin = Attestation::Bitcoin { height: 358_391 };
+    let pending = Attestation::Pending { uri: PENDING_URI.to_string() };
+    let mut vectors = vec![];
+
+    // Every digest type
+    for digest_type in
+        [DigestType::Sha1, DigestType::Ripemd160, DigestType::Sha256, DigestType::Keccak256]
+    {
+        let digest = digest_type.digest_reader(MESSAGE)?;
+        vectors.push(Vector::valid(
+            &format!("digest-{}.ots", digest_type.to_string().to_lowercase()),
+            &format!("{digest_type} file digest with a pending attestation"),
+            proof(&digest, |w| w.attestation(&pending))?,
+        ));
+    }
+
+    // Every op
+    let ops = [
+        ("sha1", Op::Sha1),
+        ("ripemd160", Op::Ripemd160),
+        ("sha256", Op::Sha256),
+        ("keccak256", Op::Keccak256),
+        ("hexlify", Op::Hexlify),
+        ("reverse", Op::Reverse),
+        ("append", Op::Append(vec![0xaa; 16])),
+        ("prepend", Op::Prepend(vec![0xbb; 16])),
+    ];
+    for (name, op) in ops {
+        vectors.push(Vector::valid(
+            &format!("op-{name}.ots"),
+            &format!("A single {name} op before a Bitcoin attestation"),
+            proof(&sha256, |w| {
+                w.op(&op)?;
+                w.attestation(&bitcoin)
+            })?,
+        ));
+    }
+
+    // Forks and attestation types
+    vectors.push(Vector::valid(
+        "fork.ots",
+        "A three-way fork to Bitcoin, Litecoin and pending attestations",
+        proof(&sha256, |w| {
+            w.begin_fork()?;
+            w.attestation(&bitcoin)?;
+            w.begin_fork()?;
+            w.attestation(&Attestation::Litecoin { height: 1_000_000 })?;
+            w.attestation(&pending)
+        })?,
+    ));
+    vectors.push(Vector::valid(
+        "unknown-attestation.ots",
+        "An attestation with an unknown tag, which must be preserved, not rejected",
+        proof(&sha256, |w| {
+            w.attestation(&Attestation::unknown(
+                b"\x01\x02\x03\x04\x05\x06\x07\x08".to_vec(),
+                b"custom payload".to_vec(),
+            )?)
+        })?,
+    ));
+    vectors.push(Vector::valid(
+        "op-max-length.ots",
+        "Append op with the largest allowed argument",
+        proof(&sha256, |w| {
+            w.op(&Op::Append(vec![0xcc; MAX_RESULT_LENGTH - sha256.as_bytes().len()]))?;
+            w.attestation(&bitcoin)
+        })?,
+    ));
+
+    // Recursion depth: every op and the attestation count as one level
+    let deep = |ops: usize| {
+        proof(&sha256, |w| {
+            for _ in 0..ops {
+                w.op(&Op::Reverse)?;
+            }
+            w.attestation(&bitcoin)
+        })
+    };
+    vectors.push(Vector::valid(
+        "deep-at-limit.ots",
+        &format!("{} reverse ops, the deepest path allowed", RECURSION_LIMIT - 1),
+        deep(RECURSION_LIMIT - 1)?,
+    ));
+    vectors.push(Vector::invalid(
+        "deep-over-limit.ots",
+        &format!("{RECURSION_LIMIT} reverse ops, one level past the recursion limit"),
+        deep(RECURSION_LIMIT)?,
+    ));
+
+    // Malformed headers
+    let valid = proof(&sha256, |w| w.attestation(&bitcoin))?;
+    let mut bad_magic = valid.clone();
+    bad_magic[1] = b'o';
+    vectors.push(Vector::invalid("bad-magic.ots", "Corrupted magic bytes", bad_magic));
+    vectors.push(Vector::invalid(
+        "bad-version.ots",
+        "Unsupported major version 2",
+        raw(|ser| {
+            ser.write_magic()?;
+            ser.write_uint(2)?;
+            ser.write_byte(DigestType::Sha256.to_tag())?;
+            ser.write_fixed_bytes(sha256.as_bytes())
+        })?,
+    ));
+    vectors.push(Vector::invalid(
+        "bad-digest-tag.ots",
+        "Unknown file digest type 0x09",
+        raw(|ser| {
+            ser.write_magic()?;
+            ser.write_version()?;
+            ser.write_byte(0x09)?;
+            ser.write_fixed_bytes(sha256.as_bytes())
+        })?,
+    ));
+
+    // Malformed timestamps
+    vectors.push(Vector::invalid(
+        "bad-op-tag.ots",
+        "Unknown op tag 0x04",
+        raw(|ser| {
+            header(ser, sha256.as_bytes())?;
+            ser.write_byte(0x04)
+        })?,
+    ));
+    vectors.push(Vector::invalid(
+        "op-too-long.ots",
+        &format!("Append op with a {}-byte argument", MAX_OP_LENGTH + 1),
+        proof(&sha256, |w| {
+            w.op(&Op::Append(vec![0xcc; MAX_OP_LENGTH + 1]))?;
+            w.attestation(&bitcoin)
+        })?,
+    ));
+    vectors.push(Vector::invalid(
+        "op-result-too-long.ots",
+        &format!("Append op whose result exceeds {MAX_RESULT_LENGTH} bytes"),
+        proof(&sha256, |w| {
+            w.op(&Op::Append(vec![0xcc; MAX_RESULT_LENGTH - sha256.as_bytes().len() + 1]))?;
+            w.attestation(&bitcoin)
+        })?,
+    ));
+    vectors.push(Vector::invalid(
+        "bad-uri-char.ots",
+        "Pending attestation whose URI contains a space",
+        proof(&sha256, |w| {
+            w.attestation(&Attestation::Pending { uri: "https://calendar example".to_string() })
+        })?,
+    ));
+    vectors.push(Vector::invalid(
+        "truncated.ots",
+        "Proof cut off in the middle of its attestation",
+        valid[..valid.len() - 2].to_vec(),
+    ));
+    vectors.push(Vector::invalid(
+        "missing-attestation.ots",
+        "Path that ends without an attestation",
+        raw(|ser| {
+            header(ser, sha256.as_bytes())?;
+            Op::Sha256.serialize(ser)
+        })?,
+    ));
+    let mut trailing = valid;
+    trailing.push(0x00);
+    vectors.push(Vector::invalid(
+        "trailing-data.ots",
+        "Valid proof followed by an extra byte",
+        trailing,
+    ));
+
+    Ok(vectors)
+}
+
+/// Serialize a complete proof written step by step
+fn proof(
+    digest: &Digest,
+    steps: impl FnOnce(&mut TimestampWriter<Vec<u8>>) -> std::result::Result<(), OtsError>,
+) -> Result<Vec<u8>> {
+    let mut writer = TimestampWriter::new_file(vec![], digest)?;
+    steps(&mut writer)?;
+    Ok(writer.finish()?.into_inner())
+}
+
+/// Serialize arbitrary, possibly malformed, bytes
+fn raw(
+    write: impl FnOnce(&mut Serializer<Vec<u8>>) -> std::result::Result<(), OtsError>,
+) -> Result<Vec<u8>> {
+    let mut ser = Serializer::new(vec![]);
+    write(&mut ser)?;
+    Ok(ser.into_inner())
+}
+
+/// Write a valid file header for a SHA256 digest
+fn header(ser: &mut Serializer<Vec<u8>>, digest: &[u8]) -> std::result::Result<(), OtsError> {
+    ser.write_magic()?;
+    ser.write_version()?;
+    ser.write_byte(DigestType::Sha256.to_tag())?;
+    ser.write_fixed_bytes(digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ots::DetachedTimestampFile;
+
+    #[test]
+    fn test_vectors_match_parser() {
+        let vectors = vectors().unwrap();
+        for vector in &vectors {
+            let result = DetachedTimestampFile::from_reader(vector.bytes.as_slice());
+            assert_eq!(result.is_ok(), vector.valid, "{}: {:?}", vector.file, result.err());
+        }
+
+        let mut names: Vec<&str> = vectors.iter().map(|vector| vector.file.as_str()).collect();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), vectors.len());
+    }
+}
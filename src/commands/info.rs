@@ -1,11 +1,16 @@
+use crate::commands::{append_extension, open_proof};
 use crate::error::Result;
-use crate::ots::{Attestation, DetachedTimestampFile, Step, StepData};
-use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
+use crate::ots::{
+    AttachedTimestampFile, Attestation, DeserializerOptions, DetachedTimestampFile, Metadata, Step,
+    StepData,
+};
+use crate::state::StampStatus;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 /// Recursively collect all attestations from the timestamp tree
-fn collect_attestations(step: &Step, attestations: &mut Vec<Attestation>) {
+pub(crate) fn collect_attestations(step: &Step, attestations: &mut Vec<Attestation>) {
     match &step.data {
         StepData::Attestation(att) => {
             attestations.push(att.clone());
@@ -28,14 +33,16 @@ fn collect_attestations(step: &Step, attestations: &mut Vec<Attestation>) {
 /// Reads an OTS file and displays its timestamp information.
 /// In normal mode, shows a summary (digest, attestations).
 /// In detailed mode, prints the full structure using the Display trait.
+/// A `file` of `-` reads the proof from stdin. With `lenient`, proofs a
+/// newer or sloppy client wrote are shown as far as they can be read; see
+/// `DeserializerOptions::lenient`.
 ///
 /// # Errors
 /// Returns error if file cannot be read or parsed
-pub fn execute(file: &Path, detailed: bool) -> Result<()> {
-    let f = File::open(file)?;
-    let reader = BufReader::new(f);
-
-    let ots = DetachedTimestampFile::from_reader(reader)?;
+pub fn execute(file: &Path, detailed: bool, lenient: bool) -> Result<()> {
+    let options =
+        if lenient { DeserializerOptions::lenient() } else { DeserializerOptions::default() };
+    let ots = DetachedTimestampFile::from_reader_with_options(open_proof(file)?, options)?;
 
     if detailed {
         // Print full details using Display trait
@@ -43,12 +50,12 @@ pub fn execute(file: &Path, detailed: bool) -> Result<()> {
     } else {
         // Print summary
         println!("File: {}", file.display());
-        println!("Digest type: {:?}", ots.digest_type);
-        println!("Digest: {}", hex::encode(&ots.timestamp.start_digest));
+        println!("Digest type: {:?}", ots.digest_type());
+        println!("Digest: {}", hex::encode(ots.timestamp.start_digest()));
 
         // Collect attestations
         let mut attestations = Vec::new();
-        collect_attestations(&ots.timestamp.first_step, &mut attestations);
+        collect_attestations(&ots.timestamp.first_step(), &mut attestations);
 
         println!("Attestations: {}", attestations.len());
 
@@ -57,15 +64,213 @@ pub fn execute(file: &Path, detailed: bool) -> Result<()> {
                 Attestation::Bitcoin { height } => {
                     println!("  - Bitcoin block {height}");
                 }
+                Attestation::Litecoin { height } => {
+                    println!("  - Litecoin block {height}");
+                }
                 Attestation::Pending { uri } => {
                     println!("  - Pending: {uri}");
                 }
-                Attestation::Unknown { tag, .. } => {
-                    println!("  - Unknown (tag: {})", hex::encode(tag));
-                }
+                Attestation::Unknown { tag, .. } => match Metadata::from_attestation(att) {
+                    Some(Ok(metadata)) => println!("  - Metadata: {metadata}"),
+                    Some(Err(e)) => println!("  - Metadata (malformed: {e})"),
+                    None => println!("  - Unknown (tag: {})", hex::encode(tag)),
+                },
             }
         }
     }
 
     Ok(())
 }
+
+/// Machine-readable description of a proof, written next to it
+///
+/// The binary proof stays authoritative; this only spares web frontends a
+/// parser for it when they show the proof's status.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct ProofSummary {
+    /// Hash function of the stamped file
+    pub digest_type: String,
+    /// Hex-encoded digest of the stamped file
+    pub digest: String,
+    /// `pending` until the proof has a block attestation, then `confirmed`
+    pub status: &'static str,
+    /// Every attestation in the proof, except metadata
+    pub attestations: Vec<AttestationSummary>,
+    /// Calendars the proof is still waiting for
+    pub calendars: Vec<String>,
+    /// Metadata labels of the proof
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// One attestation in a `ProofSummary`
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct AttestationSummary {
+    /// `pending`, `confirmed` or `unknown`
+    pub state: &'static str,
+    /// Chain of a block attestation, e.g. `bitcoin`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chain: Option<&'static str>,
+    /// Height of the block
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<usize>,
+    /// Calendar of a pending attestation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub calendar: Option<String>,
+    /// Hex-encoded tag of an unknown attestation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+}
+
+impl AttestationSummary {
+    fn new(state: &'static str) -> Self {
+        Self { state, chain: None, height: None, calendar: None, tag: None }
+    }
+}
+
+impl ProofSummary {
+    /// Describe `ots`
+    #[must_use]
+    pub fn new(ots: &DetachedTimestampFile) -> Self {
+        let mut attestations = Vec::new();
+        collect_attestations(&ots.timestamp.first_step(), &mut attestations);
+
+        let mut summary = Self {
+            digest_type: ots.digest_type().to_string(),
+            digest: hex::encode(ots.timestamp.start_digest()),
+            status: StampStatus::Pending.as_str(),
+            attestations: vec![],
+            calendars: vec![],
+            metadata: BTreeMap::new(),
+        };
+        for att in &attestations {
+            let entry = match att {
+                Attestation::Bitcoin { height } | Attestation::Litecoin { height } => {
+                    summary.status = StampStatus::Confirmed.as_str();
+                    let chain = if matches!(att, Attestation::Bitcoin { .. }) {
+                        "bitcoin"
+                    } else {
+                        "litecoin"
+                    };
+                    AttestationSummary {
+                        chain: Some(chain),
+                        height: Some(*height),
+                        ..AttestationSummary::new("confirmed")
+                    }
+                }
+                Attestation::Pending { uri } => {
+                    if !summary.calendars.contains(uri) {
+                        summary.calendars.push(uri.clone());
+                    }
+                    AttestationSummary {
+                        calendar: Some(uri.clone()),
+                        ..AttestationSummary::new("pending")
+                    }
+                }
+                Attestation::Unknown { tag, .. } => {
+                    if let Some(Ok(metadata)) = Metadata::from_attestation(att) {
+                        summary
+                            .metadata
+                            .extend(metadata.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+                        continue;
+                    }
+                    AttestationSummary {
+                        tag: Some(hex::encode(tag)),
+                        ..AttestationSummary::new("unknown")
+                    }
+                }
+            };
+            summary.attestations.push(entry);
+        }
+        summary
+    }
+}
+
+/// Write the `ProofSummary` of a proof to `<proof>.json`
+///
+/// Reads `.otsa` files as attached proofs, anything else as a detached
+/// proof. An existing summary is replaced, since it only mirrors the proof.
+///
+/// # Errors
+/// Returns error if the proof cannot be read or the summary cannot be written
+pub fn write_json_summary(proof: &Path) -> Result<PathBuf> {
+    let reader = open_proof(proof)?;
+    let ots = if proof.extension().is_some_and(|ext| ext == "otsa") {
+        AttachedTimestampFile::from_reader(reader)?.proof
+    } else {
+        DetachedTimestampFile::from_reader(reader)?
+    };
+
+    let path = append_extension(proof, "json");
+    let text =
+        serde_json::to_string_pretty(&ProofSummary::new(&ots)).map_err(std::io::Error::from)?;
+    std::fs::write(&path, format!("{text}\n"))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ots::{Digest, Op, Timestamp};
+
+    #[test]
+    fn test_proof_summary() {
+        let attestation = |att: Attestation| Step {
+            data: StepData::Attestation(att),
+            output: vec![0xab; 32],
+            next: vec![],
+        };
+        let digest = Op::Sha256.execute(&[0xab; 32]);
+        let mut metadata = Metadata::default();
+        metadata.insert("doc-id", "INV-42").unwrap();
+        let ots = DetachedTimestampFile {
+            timestamp: Timestamp::new(
+                Digest::sha256([0xab; 32]),
+                &Step {
+                    data: StepData::Fork,
+                    output: vec![0xab; 32],
+                    next: vec![
+                        attestation(metadata.to_attestation().unwrap()),
+                        attestation(Attestation::Pending { uri: "https://a.example".into() }),
+                        Step {
+                            data: StepData::Op(Op::Sha256),
+                            output: digest.clone(),
+                            next: vec![Step {
+                                data: StepData::Attestation(Attestation::Bitcoin {
+                                    height: 850_000,
+                                }),
+                                output: digest,
+                                next: vec![],
+                            }],
+                        },
+                    ],
+                },
+            )
+            .unwrap(),
+        };
+
+        let summary = ProofSummary::new(&ots);
+        assert_eq!(summary.status, "confirmed");
+        assert_eq!(summary.calendars, vec!["https://a.example".to_string()]);
+        assert_eq!(summary.metadata.get("doc-id").map(String::as_str), Some("INV-42"));
+        assert_eq!(summary.attestations.len(), 2);
+
+        let json = serde_json::to_value(&summary).unwrap();
+        assert_eq!(json["digest_type"], "SHA256");
+        assert_eq!(
+            json["attestations"][0],
+            serde_json::json!({
+                "state": "pending",
+                "calendar": "https://a.example",
+            })
+        );
+        assert_eq!(
+            json["attestations"][1],
+            serde_json::json!({
+                "state": "confirmed",
+                "chain": "bitcoin",
+                "height": 850_000,
+            })
+        );
+    }
+}
@@ -0,0 +1,320 @@
+use crate::calendar::CalendarClient;
+use crate::commands::append_extension;
+use crate::commands::stamp::stamp_digest;
+use crate::commands::verify::verify_timestamp;
+use crate::error::{Error, Result};
+use crate::ots::{DetachedTimestampFile, Digest, Op, Step, StepData, Timestamp};
+use crate::verbosity::progress;
+use crate::verifier::ChainVerifiers;
+use log::debug;
+use sha2::{Digest as _, Sha256};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Chain head before the first entry
+pub const GENESIS_HEAD: [u8; 32] = [0u8; 32];
+
+/// Execute the log append command
+///
+/// Appends each entry as a line of `logfile` and extends the hash chain over
+/// the log: `head = SHA256(previous_head || SHA256(entry))`. The current head
+/// is kept in `logfile.head` so appending does not need to reread the log.
+///
+/// # Arguments
+///
+/// * `logfile` - Path to the log
+/// * `entries` - Entries to append, one line each
+/// * `stamp_every` - Stamp the chain head whenever the entry count is a multiple of this
+/// * `calendar_groups` - Calendar server URLs per chain used for stamping (an empty group
+///   uses the defaults)
+/// * `client` - Calendar client used for stamping
+///
+/// # Errors
+///
+/// Returns error if:
+/// - An entry contains a newline
+/// - The log or its head file cannot be read or written
+/// - Stamping fails
+#[allow(clippy::future_not_send)]
+pub async fn append(
+    logfile: &Path,
+    entries: &[String],
+    stamp_every: Option<usize>,
+    calendar_groups: &[Vec<String>],
+    client: &CalendarClient,
+) -> Result<()> {
+    if entries.iter().any(|entry| entry.contains('\n')) {
+        return Err(Error::Log("Log entries must not contain newlines".into()));
+    }
+
+    let (mut count, mut head) = load_head(logfile)?;
+    let mut log = OpenOptions::new().create(true).append(true).open(logfile)?;
+    for entry in entries {
+        writeln!(log, "{entry}")?;
+        head = next_head(&head, entry.as_bytes());
+        count += 1;
+        save_head(logfile, count, &head)?;
+        debug!("Entry {count} appended, chain head {}", hex::encode(head));
+
+        if stamp_every.is_some_and(|n| n > 0 && count % n == 0) {
+            stamp_head(logfile, count, head, calendar_groups, client).await?;
+        }
+    }
+
+    progress!("Log has {count} entries, chain head {}", hex::encode(head));
+    Ok(())
+}
+
+/// Execute the log stamp command
+///
+/// Timestamps the current chain head, saving the proof as
+/// `logfile.<count>.ots`.
+///
+/// # Errors
+///
+/// Returns error if the log is empty or cannot be read, or stamping fails
+#[allow(clippy::future_not_send)]
+pub async fn stamp(
+    logfile: &Path,
+    calendar_groups: &[Vec<String>],
+    client: &CalendarClient,
+) -> Result<()> {
+    let (count, head) = load_head(logfile)?;
+    if count == 0 {
+        return Err(Error::Log(format!("{} has no entries", logfile.display())));
+    }
+    stamp_head(logfile, count, head, calendar_groups, client).await
+}
+
+/// Execute the log verify command
+///
+/// Recomputes the hash chain from the log itself, then checks that the
+/// entry on `line` (1-based) is committed to by a stamped chain head with a
+/// Bitcoin attestation. Stamps are tried from the earliest one covering the
+/// entry, so the reported block time is as tight as possible.
+///
+/// # Errors
+///
+/// Returns error if:
+/// - The log cannot be read or has no such line
+/// - No stamp covering the entry has a valid Bitcoin attestation
+pub async fn verify(logfile: &Path, line: usize, verifiers: &ChainVerifiers) -> Result<()> {
+    let entries = read_entries(logfile)?;
+    if line == 0 || line > entries.len() {
+        return Err(Error::Log(format!("{} has no line {line}", logfile.display())));
+    }
+
+    for count in line..=entries.len() {
+        let path = stamp_path(logfile, count);
+        if !path.exists() {
+            continue;
+        }
+        let ots = DetachedTimestampFile::from_reader(BufReader::new(File::open(&path)?))?;
+        let Some(timestamp) = entry_timestamp(&entries, line, count, ots.timestamp) else {
+            return Err(Error::Verification(format!(
+                "{} does not match the log; entries were modified",
+                path.display()
+            )));
+        };
+
+        progress!("Entry {line} is covered by {}", path.display());
+        match verify_timestamp(&timestamp, verifiers).await {
+            Err(Error::NoBitcoinAttestation | Error::PendingTimestamp) => {
+                debug!("{} has no Bitcoin attestation yet", path.display());
+            }
+            result => return result,
+        }
+    }
+
+    Err(Error::NoBitcoinAttestation)
+}
+
+/// Stamp a chain head and save the proof next to the log
+#[allow(clippy::future_not_send)]
+async fn stamp_head(
+    logfile: &Path,
+    count: usize,
+    head: [u8; 32],
+    calendar_groups: &[Vec<String>],
+    client: &CalendarClient,
+) -> Result<()> {
+    let ots = stamp_digest(head, calendar_groups, client).await?;
+    let path = stamp_path(logfile, count);
+    let mut writer = BufWriter::new(File::create(&path)?);
+    ots.to_writer(&mut writer)?;
+    writer.flush()?;
+
+    progress!("Stamped chain head after entry {count}: {}", path.display());
+    Ok(())
+}
+
+/// Build a timestamp for the entry on `line` from the stamp of the head after `count` entries
+///
+/// The chain links from the entry to the stamped head are expressed as
+/// ordinary ops, so the result is a normal proof for `SHA256(entry)`.
+/// Returns `None` if the stamp is not for the head recomputed from `entries`.
+fn entry_timestamp(
+    entries: &[Vec<u8>],
+    line: usize,
+    count: usize,
+    stamped: Timestamp,
+) -> Option<Timestamp> {
+    let mut head = GENESIS_HEAD;
+    for entry in &entries[..line - 1] {
+        head = next_head(&head, entry);
+    }
+
+    let start_digest: [u8; 32] = Sha256::digest(&entries[line - 1]).into();
+    let mut ops = vec![Op::Prepend(head.to_vec()), Op::Sha256];
+    for entry in &entries[line..count] {
+        ops.push(Op::Append(Sha256::digest(entry).to_vec()));
+        ops.push(Op::Sha256);
+    }
+
+    // Replay the ops to get every intermediate digest
+    let mut outputs = Vec::with_capacity(ops.len());
+    let mut digest = start_digest.to_vec();
+    for op in &ops {
+        digest = op.execute(&digest);
+        outputs.push(digest.clone());
+    }
+    if digest != stamped.start_digest().as_bytes() {
+        return None;
+    }
+
+    let first_step =
+        ops.into_iter().zip(outputs).rev().fold(stamped.first_step(), |next, (op, output)| Step {
+            data: StepData::Op(op),
+            output,
+            next: vec![next],
+        });
+    Timestamp::new(Digest::sha256(start_digest), &first_step).ok()
+}
+
+/// Extend the chain head by one entry
+fn next_head(head: &[u8; 32], entry: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(head);
+    hasher.update(Sha256::digest(entry));
+    hasher.finalize().into()
+}
+
+/// Read the entries of a log, one per line
+fn read_entries(logfile: &Path) -> Result<Vec<Vec<u8>>> {
+    let contents = std::fs::read(logfile)?;
+    let mut entries: Vec<Vec<u8>> = contents.split(|&b| b == b'\n').map(<[u8]>::to_vec).collect();
+    // The last line is terminated by a newline too
+    entries.pop();
+    Ok(entries)
+}
+
+/// Load the entry count and chain head, recomputing them if the head file is missing
+fn load_head(logfile: &Path) -> Result<(usize, [u8; 32])> {
+    let path = head_path(logfile);
+    if !path.exists() {
+        if !logfile.exists() {
+            return Ok((0, GENESIS_HEAD));
+        }
+        let entries = read_entries(logfile)?;
+        let head = entries.iter().fold(GENESIS_HEAD, |head, entry| next_head(&head, entry));
+        return Ok((entries.len(), head));
+    }
+
+    let contents = std::fs::read_to_string(&path)?;
+    let invalid = || Error::Log(format!("Invalid head file {}", path.display()));
+    let (count, head) = contents.trim().split_once(' ').ok_or_else(invalid)?;
+    let count = count.parse().map_err(|_| invalid())?;
+    let head = hex::decode(head).ok().and_then(|h| h.try_into().ok()).ok_or_else(invalid)?;
+    Ok((count, head))
+}
+
+/// Save the entry count and chain head
+fn save_head(logfile: &Path, count: usize, head: &[u8; 32]) -> Result<()> {
+    std::fs::write(head_path(logfile), format!("{count} {}\n", hex::encode(head)))?;
+    Ok(())
+}
+
+/// Path of the file holding the current chain head
+fn head_path(logfile: &Path) -> PathBuf {
+    append_extension(logfile, "head")
+}
+
+/// Path of the proof for the chain head after `count` entries
+fn stamp_path(logfile: &Path, count: usize) -> PathBuf {
+    append_extension(logfile, &format!("{count}.ots"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ots::Attestation;
+
+    fn entries() -> Vec<Vec<u8>> {
+        vec![b"first".to_vec(), b"second".to_vec(), b"third".to_vec()]
+    }
+
+    fn stamp_of(head: [u8; 32]) -> Timestamp {
+        Timestamp::new(
+            Digest::sha256(head),
+            &Step {
+                data: StepData::Attestation(Attestation::Bitcoin { height: 1 }),
+                output: head.to_vec(),
+                next: vec![],
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_entry_timestamp() {
+        let entries = entries();
+        let heads: Vec<[u8; 32]> = entries
+            .iter()
+            .scan(GENESIS_HEAD, |head, entry| {
+                *head = next_head(head, entry);
+                Some(*head)
+            })
+            .collect();
+
+        for line in 1..=3 {
+            let timestamp = entry_timestamp(&entries, line, 3, stamp_of(heads[2])).unwrap();
+            let entry_digest = Sha256::digest(&entries[line - 1]).into();
+            assert_eq!(timestamp.start_digest(), &Digest::sha256(entry_digest));
+
+            // Every op in the chain must reproduce the recorded outputs
+            let mut digest = timestamp.start_digest().as_bytes().to_vec();
+            let mut step = &timestamp.first_step();
+            while let StepData::Op(op) = &step.data {
+                digest = op.execute(&digest);
+                assert_eq!(digest, step.output);
+                step = &step.next[0];
+            }
+            assert_eq!(digest, heads[2].to_vec());
+        }
+
+        // A stamp of an earlier head does not cover a later entry
+        assert!(entry_timestamp(&entries, 3, 3, stamp_of(heads[1])).is_none());
+
+        // Modifying an entry breaks the chain
+        let mut tampered = entries.clone();
+        tampered[1] = b"changed".to_vec();
+        assert!(entry_timestamp(&tampered, 1, 3, stamp_of(heads[2])).is_none());
+    }
+
+    #[test]
+    fn test_load_head_recomputes() {
+        let logfile = std::env::temp_dir().join(format!("ots_log_{}.log", std::process::id()));
+        std::fs::write(&logfile, b"first\nsecond\nthird\n").unwrap();
+
+        let expected = entries().iter().fold(GENESIS_HEAD, |head, entry| next_head(&head, entry));
+        assert_eq!(read_entries(&logfile).unwrap(), entries());
+        assert_eq!(load_head(&logfile).unwrap(), (3, expected));
+
+        save_head(&logfile, 3, &expected).unwrap();
+        assert_eq!(load_head(&logfile).unwrap(), (3, expected));
+
+        std::fs::remove_file(head_path(&logfile)).unwrap();
+        std::fs::remove_file(&logfile).unwrap();
+    }
+}
@@ -1,11 +1,287 @@
+/// API server command implementation
+#[cfg(feature = "api")]
+pub mod api;
+
+/// Bundle commands implementation
+pub mod bundle;
+
+/// Convert command implementation
+pub mod convert;
+
+/// Diff command implementation
+pub mod diff;
+
+/// Doctor command implementation
+pub mod doctor;
+
+/// Extract command implementation
+pub mod extract;
+
+/// Test vector generation command implementation
+pub mod gen_vectors;
+
 /// Info command implementation
 pub mod info;
 
+/// Hash-chained log commands implementation
+pub mod log_chain;
+
+/// Reanchor command implementation
+pub mod reanchor;
+
+/// Rolling stamp command implementation
+pub mod rolling;
+
+/// Sign command implementation
+pub mod sign;
+
 /// Stamp command implementation
 pub mod stamp;
 
+/// Stats command implementation
+pub mod stats;
+
+/// Unpack command implementation
+pub mod unpack;
+
+/// Status command implementation
+pub mod status;
+
 /// Upgrade command implementation
 pub mod upgrade;
 
 /// Verify command implementation
 pub mod verify;
+
+use crate::error::Result;
+use crate::ots::{dearmor, is_armored, DetachedTimestampFile};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Proof path meaning standard input, or standard output for the result
+pub const STDIO_PATH: &str = "-";
+
+/// Extension of zstd-compressed proofs, as in `file.txt.ots.zst`
+pub const COMPRESSED_EXTENSION: &str = "zst";
+
+/// Magic number starting every zstd frame
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// zstd level for compressed proofs; they are small, so favour ratio over speed
+const ZSTD_LEVEL: i32 = 19;
+
+/// Largest decompressed proof accepted from memory, against compression bombs
+const MAX_DECOMPRESSED_PROOF: u64 = 64 * 1024 * 1024;
+
+/// Whether a proof path argument means standard input
+#[must_use]
+pub fn is_stdio(path: &Path) -> bool {
+    path == Path::new(STDIO_PATH)
+}
+
+/// Append an extension to a path, keeping any it already has
+///
+/// `file.txt` becomes `file.txt.ots`. Works on the raw `OsStr`, so names
+/// that are not valid UTF-8 survive unchanged.
+#[must_use]
+pub fn append_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+/// Remove `extension` from a path that ends with it
+///
+/// `file.txt.ots` becomes `file.txt`; returns `None` if the path has a
+/// different extension or none.
+#[must_use]
+pub fn strip_extension(path: &Path, extension: &str) -> Option<PathBuf> {
+    (path.extension()? == extension).then(|| path.with_extension(""))
+}
+
+/// Whether a proof path names a zstd-compressed proof
+#[must_use]
+pub fn is_compressed(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == COMPRESSED_EXTENSION)
+}
+
+/// The path of a proof without its compression extension
+///
+/// `file.txt.ots.zst` becomes `file.txt.ots`; other paths are returned as is.
+#[must_use]
+pub fn without_compression(path: &Path) -> PathBuf {
+    strip_extension(path, COMPRESSED_EXTENSION).unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Open a proof for reading, from standard input if `path` is `-`
+///
+/// ASCII-armored and zstd-compressed proofs are recognized by their
+/// content, whatever their name, and decoded on the fly.
+///
+/// # Errors
+/// Returns error if the file cannot be opened, or holds an armor that
+/// cannot be decoded
+pub fn open_proof(path: &Path) -> Result<Box<dyn BufRead>> {
+    let mut reader: Box<dyn BufRead> = if is_stdio(path) {
+        Box::new(BufReader::new(std::io::stdin().lock()))
+    } else {
+        Box::new(BufReader::new(File::open(path)?))
+    };
+    if is_armored(reader.fill_buf()?) {
+        let mut text = Vec::new();
+        reader.take(MAX_DECOMPRESSED_PROOF).read_to_end(&mut text)?;
+        reader = Box::new(Cursor::new(dearmor(&text)?));
+    }
+    if reader.fill_buf()?.starts_with(&ZSTD_MAGIC) {
+        reader = Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?));
+    }
+    Ok(reader)
+}
+
+/// Decode a proof held in memory if it is ASCII-armored, then decompress
+/// it if it is zstd-compressed
+///
+/// # Errors
+/// Returns error if the armor is invalid, or the data is not valid zstd or
+/// decompresses to more than `MAX_DECOMPRESSED_PROOF` bytes
+pub fn decompress_proof(mut bytes: Vec<u8>) -> Result<Vec<u8>> {
+    if is_armored(&bytes) {
+        bytes = dearmor(&bytes)?;
+    }
+    if !bytes.starts_with(&ZSTD_MAGIC) {
+        return Ok(bytes);
+    }
+    let mut proof = Vec::new();
+    zstd::Decoder::new(bytes.as_slice())?
+        .take(MAX_DECOMPRESSED_PROOF + 1)
+        .read_to_end(&mut proof)?;
+    if proof.len() as u64 > MAX_DECOMPRESSED_PROOF {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Compressed proof exceeds {MAX_DECOMPRESSED_PROOF} bytes"),
+        )
+        .into());
+    }
+    Ok(proof)
+}
+
+/// Write a proof, zstd-compressed if `compress` is set
+///
+/// # Errors
+/// Returns error if the proof cannot be serialized or written
+pub fn write_proof<W: Write>(ots: &DetachedTimestampFile, writer: W, compress: bool) -> Result<()> {
+    write_serialized(writer, compress, |writer| ots.to_writer(writer))
+}
+
+/// Write a proof in canonical form, see `Timestamp::serialize_canonical`
+///
+/// # Errors
+/// Returns error if the proof cannot be serialized or written
+pub fn write_canonical_proof<W: Write>(
+    ots: &DetachedTimestampFile,
+    writer: W,
+    compress: bool,
+) -> Result<()> {
+    write_serialized(writer, compress, |writer| ots.to_writer_canonical(writer))
+}
+
+/// Run `serialize` on `writer`, through a zstd encoder if `compress` is set
+fn write_serialized<W: Write>(
+    writer: W,
+    compress: bool,
+    serialize: impl FnOnce(&mut dyn Write) -> crate::ots::Result<()>,
+) -> Result<()> {
+    if compress {
+        let mut encoder = zstd::Encoder::new(writer, ZSTD_LEVEL)?;
+        serialize(&mut encoder)?;
+        encoder.finish()?.flush()?;
+    } else {
+        let mut writer = BufWriter::new(writer);
+        serialize(&mut writer)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extensions() {
+        let proof = append_extension(Path::new("dir/file.txt"), "ots");
+        assert_eq!(proof, Path::new("dir/file.txt.ots"));
+        assert_eq!(strip_extension(&proof, "ots").unwrap(), Path::new("dir/file.txt"));
+        assert!(strip_extension(&proof, "otsa").is_none());
+        assert!(strip_extension(Path::new("file"), "ots").is_none());
+    }
+
+    fn proof() -> DetachedTimestampFile {
+        use crate::ots::{Attestation, Digest, Step, StepData, Timestamp};
+
+        DetachedTimestampFile {
+            timestamp: Timestamp::new(
+                Digest::sha256([0xab; 32]),
+                &Step {
+                    data: StepData::Attestation(Attestation::Bitcoin { height: 100 }),
+                    output: vec![0xab; 32],
+                    next: vec![],
+                },
+            )
+            .unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_compressed_round_trip() {
+        let ots = proof();
+        let dir = std::env::temp_dir().join(format!("ots-compress-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file.txt.ots.zst");
+        write_proof(&ots, File::create(&path).unwrap(), true).unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(bytes.starts_with(&ZSTD_MAGIC));
+        let from_file = DetachedTimestampFile::from_reader(open_proof(&path).unwrap()).unwrap();
+        let from_bytes = DetachedTimestampFile::from_bytes(&decompress_proof(bytes).unwrap());
+        assert_eq!(from_file, ots);
+        assert_eq!(from_bytes.unwrap(), ots);
+
+        assert!(is_compressed(&path));
+        assert_eq!(without_compression(&path), dir.join("file.txt.ots"));
+        assert_eq!(without_compression(Path::new("a.ots")), Path::new("a.ots"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_armored() {
+        use crate::ots::armor;
+
+        let dir = std::env::temp_dir().join(format!("ots-armor-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file.txt.ots.asc");
+        let mut compressed = Vec::new();
+        write_proof(&proof(), &mut compressed, true).unwrap();
+        std::fs::write(&path, format!("Release proof:\n{}", armor(&compressed))).unwrap();
+
+        let from_file = DetachedTimestampFile::from_reader(open_proof(&path).unwrap()).unwrap();
+        let bytes = decompress_proof(std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(from_file, proof());
+        assert_eq!(DetachedTimestampFile::from_bytes(&bytes).unwrap(), proof());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_extensions_non_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let file = Path::new(OsStr::from_bytes(b"caf\xe9.txt"));
+        let proof = append_extension(file, "ots");
+        assert_eq!(proof.as_os_str().as_bytes(), b"caf\xe9.txt.ots");
+        assert_eq!(strip_extension(&proof, "ots").unwrap(), file);
+    }
+}
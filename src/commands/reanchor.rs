@@ -0,0 +1,188 @@
+use crate::calendar::CalendarClient;
+use crate::commands::stamp::submit_commitment;
+use crate::commands::{is_compressed, open_proof, write_proof};
+use crate::error::{Error, Result};
+use crate::ots::{Attestation, DetachedTimestampFile, Step, StepData};
+use crate::output::{AtomicFile, Overwrite};
+use crate::verbosity::progress;
+use log::debug;
+use std::path::Path;
+
+/// Execute the reanchor command
+///
+/// Submits the block merkle root of a confirmed proof to calendars again and
+/// adds their pending attestations next to the Bitcoin attestation. Once the
+/// new branch is upgraded, the proof is anchored a second time, independent
+/// of the calendar and block it was first anchored through.
+///
+/// The earliest Bitcoin attestation is re-anchored. The proof is updated in place.
+///
+/// # Arguments
+/// * `file` - Path to a confirmed .ots file
+/// * `calendar_groups` - Calendar server URLs per chain, each group getting its own fork
+///   (an empty group uses the defaults)
+/// * `client` - Calendar client used for submissions
+///
+/// # Errors
+/// Returns error if:
+/// - File cannot be read or parsed
+/// - The proof has no Bitcoin attestation yet
+/// - Submission fails for every calendar group
+/// - Updated file cannot be written
+#[allow(clippy::future_not_send)]
+pub async fn execute(
+    file: &Path,
+    calendar_groups: &[Vec<String>],
+    client: &CalendarClient,
+) -> Result<()> {
+    progress!("Re-anchoring timestamp: {}", file.display());
+    let mut ots = DetachedTimestampFile::from_reader(open_proof(file)?)?;
+
+    // 1. Find the digest the earliest Bitcoin attestation commits to
+    let height =
+        earliest_bitcoin_height(&ots.timestamp.first_step()).ok_or(Error::NoBitcoinAttestation)?;
+    let attestation = Attestation::Bitcoin { height };
+    let (msg, _) = ots
+        .timestamp
+        .map()
+        .all_attestations()
+        .into_iter()
+        .find(|(_, found)| **found == attestation)
+        .ok_or(Error::NoBitcoinAttestation)?;
+    let commitment: [u8; 32] = msg.try_into().map_err(|_| {
+        Error::Verification(format!(
+            "Bitcoin attestation at block {height} is not on a 32-byte digest"
+        ))
+    })?;
+    debug!("Re-anchoring commitment {} of block {height}", hex::encode(commitment));
+
+    // 2. Submit it and add the new anchor next to the old one
+    let calendar_step = submit_commitment(&commitment, calendar_groups, client).await?;
+    ots.timestamp.merge_at(&commitment, &calendar_step)?;
+
+    // 3. Save the proof
+    let mut writer = AtomicFile::create(file, Overwrite::Always)?;
+    write_proof(&ots, &mut writer, is_compressed(file))?;
+    writer.commit()?;
+
+    progress!("Added pending branch to block {height} commitment; run upgrade later to confirm it");
+    Ok(())
+}
+
+/// Check whether a re-anchored branch is still waiting for its calendars
+///
+/// That is a pending attestation forked off right next to a Bitcoin
+/// attestation, which `upgrade` must not skip just because the proof is
+/// already confirmed.
+#[must_use]
+pub fn has_pending_reanchor(step: &Step) -> bool {
+    let anchored = step.data == StepData::Fork
+        && step
+            .next
+            .iter()
+            .any(|next| matches!(next.data, StepData::Attestation(Attestation::Bitcoin { .. })));
+    if anchored && step.next.iter().any(has_pending_attestation) {
+        return true;
+    }
+    step.next.iter().any(has_pending_reanchor)
+}
+
+/// Check whether any path from a step ends in a pending attestation not yet upgraded
+///
+/// `upgrade` keeps a pending attestation and forks the chain its calendar
+/// returned off next to it, so one beside a chain reaching Bitcoin is done.
+fn has_pending_attestation(step: &Step) -> bool {
+    let is_pending =
+        |step: &Step| matches!(step.data, StepData::Attestation(Attestation::Pending { .. }));
+    let upgraded = step.data == StepData::Fork
+        && step.next.iter().any(|next| {
+            matches!(next.data, StepData::Op(_)) && earliest_bitcoin_height(next).is_some()
+        });
+    is_pending(step)
+        || step
+            .next
+            .iter()
+            .filter(|next| !(upgraded && is_pending(next)))
+            .any(has_pending_attestation)
+}
+
+/// Lowest block height of any Bitcoin attestation in a timestamp tree
+fn earliest_bitcoin_height(step: &Step) -> Option<usize> {
+    let own = match step.data {
+        StepData::Attestation(Attestation::Bitcoin { height }) => Some(height),
+        _ => None,
+    };
+    step.next.iter().filter_map(earliest_bitcoin_height).chain(own).min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attestation_step(attestation: Attestation) -> Step {
+        Step { data: StepData::Attestation(attestation), output: vec![9; 32], next: vec![] }
+    }
+
+    #[test]
+    fn test_earliest_height() {
+        let step = Step {
+            data: StepData::Fork,
+            output: vec![9; 32],
+            next: vec![
+                attestation_step(Attestation::Bitcoin { height: 850_000 }),
+                attestation_step(Attestation::Pending { uri: "https://a.example".into() }),
+                attestation_step(Attestation::Bitcoin { height: 849_999 }),
+            ],
+        };
+        assert_eq!(earliest_bitcoin_height(&step), Some(849_999));
+        assert_eq!(
+            earliest_bitcoin_height(&attestation_step(Attestation::Pending { uri: String::new() })),
+            None
+        );
+    }
+
+    #[test]
+    fn test_has_pending_reanchor() {
+        let bitcoin = attestation_step(Attestation::Bitcoin { height: 1 });
+        let pending = attestation_step(Attestation::Pending { uri: "https://a.example".into() });
+        assert!(!has_pending_reanchor(&bitcoin));
+
+        // A calendar still pending elsewhere in the proof is not a re-anchor
+        let elsewhere = Step {
+            data: StepData::Fork,
+            output: vec![1; 32],
+            next: vec![
+                Step {
+                    data: StepData::Op(crate::ots::Op::Sha256),
+                    output: vec![9; 32],
+                    next: vec![bitcoin.clone()],
+                },
+                pending.clone(),
+            ],
+        };
+        assert!(!has_pending_reanchor(&elsewhere));
+
+        let reanchored = Step {
+            data: StepData::Fork,
+            output: vec![9; 32],
+            next: vec![bitcoin.clone(), pending.clone()],
+        };
+        assert!(has_pending_reanchor(&reanchored));
+
+        // Upgrading keeps the pending attestation next to the new anchor
+        let mut upgraded = reanchored;
+        upgraded.next[1] = Step {
+            data: StepData::Fork,
+            output: vec![9; 32],
+            next: vec![
+                pending,
+                Step {
+                    data: StepData::Op(crate::ots::Op::Sha256),
+                    output: vec![8; 32],
+                    next: vec![attestation_step(Attestation::Bitcoin { height: 2 })],
+                },
+            ],
+        };
+        assert!(!has_pending_reanchor(&upgraded));
+    }
+}
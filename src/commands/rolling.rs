@@ -0,0 +1,97 @@
+use crate::calendar::CalendarClient;
+use crate::commands::stamp::{hash_file, stamp_digest};
+use crate::error::Result;
+use crate::verbosity::progress;
+use log::debug;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Name of the file in the history directory listing stamps in order
+pub const INDEX_FILE: &str = "index";
+
+/// Execute the rolling stamp command
+///
+/// Checks `file` every `interval` and stamps its content whenever it hashes
+/// to a digest that has not been stamped before. Proofs are kept in
+/// `history` as `<digest>.ots`, and every stamp is recorded as a
+/// `<unix time> <digest>` line in `history/index`.
+///
+/// # Arguments
+///
+/// * `file` - File to watch
+/// * `history` - Directory holding the stamped digests and their proofs
+/// * `interval` - How often to check the file; `None` checks once and returns
+/// * `calendar_groups` - Calendar server URLs per chain (an empty group uses the defaults)
+/// * `client` - Calendar client used for stamping
+///
+/// # Errors
+///
+/// Returns error if the file cannot be read, the history cannot be written,
+/// or stamping fails
+#[allow(clippy::future_not_send)]
+pub async fn execute(
+    file: &Path,
+    history: &Path,
+    interval: Option<Duration>,
+    calendar_groups: &[Vec<String>],
+    client: &CalendarClient,
+) -> Result<()> {
+    std::fs::create_dir_all(history)?;
+    loop {
+        stamp_if_changed(file, history, calendar_groups, client).await?;
+        let Some(interval) = interval else {
+            return Ok(());
+        };
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Stamp the current content of `file` unless it is already in the history
+#[allow(clippy::future_not_send)]
+async fn stamp_if_changed(
+    file: &Path,
+    history: &Path,
+    calendar_groups: &[Vec<String>],
+    client: &CalendarClient,
+) -> Result<()> {
+    let digest = hash_file(file)?;
+
+    let proof_path = proof_path(history, &digest);
+    if proof_path.exists() {
+        debug!("{} unchanged ({})", file.display(), hex::encode(digest));
+        return Ok(());
+    }
+
+    progress!("Stamping {} ({})", file.display(), hex::encode(digest));
+    let ots = stamp_digest(digest, calendar_groups, client).await?;
+    let mut writer = BufWriter::new(File::create(&proof_path)?);
+    ots.to_writer(&mut writer)?;
+    writer.flush()?;
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let mut index = OpenOptions::new().create(true).append(true).open(history.join(INDEX_FILE))?;
+    writeln!(index, "{now} {}", hex::encode(digest))?;
+
+    progress!("Created timestamp: {}", proof_path.display());
+    Ok(())
+}
+
+/// Path of the proof for a digest in the history directory
+fn proof_path(history: &Path, digest: &[u8]) -> PathBuf {
+    history.join(format!("{}.ots", hex::encode(digest)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proof_path() {
+        assert_eq!(
+            proof_path(Path::new("history"), &[0xab, 0xcd]),
+            Path::new("history").join("abcd.ots")
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use crate::commands::{open_proof, without_compression};
+use crate::error::{Error, Result};
+use crate::ots::{DetachedTimestampFile, SignedTimestampFile};
+use crate::output::{create_file, Overwrite};
+use crate::verbosity::progress;
+use ed25519_dalek::SigningKey;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Execute the sign command
+///
+/// Reads an `.ots` file and writes a signed `.otss` file next to it:
+/// `file.txt.ots` becomes `file.txt.otss`.
+///
+/// # Arguments
+///
+/// * `file` - Path to the .ots file to sign
+/// * `key_file` - File holding the hex-encoded 32-byte ed25519 secret key
+/// * `overwrite` - What to do when the signed file already exists
+///
+/// # Errors
+///
+/// Returns error if:
+/// - The key file cannot be read or does not hold a valid key
+/// - The proof cannot be read or parsed
+/// - The signed file exists or cannot be written
+pub fn execute(file: &Path, key_file: &Path, overwrite: Overwrite) -> Result<()> {
+    let key = load_signing_key(key_file)?;
+    let signed_path = signed_path(file)?;
+
+    let ots = DetachedTimestampFile::from_reader(open_proof(file)?)?;
+    let signed = SignedTimestampFile::sign(ots, &key)?;
+
+    let mut writer = BufWriter::new(create_file(&signed_path, overwrite)?);
+    signed.to_writer(&mut writer)?;
+    writer.flush()?;
+
+    progress!("Created signed timestamp: {}", signed_path.display());
+    progress!("Signer: {}", hex::encode(signed.signer().as_bytes()));
+    Ok(())
+}
+
+/// Read a hex-encoded ed25519 secret key from a file
+fn load_signing_key(path: &Path) -> Result<SigningKey> {
+    let contents = std::fs::read_to_string(path)?;
+    let bytes = hex::decode(contents.trim())
+        .map_err(|e| Error::Signing(format!("{}: {e}", path.display())))?;
+    let seed: [u8; 32] = bytes.try_into().map_err(|_| {
+        Error::Signing(format!("{}: expected 32-byte hex-encoded key", path.display()))
+    })?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+/// Derive the signed file path by replacing the `.ots` or `.ots.zst` extension
+fn signed_path(file: &Path) -> Result<PathBuf> {
+    let file = &without_compression(file);
+    if file.extension().is_some_and(|ext| ext == "ots") {
+        Ok(file.with_extension("otss"))
+    } else {
+        Err(Error::Signing(format!(
+            "Cannot determine output file: .ots extension missing on {}",
+            file.display()
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signed_path() {
+        assert_eq!(
+            signed_path(Path::new("dir/file.txt.ots")).unwrap(),
+            Path::new("dir/file.txt.otss")
+        );
+        assert_eq!(
+            signed_path(Path::new("dir/file.txt.ots.zst")).unwrap(),
+            Path::new("dir/file.txt.otss")
+        );
+        assert!(signed_path(Path::new("file.txt.otsa")).is_err());
+    }
+
+    #[test]
+    fn test_load_signing_key() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("ots_sign_key_{}", std::process::id()));
+
+        std::fs::write(&path, format!("{}\n", "42".repeat(32))).unwrap();
+        let key = load_signing_key(&path).unwrap();
+        assert_eq!(key.to_bytes(), [0x42; 32]);
+
+        std::fs::write(&path, "4242").unwrap();
+        assert!(matches!(load_signing_key(&path), Err(Error::Signing(_))));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,93 +1,1022 @@
-use crate::calendar::CalendarClient;
-use crate::error::Result;
-use crate::ots::{Deserializer, DetachedTimestampFile, DigestType, Op, Step, StepData, Timestamp};
-use log::debug;
-use sha2::{Digest, Sha256};
+use crate::calendar::{parse_calendar_response, CalendarClient, DEFAULT_CALENDARS};
+use crate::commands::{
+    append_extension, is_compressed, is_stdio, strip_extension, without_compression, write_proof,
+    COMPRESSED_EXTENSION,
+};
+use crate::error::{Error, Result};
+use crate::journal::Journal;
+use crate::merkle::{self, MerkleTree, Side};
+use crate::ots::{
+    AttachedTimestampFile, DetachedTimestampFile, Digest, DigestType, Metadata, Op, Step, StepData,
+    Timestamp,
+};
+use crate::output::{create_file, AtomicFile, Overwrite};
+use crate::progress::BatchProgress;
+use crate::state::{self, StampRecord, StampStatus, StateStore};
+use crate::verbosity::progress;
+use futures::future::join_all;
+use glob::Pattern;
+use log::{debug, warn};
+use serde::Serialize;
+use sha2::{Digest as _, Sha256};
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Cursor, Read, Write};
-use std::path::Path;
-use std::time::Duration;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Nonce policy of stamps committing to each file with its own nonce
+pub const NONCE_PER_FILE: &str = "per-file";
+
+/// Nonce policy of stamps aggregated into a Merkle tree, each leaf with its own nonce
+pub const NONCE_MERKLE: &str = "merkle";
+
+/// Nonce policy of stamps sharing a nonce given on the command line
+pub const NONCE_FIXED: &str = "fixed";
+
+/// Nonce policy of stamps committing to the digest without a nonce
+pub const NONCE_NONE: &str = "none";
+
+/// Nonce policy of stamps sending every calendar a commitment with its own nonce
+pub const NONCE_PER_CALENDAR: &str = "per-calendar";
+
+/// Number of files from which `stamp` aggregates them into a Merkle tree
+/// unless told not to, sparing the calendars one request per file
+pub const AUTO_MERKLE_FILES: usize = 100;
+
+/// Read size used when hashing files, unless told otherwise
+pub const DEFAULT_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Extensions of files this client writes, which directory walks skip
+const PROOF_EXTENSIONS: &[&str] = &["ots", "otsa", "otss", "otsz"];
+
+/// How `stamp` saves each proof
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ProofFormat {
+    /// A detached `.ots` proof
+    Detached,
+    /// A detached proof compressed with zstd, `.ots.zst`
+    Compressed,
+    /// An `.otsa` file embedding the data
+    Attached,
+}
+
+impl ProofFormat {
+    /// Pick the format from the `--attach` and `--compress` flags
+    #[must_use]
+    pub const fn from_flags(attach: bool, compress: bool) -> Self {
+        if attach {
+            Self::Attached
+        } else if compress {
+            Self::Compressed
+        } else {
+            Self::Detached
+        }
+    }
+
+    /// Path of the proof saved for `file`
+    #[must_use]
+    pub fn proof_path(self, file: &Path) -> PathBuf {
+        match self {
+            Self::Detached => append_extension(file, "ots"),
+            Self::Compressed => {
+                append_extension(&append_extension(file, "ots"), COMPRESSED_EXTENSION)
+            }
+            Self::Attached => append_extension(file, "otsa"),
+        }
+    }
+}
+
+/// The nonce appended to each digest before it is sent to the calendars
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub enum Nonce {
+    /// 16 fresh random bytes per digest, so calendars learn nothing about the file
+    #[default]
+    Random,
+    /// The same bytes for every digest, for reproducible commitments
+    Fixed(Vec<u8>),
+    /// No nonce, for digests that are public anyway
+    None,
+    /// 16 fresh random bytes per digest and calendar, so that submissions to
+    /// different calendars cannot be linked to each other
+    PerCalendar,
+}
+
+impl Nonce {
+    /// Pick the nonce from the `--nonce`, `--no-nonce` and `--nonce-per-calendar` options
+    #[must_use]
+    pub fn from_flags(nonce: Option<Vec<u8>>, no_nonce: bool, per_calendar: bool) -> Self {
+        match nonce {
+            Some(nonce) => Self::Fixed(nonce),
+            None if no_nonce => Self::None,
+            None if per_calendar => Self::PerCalendar,
+            None => Self::Random,
+        }
+    }
+
+    /// The nonce for the next digest, or digest and calendar; empty for `Nonce::None`
+    fn generate(&self) -> Vec<u8> {
+        match self {
+            Self::Random | Self::PerCalendar => rand::random::<[u8; 16]>().to_vec(),
+            Self::Fixed(nonce) => nonce.clone(),
+            Self::None => vec![],
+        }
+    }
+
+    /// Nonce policy to record in the state store, `default` for random nonces
+    const fn policy(&self, default: &'static str) -> &'static str {
+        match self {
+            Self::Random => default,
+            Self::Fixed(_) => NONCE_FIXED,
+            Self::None => NONCE_NONE,
+            Self::PerCalendar => NONCE_PER_CALENDAR,
+        }
+    }
+}
+
+/// How `stamp` commits to each file and saves its proof
+#[derive(Clone)]
+pub struct StampOptions<'a> {
+    /// Hash of the file content the proof starts from
+    pub algorithm: DigestType,
+    /// Further hashes of each file, committed in the same submission with a
+    /// proof of their own
+    pub extra_algorithms: &'a [DigestType],
+    /// How files are read for hashing
+    pub read_mode: ReadMode,
+    /// Nonce hiding each digest from the calendars
+    pub nonce: Nonce,
+    /// Whether to write detached, compressed or attached proofs
+    pub format: ProofFormat,
+    /// Metadata to label every proof with
+    pub metadata: Option<&'a Metadata>,
+    /// What to do when a proof already exists
+    pub overwrite: Overwrite,
+    /// Progress bars to advance while hashing and submitting
+    pub progress: &'a BatchProgress,
+    /// Number of files to hash at the same time
+    pub jobs: NonZeroUsize,
+    /// Journal to record every stamped file in, so an interrupted batch can resume
+    pub journal: Option<&'a Journal>,
+}
+
+/// How files are read for hashing
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ReadMode {
+    /// Read through a buffer of this many bytes
+    Buffered(usize),
+    /// Map the whole file into memory, fastest for very large files
+    #[cfg(feature = "mmap")]
+    Mapped,
+}
+
+impl Default for ReadMode {
+    fn default() -> Self {
+        Self::Buffered(DEFAULT_BUFFER_SIZE)
+    }
+}
+
+/// A file to stamp and where its proof goes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Target {
+    /// File to timestamp
+    pub file: PathBuf,
+    /// Path of its proof
+    pub proof: PathBuf,
+}
+
+impl AsRef<Path> for Target {
+    fn as_ref(&self) -> &Path {
+        &self.file
+    }
+}
+
+/// Pair every file with the path of its proof
+///
+/// Without `output`, each proof goes next to its file, and the proof of a
+/// URL into the current directory, named after the URL's last path
+/// segment. `output` names a
+/// directory if it is one, ends with a path separator or more than one file
+/// is given; the directory is created if needed and the proofs are named
+/// after their files, with a `-2`, `-3`, ... suffix on the name of a file
+/// whose name an earlier file already took. Otherwise `output` is the proof
+/// of the single file.
+///
+/// # Errors
+///
+/// Returns error if the output directory cannot be created
+pub fn targets(
+    files: Vec<PathBuf>,
+    format: ProofFormat,
+    output: Option<&Path>,
+) -> Result<Vec<Target>> {
+    let Some(output) = output else {
+        return Ok(files
+            .into_iter()
+            .map(|file| {
+                let proof = match remote_url(&file) {
+                    Some(url) => format.proof_path(&url_name(url)),
+                    None => format.proof_path(&file),
+                };
+                Target { file, proof }
+            })
+            .collect());
+    };
+    let is_dir = output.is_dir()
+        || output.as_os_str().to_string_lossy().ends_with(std::path::is_separator)
+        || files.len() > 1;
+    if !is_dir {
+        return Ok(files
+            .into_iter()
+            .map(|file| Target { file, proof: output.to_path_buf() })
+            .collect());
+    }
+
+    std::fs::create_dir_all(output)?;
+    let mut taken = Vec::with_capacity(files.len());
+    let mut targets = Vec::with_capacity(files.len());
+    for file in files {
+        let name = match remote_url(&file) {
+            Some(url) => url_name(url),
+            None => PathBuf::from(file.file_name().unwrap_or(file.as_os_str())),
+        };
+        let mut unique = name.clone();
+        let mut n = 1;
+        while taken.contains(&unique) {
+            n += 1;
+            unique = numbered(&name, n);
+        }
+        if n > 1 {
+            warn!(
+                "{} has the same name as another file, saving its proof as {}",
+                file.display(),
+                unique.display()
+            );
+        }
+        let proof = format.proof_path(&output.join(&unique));
+        taken.push(unique);
+        targets.push(Target { file, proof });
+    }
+    Ok(targets)
+}
+
+/// Path of the proof of an extra digest: `file.txt.ots` becomes `file.txt.sha1.ots`
+#[must_use]
+pub fn extra_proof_path(proof: &Path, algorithm: DigestType) -> PathBuf {
+    let detached = without_compression(proof);
+    let stem = strip_extension(&detached, "ots").unwrap_or_else(|| detached.clone());
+    let name = algorithm.to_string().to_ascii_lowercase();
+    let extra = append_extension(&append_extension(&stem, &name), "ots");
+    if is_compressed(proof) {
+        append_extension(&extra, COMPRESSED_EXTENSION)
+    } else {
+        extra
+    }
+}
+
+/// Drop the targets whose proof already exists, with a notice for each
+///
+/// Lets a batch be run again after a partial failure without replacing the
+/// proofs it already produced.
+#[must_use]
+pub fn skip_stamped(targets: Vec<Target>) -> Vec<Target> {
+    targets
+        .into_iter()
+        .filter(|target| {
+            let stamped = target.proof.exists();
+            if stamped {
+                progress!(
+                    "Skipping {}: already stamped in {} (use --force to re-stamp)",
+                    target.file.display(),
+                    target.proof.display()
+                );
+            }
+            !stamped
+        })
+        .collect()
+}
+
+/// Drop the targets an interrupted run already stamped, with a notice for each
+#[must_use]
+pub fn skip_journaled(targets: Vec<Target>, journal: &Journal) -> Vec<Target> {
+    targets
+        .into_iter()
+        .filter(|target| {
+            let done = journal.is_done(&target.file);
+            if done {
+                progress!("Skipping {}: stamped before the interruption", target.file.display());
+            }
+            !done
+        })
+        .collect()
+}
+
+/// `name` with `-n` added before its extension: `data.csv` becomes `data-2.csv`
+fn numbered(name: &Path, n: usize) -> PathBuf {
+    let mut numbered = name.file_stem().unwrap_or(name.as_os_str()).to_os_string();
+    numbered.push(format!("-{n}"));
+    let numbered = PathBuf::from(numbered);
+    match name.extension() {
+        Some(extension) => numbered.with_extension(extension),
+        None => numbered,
+    }
+}
 
 /// Execute the stamp command
 ///
 /// Creates timestamps for one or more files by:
-/// 1. Computing SHA256 hash of each file
-/// 2. Adding a random 16-byte nonce for privacy
+/// 1. Hashing each file with the chosen algorithm (SHA256 by default), on
+///    up to `options.jobs` threads
+/// 2. Adding a random 16-byte nonce for privacy, unless `options` gives one
 /// 3. Computing SHA256 of (`file_hash` + nonce)
-/// 4. Submitting the commitment to calendar servers
-/// 5. Parsing the calendar response into a Timestamp
-/// 6. Building the complete timestamp chain
-/// 7. Saving the .ots file
+/// 4. Submitting the commitments to calendar servers, in batches to those
+///    configured under `[calendars.batch]`
+/// 5. Parsing the calendar responses into Timestamps
+/// 6. Building the complete timestamp chains
+/// 7. Saving the .ots files
 ///
 /// # Arguments
 ///
-/// * `files` - List of file paths to timestamp
-/// * `calendar_urls` - Optional list of calendar server URLs (uses defaults if None)
-/// * `timeout` - Timeout in seconds for HTTP requests
+/// * `files` - Files to timestamp, each with the path of its proof
+/// * `calendar_groups` - Calendar server URLs per chain, each group getting its own fork
+///   in the proof (an empty group uses the defaults)
+/// * `client` - Calendar client used for submissions
+/// * `options` - Hash algorithm, nonce, proof format, metadata and overwrite policy
+/// * `state` - Store to record every stamp in
 ///
-/// # Errors
+/// # Returns
 ///
-/// Returns error if:
-/// - File cannot be read
+/// One result per file, in the order of `files`. A file fails if:
+/// - It cannot be read
 /// - Calendar submission fails
-/// - .ots file cannot be written
+/// - Its .ots file exists or cannot be written
 #[allow(clippy::future_not_send)]
 pub async fn execute(
-    files: &[impl AsRef<Path>],
-    calendar_urls: Option<Vec<String>>,
-    timeout: u64,
+    files: &[Target],
+    calendar_groups: &[Vec<String>],
+    client: &CalendarClient,
+    options: &StampOptions<'_>,
+    state: Option<&StateStore>,
+) -> Vec<Result<()>> {
+    // 1. Read every file and compute its digest
+    for file_path in files {
+        progress!("Stamping file: {}", file_path.as_ref().display());
+    }
+    let digests = hash_targets(files, client, options, options.algorithm).await;
+
+    // 2. Commit to each digest with its own nonce and submit them together
+    let hashed: Vec<Digest> = digests.iter().filter_map(|d| d.as_ref().ok().cloned()).collect();
+    options.progress.stage(format!("submitting {} commitments to calendars", hashed.len()));
+    let mut proofs =
+        stamp_digests(&hashed, &options.nonce, calendar_groups, client).await.into_iter();
+
+    // 3. Save .ots files, or .otsa files with the data attached
+    files
+        .iter()
+        .zip(digests)
+        .map(|(target, digest)| {
+            let path = target.as_ref();
+            let digest = digest?;
+            let mut ots = proofs
+                .next()
+                .unwrap_or_else(|| Err(Error::Calendar("No calendar response".into())))?;
+            if let Some(metadata) = options.metadata {
+                ots.timestamp.add_attestation(metadata.to_attestation()?);
+            }
+            let proof = save_proof(target, ots, options.format, options.overwrite)?;
+            let policy = options.nonce.policy(NONCE_PER_FILE);
+            record_stamp(state, path, digest.as_bytes(), policy, calendar_groups, &proof);
+            record_journal(options.journal, path);
+            Ok(())
+        })
+        .collect()
+}
+
+/// Execute the stamp command for a digest computed elsewhere
+///
+/// Stamps `digest` as the SHA256 of a file that need not be present, and
+/// writes the proof to `output`. Nothing is recorded in the state store,
+/// since there is no file to track.
+///
+/// # Arguments
+///
+/// * `digest` - SHA256 digest to timestamp
+/// * `output` - Path of the proof, zstd-compressed if it ends with `.zst` or
+///   the format says so
+/// * `calendar_groups` - Calendar server URLs per chain (an empty group uses the defaults)
+/// * `client` - Calendar client used for submissions
+/// * `options` - Nonce, proof format, metadata and overwrite policy
+///
+/// # Errors
+///
+/// Returns error if calendar submission fails or the proof exists or cannot
+/// be written
+#[allow(clippy::future_not_send)]
+pub async fn execute_digest(
+    digest: [u8; 32],
+    output: &Path,
+    calendar_groups: &[Vec<String>],
+    client: &CalendarClient,
+    options: &StampOptions<'_>,
 ) -> Result<()> {
-    let client = CalendarClient::new(Duration::from_secs(timeout))?;
+    progress!("Stamping digest: {}", hex::encode(digest));
+    let digests = [Digest::sha256(digest)];
+    let mut ots = stamp_digests(&digests, &options.nonce, calendar_groups, client)
+        .await
+        .pop()
+        .unwrap_or_else(|| Err(Error::Calendar("No calendar response".into())))?;
+    if let Some(metadata) = options.metadata {
+        ots.timestamp.add_attestation(metadata.to_attestation()?);
+    }
+    ots.timestamp.normalize();
+    let compress = options.format == ProofFormat::Compressed || is_compressed(output);
+    let mut file = AtomicFile::create(output, options.overwrite)?;
+    write_proof(&ots, &mut file, compress)?;
+    file.commit()?;
 
-    // Use provided URLs or empty vec (client will use defaults)
-    let calendar_urls_ref: Vec<String> = calendar_urls.unwrap_or_default();
+    progress!("Created timestamp: {}", output.display());
+    Ok(())
+}
 
-    for file_path in files {
-        let path = file_path.as_ref();
-        println!("Stamping file: {}", path.display());
+/// Execute the stamp command, aggregating all files into one Merkle tree
+///
+/// Each file gets its own nonce as usual (or the one `options` gives), but
+/// only the root of a tree over the nonced commitments is submitted to the
+/// calendars. Every proof then contains the path from its commitment to that
+/// root.
+///
+/// Every extra algorithm of `options` adds a leaf per file for that digest,
+/// with a proof of its own next to the main one (`file.txt.sha1.ots`), so a
+/// file stays provably timestamped if one hash function is broken. A proof
+/// starts from a single digest, so one proof cannot hold them all.
+///
+/// # Arguments
+///
+/// * `files` - Files to timestamp, each with the path of its proof
+/// * `calendar_groups` - Calendar server URLs per chain, each group getting its own fork
+///   in the proof (an empty group uses the defaults)
+/// * `client` - Calendar client used for submissions
+/// * `options` - Hash algorithm, nonce, proof format, metadata and overwrite policy,
+///   which also applies to the audit trail
+/// * `audit` - Write a JSON audit trail of every leaf and its path to the root here
+/// * `state` - Store to record every stamp in
+///
+/// # Returns
+///
+/// One result per file, in the order of `files`. A file that cannot be read
+/// fails on its own and is left out of the tree, as is a file whose proof
+/// exists or cannot be written, while the other files are stamped.
+///
+/// # Errors
+///
+/// Returns error, for the whole batch, if calendar submission fails or the
+/// audit file exists or cannot be written
+#[allow(clippy::future_not_send)]
+pub async fn execute_merkle(
+    files: &[Target],
+    calendar_groups: &[Vec<String>],
+    client: &CalendarClient,
+    options: &StampOptions<'_>,
+    audit: Option<&Path>,
+    state: Option<&StateStore>,
+) -> Result<Vec<Result<()>>> {
+    // 1. Hash every file with every algorithm and commit to each digest with its own nonce
+    let (leaf_files, mut results) = hash_batch(files, client, options).await;
+    let nonces: Vec<Vec<u8>> = leaf_files.iter().map(|_| options.nonce.generate()).collect();
+    let leaves = leaf_files
+        .iter()
+        .zip(&nonces)
+        .map(|(leaf, nonce)| commitment(leaf.digest.as_bytes(), nonce))
+        .collect();
+    let Some(tree) = MerkleTree::new(leaves) else {
+        return Ok(results);
+    };
+
+    // 2. Submit only the root
+    let root = tree.root();
+    let stamped = results.iter().filter(|result| result.is_ok()).count();
+    progress!("Stamping {stamped} files with Merkle root {}", hex::encode(root));
+    options.progress.stage("submitting the Merkle root to calendars");
+    let calendar_step = submit_commitment(&root, calendar_groups, client).await?;
+
+    // 3. Build and save every proof, collecting the audit trail
+    let mut audit_leaves = Vec::with_capacity(leaf_files.len());
+    for (i, leaf_file) in leaf_files.iter().enumerate() {
+        let leaf = tree.leaves()[i];
+        let merkle_path = tree.path(i);
+        let leaf_step = merkle::path_steps(leaf, &merkle_path, calendar_step.clone());
+        let saved = build_timestamp(leaf_file.digest.clone(), nonces[i].clone(), leaf_step)
+            .and_then(|timestamp| {
+                save_batch_proof(leaf_file, timestamp, calendar_groups, options, state)
+            });
+        if let (Err(e), Ok(())) = (saved, &results[leaf_file.file]) {
+            results[leaf_file.file] = Err(e);
+        }
+
+        audit_leaves.push(AuditLeaf {
+            file: leaf_file.target.file.display().to_string(),
+            algorithm: leaf_file.algorithm.to_string(),
+            digest: hex::encode(&leaf_file.digest),
+            nonce: hex::encode(&nonces[i]),
+            leaf: hex::encode(leaf),
+            path: merkle_path
+                .iter()
+                .map(|node| AuditNode { side: node.side, hash: hex::encode(node.sibling) })
+                .collect(),
+        });
+    }
+    for (target, result) in files.iter().zip(&results) {
+        if result.is_ok() {
+            record_journal(options.journal, target.as_ref());
+        }
+    }
 
-        // 1. Read file and compute SHA256
-        let file_digest = hash_file(path)?;
-        debug!("File digest: {}", hex::encode(file_digest));
+    // 4. Write the audit trail
+    if let Some(audit_path) = audit {
+        let trail = AuditTrail { root: hex::encode(root), leaves: audit_leaves };
+        let mut writer = BufWriter::new(create_file(audit_path, options.overwrite)?);
+        serde_json::to_writer_pretty(&mut writer, &trail).map_err(std::io::Error::from)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+        progress!("Created audit trail: {}", audit_path.display());
+    }
 
-        // 2. Add nonce for privacy (16 random bytes)
-        let nonce: [u8; 16] = rand::random();
-        debug!("Nonce: {}", hex::encode(nonce));
+    Ok(results)
+}
+
+/// One digest of a file in a Merkle batch
+struct BatchLeaf {
+    /// Position of the file among the targets of the batch
+    file: usize,
+    /// The file, with the proof of this digest
+    target: Target,
+    /// Hash the digest was computed with
+    algorithm: DigestType,
+    /// The digest of the file's content
+    digest: Digest,
+}
+
+/// Hash every file of a Merkle batch with every algorithm of `options`
+///
+/// Returns the leaves of the batch, every digest of the main algorithm first
+/// and then those of each extra one, with a result per file. A file that
+/// cannot be hashed with some algorithm fails with the first such error, and
+/// none of its digests become leaves.
+async fn hash_batch(
+    files: &[Target],
+    client: &CalendarClient,
+    options: &StampOptions<'_>,
+) -> (Vec<BatchLeaf>, Vec<Result<()>>) {
+    let algorithms: Vec<DigestType> = std::iter::once(options.algorithm)
+        .chain(options.extra_algorithms.iter().copied())
+        .collect();
+    let mut results: Vec<Result<()>> = files.iter().map(|_| Ok(())).collect();
+    let mut hashed = Vec::with_capacity(algorithms.len());
+    for &algorithm in &algorithms {
+        let digests = hash_targets(files, client, options, algorithm).await;
+        let mut found = Vec::with_capacity(files.len());
+        for (result, digest) in results.iter_mut().zip(digests) {
+            match digest {
+                Ok(digest) => found.push(Some(digest)),
+                Err(e) => {
+                    if result.is_ok() {
+                        *result = Err(e);
+                    }
+                    found.push(None);
+                }
+            }
+        }
+        hashed.push(found);
+    }
 
-        // 3. Compute commitment: SHA256(file_digest || nonce)
-        let mut hasher = Sha256::new();
-        hasher.update(file_digest);
-        hasher.update(nonce);
-        let commitment: [u8; 32] = hasher.finalize().into();
-        debug!("Commitment: {}", hex::encode(commitment));
+    let mut leaves = Vec::with_capacity(files.len() * algorithms.len());
+    for (a, (&algorithm, digests)) in algorithms.iter().zip(hashed).enumerate() {
+        for (file, (target, digest)) in files.iter().zip(digests).enumerate() {
+            let (Some(digest), Ok(())) = (digest, &results[file]) else {
+                continue;
+            };
+            let proof = if a == 0 {
+                target.proof.clone()
+            } else {
+                extra_proof_path(&target.proof, algorithm)
+            };
+            let target = Target { file: target.file.clone(), proof };
+            leaves.push(BatchLeaf { file, target, algorithm, digest });
+        }
+    }
+    (leaves, results)
+}
 
-        // 4. Submit to calendars
-        let response = client.submit_to_calendars(&calendar_urls_ref, &commitment).await?;
+/// Save the proof of one leaf of a Merkle batch and record the stamp
+///
+/// # Errors
+///
+/// Returns error if the metadata is invalid, or the proof exists or cannot be
+/// written
+fn save_batch_proof(
+    leaf: &BatchLeaf,
+    mut timestamp: Timestamp,
+    calendar_groups: &[Vec<String>],
+    options: &StampOptions<'_>,
+    state: Option<&StateStore>,
+) -> Result<()> {
+    if let Some(metadata) = options.metadata {
+        timestamp.add_attestation(metadata.to_attestation()?);
+    }
+    let ots = DetachedTimestampFile { timestamp };
+    let proof = save_proof(&leaf.target, ots, options.format, options.overwrite)?;
+    let policy = options.nonce.policy(NONCE_MERKLE);
+    record_stamp(state, &leaf.target.file, leaf.digest.as_bytes(), policy, calendar_groups, &proof);
+    Ok(())
+}
 
-        // 5. Parse calendar response into Timestamp
-        let calendar_timestamp = parse_calendar_response(&commitment, &response)?;
+/// Audit trail of a Merkle batch stamp
+///
+/// Lets anyone check that a file was part of the batch from its digest
+/// alone: SHA256(`digest` + `nonce`) gives `leaf`, and folding `path` over
+/// it gives `root`.
+#[derive(Serialize)]
+struct AuditTrail {
+    /// Root submitted to the calendars
+    root: String,
+    /// Every stamped file
+    leaves: Vec<AuditLeaf>,
+}
 
-        // 6. Build full timestamp structure
-        // Structure: file_digest -> append(nonce) -> sha256 -> calendar_timestamp
-        let timestamp = build_timestamp(file_digest.to_vec(), nonce.to_vec(), calendar_timestamp);
+/// A single file in an audit trail
+#[derive(Serialize)]
+struct AuditLeaf {
+    file: String,
+    algorithm: String,
+    digest: String,
+    nonce: String,
+    leaf: String,
+    path: Vec<AuditNode>,
+}
 
-        // 7. Create DetachedTimestampFile
-        let ots = DetachedTimestampFile { digest_type: DigestType::Sha256, timestamp };
+/// A sibling on the path from a leaf to the root
+#[derive(Serialize)]
+struct AuditNode {
+    side: Side,
+    hash: String,
+}
 
-        // 8. Save .ots file
-        let ots_path = format!("{}.ots", path.display());
-        save_ots(&ots, &ots_path)?;
+/// Timestamp a SHA256 digest
+///
+/// Adds a random 16-byte nonce for privacy, submits SHA256(`digest` + nonce)
+/// to every group of calendars and builds a proof for `digest` from their
+/// responses.
+///
+/// # Errors
+///
+/// Returns error if the submission fails for every group
+#[allow(clippy::future_not_send)]
+pub async fn stamp_digest(
+    digest: [u8; 32],
+    calendar_groups: &[Vec<String>],
+    client: &CalendarClient,
+) -> Result<DetachedTimestampFile> {
+    let digests = [Digest::sha256(digest)];
+    let mut proofs = stamp_digests(&digests, &Nonce::Random, calendar_groups, client).await;
+    proofs.pop().unwrap_or_else(|| Err(Error::Calendar("No calendar response".into())))
+}
 
-        println!("Created timestamp: {ots_path}");
+/// Timestamp many digests, each with a nonce from `nonce`
+///
+/// Like `stamp_digest`, but the commitments are submitted together so
+/// calendars configured for batching get them in a few requests. The
+/// commitment is SHA256(`digest` + nonce) whatever the digest type, so
+/// calendars always receive 32-byte digests.
+///
+/// # Returns
+///
+/// One proof per digest, in the order of `digests`
+#[allow(clippy::future_not_send)]
+pub async fn stamp_digests(
+    digests: &[Digest],
+    nonce: &Nonce,
+    calendar_groups: &[Vec<String>],
+    client: &CalendarClient,
+) -> Vec<Result<DetachedTimestampFile>> {
+    if *nonce == Nonce::PerCalendar {
+        return stamp_digests_per_calendar(digests, calendar_groups, client).await;
     }
 
+    // Add nonce for privacy (16 random bytes by default)
+    let nonces: Vec<Vec<u8>> = digests.iter().map(|_| nonce.generate()).collect();
+
+    // Compute commitments: SHA256(digest || nonce)
+    let commitments: Vec<[u8; 32]> = digests
+        .iter()
+        .zip(&nonces)
+        .map(|(digest, nonce)| {
+            let commitment = commitment(digest.as_bytes(), nonce);
+            debug!("Nonce: {}, commitment: {}", hex::encode(nonce), hex::encode(commitment));
+            commitment
+        })
+        .collect();
+
+    // Submit to calendars and parse the responses into timestamps of the commitments
+    let calendar_steps = submit_commitments(&commitments, calendar_groups, client).await;
+
+    // Structure: digest -> append(nonce) -> sha256 -> calendar_step
+    digests
+        .iter()
+        .zip(nonces)
+        .zip(calendar_steps)
+        .map(|((digest, nonce), calendar_step)| {
+            let timestamp = build_timestamp(digest.clone(), nonce, calendar_step?)?;
+            Ok(DetachedTimestampFile { timestamp })
+        })
+        .collect()
+}
+
+/// Submit a commitment to every calendar and merge the responses
+///
+/// All calendars of all groups are contacted concurrently, and the timestamp
+/// forks into one branch per calendar that accepted the commitment, so it
+/// survives any single calendar disappearing. A failing calendar is skipped
+/// with a warning; only a failure of every calendar is an error. Returns the
+/// steps of the timestamp of `commitment`.
+///
+/// # Errors
+///
+/// Returns error if the submission fails for every calendar
+#[allow(clippy::future_not_send)]
+pub async fn submit_commitment(
+    commitment: &[u8; 32],
+    calendar_groups: &[Vec<String>],
+    client: &CalendarClient,
+) -> Result<Step> {
+    let mut steps = submit_commitments(&[*commitment], calendar_groups, client).await;
+    steps.pop().unwrap_or_else(|| Err(Error::Calendar("No calendar response".into())))
+}
+
+/// Submit commitments to every group of calendars and merge the responses
+///
+/// See `submit_commitment`; every calendar gets all commitments at once.
+///
+/// # Returns
+///
+/// The steps of one timestamp per commitment, in the order of `commitments`. A commitment
+/// fails if fewer distinct calendars than the client's `min_responses`
+/// accepted it.
+#[allow(clippy::future_not_send)]
+async fn submit_commitments(
+    commitments: &[[u8; 32]],
+    calendar_groups: &[Vec<String>],
+    client: &CalendarClient,
+) -> Vec<Result<Step>> {
+    let mut branches: Vec<Vec<Step>> = vec![vec![]; commitments.len()];
+    let mut accepted: Vec<Vec<String>> = vec![vec![]; commitments.len()];
+    let mut last_errors: Vec<Option<Error>> = commitments.iter().map(|_| None).collect();
+    let submissions =
+        calendar_groups.iter().map(|urls| client.submit_many_to_all_calendars(urls, commitments));
+    for (calendar, responses) in join_all(submissions).await.into_iter().flatten() {
+        for (i, response) in responses.into_iter().enumerate() {
+            match response.and_then(|response| parse_calendar_response(&commitments[i], &response))
+            {
+                // A calendar listed in several groups only counts once
+                Ok(_) if accepted[i].contains(&calendar) => {}
+                Ok(step) => {
+                    branches[i].push(step);
+                    accepted[i].push(calendar.clone());
+                }
+                Err(e) => {
+                    warn!("Calendar {calendar} failed: {e}");
+                    last_errors[i] = Some(e);
+                }
+            }
+        }
+    }
+
+    commitments
+        .iter()
+        .zip(branches)
+        .zip(last_errors)
+        .map(|((commitment, branches), last_error)| {
+            merge_branches(commitment, branches, last_error, client)
+        })
+        .collect()
+}
+
+/// Timestamp many digests, sending every calendar its own commitments
+///
+/// Each calendar gets SHA256(`digest` + nonce) with a nonce drawn for that
+/// calendar alone, so no two calendars see the same commitment. The proof
+/// forks at the digest into one append/SHA256 branch per calendar that
+/// accepted its commitment.
+///
+/// # Returns
+///
+/// One proof per digest, in the order of `digests`
+#[allow(clippy::future_not_send)]
+async fn stamp_digests_per_calendar(
+    digests: &[Digest],
+    calendar_groups: &[Vec<String>],
+    client: &CalendarClient,
+) -> Vec<Result<DetachedTimestampFile>> {
+    // Independent nonces, and so commitments, for every calendar
+    let mut nonces: Vec<Vec<Vec<u8>>> = vec![];
+    let mut submissions: Vec<(String, Vec<[u8; 32]>)> = vec![];
+    for calendar in distinct_calendars(calendar_groups) {
+        let calendar_nonces: Vec<Vec<u8>> =
+            digests.iter().map(|_| Nonce::PerCalendar.generate()).collect();
+        let commitments = digests
+            .iter()
+            .zip(&calendar_nonces)
+            .map(|(d, n)| commitment(d.as_bytes(), n))
+            .collect();
+        nonces.push(calendar_nonces);
+        submissions.push((calendar, commitments));
+    }
+
+    let mut branches: Vec<Vec<Step>> = vec![vec![]; digests.len()];
+    let mut last_errors: Vec<Option<Error>> = digests.iter().map(|_| None).collect();
+    for (calendar, responses) in client.submit_many_per_calendar(&submissions).await {
+        let Some(c) = submissions.iter().position(|(url, _)| *url == calendar) else {
+            continue;
+        };
+        for (i, response) in responses.into_iter().enumerate() {
+            let commitment = &submissions[c].1[i];
+            match response.and_then(|response| parse_calendar_response(commitment, &response)) {
+                Ok(step) => match build_timestamp(digests[i].clone(), nonces[c][i].clone(), step) {
+                    Ok(branch) => branches[i].push(branch.first_step()),
+                    Err(e) => last_errors[i] = Some(e),
+                },
+                Err(e) => {
+                    warn!("Calendar {calendar} failed: {e}");
+                    last_errors[i] = Some(e);
+                }
+            }
+        }
+    }
+
+    digests
+        .iter()
+        .zip(branches)
+        .zip(last_errors)
+        .map(|((digest, branches), last_error)| {
+            let first_step = merge_branches(digest.as_bytes(), branches, last_error, client)?;
+            Ok(DetachedTimestampFile::new(digest.clone(), &first_step)?)
+        })
+        .collect()
+}
+
+/// Every calendar of every group once, an empty group standing for the defaults
+fn distinct_calendars(calendar_groups: &[Vec<String>]) -> Vec<String> {
+    let mut calendars: Vec<String> = vec![];
+    for group in calendar_groups {
+        let group = if group.is_empty() {
+            DEFAULT_CALENDARS.iter().map(ToString::to_string).collect()
+        } else {
+            group.clone()
+        };
+        for calendar in group {
+            if !calendars.contains(&calendar) {
+                calendars.push(calendar);
+            }
+        }
+    }
+    calendars
+}
+
+/// Join the branches of the calendars that accepted a commitment
+///
+/// A single branch becomes the step itself, several are forked at `input`.
+///
+/// # Errors
+///
+/// Returns the last calendar error if no calendar accepted it, or an error
+/// if fewer than the client's `min_responses` did
+fn merge_branches(
+    input: &[u8],
+    mut branches: Vec<Step>,
+    last_error: Option<Error>,
+    client: &CalendarClient,
+) -> Result<Step> {
+    let min = client.min_responses();
+    match branches.len() {
+        0 => Err(last_error.unwrap_or_else(|| Error::Calendar("No calendars available".into()))),
+        n if n < min => Err(Error::Calendar(format!(
+            "only {n} of the required {min} calendars accepted the commitment"
+        ))),
+        1 => Ok(branches.remove(0)),
+        _ => Ok(Step { data: StepData::Fork, output: input.to_vec(), next: branches }),
+    }
+}
+
+/// Expand the directories among `paths` into the files below them
+///
+/// Files given directly are kept as they are. Directories are walked
+/// recursively in name order, without following symlinked directories. A
+/// file found there is kept if it matches one of `include` (or `include` is
+/// empty) and none of `exclude`; patterns are matched against both the path
+/// relative to the walked directory and the file name. Excluded directories
+/// are not entered, and proofs written by this client are skipped.
+///
+/// # Errors
+///
+/// Returns error if a directory cannot be read
+pub fn expand_paths(
+    paths: &[PathBuf],
+    include: &[Pattern],
+    exclude: &[Pattern],
+) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for path in paths {
+        if path.is_dir() {
+            walk(path, path, include, exclude, &mut files)?;
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
+/// Read the paths listed in a file, one per line, from standard input if `path` is `-`
+///
+/// Blank lines are skipped and a trailing `\r` is dropped; otherwise every
+/// line is taken as is, spaces included, so lists produced by `find` work
+/// whatever the file names.
+///
+/// # Errors
+///
+/// Returns error if the list cannot be read or is not valid UTF-8
+pub fn read_file_list(path: &Path) -> Result<Vec<PathBuf>> {
+    if is_stdio(path) {
+        parse_file_list(std::io::stdin().lock())
+    } else {
+        parse_file_list(BufReader::new(File::open(path)?))
+    }
+}
+
+/// Parse a list of paths, see `read_file_list`
+fn parse_file_list(reader: impl BufRead) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.strip_suffix('\r').unwrap_or(&line);
+        if !line.is_empty() {
+            files.push(PathBuf::from(line));
+        }
+    }
+    Ok(files)
+}
+
+/// Collect the files below `dir` for `expand_paths`
+fn walk(
+    root: &Path,
+    dir: &Path,
+    include: &[Pattern],
+    exclude: &[Pattern],
+    files: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(std::fs::DirEntry::file_name);
+    for entry in entries {
+        let path = entry.path();
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let matches = |patterns: &[Pattern]| {
+            patterns.iter().any(|pattern| pattern.matches_path(relative) || pattern.matches(&name))
+        };
+        if matches(exclude) {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            walk(root, &path, include, exclude, files)?;
+        } else if path.is_file() && !is_proof(&path) && (include.is_empty() || matches(include)) {
+            files.push(path);
+        }
+    }
     Ok(())
 }
 
+/// Whether a file is a proof or proof summary written by this client
+fn is_proof(path: &Path) -> bool {
+    let path = without_compression(path);
+    let path = strip_extension(&path, "json").unwrap_or(path);
+    path.extension().is_some_and(|ext| PROOF_EXTENSIONS.iter().any(|proof| ext == *proof))
+}
+
+/// Commit to a digest with a nonce: SHA256(`digest` || `nonce`)
+fn commitment(digest: &[u8], nonce: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(digest);
+    hasher.update(nonce);
+    hasher.finalize().into()
+}
+
 /// Hash a file using SHA256
 ///
 /// Reads the file in chunks to handle large files efficiently.
-fn hash_file(path: &Path) -> Result<[u8; 32]> {
+///
+/// # Errors
+///
+/// Returns error if the file cannot be read
+pub fn hash_file(path: &Path) -> Result<[u8; 32]> {
     let file = File::open(path)?;
     let mut reader = BufReader::new(file);
     let mut hasher = Sha256::new();
@@ -104,72 +1033,274 @@ fn hash_file(path: &Path) -> Result<[u8; 32]> {
     Ok(hasher.finalize().into())
 }
 
-/// Parse calendar server response into a Timestamp
+/// Hash every target with `algorithm`
+///
+/// Local files are hashed on up to `options.jobs` threads while URLs are
+/// downloaded concurrently.
 ///
-/// The calendar returns binary timestamp data that needs to be deserialized
-/// using the opentimestamps library.
-fn parse_calendar_response(commitment: &[u8], response: &[u8]) -> Result<Timestamp> {
-    let cursor = Cursor::new(response);
-    let mut deserializer = Deserializer::new(cursor);
+/// # Returns
+///
+/// One digest per target, in the order of `files`
+#[allow(clippy::future_not_send)]
+async fn hash_targets(
+    files: &[Target],
+    client: &CalendarClient,
+    options: &StampOptions<'_>,
+    algorithm: DigestType,
+) -> Vec<Result<Digest>> {
+    let (remote, local): (IndexedFiles, IndexedFiles) = files
+        .iter()
+        .map(|target| target.file.as_path())
+        .enumerate()
+        .partition(|(_, file)| remote_url(file).is_some());
+    let downloads = remote.iter().map(|(_, file)| async move {
+        let url = remote_url(file).unwrap_or_default();
+        let _progress = options.progress.file(file, 0);
+        let digest = client.hash_url(url, algorithm).await?;
+        debug!("{url}: digest {}", hex::encode(&digest));
+        Ok(digest)
+    });
+    let downloaded = join_all(downloads).await;
+    let local_files: Vec<&Path> = local.iter().map(|(_, file)| *file).collect();
+    let hashed = hash_files(&local_files, options, algorithm);
+
+    let mut digests: Vec<(usize, Result<Vec<u8>>)> = remote
+        .iter()
+        .map(|(i, _)| *i)
+        .zip(downloaded)
+        .chain(local.iter().map(|(i, _)| *i).zip(hashed))
+        .collect();
+    digests.sort_by_key(|(i, _)| *i);
+    digests.into_iter().map(|(_, digest)| Ok(Digest::new(algorithm, digest?)?)).collect()
+}
+
+/// Files of a stamp, each with its position among the targets
+type IndexedFiles<'a> = Vec<(usize, &'a Path)>;
 
-    Timestamp::deserialize(&mut deserializer, commitment.to_vec())
-        .map_err(crate::error::Error::InvalidOts)
+/// The URL a stamp input stands for, if it is an `http://` or `https://` URL
+#[must_use]
+pub fn remote_url(file: &Path) -> Option<&str> {
+    file.to_str().filter(|file| file.starts_with("http://") || file.starts_with("https://"))
+}
+
+/// Local name for the proof of a URL: its last path segment, or its host
+fn url_name(url: &str) -> PathBuf {
+    let parsed = reqwest::Url::parse(url).ok();
+    let segment =
+        parsed.as_ref().and_then(|url| url.path_segments()?.rfind(|segment| !segment.is_empty()));
+    let host = parsed.as_ref().and_then(reqwest::Url::host_str);
+    PathBuf::from(segment.or(host).unwrap_or("download"))
+}
+
+/// Hash every file with `algorithm`, on up to `options.jobs` threads
+///
+/// # Returns
+///
+/// One digest per file, in the order of `files`
+fn hash_files(
+    files: &[&Path],
+    options: &StampOptions<'_>,
+    algorithm: DigestType,
+) -> Vec<Result<Vec<u8>>> {
+    // Workers take the next unhashed file until none are left
+    let next = AtomicUsize::new(0);
+    let hash_next = || {
+        let mut hashed = vec![];
+        loop {
+            let i = next.fetch_add(1, Ordering::Relaxed);
+            let Some(file) = files.get(i) else {
+                return hashed;
+            };
+            let digest = hash_file_with(file, algorithm, options.read_mode, options.progress);
+            if let Ok(digest) = &digest {
+                debug!("{}: digest {}", file.display(), hex::encode(digest));
+            }
+            hashed.push((i, digest));
+        }
+    };
+
+    let jobs = options.jobs.get().min(files.len()).max(1);
+    let mut hashed = std::thread::scope(|scope| {
+        let workers: Vec<_> = (1..jobs).map(|_| scope.spawn(hash_next)).collect();
+        let mut hashed = hash_next();
+        for worker in workers {
+            hashed.extend(worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)));
+        }
+        hashed
+    });
+    hashed.sort_by_key(|(i, _)| *i);
+    hashed.into_iter().map(|(_, digest)| digest).collect()
+}
+
+/// Hash a file with `algorithm`, showing how much of it was read in `progress`
+///
+/// # Errors
+///
+/// Returns error if the file cannot be read or mapped
+pub fn hash_file_with(
+    path: &Path,
+    algorithm: DigestType,
+    read_mode: ReadMode,
+    progress: &BatchProgress,
+) -> Result<Vec<u8>> {
+    let file = File::open(path)?;
+    let file_progress = progress.file(path, file.metadata()?.len());
+    match read_mode {
+        ReadMode::Buffered(size) => {
+            let reader = BufReader::with_capacity(size, file_progress.wrap(file));
+            Ok(algorithm.hash_reader(reader)?)
+        }
+        #[cfg(feature = "mmap")]
+        ReadMode::Mapped => {
+            // SAFETY: the map is only read while hashing; a file truncated by
+            // another process meanwhile can fault, as with any mmap reader
+            let map = unsafe { memmap2::Mmap::map(&file)? };
+            Ok(algorithm.hash_reader(file_progress.wrap(&map[..]))?)
+        }
+    }
 }
 
 /// Build the complete timestamp structure
 ///
-/// Creates the chain: `file_digest` -> append(nonce) -> sha256 -> `calendar_timestamp`
+/// Creates the chain: `file_digest` -> append(nonce) -> sha256 -> `calendar_step`
 ///
 /// The structure represents:
 /// 1. Start with file digest
-/// 2. Append nonce operation
+/// 2. Append nonce operation, left out for an empty nonce
 /// 3. SHA256 hash operation
-/// 4. Calendar timestamp (contains attestations)
-fn build_timestamp(
-    file_digest: Vec<u8>,
-    nonce: Vec<u8>,
-    calendar_timestamp: Timestamp,
-) -> Timestamp {
+/// 4. Calendar timestamp of the commitment (contains attestations)
+///
+/// # Errors
+///
+/// Returns an error of `Timestamp::new` if `calendar_step` does not start
+/// from the commitment
+fn build_timestamp(file_digest: Digest, nonce: Vec<u8>, calendar_step: Step) -> Result<Timestamp> {
     // Calculate intermediate value: file_digest || nonce
-    let mut appended = file_digest.clone();
+    let mut appended = file_digest.as_bytes().to_vec();
     appended.extend_from_slice(&nonce);
 
-    // The commitment should match calendar_timestamp's start_digest
+    // The calendar steps start from this commitment
     let commitment = Sha256::digest(&appended).to_vec();
 
     // Build the chain:
-    // Step 1: Append nonce
-    let append_step = Step {
-        data: StepData::Op(Op::Append(nonce)),
-        output: appended,
-        next: vec![Step {
-            // Step 2: SHA256 hash
-            data: StepData::Op(Op::Sha256),
-            output: commitment,
-            // Step 3: Calendar timestamp (contains the actual attestations)
-            next: vec![calendar_timestamp.first_step],
-        }],
+    // Step 2: SHA256 hash
+    let hash_step = Step {
+        data: StepData::Op(Op::Sha256),
+        output: commitment,
+        // Step 3: Calendar timestamp (contains the actual attestations)
+        next: vec![calendar_step],
     };
+    if nonce.is_empty() {
+        return Ok(Timestamp::new(file_digest, &hash_step)?);
+    }
+
+    // Step 1: Append nonce
+    let append_step =
+        Step { data: StepData::Op(Op::Append(nonce)), output: appended, next: vec![hash_step] };
+
+    Ok(Timestamp::new(file_digest, &append_step)?)
+}
+
+/// Save the proof of a target
+///
+/// Writes a detached, compressed or attached proof with the data, as
+/// `format` says, in the canonical layout of `Timestamp::normalize`.
+fn save_proof(
+    target: &Target,
+    mut ots: DetachedTimestampFile,
+    format: ProofFormat,
+    overwrite: Overwrite,
+) -> Result<PathBuf> {
+    ots.timestamp.normalize();
+    let proof_path = target.proof.clone();
+    if format == ProofFormat::Attached {
+        if remote_url(&target.file).is_some() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "the data of a URL cannot be attached to its proof",
+            )
+            .into());
+        }
+        let attached = AttachedTimestampFile { data: std::fs::read(&target.file)?, proof: ots };
+        // Guard against the file changing between hashing and reading
+        attached.check_digest()?;
+        save_attached(&attached, &proof_path, overwrite)?;
+
+        progress!("Created attached timestamp: {}", proof_path.display());
+    } else {
+        let mut file = AtomicFile::create(&proof_path, overwrite)?;
+        write_proof(&ots, &mut file, format == ProofFormat::Compressed)?;
+        file.commit()?;
 
-    Timestamp { start_digest: file_digest, first_step: append_step }
+        progress!("Created timestamp: {}", proof_path.display());
+    }
+    Ok(proof_path)
 }
 
-/// Save a `DetachedTimestampFile` to disk
+/// Record a new stamp in the state store
 ///
-/// Uses the opentimestamps library's serialization to write the .ots file.
-fn save_ots(ots: &DetachedTimestampFile, path: &str) -> Result<()> {
-    let file = File::create(path)?;
-    let mut writer = BufWriter::new(file);
+/// The proof is already saved at this point, so failing to record it only
+/// produces a warning.
+fn record_stamp(
+    state: Option<&StateStore>,
+    file: &Path,
+    digest: &[u8],
+    nonce_policy: &str,
+    calendar_groups: &[Vec<String>],
+    proof: &Path,
+) {
+    let Some(state) = state else {
+        return;
+    };
+    let now = state::now();
+    let record = StampRecord {
+        // URLs are recorded as they are
+        file: if remote_url(file).is_some() { file.to_path_buf() } else { state::absolute(file) },
+        digest: hex::encode(digest),
+        nonce_policy: nonce_policy.to_string(),
+        calendars: calendar_groups.to_vec(),
+        proof: state::absolute(proof),
+        status: StampStatus::Pending,
+        created: now,
+        updated: now,
+    };
+    if let Err(e) = state.record(&record) {
+        warn!("Failed to record stamp of {}: {e}", file.display());
+    }
+}
 
-    ots.to_writer(&mut writer)?;
+/// Record a stamped file in the batch journal
+///
+/// Like `record_stamp`, a failure only produces a warning; the file is then
+/// stamped again on resume.
+fn record_journal(journal: Option<&Journal>, file: &Path) {
+    if let Some(journal) = journal {
+        if let Err(e) = journal.record(file) {
+            warn!("Failed to record {} in the stamp journal: {e}", file.display());
+        }
+    }
+}
+
+/// Save an `AttachedTimestampFile` to disk
+fn save_attached(
+    attached: &AttachedTimestampFile,
+    path: &Path,
+    overwrite: Overwrite,
+) -> Result<()> {
+    let mut file = AtomicFile::create(path, overwrite)?;
+    let mut writer = BufWriter::new(&mut file);
+
+    attached.to_writer(&mut writer)?;
     writer.flush()?;
+    drop(writer);
 
-    Ok(())
+    file.commit()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ots::Attestation;
 
     #[test]
     fn test_hash_file() {
@@ -183,6 +1314,16 @@ mod tests {
 
         // Verify it's a valid 32-byte hash
         assert_eq!(hash.len(), 32);
+        let progress = BatchProgress::hidden();
+        let hash_with = |algorithm, read_mode| {
+            hash_file_with(&test_file, algorithm, read_mode, &progress).unwrap()
+        };
+        assert_eq!(hash_with(DigestType::Sha256, ReadMode::default()), hash.to_vec());
+        assert_eq!(hash_with(DigestType::Sha256, ReadMode::Buffered(3)), hash.to_vec());
+        #[cfg(feature = "mmap")]
+        assert_eq!(hash_with(DigestType::Sha256, ReadMode::Mapped), hash.to_vec());
+        assert_eq!(hash_with(DigestType::Sha1, ReadMode::default()).len(), 20);
+        assert_eq!(hash_with(DigestType::Ripemd160, ReadMode::default()).len(), 20);
 
         // Clean up
         std::fs::remove_file(&test_file).unwrap();
@@ -190,23 +1331,160 @@ mod tests {
 
     #[test]
     fn test_build_timestamp_structure() {
-        let file_digest = vec![1u8; 32];
+        let file_digest = Digest::sha256([1u8; 32]);
         let nonce = vec![2u8; 16];
 
-        // Create a minimal calendar timestamp for testing
-        let calendar_timestamp = Timestamp {
-            start_digest: vec![0u8; 32], // This should be the commitment
-            first_step: Step {
-                data: StepData::Op(Op::Sha256),
-                output: vec![0u8; 32],
-                next: vec![],
-            },
+        // Create minimal calendar steps for testing, starting from the commitment
+        let calendar_step = |nonce: &[u8]| Step {
+            data: StepData::Attestation(Attestation::Pending { uri: "https://a.example".into() }),
+            output: commitment(file_digest.as_bytes(), nonce).to_vec(),
+            next: vec![],
         };
 
-        let timestamp = build_timestamp(file_digest.clone(), nonce, calendar_timestamp);
+        let timestamp =
+            build_timestamp(file_digest.clone(), nonce.clone(), calendar_step(&nonce)).unwrap();
 
         // Verify structure
-        assert_eq!(timestamp.start_digest, file_digest);
-        assert!(matches!(timestamp.first_step.data, StepData::Op(Op::Append(_))));
+        assert_eq!(timestamp.start_digest(), &file_digest);
+        assert!(matches!(timestamp.first_step().data, StepData::Op(Op::Append(_))));
+
+        // Without a nonce the digest is hashed directly
+        let timestamp = build_timestamp(file_digest.clone(), vec![], calendar_step(&[])).unwrap();
+        let first_step = timestamp.first_step();
+        assert_eq!(first_step.data, StepData::Op(Op::Sha256));
+        assert_eq!(first_step.output, commitment(file_digest.as_bytes(), &[]).to_vec());
+
+        // Calendar steps from another commitment are refused
+        assert!(build_timestamp(file_digest.clone(), nonce, calendar_step(&[])).is_err());
+    }
+
+    #[test]
+    fn test_distinct_calendars() {
+        let groups = vec![
+            vec!["https://a.example".to_string(), "https://b.example".to_string()],
+            vec!["https://b.example".to_string()],
+            vec![],
+        ];
+        let calendars = distinct_calendars(&groups);
+        assert_eq!(calendars[..2], ["https://a.example", "https://b.example"]);
+        assert_eq!(calendars.len(), 2 + DEFAULT_CALENDARS.len());
+    }
+
+    #[test]
+    fn test_targets() {
+        let files = vec![PathBuf::from("a/data.csv"), PathBuf::from("b/data.csv")];
+        let next_to = targets(files.clone(), ProofFormat::Detached, None).unwrap();
+        assert_eq!(next_to[1].proof, Path::new("b/data.csv.ots"));
+
+        let dir = std::env::temp_dir().join(format!("ots-targets-{}", std::process::id()));
+        let into_dir = targets(files, ProofFormat::Compressed, Some(&dir)).unwrap();
+        assert!(dir.is_dir());
+        assert_eq!(into_dir[0].proof, dir.join("data.csv.ots.zst"));
+        assert_eq!(into_dir[1].proof, dir.join("data-2.csv.ots.zst"));
+        assert_eq!(into_dir[1].file, Path::new("b/data.csv"));
+
+        let url = PathBuf::from("https://example.com/dl/release.tar.gz?mirror=1");
+        let remote = targets(vec![url.clone()], ProofFormat::Detached, None).unwrap();
+        assert_eq!(remote[0], Target { file: url, proof: PathBuf::from("release.tar.gz.ots") });
+        assert_eq!(url_name("https://example.com/"), Path::new("example.com"));
+
+        let single = targets(vec![PathBuf::from("x")], ProofFormat::Detached, Some(Path::new("p")));
+        assert_eq!(single.unwrap()[0].proof, Path::new("p"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_extra_proof_path() {
+        let sha1 = extra_proof_path(Path::new("dir/file.txt.ots"), DigestType::Sha1);
+        assert_eq!(sha1, Path::new("dir/file.txt.sha1.ots"));
+        let ripemd160 = extra_proof_path(Path::new("file.txt.ots.zst"), DigestType::Ripemd160);
+        assert_eq!(ripemd160, Path::new("file.txt.ripemd160.ots.zst"));
+    }
+
+    #[test]
+    fn test_skip_stamped() {
+        let dir = std::env::temp_dir().join(format!("ots-skip-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.txt.ots"), b"proof").unwrap();
+        let files = vec![dir.join("a.txt"), dir.join("b.txt")];
+        let left = skip_stamped(targets(files, ProofFormat::Detached, None).unwrap());
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].file, dir.join("b.txt"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_hash_batch_unreadable_file() {
+        let dir = std::env::temp_dir().join(format!("ots-hash-batch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.txt"), b"first").unwrap();
+        std::fs::write(dir.join("c.txt"), b"third").unwrap();
+        let files = vec![dir.join("a.txt"), dir.join("missing.txt"), dir.join("c.txt")];
+        let files = targets(files, ProofFormat::Detached, None).unwrap();
+
+        let progress = BatchProgress::hidden();
+        let options = StampOptions {
+            algorithm: DigestType::Sha256,
+            extra_algorithms: &[DigestType::Sha1],
+            read_mode: ReadMode::default(),
+            nonce: Nonce::Random,
+            format: ProofFormat::Detached,
+            metadata: None,
+            overwrite: Overwrite::Never,
+            progress: &progress,
+            jobs: NonZeroUsize::MIN,
+            journal: None,
+        };
+        let client = CalendarClient::new(std::time::Duration::from_secs(1)).unwrap();
+        let (leaves, results) = hash_batch(&files, &client, &options).await;
+
+        // The unreadable file fails alone, and only the others become leaves
+        assert!(results[0].is_ok() && results[2].is_ok());
+        assert!(results[1].is_err());
+        let leaf_files: Vec<_> = leaves.iter().map(|leaf| (leaf.file, leaf.algorithm)).collect();
+        assert_eq!(
+            leaf_files,
+            [
+                (0, DigestType::Sha256),
+                (2, DigestType::Sha256),
+                (0, DigestType::Sha1),
+                (2, DigestType::Sha1)
+            ]
+        );
+        assert_eq!(leaves[1].target.proof, dir.join("c.txt.ots"));
+        assert_eq!(leaves[1].digest, Digest::sha256(hash_file(&dir.join("c.txt")).unwrap()));
+        assert_eq!(leaves[3].target.proof, extra_proof_path(&files[2].proof, DigestType::Sha1));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_file_list() {
+        let list = b"a.txt\n\n dir/with space.csv\r\nlast";
+        let files = parse_file_list(&list[..]).unwrap();
+        assert_eq!(files, ["a.txt", " dir/with space.csv", "last"].map(PathBuf::from));
+    }
+
+    #[test]
+    fn test_expand_paths() {
+        let dir = std::env::temp_dir().join(format!("ots-walk-{}", std::process::id()));
+        for file in ["a.csv", "a.csv.ots", "b.txt", "sub/c.csv", "sub/c.csv.ots.zst", "skip/d.csv"]
+        {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, file).unwrap();
+        }
+        let extra = dir.join("b.txt");
+        let paths = [dir.clone(), extra.clone()];
+
+        let all = expand_paths(&paths, &[], &[]).unwrap();
+        let expected = ["a.csv", "b.txt", "skip/d.csv", "sub/c.csv", "b.txt"];
+        assert_eq!(all, expected.map(|file| dir.join(file)));
+
+        let csv = [Pattern::new("*.csv").unwrap()];
+        let skip = [Pattern::new("skip").unwrap()];
+        let filtered = expand_paths(&paths, &csv, &skip).unwrap();
+        // Files given directly are not filtered
+        assert_eq!(filtered, vec![dir.join("a.csv"), dir.join("sub/c.csv"), extra]);
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -0,0 +1,238 @@
+use crate::commands::info::collect_attestations;
+use crate::commands::{decompress_proof, without_compression};
+use crate::error::Result;
+use crate::ots::{Attestation, DetachedTimestampFile};
+use crate::time_style::format_time;
+use crate::verifier::BlockVerifier;
+use log::{debug, warn};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Width of the block height buckets in the distribution table
+const HEIGHT_BUCKET: usize = 1000;
+
+/// Aggregated statistics over a collection of proofs
+#[derive(Default, Debug)]
+struct ArchiveStats {
+    /// Proofs with at least one Bitcoin attestation
+    confirmed: usize,
+    /// Proofs with only pending attestations
+    pending: usize,
+    /// Proofs with neither Bitcoin nor pending attestations
+    unknown: usize,
+    /// Files that could not be parsed as proofs
+    invalid: usize,
+    /// Number of proofs per (earliest) attested block height
+    heights: BTreeMap<usize, usize>,
+    /// Number of pending attestations per calendar URI
+    calendars: BTreeMap<String, usize>,
+    /// Total size in bytes of all valid proofs
+    total_size: u64,
+}
+
+impl ArchiveStats {
+    /// Account for a single parsed proof of `size` bytes
+    fn add(&mut self, ots: &DetachedTimestampFile, size: u64) {
+        let mut attestations = Vec::new();
+        collect_attestations(&ots.timestamp.first_step(), &mut attestations);
+
+        let mut earliest: Option<usize> = None;
+        let mut has_pending = false;
+        for att in &attestations {
+            match att {
+                Attestation::Bitcoin { height } => {
+                    earliest = Some(earliest.map_or(*height, |h| h.min(*height)));
+                }
+                Attestation::Pending { uri } => {
+                    has_pending = true;
+                    *self.calendars.entry(uri.clone()).or_default() += 1;
+                }
+                Attestation::Litecoin { .. } | Attestation::Unknown { .. } => {}
+            }
+        }
+
+        if let Some(height) = earliest {
+            self.confirmed += 1;
+            *self.heights.entry(height).or_default() += 1;
+        } else if has_pending {
+            self.pending += 1;
+        } else {
+            self.unknown += 1;
+        }
+        self.total_size += size;
+    }
+
+    /// Number of successfully parsed proofs
+    const fn proofs(&self) -> usize {
+        self.confirmed + self.pending + self.unknown
+    }
+
+    /// Group the attested heights into buckets of `HEIGHT_BUCKET` blocks
+    fn height_buckets(&self) -> BTreeMap<usize, usize> {
+        let mut buckets = BTreeMap::new();
+        for (height, count) in &self.heights {
+            *buckets.entry(height / HEIGHT_BUCKET * HEIGHT_BUCKET).or_default() += count;
+        }
+        buckets
+    }
+}
+
+/// Execute the stats command
+///
+/// Walks `dir` recursively, parses every `.ots` and `.ots.zst` file and
+/// prints aggregate totals: proofs per attestation state, block height
+/// distribution, calendars used, proof sizes and the oldest/newest attested
+/// times. Block times are looked up through `verifier`.
+///
+/// # Errors
+/// Returns error if the directory cannot be read
+pub async fn execute(dir: &Path, verifier: &dyn BlockVerifier) -> Result<()> {
+    let mut files = Vec::new();
+    find_ots_files(dir, &mut files)?;
+    files.sort();
+
+    let mut stats = ArchiveStats::default();
+    for path in &files {
+        let bytes = match fs::read(path).map_err(Into::into).and_then(decompress_proof) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                warn!("Skipping {}: {e}", path.display());
+                stats.invalid += 1;
+                continue;
+            }
+        };
+        match DetachedTimestampFile::from_reader(bytes.as_slice()) {
+            Ok(ots) => stats.add(&ots, bytes.len() as u64),
+            Err(e) => {
+                warn!("Skipping {}: {e}", path.display());
+                stats.invalid += 1;
+            }
+        }
+    }
+
+    println!("Directory: {}", dir.display());
+    println!("Proofs: {}", stats.proofs());
+    println!("  Confirmed: {}", stats.confirmed);
+    println!("  Pending: {}", stats.pending);
+    println!("  Unknown: {}", stats.unknown);
+    if stats.invalid > 0 {
+        println!("  Invalid: {}", stats.invalid);
+    }
+
+    if stats.proofs() > 0 {
+        println!("Total size: {} bytes", stats.total_size);
+        println!("Average size: {} bytes", stats.total_size / stats.proofs() as u64);
+    }
+
+    if !stats.heights.is_empty() {
+        println!("Block heights:");
+        for (start, count) in stats.height_buckets() {
+            println!("  {start}-{}: {count}", start + HEIGHT_BUCKET - 1);
+        }
+    }
+
+    if !stats.calendars.is_empty() {
+        println!("Calendars:");
+        for (uri, count) in &stats.calendars {
+            println!("  {uri}: {count}");
+        }
+    }
+
+    if let (Some(oldest), Some(newest)) =
+        (stats.heights.keys().next().copied(), stats.heights.keys().next_back().copied())
+    {
+        println!("Oldest attestation: {}", attested_time(oldest, verifier).await);
+        println!("Newest attestation: {}", attested_time(newest, verifier).await);
+    }
+
+    Ok(())
+}
+
+/// Recursively collect all `.ots` and `.ots.zst` files below `dir`
+fn find_ots_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_ots_files(&path, files)?;
+        } else if without_compression(&path).extension().is_some_and(|ext| ext == "ots") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Describe the block time of `height`, falling back to the bare height
+/// if the block header cannot be fetched
+#[allow(clippy::cast_possible_truncation)]
+async fn attested_time(height: usize, verifier: &dyn BlockVerifier) -> String {
+    match verifier.get_block_header(height as u32).await {
+        Ok(header) => format!("{} (block {height})", format_time(header.time)),
+        Err(e) => {
+            debug!("Failed to fetch block header {height}: {e}");
+            format!("block {height}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ots::{Digest, DigestType, Step, StepData, Timestamp};
+
+    fn proof(attestations: Vec<Attestation>) -> DetachedTimestampFile {
+        let digest = vec![0u8; 32];
+        let next = attestations
+            .into_iter()
+            .map(|att| Step {
+                data: StepData::Attestation(att),
+                output: digest.clone(),
+                next: vec![],
+            })
+            .collect();
+        DetachedTimestampFile {
+            timestamp: Timestamp::new(
+                Digest::new(DigestType::Sha256, digest.clone()).unwrap(),
+                &Step { data: StepData::Fork, output: digest, next },
+            )
+            .unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_stats_counts_states() {
+        let mut stats = ArchiveStats::default();
+        stats.add(
+            &proof(vec![
+                Attestation::Bitcoin { height: 800_500 },
+                Attestation::Pending { uri: "https://a.example".into() },
+            ]),
+            100,
+        );
+        stats.add(&proof(vec![Attestation::Pending { uri: "https://a.example".into() }]), 50);
+        stats.add(&proof(vec![Attestation::Unknown { tag: vec![0; 8], data: vec![] }]), 30);
+
+        assert_eq!(stats.proofs(), 3);
+        assert_eq!(stats.confirmed, 1);
+        assert_eq!(stats.pending, 1);
+        assert_eq!(stats.unknown, 1);
+        assert_eq!(stats.total_size, 180);
+        assert_eq!(stats.calendars.get("https://a.example"), Some(&2));
+    }
+
+    #[test]
+    fn test_stats_uses_earliest_height() {
+        let mut stats = ArchiveStats::default();
+        stats.add(
+            &proof(vec![
+                Attestation::Bitcoin { height: 801_200 },
+                Attestation::Bitcoin { height: 800_999 },
+            ]),
+            10,
+        );
+        stats.add(&proof(vec![Attestation::Bitcoin { height: 800_001 }]), 10);
+
+        assert_eq!(stats.heights.keys().copied().collect::<Vec<_>>(), vec![800_001, 800_999]);
+        assert_eq!(stats.height_buckets().get(&800_000), Some(&2));
+    }
+}
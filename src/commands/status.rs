@@ -0,0 +1,75 @@
+use crate::commands::info::collect_attestations;
+use crate::commands::open_proof;
+use crate::error::Result;
+use crate::ots::{AttachedTimestampFile, Attestation, DetachedTimestampFile};
+use crate::state::{StampStatus, StateStore};
+use log::warn;
+use std::path::Path;
+
+/// Execute the status command
+///
+/// Lists the stamps recorded in the state database, oldest first.
+///
+/// # Arguments
+///
+/// * `state` - The state database
+/// * `status` - Only list stamps with this status
+/// * `file` - Only list stamps whose file path contains this text
+/// * `refresh` - First re-read every pending proof and mark it confirmed if
+///   it has been upgraded since
+///
+/// # Errors
+///
+/// Returns error if the database cannot be read or written
+pub fn execute(
+    state: &StateStore,
+    status: Option<StampStatus>,
+    file: Option<&str>,
+    refresh: bool,
+) -> Result<()> {
+    if refresh {
+        for record in state.list(Some(StampStatus::Pending), file)? {
+            if let Err(e) = refresh_proof(state, &record.proof) {
+                warn!("Cannot refresh {}: {e}", record.proof.display());
+            }
+        }
+    }
+
+    let records = state.list(status, file)?;
+    for record in &records {
+        let created =
+            chrono::DateTime::from_timestamp(i64::try_from(record.created).unwrap_or(i64::MAX), 0)
+                .map_or_else(
+                    || "unknown".to_string(),
+                    |dt| dt.format("%Y-%m-%d %H:%M:%S").to_string(),
+                );
+        println!("{:<9}  {created}  {}", record.status.as_str(), record.file.display());
+        println!("           proof {}", record.proof.display());
+    }
+    println!("{} stamps", records.len());
+    Ok(())
+}
+
+/// Mark the stamp with this proof confirmed if the proof has a block attestation
+///
+/// # Errors
+///
+/// Returns error if the proof cannot be read or the database cannot be updated
+pub fn refresh_proof(state: &StateStore, proof: &Path) -> Result<()> {
+    let reader = open_proof(proof)?;
+    let ots = if proof.extension().is_some_and(|ext| ext == "otsa") {
+        AttachedTimestampFile::from_reader(reader)?.proof
+    } else {
+        DetachedTimestampFile::from_reader(reader)?
+    };
+
+    let mut attestations = vec![];
+    collect_attestations(&ots.timestamp.first_step(), &mut attestations);
+    let confirmed = attestations
+        .iter()
+        .any(|a| matches!(a, Attestation::Bitcoin { .. } | Attestation::Litecoin { .. }));
+    if confirmed {
+        state.set_status(proof, StampStatus::Confirmed)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use crate::commands::{append_extension, strip_extension};
+use crate::error::{Error, Result};
+use crate::ots::AttachedTimestampFile;
+use crate::output::{create_file, Overwrite};
+use crate::verbosity::progress;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Execute the unpack command
+///
+/// Reads an attached `.otsa` file, checks that the embedded data matches its
+/// timestamp, and writes the data and a detached `.ots` proof next to it:
+/// `file.txt.otsa` becomes `file.txt` and `file.txt.ots`. Existing output
+/// files are handled according to `overwrite`.
+///
+/// # Errors
+/// Returns error if:
+/// - The file is not an attached timestamp file
+/// - The data does not match the timestamp
+/// - One of the output files already exists or cannot be written
+pub fn execute(file: &Path, overwrite: Overwrite) -> Result<()> {
+    let data_path = data_path(file)?;
+    let ots_path = append_extension(&data_path, "ots");
+
+    let reader = BufReader::new(File::open(file)?);
+    let attached = AttachedTimestampFile::from_reader(reader)?;
+
+    create_file(&data_path, overwrite)?.write_all(&attached.data)?;
+
+    let mut writer = BufWriter::new(create_file(&ots_path, overwrite)?);
+    attached.proof.to_writer(&mut writer)?;
+    writer.flush()?;
+
+    progress!("Extracted data: {}", data_path.display());
+    progress!("Extracted timestamp: {}", ots_path.display());
+    Ok(())
+}
+
+/// Derive the data file path by stripping the `.otsa` extension
+fn data_path(file: &Path) -> Result<PathBuf> {
+    strip_extension(file, "otsa").ok_or_else(|| {
+        Error::Verification(format!(
+            "Cannot determine output file: .otsa extension missing on {}",
+            file.display()
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_path() {
+        assert_eq!(data_path(Path::new("dir/file.txt.otsa")).unwrap(), Path::new("dir/file.txt"));
+        assert!(data_path(Path::new("file.txt.ots")).is_err());
+    }
+}
@@ -1,144 +1,482 @@
-use crate::calendar::CalendarClient;
+use crate::calendar::{parse_calendar_response, CalendarClient};
+use crate::commands::info::collect_attestations;
+use crate::commands::reanchor::has_pending_reanchor;
+use crate::commands::{is_compressed, is_stdio, open_proof, write_proof};
+use crate::config::HooksConfig;
 use crate::error::{Error, Result};
-use crate::ots::{Attestation, Deserializer, DetachedTimestampFile, Step, StepData, Timestamp};
+use crate::hooks::{self, HookPayload, EVENT_CONFIRMED};
+use crate::ots::{
+    Attestation, DetachedTimestampFile, Metadata, Step, StepData, Timestamp, TimestampMap,
+};
+use crate::output::{AtomicFile, Overwrite};
+use crate::verbosity::{self, eprogress, progress};
 use log::debug;
-use std::fs::File;
-use std::io::{BufReader, BufWriter, Cursor};
-use std::path::Path;
-use std::time::Duration;
+use serde::Serialize;
+use std::fmt;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// How to upgrade proofs and save the result
+#[derive(Clone, Copy)]
+pub struct UpgradeOptions<'a> {
+    /// Don't save changes (just check availability)
+    pub dry_run: bool,
+    /// Treat an unknown attestation as complete, like a Bitcoin one
+    pub accept_unknown: bool,
+    /// Remove the pending attestations once the proof has a Bitcoin one
+    pub prune_pending: bool,
+    /// Whether an existing proof at the output path may be replaced
+    pub overwrite: Overwrite,
+    /// Notifications to fire once the saved proof has a Bitcoin attestation
+    pub hooks: &'a HooksConfig,
+}
+
+/// What a calendar said about one pending attestation during an upgrade
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    /// Calendar named by the pending attestation
+    pub calendar: String,
+    /// Commitment the calendar was asked about, hex-encoded
+    pub commitment: String,
+    /// Whether the calendar has completed the timestamp
+    #[serde(flatten)]
+    pub status: ReadinessStatus,
+}
+
+/// Whether a calendar has completed the timestamp of a pending attestation
+#[derive(Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ReadinessStatus {
+    /// The calendar's timestamp reaches a block
+    Confirmed {
+        /// Chain of the block, `Bitcoin` unless the calendar only reached Litecoin
+        chain: &'static str,
+        /// Height of the block
+        height: usize,
+    },
+    /// The calendar has not committed to a block yet
+    Pending,
+    /// The calendar could not be queried or sent an invalid response
+    Error {
+        /// What went wrong
+        error: String,
+    },
+}
+
+impl fmt::Display for Readiness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.status {
+            ReadinessStatus::Confirmed { chain, height } => {
+                write!(f, "{}: confirmed in {chain} block {height}", self.calendar)
+            }
+            ReadinessStatus::Pending => write!(f, "{}: still pending", self.calendar),
+            ReadinessStatus::Error { error } => {
+                write!(f, "{}: calendar error: {error}", self.calendar)
+            }
+        }
+    }
+}
+
+/// Readiness of the pending attestations of one proof, for `--readiness`
+#[derive(Debug, Serialize)]
+pub struct ProofReadiness {
+    /// Proof as given on the command line
+    pub file: String,
+    /// Every pending attestation a calendar was asked about
+    pub attestations: Vec<Readiness>,
+}
+
+/// Write the readiness of every proof as JSON to `path`, or stdout for `-`
+///
+/// # Errors
+/// Returns error if the report cannot be written
+pub fn write_readiness(path: &Path, proofs: &[ProofReadiness]) -> Result<()> {
+    let text = serde_json::to_string_pretty(proofs).map_err(std::io::Error::from)?;
+    if is_stdio(path) {
+        println!("{text}");
+    } else {
+        std::fs::write(path, format!("{text}\n"))?;
+    }
+    Ok(())
+}
 
 /// Execute the upgrade command
 ///
 /// Reads an existing .ots file, finds pending attestations, queries calendar
 /// servers for completed Bitcoin attestations, and merges them into the timestamp.
+/// A dry run prints what each calendar said about its pending attestation.
+///
+/// Returns what the calendars said about every pending attestation.
 ///
 /// # Arguments
 ///
-/// * `file` - Path to the .ots file to upgrade
-/// * `dry_run` - If true, don't save changes (just check availability)
+/// * `file` - Path to the .ots file to upgrade; `-` reads the proof from stdin and
+///   writes it to stdout, upgraded or not
+/// * `output` - Where to write the proof, upgraded or not, instead of over `file`
+/// * `client` - Calendar client used to fetch completed timestamps
+/// * `options` - How to upgrade and save the proof
 ///
 /// # Errors
 ///
 /// Returns error if:
 /// - File cannot be read or parsed
 /// - Updated file cannot be written
-pub async fn execute(file: &Path, dry_run: bool) -> Result<()> {
-    println!("Upgrading timestamp: {}", file.display());
+pub async fn execute(
+    file: &Path,
+    output: Option<&Path>,
+    client: &CalendarClient,
+    options: &UpgradeOptions<'_>,
+) -> Result<Vec<Readiness>> {
+    if is_stdio(output.unwrap_or(file)) {
+        verbosity::progress_to_stderr();
+    }
+    progress!("Upgrading timestamp: {}", file.display());
 
     // 1. Read .ots file
-    let f = File::open(file)?;
-    let reader = BufReader::new(f);
-    let mut ots = DetachedTimestampFile::from_reader(reader)?;
+    let mut ots = DetachedTimestampFile::from_reader(open_proof(file)?)?;
 
-    // 2. Check if already upgraded (has Bitcoin attestation)
-    if has_bitcoin_attestation(&ots.timestamp.first_step) {
-        println!("Timestamp already upgraded (Bitcoin attestation present)");
-        return Ok(());
+    // 2. Upgrade pending attestations unless already complete
+    let mut readiness = vec![];
+    let upgraded = upgrade_proof(&mut ots, options.accept_unknown, client, &mut readiness).await?;
+    let pruned = options.prune_pending && prune(&mut ots);
+    ots.timestamp.normalize();
+    if options.dry_run && !readiness.is_empty() {
+        progress!("Readiness of {}:", file.display());
+        for entry in &readiness {
+            progress!("  {entry}");
+        }
     }
 
-    // 3. Find pending attestations and try to upgrade
-    let client = CalendarClient::new(Duration::from_secs(30))?;
-    let upgraded = upgrade_timestamp(&mut ots.timestamp, &client).await?;
+    // 3. Save updated .ots
+    save(&ots, file, output, upgraded, pruned, options).await?;
+    Ok(readiness)
+}
 
-    if !upgraded {
-        println!("Timestamp not yet ready for upgrade (still pending)");
-        return Ok(());
+/// Execute the upgrade command with a calendar response obtained elsewhere
+///
+/// For proofs kept on a network-isolated machine: the calendar is queried
+/// from another machine (`GET {uri}/timestamp/{commitment}`), and the saved
+/// response is merged here without any network access. The response is
+/// validated against the commitment of the pending attestation for `uri`
+/// before it is merged next to that attestation.
+///
+/// # Arguments
+///
+/// * `file` - Path to the .ots file to upgrade; `-` reads the proof from stdin and
+///   writes it to stdout
+/// * `output` - Where to write the proof instead of over `file`
+/// * `uri` - Calendar the response came from, as named in the pending attestation
+/// * `response` - Raw response body of the calendar
+/// * `options` - How to save the proof; `accept_unknown` does not apply
+///
+/// # Errors
+///
+/// Returns error if:
+/// - File cannot be read or parsed
+/// - The proof has no pending attestation for `uri`
+/// - The response is not a valid timestamp for its commitment
+/// - Updated file cannot be written
+pub async fn execute_merge(
+    file: &Path,
+    output: Option<&Path>,
+    uri: &str,
+    response: &[u8],
+    options: &UpgradeOptions<'_>,
+) -> Result<()> {
+    if is_stdio(output.unwrap_or(file)) {
+        verbosity::progress_to_stderr();
     }
+    progress!("Merging response from {uri} into: {}", file.display());
 
-    if dry_run {
-        println!("Dry run - not saving changes");
+    let mut ots = DetachedTimestampFile::from_reader(open_proof(file)?)?;
+    merge_response(&mut ots.timestamp, uri, response)?;
+    progress!("Upgraded pending attestation");
+    let pruned = options.prune_pending && prune(&mut ots);
+    ots.timestamp.normalize();
+
+    save(&ots, file, output, true, pruned, options).await
+}
+
+/// Where to save the upgraded proof of each file
+///
+/// Without `output`, every proof is upgraded in place. Like `stamp -o`,
+/// `output` names a directory if it is one, ends with a path separator or
+/// more than one proof is given; the directory is created if needed and the
+/// proofs keep their names, except that a proof from stdin still goes to
+/// stdout. Otherwise `output` is the upgraded proof of the single file.
+///
+/// # Errors
+///
+/// Returns error if the output directory cannot be created
+pub fn outputs(files: &[PathBuf], output: Option<&Path>) -> Result<Vec<Option<PathBuf>>> {
+    let Some(output) = output else {
+        return Ok(vec![None; files.len()]);
+    };
+    let is_dir = output.is_dir()
+        || output.as_os_str().to_string_lossy().ends_with(std::path::is_separator)
+        || files.len() > 1;
+    if !is_dir {
+        return Ok(vec![Some(output.to_path_buf()); files.len()]);
+    }
+
+    std::fs::create_dir_all(output)?;
+    Ok(files
+        .iter()
+        .map(|file| match file.file_name() {
+            Some(name) if !is_stdio(file) => Some(output.join(name)),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Save a proof after an upgrade and fire the confirmation hooks
+///
+/// A proof from stdin is always passed on to stdout, and one given an
+/// `output` is always written there; otherwise the proof is rewritten in
+/// place if it changed. A proof that was only pruned is saved without firing
+/// the hooks.
+async fn save(
+    ots: &DetachedTimestampFile,
+    file: &Path,
+    output: Option<&Path>,
+    upgraded: bool,
+    pruned: bool,
+    options: &UpgradeOptions<'_>,
+) -> Result<()> {
+    let destination = output.unwrap_or(file);
+    if options.dry_run {
+        if upgraded || pruned {
+            progress!("Dry run - not saving changes");
+        }
         return Ok(());
     }
 
-    // 4. Save updated .ots
-    let f = File::create(file)?;
-    let mut writer = BufWriter::new(f);
-    ots.to_writer(&mut writer)?;
+    if is_stdio(destination) {
+        let mut writer = BufWriter::new(std::io::stdout().lock());
+        ots.to_writer(&mut writer)?;
+        writer.flush()?;
+    } else if output.is_some() || upgraded || pruned {
+        // Nobody is asked before rewriting a proof in place
+        let overwrite = if output.is_some() { options.overwrite } else { Overwrite::Always };
+        let mut writer = AtomicFile::create(destination, overwrite)?;
+        write_proof(ots, &mut writer, is_compressed(destination))?;
+        writer.commit()?;
+        if output.is_some() {
+            progress!("Created timestamp: {}", destination.display());
+        }
+    }
+    if !upgraded {
+        return Ok(());
+    }
 
-    println!("Timestamp upgraded successfully");
+    progress!("Timestamp upgraded successfully");
+    if has_bitcoin_attestation(&ots.timestamp.first_step()) {
+        hooks::fire(options.hooks, &HookPayload::new(EVENT_CONFIRMED, destination, ots)).await;
+    }
     Ok(())
 }
 
-/// Recursively upgrade a timestamp by finding and replacing pending attestations
+/// Upgrade a proof unless it is already complete
+///
+/// Adds what the calendars said to `readiness`. Returns true if any
+/// attestations were upgraded.
+async fn upgrade_proof(
+    ots: &mut DetachedTimestampFile,
+    accept_unknown: bool,
+    client: &CalendarClient,
+    readiness: &mut Vec<Readiness>,
+) -> Result<bool> {
+    let step = ots.timestamp.first_step();
+    if has_bitcoin_attestation(&step) && !has_pending_reanchor(&step) {
+        progress!("Timestamp already upgraded (Bitcoin attestation present)");
+        return Ok(false);
+    }
+    if accept_unknown && has_unknown_attestation(&step) {
+        progress!("Timestamp already complete (unknown attestation present)");
+        return Ok(false);
+    }
+
+    let upgraded = upgrade_pending(&mut ots.timestamp, client, readiness).await?;
+    if !upgraded {
+        progress!("Timestamp not yet ready for upgrade (still pending)");
+    }
+    Ok(upgraded)
+}
+
+/// Remove the pending attestations of a proof confirmed in Bitcoin
 ///
-/// Walks the timestamp tree looking for `PendingAttestation` nodes, queries the
-/// calendar server for the completed timestamp, and merges the result.
+/// A re-anchor still waiting for its calendars keeps its pending
+/// attestations. Returns true if anything was removed.
+fn prune(ots: &mut DetachedTimestampFile) -> bool {
+    let step = ots.timestamp.first_step();
+    if !has_bitcoin_attestation(&step) || has_pending_reanchor(&step) {
+        return false;
+    }
+    let pruned = ots.timestamp.prune_pending();
+    if pruned {
+        progress!("Pruned pending attestations");
+    }
+    pruned
+}
+
+/// Upgrade a timestamp by merging completed timestamps into it
+///
+/// Finds the pending attestations, queries their calendar servers for the
+/// completed timestamps, and merges the results next to them.
+///
+/// Returns true if any attestations were added.
+///
+/// # Errors
 ///
-/// Returns true if any attestations were upgraded.
-async fn upgrade_timestamp(timestamp: &mut Timestamp, client: &CalendarClient) -> Result<bool> {
-    upgrade_step(&mut timestamp.first_step, client).await
+/// Returns error if a calendar request fails in a way that is not just a
+/// timestamp still being pending
+pub async fn upgrade_timestamp(timestamp: &mut Timestamp, client: &CalendarClient) -> Result<bool> {
+    upgrade_pending(timestamp, client, &mut vec![]).await
 }
 
-/// Recursively upgrade a single step in the timestamp tree
+/// Upgrade every pending attestation of a timestamp
 ///
-/// Handles three cases:
-/// - Attestation: If pending, try to fetch completed version from calendar
-/// - Fork: Process all branches
-/// - Op: Process next steps
+/// For each pending attestation, asks its calendar for the completed
+/// timestamp of the message it is on, and merges the result into that
+/// message, next to the pending attestation, which is kept.
 ///
-/// Returns true if any attestations were upgraded in this step or its children.
-#[async_recursion::async_recursion]
-async fn upgrade_step(step: &mut Step, client: &CalendarClient) -> Result<bool> {
+/// Returns true if any attestations were upgraded, and adds what the
+/// calendar of each pending attestation said to `readiness`.
+async fn upgrade_pending(
+    timestamp: &mut Timestamp,
+    client: &CalendarClient,
+    readiness: &mut Vec<Readiness>,
+) -> Result<bool> {
+    let pending = pending_attestations(timestamp.map());
+
     let mut upgraded = false;
+    for (commitment, uri) in pending {
+        progress!("Found pending attestation at {uri}");
+        let calendar = client.mirror(&uri);
+        if calendar != uri {
+            progress!("Fetching it from mirror {calendar}");
+        }
 
-    match &step.data {
-        StepData::Attestation(Attestation::Pending { uri }) => {
-            println!("Found pending attestation at {uri}");
-
-            // Try to get completed timestamp from calendar
-            match client.get_timestamp(uri, &step.output).await {
-                Ok(Some(response)) => {
-                    // Parse the response into a timestamp
-                    match parse_calendar_response(&step.output, &response) {
-                        Ok(new_timestamp) => {
-                            // Merge the new timestamp steps into this step's next chain
-                            // The calendar returns a timestamp that should contain Bitcoin attestation
-                            debug!(
-                                "Merging {} new steps from calendar",
-                                count_steps(&new_timestamp.first_step)
-                            );
-
-                            // Replace this attestation node with the new timestamp chain
-                            step.data = new_timestamp.first_step.data.clone();
-                            step.next.clone_from(&new_timestamp.first_step.next);
-
-                            println!("Upgraded pending attestation");
+        // Try to get completed timestamp from calendar
+        let status = match client.get_timestamp(calendar, &commitment).await {
+            Ok(Some(response)) => {
+                // Parse the response into a timestamp
+                match parse_calendar_response(&commitment, &response) {
+                    Ok(new_step) => {
+                        let status = block_status(&new_step);
+                        if merge_timestamp(timestamp, &commitment, &new_step)? {
+                            progress!("Upgraded pending attestation");
                             upgraded = true;
+                        } else {
+                            debug!("Calendar {uri} returned no new attestations");
                         }
-                        Err(e) => {
-                            eprintln!("Failed to parse calendar response: {e}");
-                        }
+                        status
+                    }
+                    Err(e) => {
+                        eprogress!("Failed to parse calendar response: {e}");
+                        ReadinessStatus::Error { error: e.to_string() }
                     }
-                }
-                Ok(None) => {
-                    debug!("Attestation not yet available at {uri}");
-                }
-                Err(e) => {
-                    eprintln!("Failed to query calendar {uri}: {e}");
                 }
             }
-        }
-        StepData::Fork => {
-            // Process all branches in a fork
-            for next_step in &mut step.next {
-                let branch_upgraded = upgrade_step(next_step, client).await?;
-                upgraded |= branch_upgraded;
+            Ok(None) => {
+                debug!("Attestation not yet available at {uri}");
+                ReadinessStatus::Pending
             }
-        }
-        StepData::Op(_) => {
-            // Process all next steps after an operation
-            for next_step in &mut step.next {
-                let branch_upgraded = upgrade_step(next_step, client).await?;
-                upgraded |= branch_upgraded;
+            Err(e) => {
+                eprogress!("Failed to query calendar {uri}: {e}");
+                ReadinessStatus::Error { error: e.to_string() }
             }
-        }
-        // Bitcoin attestations and unknown attestations are already complete
-        StepData::Attestation(Attestation::Bitcoin { .. } | Attestation::Unknown { .. }) => {}
+        };
+        readiness.push(Readiness { calendar: uri, commitment: hex::encode(&commitment), status });
     }
 
     Ok(upgraded)
 }
 
+/// The message and calendar URI of every pending attestation of a timestamp
+fn pending_attestations(map: &TimestampMap) -> Vec<(Vec<u8>, String)> {
+    map.all_attestations()
+        .into_iter()
+        .filter_map(|(msg, attestation)| match attestation {
+            Attestation::Pending { uri } => Some((msg.to_vec(), uri.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Whether the timestamp steps returned by a calendar reach a block
+///
+/// Reports the earliest Bitcoin block, or the earliest Litecoin block if
+/// there is no Bitcoin one.
+fn block_status(step: &Step) -> ReadinessStatus {
+    let mut attestations = vec![];
+    collect_attestations(step, &mut attestations);
+    attestations
+        .iter()
+        .filter_map(|attestation| match attestation {
+            Attestation::Bitcoin { height } => Some(("Bitcoin", *height)),
+            Attestation::Litecoin { height } => Some(("Litecoin", *height)),
+            Attestation::Pending { .. } | Attestation::Unknown { .. } => None,
+        })
+        .min_by_key(|&(chain, height)| (chain != "Bitcoin", height))
+        .map_or(ReadinessStatus::Pending, |(chain, height)| ReadinessStatus::Confirmed {
+            chain,
+            height,
+        })
+}
+
+/// Merge the timestamp steps a calendar returned for `commitment` into a proof
+///
+/// The steps join the message of the pending attestation, which is kept.
+/// Like in python-opentimestamps, ops the proof already applies there have
+/// their timestamps merged, so merging the same response twice changes
+/// nothing. Returns whether the merge added an attestation.
+///
+/// # Errors
+///
+/// Returns error if the proof does not reach `commitment`, or the steps do
+/// not start from it
+fn merge_timestamp(timestamp: &mut Timestamp, commitment: &[u8], new_step: &Step) -> Result<bool> {
+    // The calendar returns a timestamp that should contain Bitcoin attestation
+    debug!("Merging {} new steps from calendar", count_steps(new_step));
+    let before = timestamp.map().all_attestations().len();
+    timestamp.merge_at(commitment, new_step)?;
+    Ok(timestamp.map().all_attestations().len() > before)
+}
+
+/// Merge a calendar response into the pending attestation for `uri`
+///
+/// A trailing `/` on either URI is ignored. If several pending attestations
+/// name the calendar, the response is merged into the first one it is valid for.
+///
+/// # Errors
+///
+/// Returns `Error::Calendar` if no pending attestation names the calendar, or
+/// the parse error of the response if it is not valid for any of them
+fn merge_response(timestamp: &mut Timestamp, uri: &str, response: &[u8]) -> Result<()> {
+    let uri = uri.trim_end_matches('/');
+    let candidates: Vec<Vec<u8>> = pending_attestations(timestamp.map())
+        .into_iter()
+        .filter(|(_, pending)| pending.trim_end_matches('/') == uri)
+        .map(|(msg, _)| msg)
+        .collect();
+    if candidates.is_empty() {
+        return Err(Error::Calendar(format!("Proof has no pending attestation for {uri}")));
+    }
+
+    let mut last_error = None;
+    for commitment in candidates {
+        match parse_calendar_response(&commitment, response) {
+            Ok(new_step) => {
+                merge_timestamp(timestamp, &commitment, &new_step)?;
+                return Ok(());
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| Error::Calendar("No pending attestation matched".into())))
+}
+
 /// Check if timestamp already contains a Bitcoin attestation
 fn has_bitcoin_attestation(step: &Step) -> bool {
     match &step.data {
@@ -148,15 +486,15 @@ fn has_bitcoin_attestation(step: &Step) -> bool {
     }
 }
 
-/// Parse calendar server response into a Timestamp
+/// Check if timestamp contains an attestation of a type we don't interpret
 ///
-/// The calendar returns binary timestamp data that needs to be deserialized
-/// using the opentimestamps library.
-fn parse_calendar_response(commitment: &[u8], response: &[u8]) -> Result<Timestamp> {
-    let cursor = Cursor::new(response);
-    let mut deserializer = Deserializer::new(cursor);
-
-    Timestamp::deserialize(&mut deserializer, commitment.to_vec()).map_err(Error::InvalidOts)
+/// Metadata attestations make no time claim, so they don't count.
+fn has_unknown_attestation(step: &Step) -> bool {
+    match &step.data {
+        StepData::Attestation(att @ Attestation::Unknown { .. }) => !Metadata::is_metadata(att),
+        StepData::Fork | StepData::Op(_) => step.next.iter().any(has_unknown_attestation),
+        _ => false,
+    }
 }
 
 /// Count the number of steps in a timestamp (for debugging)
@@ -167,7 +505,14 @@ fn count_steps(step: &Step) -> usize {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::ots::Op;
+    use crate::ots::{Digest, Op};
+
+    /// The message a calendar's pending attestation is on: the submitted
+    /// digest with the time prepended and an HMAC appended
+    fn pending_commitment() -> Vec<u8> {
+        let timed = Op::Prepend(vec![0x5f, 0x1a, 0x6b, 0x66]).execute(&[7u8; 32]);
+        Op::Append(vec![0x8b; 8]).execute(&timed)
+    }
 
     #[test]
     fn test_count_steps() {
@@ -188,9 +533,116 @@ mod tests {
         assert_eq!(count_steps(&step_with_next), 2);
     }
 
+    #[test]
+    fn test_outputs() {
+        let files = vec![PathBuf::from("a/x.ots"), PathBuf::from("-")];
+        assert_eq!(outputs(&files, None).unwrap(), vec![None, None]);
+
+        let dir = std::env::temp_dir().join(format!("ots-upgrade-out-{}", std::process::id()));
+        let into_dir = outputs(&files, Some(&dir)).unwrap();
+        assert!(dir.is_dir());
+        assert_eq!(into_dir, vec![Some(dir.join("x.ots")), None]);
+
+        let single = outputs(&files[..1], Some(Path::new("y.ots"))).unwrap();
+        assert_eq!(single, vec![Some(PathBuf::from("y.ots"))]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_readiness() {
+        let commitment = pending_commitment();
+        let leaf = |attestation: Attestation| Step {
+            data: StepData::Attestation(attestation),
+            output: commitment.clone(),
+            next: vec![],
+        };
+        let timestamp =
+            |next: Vec<Step>| Step { data: StepData::Fork, output: commitment.clone(), next };
+        let pending = leaf(Attestation::Pending { uri: "https://a.example".into() });
+
+        let litecoin = timestamp(vec![pending.clone(), leaf(Attestation::Litecoin { height: 5 })]);
+        assert_eq!(
+            block_status(&litecoin),
+            ReadinessStatus::Confirmed { chain: "Litecoin", height: 5 }
+        );
+        let bitcoin = timestamp(vec![
+            leaf(Attestation::Litecoin { height: 1 }),
+            leaf(Attestation::Bitcoin { height: 9 }),
+            leaf(Attestation::Bitcoin { height: 8 }),
+        ]);
+        let confirmed = ReadinessStatus::Confirmed { chain: "Bitcoin", height: 8 };
+        assert_eq!(block_status(&bitcoin), confirmed);
+        assert_eq!(
+            block_status(&timestamp(vec![pending.clone(), pending])),
+            ReadinessStatus::Pending
+        );
+
+        let readiness = Readiness {
+            calendar: "https://a.example".into(),
+            commitment: "00".into(),
+            status: confirmed,
+        };
+        assert_eq!(readiness.to_string(), "https://a.example: confirmed in Bitcoin block 8");
+        let json = serde_json::to_value(&readiness).unwrap();
+        assert_eq!(json["status"], "confirmed");
+        assert_eq!(json["height"], 8);
+    }
+
+    #[test]
+    fn test_merge_response() {
+        // A proof of a digest whose two calendars are still pending
+        let digest = [7u8; 32];
+        let commitment = pending_commitment();
+        let pending = |uri: &str| Step {
+            data: StepData::Attestation(Attestation::Pending { uri: uri.to_string() }),
+            output: commitment.clone(),
+            next: vec![],
+        };
+        let timed = Op::Prepend(vec![0x5f, 0x1a, 0x6b, 0x66]).execute(&digest);
+        let first_step = Step {
+            data: StepData::Op(Op::Prepend(vec![0x5f, 0x1a, 0x6b, 0x66])),
+            output: timed,
+            next: vec![Step {
+                data: StepData::Op(Op::Append(vec![0x8b; 8])),
+                output: commitment.clone(),
+                next: vec![Step {
+                    data: StepData::Fork,
+                    output: commitment.clone(),
+                    next: vec![pending("https://a.example"), pending("https://b.example/")],
+                }],
+            }],
+        };
+        let mut timestamp = Timestamp::new(Digest::sha256(digest), &first_step).unwrap();
+
+        // SHA256 of the commitment, then a Bitcoin attestation
+        let mut response = Vec::new();
+        let mut ser = crate::ots::Serializer::new(&mut response);
+        ser.write_byte(Op::Sha256.tag()).unwrap();
+        ser.write_byte(0x00).unwrap();
+        Attestation::Bitcoin { height: 850_000 }.serialize(&mut ser).unwrap();
+
+        assert!(merge_response(&mut timestamp, "https://c.example", &response).is_err());
+        assert!(merge_response(&mut timestamp, "https://b.example", &[0xff]).is_err());
+        assert!(!has_bitcoin_attestation(&timestamp.first_step()));
+
+        merge_response(&mut timestamp, "https://b.example", &response).unwrap();
+        assert!(has_bitcoin_attestation(&timestamp.first_step()));
+        let pending_node = &timestamp.map().ops[0].1.ops[0].1;
+        assert_eq!(pending_node.msg, commitment);
+        assert_eq!(pending_node.attestations.len(), 2);
+        assert_eq!(pending_node.ops.len(), 1);
+        assert_eq!(pending_node.ops[0].0, Op::Sha256);
+        assert_eq!(pending_node.ops[0].1.msg, Op::Sha256.execute(&commitment));
+
+        // The same response again adds nothing
+        let merged = timestamp.clone();
+        merge_response(&mut timestamp, "https://b.example", &response).unwrap();
+        assert_eq!(timestamp, merged);
+    }
+
     #[test]
     fn test_parse_calendar_response_invalid() {
-        let commitment = vec![0u8; 32];
+        let commitment = pending_commitment();
         let invalid_response = vec![0xff, 0xff];
 
         let result = parse_calendar_response(&commitment, &invalid_response);
@@ -268,4 +720,28 @@ mod tests {
         };
         assert!(!has_bitcoin_attestation(&step));
     }
+
+    #[test]
+    fn test_has_unknown_attestation() {
+        let step = Step {
+            data: StepData::Op(Op::Sha256),
+            output: vec![0u8; 32],
+            next: vec![Step {
+                data: StepData::Attestation(Attestation::Unknown {
+                    tag: vec![0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08],
+                    data: vec![0xaa],
+                }),
+                output: vec![0u8; 32],
+                next: vec![],
+            }],
+        };
+        assert!(has_unknown_attestation(&step));
+
+        let step = Step {
+            data: StepData::Attestation(Attestation::Bitcoin { height: 123456 }),
+            output: vec![0u8; 32],
+            next: vec![],
+        };
+        assert!(!has_unknown_attestation(&step));
+    }
 }
@@ -1,51 +1,350 @@
+use crate::calendar::CalendarClient;
+use crate::commands::info::collect_attestations;
+use crate::commands::upgrade::upgrade_timestamp;
+use crate::commands::{
+    is_compressed, is_stdio, open_proof, strip_extension, without_compression, write_proof,
+};
 use crate::error::{Error, Result};
-use crate::ots::{Attestation, DetachedTimestampFile, Step, StepData};
-use crate::verifier::BlockVerifier;
-#[cfg(feature = "electrum")]
-use crate::verifier::ElectrumVerifier;
-#[cfg(all(feature = "esplora", not(feature = "electrum")))]
-use crate::verifier::EsploraVerifier;
-#[cfg(all(feature = "rpc", not(feature = "electrum"), not(feature = "esplora")))]
-use crate::verifier::RpcVerifier;
-use log::debug;
-use sha2::{Digest, Sha256};
+use crate::ots::{
+    Attestation, DetachedTimestampFile, Digest, Metadata, SignedTimestampFile, Timestamp,
+};
+use crate::output::{AtomicFile, Overwrite};
+use crate::time_style::format_time;
+use crate::verbosity::progress;
+use crate::verifier::{
+    self, block_attestations, verify_proof, AttestationStatus, AttestationVerification,
+    BlockHeader, BlockVerifier, ChainVerifiers, VerificationResult,
+};
+use async_trait::async_trait;
+use log::{debug, warn};
+use serde::Serialize;
 use std::fs::File;
-use std::io::{BufReader, Read};
-use std::path::Path;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What to check a proof against, and whether to upgrade it first
+#[derive(Clone, Copy, Default)]
+pub struct VerifyOptions<'a> {
+    /// Original file, derived from the proof filename if not given
+    /// (required when reading from stdin)
+    pub target: Option<&'a Path>,
+    /// Digest of the original file, checked instead of hashing the file
+    pub digest: Option<&'a [u8]>,
+    /// Hex-encoded public key that must have signed the proof
+    pub signer: Option<&'a str>,
+    /// Verify offline against this Bitcoin block header instead of fetching
+    /// headers, see `verify_against_header`
+    pub block_header: Option<&'a BlockHeader>,
+    /// Upgrade pending attestations in memory with this client before verifying
+    pub upgrade: Option<&'a CalendarClient>,
+    /// Write a proof that was upgraded back to its file
+    pub save: bool,
+    /// Also validate the Bitcoin header chain up to each attested block, see
+    /// `verifier::validate_header_chain`
+    pub strict: bool,
+}
+
+/// Backend named in reports of attestations checked against `--block-header`
+const OFFLINE_BACKEND: &str = "block header";
+
+/// What verifying one block attestation found, for `verify --report`
+#[derive(Debug, Serialize)]
+pub struct AttestationReport {
+    /// `Bitcoin` or `Litecoin`
+    pub chain: &'static str,
+    /// Height of the attested block
+    pub height: u32,
+    /// `verified`, `mismatch`, `unavailable` or `skipped`
+    pub status: &'static str,
+    /// Merkle root the proof commits to, hex-encoded
+    pub merkle_root: String,
+    /// Id of the transaction committing to the proof, in display order
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub txid: Option<String>,
+    /// Time of the attested block (Unix epoch, or ISO 8601 with
+    /// `--time-style iso8601`), if its header was fetched
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::time_style::serialize_block_time"
+    )]
+    pub block_time: Option<u32>,
+    /// When the attestation was checked (Unix epoch)
+    pub checked_at: u64,
+    /// Where the block header came from
+    pub backend: &'static str,
+    /// Why the attestation did not verify
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl AttestationReport {
+    fn new(verification: &AttestationVerification, backend: &'static str) -> Self {
+        let attestation = &verification.attestation;
+        let (status, error) = match &verification.status {
+            AttestationStatus::Verified => ("verified", None),
+            AttestationStatus::MerkleRootMismatch => (
+                "mismatch",
+                verification.header.map(|header| {
+                    format!("block has merkle root {}", hex::encode(header.merkle_root))
+                }),
+            ),
+            AttestationStatus::Unavailable(e) => ("unavailable", Some(e.to_string())),
+            AttestationStatus::Skipped => ("skipped", None),
+        };
+        Self {
+            chain: attestation.chain(),
+            height: attestation.height(),
+            status,
+            merkle_root: hex::encode(attestation.merkle_root),
+            txid: attestation.txid.map(display_hash),
+            block_time: verification.block_time(),
+            checked_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+            backend,
+            error,
+        }
+    }
+}
 
 /// Execute verify command
 ///
-/// Verifies an `OpenTimestamps` proof against the Bitcoin blockchain.
+/// Verifies an `OpenTimestamps` proof against the blockchains it is anchored to.
 ///
 /// # Arguments
-/// * `file` - Path to .ots timestamp file
-/// * `target` - Optional path to original file. If None, derives from .ots filename
+/// * `file` - Path to .ots timestamp file, or .otss signed file if `options.signer`
+///   is given; `-` reads the proof from stdin
+/// * `options` - What to check the proof against, see `VerifyOptions`
+/// * `verifiers` - Backends used to fetch block headers for each chain
+/// * `report` - Receives what was found for each block attestation
 ///
 /// # Errors
 /// Returns error if:
 /// - File cannot be read
-/// - Hash doesn't match
-/// - No Bitcoin attestation found
+/// - Proof is not signed by `signer`
+/// - Hash or `digest` doesn't match
+/// - The upgraded proof cannot be saved
+/// - A step's output does not follow from its input, see `Timestamp::validate`
+/// - No block attestation found, `Error::PendingTimestamp` if the proof is
+///   only waiting for calendars; their URLs and the upgrade command are printed
 /// - Blockchain verification fails
-pub async fn execute(file: &Path, target: Option<&Path>) -> Result<()> {
-    // 1. Read .ots file
-    let f = File::open(file)?;
-    let reader = BufReader::new(f);
-    let ots = DetachedTimestampFile::from_reader(reader)?;
+pub async fn execute(
+    file: &Path,
+    options: &VerifyOptions<'_>,
+    verifiers: &ChainVerifiers,
+    report: &mut Vec<AttestationReport>,
+) -> Result<()> {
+    // 1. Read .ots file, or .otss file and check its signer
+    let reader = open_proof(file)?;
+    let (mut ots, extension) = if let Some(expected) = options.signer {
+        let signed = SignedTimestampFile::from_reader(reader)?;
+        let actual = hex::encode(signed.signer().as_bytes());
+        if !actual.eq_ignore_ascii_case(expected.trim()) {
+            return Err(Error::Verification(format!(
+                "Proof signed by {actual}, expected {expected}"
+            )));
+        }
+        progress!("Signature by {actual} is valid");
+        (signed.proof, "otss")
+    } else {
+        (DetachedTimestampFile::from_reader(reader)?, "ots")
+    };
 
-    // 2. Determine target file path
+    // 2. Hash the target file, unless only its digest is given
+    let (actual, what) = match options.digest {
+        Some(digest) => (digest.to_vec(), "Digest"),
+        None => (hash_target(&ots, file, options.target, extension)?.into_bytes(), "File hash"),
+    };
+    if actual != ots.timestamp.start_digest().as_bytes() {
+        return Err(Error::Verification(format!(
+            "{what} mismatch. Expected {}, got {}",
+            hex::encode(ots.timestamp.start_digest()),
+            hex::encode(&actual)
+        )));
+    }
+    debug!("{what} matches: {}", hex::encode(ots.timestamp.start_digest()));
+
+    // 3. Upgrade pending attestations first, if asked
+    if let Some(client) = options.upgrade {
+        upgrade_before_verifying(&mut ots, file, client, options.save).await?;
+    }
+
+    // 4. Make sure every step follows from the digest, upgraded parts included
+    ots.timestamp.validate()?;
+
+    // 5. Find block attestations and verify against their blockchains
+    let verified = verify_attestations(&ots, options, verifiers, report).await;
+    if matches!(verified, Err(Error::PendingTimestamp)) {
+        for calendar in pending_calendars(&ots.timestamp) {
+            progress!("Waiting for calendar {calendar}");
+        }
+        progress!("Run `ots upgrade {}` to complete the proof, then verify again", file.display());
+    }
+    verified?;
+
+    // 6. Show the labels the proof carries; they are not covered by the attestations
+    match Metadata::collect(&ots.timestamp.first_step()) {
+        Ok(metadata) if !metadata.is_empty() => progress!("Metadata (unattested): {metadata}"),
+        Ok(_) => {}
+        Err(e) => warn!("Ignoring malformed metadata: {e}"),
+    }
+    Ok(())
+}
+
+/// Upgrade a proof's pending attestations in memory so a fresh stamp can be verified
+///
+/// Proofs that already have a block attestation are left alone. A calendar
+/// failing only gets a warning, since the proof may verify without it. With
+/// `save`, an upgraded proof is written back to `file`.
+///
+/// # Errors
+/// Returns error if the upgraded proof cannot be saved
+async fn upgrade_before_verifying(
+    ots: &mut DetachedTimestampFile,
+    file: &Path,
+    client: &CalendarClient,
+    save: bool,
+) -> Result<()> {
+    let attested = block_attestations(&ots.timestamp).is_ok_and(|found| !found.is_empty());
+    if attested || pending_calendars(&ots.timestamp).is_empty() {
+        return Ok(());
+    }
+    let upgraded = match upgrade_timestamp(&mut ots.timestamp, client).await {
+        Ok(upgraded) => upgraded,
+        Err(e) => {
+            warn!("Could not upgrade {}: {e}", file.display());
+            return Ok(());
+        }
+    };
+    if !upgraded {
+        return Ok(());
+    }
+    if !save {
+        progress!("Upgraded in memory; pass --save to keep the upgraded proof");
+        return Ok(());
+    }
+    if is_stdio(file) {
+        return Err(Error::Verification("Cannot save a proof read from stdin".into()));
+    }
+    // Nobody is asked before rewriting a proof in place, as in `ots upgrade`
+    let mut writer = AtomicFile::create(file, Overwrite::Always)?;
+    write_proof(ots, &mut writer, is_compressed(file))?;
+    writer.commit()?;
+    progress!("Saved upgraded proof {}", file.display());
+    Ok(())
+}
+
+/// Verify a proof's block attestations, against `options.block_header` if given
+async fn verify_attestations(
+    ots: &DetachedTimestampFile,
+    options: &VerifyOptions<'_>,
+    verifiers: &ChainVerifiers,
+    report: &mut Vec<AttestationReport>,
+) -> Result<()> {
+    match options.block_header {
+        Some(header) => verify_offline(ots, header, report).await,
+        None => {
+            let result = verifiers.verify(&ots.timestamp).await;
+            report.extend(result.attestations.iter().map(|verification| {
+                AttestationReport::new(
+                    verification,
+                    verifiers.backend_name(&verification.attestation.attestation),
+                )
+            }));
+            let attested: Vec<BlockHeader> = result
+                .attestations
+                .iter()
+                .filter(|verification| {
+                    matches!(verification.status, AttestationStatus::Verified)
+                        && matches!(
+                            verification.attestation.attestation,
+                            Attestation::Bitcoin { .. }
+                        )
+                })
+                .filter_map(|verification| verification.header)
+                .collect();
+            check_verification(&ots.timestamp, result)?;
+            if options.strict {
+                for header in attested {
+                    let chain =
+                        verifier::validate_header_chain(verifiers.bitcoin.as_ref(), &header)
+                            .await?;
+                    progress!(
+                        "Header chain from checkpoint {} to block {} is valid (2^{:.1} hashes)",
+                        chain.checkpoint,
+                        chain.tip.height,
+                        chain.work.log2()
+                    );
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Verify a proof against `header` with `verify_against_header`, reporting every
+/// block attestation; those committing to another block are skipped
+async fn verify_offline(
+    ots: &DetachedTimestampFile,
+    header: &BlockHeader,
+    report: &mut Vec<AttestationReport>,
+) -> Result<()> {
+    let mut result = verify_proof(ots, &GivenHeader(header)).await;
+    for verification in &mut result.attestations {
+        if matches!(verification.status, AttestationStatus::MerkleRootMismatch) {
+            verification.header = None;
+            verification.status = AttestationStatus::Skipped;
+        }
+    }
+    report.extend(
+        result
+            .attestations
+            .iter()
+            .map(|verification| AttestationReport::new(verification, OFFLINE_BACKEND)),
+    );
+    verify_against_header(&ots.timestamp, header)
+}
+
+/// Block verifier answering with the header given to `verify --block-header`
+///
+/// The height of that header is not known, so it is returned for every
+/// Bitcoin attestation; those committing to another merkle root come out as
+/// `AttestationStatus::MerkleRootMismatch`.
+struct GivenHeader<'a>(&'a BlockHeader);
+
+#[async_trait]
+impl BlockVerifier for GivenHeader<'_> {
+    async fn get_block_header(&self, _height: u32) -> Result<BlockHeader> {
+        Ok(*self.0)
+    }
+}
+
+/// Hash the file a proof timestamps
+///
+/// The file is `target`, or the proof's path without its `extension`.
+///
+/// # Errors
+/// Returns error if the file cannot be determined, does not exist or cannot be read
+fn hash_target(
+    ots: &DetachedTimestampFile,
+    file: &Path,
+    target: Option<&Path>,
+    extension: &str,
+) -> Result<Digest> {
     let target_path = if let Some(p) = target {
         p.to_path_buf()
+    } else if is_stdio(file) {
+        return Err(Error::Verification(
+            "Cannot determine target file of a proof read from stdin: pass --target".into(),
+        ));
     } else {
-        // Strip .ots extension to derive original filename
-        let s = file.to_string_lossy();
-        if let Some(stripped) = s.strip_suffix(".ots") {
-            Path::new(stripped).to_path_buf()
-        } else {
-            return Err(Error::Verification(
-                "Cannot determine target file: .ots extension missing".into(),
-            ));
-        }
+        // Strip extension to derive original filename
+        strip_extension(&without_compression(file), extension).ok_or_else(|| {
+            Error::Verification(format!(
+                "Cannot determine target file: .{extension} extension missing"
+            ))
+        })?
     };
 
     if !target_path.exists() {
@@ -55,102 +354,362 @@ pub async fn execute(file: &Path, target: Option<&Path>) -> Result<()> {
         )));
     }
 
-    // 3. Hash target file and compare with timestamp
-    let file_hash = hash_file(&target_path)?;
-    if file_hash != ots.timestamp.start_digest.as_slice() {
-        return Err(Error::Verification(format!(
-            "File hash mismatch. Expected {}, got {}",
-            hex::encode(&ots.timestamp.start_digest),
-            hex::encode(&file_hash)
-        )));
-    }
-    debug!("File hash matches: {}", hex::encode(&ots.timestamp.start_digest));
+    Ok(ots.digest_type().digest_reader(BufReader::new(File::open(&target_path)?))?)
+}
 
-    // 4. Find Bitcoin attestation and verify against blockchain
-    if let Some((merkle_root, height)) = find_bitcoin_attestation(&ots.timestamp.first_step) {
-        println!("Found Bitcoin attestation at block {height}");
+/// Verify a timestamp's block attestations against their blockchains
+///
+/// Every Bitcoin or Litecoin attestation in the proof is checked with the
+/// verifier for its chain, so a proof stamped on several chains is verified
+/// on each of them.
+///
+/// # Errors
+/// Returns error if no block attestation is found, an op of the proof
+/// cannot be executed, or a block header cannot be fetched or does not match
+pub async fn verify_timestamp(timestamp: &Timestamp, verifiers: &ChainVerifiers) -> Result<()> {
+    check_verification(timestamp, verifiers.verify(timestamp).await)
+}
 
-        // Fetch block header from blockchain
-        let header = {
-            #[cfg(feature = "electrum")]
-            {
-                let verifier = ElectrumVerifier::new(None);
-                verifier.get_block_header(height).await?
-            }
-            #[cfg(all(feature = "esplora", not(feature = "electrum")))]
-            {
-                let verifier = EsploraVerifier::new(None)?;
-                verifier.get_block_header(height).await?
-            }
-            #[cfg(all(feature = "rpc", not(feature = "electrum"), not(feature = "esplora")))]
-            {
-                let verifier = RpcVerifier::new(None, None, None);
-                verifier.get_block_header(height).await?
-            }
-        };
+/// Print what verifying the block attestations found, failing on the first that did not verify
+fn check_verification(timestamp: &Timestamp, result: VerificationResult) -> Result<()> {
+    if let Some(e) = result.error {
+        return Err(e);
+    }
+    if result.attestations.is_empty() {
+        return Err(no_block_attestation(timestamp));
+    }
+    let verified = result.is_verified();
+    let earliest = (result.attestations.len() > 1)
+        .then(|| result.earliest())
+        .flatten()
+        .map(|earliest| (earliest.attestation.chain(), earliest.attestation.height()));
 
-        // Verify merkle root matches
-        if merkle_root != header.merkle_root {
-            return Err(Error::Verification(format!(
-                "Merkle root mismatch at block {height}. Expected {}, got {}",
-                hex::encode(merkle_root),
-                hex::encode(header.merkle_root)
-            )));
+    for AttestationVerification { attestation, header, status } in result.attestations {
+        let (chain, height) = (attestation.chain(), attestation.height());
+        progress!("Found {chain} attestation at block {height}");
+        if let Some(txid) = attestation.txid {
+            progress!("{chain} transaction: {}", display_hash(txid));
         }
+        let header = match (status, header) {
+            (AttestationStatus::Verified, Some(header)) => header,
+            (AttestationStatus::MerkleRootMismatch, Some(header)) => {
+                return Err(Error::Verification(format!(
+                    "Merkle root mismatch at {chain} block {height}. Expected {}, got {}",
+                    hex::encode(attestation.merkle_root),
+                    hex::encode(header.merkle_root)
+                )));
+            }
+            (AttestationStatus::Unavailable(e), _) => return Err(e),
+            _ => continue,
+        };
 
-        // Convert Unix timestamp to human-readable date
-        let datetime = chrono::DateTime::from_timestamp(i64::from(header.time), 0).map_or_else(
-            || "unknown".to_string(),
-            |dt| dt.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
-        );
+        let datetime = format_time(header.time);
 
-        println!("Success! Bitcoin block {height} attests existence as of {datetime}");
-        println!("Merkle root: {}", hex::encode(header.merkle_root));
-        return Ok(());
+        progress!("Success! {chain} block {height} attests existence as of {datetime}");
+        progress!("Merkle root: {}", hex::encode(header.merkle_root));
     }
 
-    Err(Error::NoBitcoinAttestation)
+    if !verified {
+        return Err(Error::Verification("No block attestation could be checked".into()));
+    }
+    if let Some((chain, height)) = earliest {
+        progress!("Earliest attestation: {chain} block {height}");
+    }
+    Ok(())
 }
 
-/// Hash file contents using SHA256
+/// Verify a timestamp offline against a Bitcoin block header
+///
+/// The header comes from a source of the user's choosing. Its proof of work
+/// is checked, then the proof must have a Bitcoin attestation committing to
+/// its merkle root. No blockchain backend is contacted.
 ///
 /// # Errors
-/// Returns error if file cannot be read
-fn hash_file(path: &Path) -> Result<Vec<u8>> {
-    let mut file = File::open(path)?;
-    let mut hasher = Sha256::new();
-    let mut buffer = [0u8; 8192];
-    loop {
-        let n = file.read(&mut buffer)?;
-        if n == 0 {
-            break;
-        }
-        hasher.update(&buffer[..n]);
+/// Returns error if the header fails its proof-of-work check, or no Bitcoin
+/// attestation in the proof commits to it
+pub fn verify_against_header(timestamp: &Timestamp, header: &BlockHeader) -> Result<()> {
+    header.validate_pow()?;
+    progress!("Block header {} has valid proof of work", display_hash(header.hash));
+
+    let attestations = block_attestations(timestamp)?;
+    if attestations.is_empty() {
+        return Err(no_block_attestation(timestamp));
+    }
+    let (height, txid) = attestations
+        .iter()
+        .find_map(|found| match found.attestation {
+            Attestation::Bitcoin { height } if found.merkle_root == header.merkle_root => {
+                Some((height, found.txid))
+            }
+            _ => None,
+        })
+        .ok_or_else(|| {
+            Error::Verification(format!(
+                "No Bitcoin attestation commits to merkle root {}",
+                hex::encode(header.merkle_root)
+            ))
+        })?;
+    if let Some(txid) = txid {
+        progress!("Bitcoin transaction: {}", display_hash(txid));
     }
-    Ok(hasher.finalize().to_vec())
+
+    let datetime = format_time(header.time);
+    progress!("Success! Bitcoin block {height} attests existence as of {datetime}");
+    progress!("Merkle root: {}", hex::encode(header.merkle_root));
+    Ok(())
 }
 
-/// Recursively search timestamp tree for Bitcoin attestation
+/// Error for a timestamp without block attestations
 ///
-/// Returns tuple of (`merkle_root`, `block_height`) if found
-#[allow(clippy::cast_possible_truncation)]
-fn find_bitcoin_attestation(step: &Step) -> Option<([u8; 32], u32)> {
-    if let StepData::Attestation(Attestation::Bitcoin { height }) = &step.data {
-        // Found Bitcoin attestation - extract merkle root from step output
-        if step.output.len() >= 32 {
-            let mut arr = [0u8; 32];
-            arr.copy_from_slice(&step.output[..32]);
-            Some((arr, *height as u32))
+/// `Error::PendingTimestamp` if calendars have yet to anchor it, otherwise
+/// `Error::NoBitcoinAttestation`.
+fn no_block_attestation(timestamp: &Timestamp) -> Error {
+    if pending_calendars(timestamp).is_empty() {
+        Error::NoBitcoinAttestation
+    } else {
+        Error::PendingTimestamp
+    }
+}
+
+/// URLs of the calendars a timestamp has pending attestations from
+fn pending_calendars(timestamp: &Timestamp) -> Vec<String> {
+    let mut attestations = vec![];
+    collect_attestations(&timestamp.first_step(), &mut attestations);
+    attestations
+        .into_iter()
+        .filter_map(|attestation| match attestation {
+            Attestation::Pending { uri } => Some(uri),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Expand the glob patterns among `files` into the proofs they match
+///
+/// Shells usually expand patterns themselves; this covers those that do not
+/// and quoted patterns. An argument that names an existing file, is `-`, or
+/// matches nothing is kept as is, so a missing proof is still reported.
+#[must_use]
+pub fn expand_globs(files: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut expanded = vec![];
+    for file in files {
+        if is_stdio(&file) || file.exists() {
+            expanded.push(file);
+            continue;
+        }
+        let matches: Vec<PathBuf> = file
+            .to_str()
+            .and_then(|pattern| glob::glob(pattern).ok())
+            .map(|paths| paths.filter_map(std::result::Result::ok).collect())
+            .unwrap_or_default();
+        if matches.is_empty() {
+            expanded.push(file);
         } else {
-            None
+            expanded.extend(matches);
         }
-    } else {
-        // Recursively search child steps
-        for next in &step.next {
-            if let Some(result) = find_bitcoin_attestation(next) {
-                return Some(result);
-            }
+    }
+    expanded
+}
+
+/// Display-order hex of a block or transaction hash
+fn display_hash(mut hash: [u8; 32]) -> String {
+    hash.reverse();
+    hex::encode(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ots::{DigestType, Step, StepData};
+    use crate::verifier::parse_header;
+
+    /// Header of the Bitcoin genesis block
+    const GENESIS_HEADER: &str = "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c";
+
+    /// The only transaction of the genesis block
+    const GENESIS_COINBASE: &str = "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff4d04ffff001d0104455468652054696d65732030332f4a616e2f32303039204368616e63656c6c6f72206f6e206272696e6b206f66207365636f6e64206261696c6f757420666f722062616e6b73ffffffff0100f2052a01000000434104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac00000000";
+
+    /// Header of Bitcoin block 1
+    const BLOCK1_HEADER: &str = "010000006fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000982051fd1e4ba744bbbe680e1fee14677ba1a3c3540bf7b1cdb606e857233e0e61bc6649ffff001d01e36299";
+
+    fn attested(height: usize, merkle_root: [u8; 32]) -> Timestamp {
+        Timestamp::new(
+            Digest::new(DigestType::Sha256, merkle_root.to_vec()).unwrap(),
+            &Step {
+                data: StepData::Attestation(Attestation::Bitcoin { height }),
+                output: merkle_root.to_vec(),
+                next: vec![],
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_pending_only() {
+        let pending = |uri: &str| Step {
+            data: StepData::Attestation(Attestation::Pending { uri: uri.into() }),
+            output: vec![1; 32],
+            next: vec![],
+        };
+        let timestamp = Timestamp::new(
+            Digest::new(DigestType::Sha256, vec![1; 32]).unwrap(),
+            &Step {
+                data: StepData::Fork,
+                output: vec![1; 32],
+                next: vec![pending("https://a.example"), pending("https://b.example")],
+            },
+        )
+        .unwrap();
+        assert_eq!(pending_calendars(&timestamp), ["https://a.example", "https://b.example"]);
+        assert!(matches!(no_block_attestation(&timestamp), Error::PendingTimestamp));
+
+        let genesis = parse_header(GENESIS_HEADER, 0).unwrap();
+        assert!(matches!(
+            verify_against_header(&timestamp, &genesis),
+            Err(Error::PendingTimestamp)
+        ));
+
+        let unknown = Timestamp::new(
+            Digest::new(DigestType::Sha256, vec![1; 32]).unwrap(),
+            &Step {
+                data: StepData::Attestation(Attestation::Unknown { tag: vec![0; 8], data: vec![] }),
+                output: vec![1; 32],
+                next: vec![],
+            },
+        )
+        .unwrap();
+        assert!(matches!(no_block_attestation(&unknown), Error::NoBitcoinAttestation));
+    }
+
+    #[test]
+    fn test_expand_globs() {
+        let dir = std::env::temp_dir().join(format!("ots-verify-glob-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["b.txt.ots", "a.txt.ots", "c.txt"] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+        let missing = dir.join("missing.ots");
+        let expanded = expand_globs(vec![
+            PathBuf::from("-"),
+            dir.join("*.ots"),
+            dir.join("c.txt"),
+            missing.clone(),
+        ]);
+        assert_eq!(
+            expanded,
+            [
+                PathBuf::from("-"),
+                dir.join("a.txt.ots"),
+                dir.join("b.txt.ots"),
+                dir.join("c.txt"),
+                missing,
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_merkle_path() {
+        use crate::ots::Op;
+
+        let genesis = parse_header(GENESIS_HEADER, 0).unwrap();
+        let coinbase = hex::decode(GENESIS_COINBASE).unwrap();
+        // The document digest sits inside the coinbase, which is the whole block
+        let (prefix, rest) = coinbase.split_at(100);
+        let (digest, suffix) = rest.split_at(32);
+        fn op(input: &[u8], op: Op, next: impl FnOnce(&[u8]) -> Step) -> Step {
+            let output = op.execute(input);
+            Step { data: StepData::Op(op), next: vec![next(&output)], output }
         }
-        None
+        let chain = |digest: &[u8]| {
+            let timestamp = op(digest, Op::Prepend(prefix.to_vec()), |msg| {
+                op(msg, Op::Append(suffix.to_vec()), |msg| {
+                    op(msg, Op::Sha256, |msg| {
+                        op(msg, Op::Sha256, |msg| Step {
+                            data: StepData::Attestation(Attestation::Bitcoin { height: 0 }),
+                            output: msg.to_vec(),
+                            next: vec![],
+                        })
+                    })
+                })
+            });
+            Timestamp::new(Digest::new(DigestType::Sha256, digest.to_vec()).unwrap(), &timestamp)
+                .unwrap()
+        };
+        let timestamp = chain(digest);
+
+        let found = block_attestations(&timestamp).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].path.len(), 4);
+        assert_eq!(found[0].merkle_root, genesis.merkle_root);
+        assert_eq!(found[0].txid, Some(genesis.merkle_root));
+        verify_against_header(&timestamp, &genesis).unwrap();
+
+        let mut tampered = digest.to_vec();
+        tampered[0] ^= 1;
+        assert!(verify_against_header(&chain(&tampered), &genesis).is_err());
+
+        // A block attestation must be on a 32-byte digest
+        let short = Timestamp::new(
+            Digest::new(DigestType::Sha1, vec![1; 20]).unwrap(),
+            &Step {
+                data: StepData::Attestation(Attestation::Bitcoin { height: 0 }),
+                output: vec![1; 20],
+                next: vec![],
+            },
+        )
+        .unwrap();
+        assert!(verify_against_header(&short, &genesis).is_err());
+    }
+
+    #[test]
+    fn test_verify_against_header() {
+        let header = parse_header(BLOCK1_HEADER, 0).unwrap();
+        verify_against_header(&attested(1, header.merkle_root), &header).unwrap();
+        assert!(verify_against_header(&attested(1, [0; 32]), &header).is_err());
+
+        // A header that fails its proof of work is rejected before anything else
+        let mut raw = header.raw;
+        raw[76] ^= 1;
+        let forged = BlockHeader::from_raw(raw, 0);
+        assert!(verify_against_header(&attested(1, forged.merkle_root), &forged).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_verify_offline_report() {
+        use crate::ots::Op;
+
+        let header = parse_header(BLOCK1_HEADER, 0).unwrap();
+        let root = header.merkle_root.to_vec();
+        let attested = |attestation: Attestation, msg: &[u8]| Step {
+            data: StepData::Attestation(attestation),
+            output: msg.to_vec(),
+            next: vec![],
+        };
+        // Another block, committed to by the hash of the merkle root
+        let hashed = Op::Sha256.execute(&root);
+        let other = Step {
+            data: StepData::Op(Op::Sha256),
+            output: hashed.clone(),
+            next: vec![attested(Attestation::Bitcoin { height: 2 }, &hashed)],
+        };
+        let first_step = Step {
+            data: StepData::Fork,
+            output: root.clone(),
+            next: vec![
+                attested(Attestation::Bitcoin { height: 1 }, &root),
+                attested(Attestation::Litecoin { height: 1 }, &root),
+                other,
+            ],
+        };
+        let ots =
+            DetachedTimestampFile::new(Digest::sha256(header.merkle_root), &first_step).unwrap();
+
+        let mut report = vec![];
+        verify_offline(&ots, &header, &mut report).await.unwrap();
+        let statuses: Vec<_> = report.iter().map(|entry| entry.status).collect();
+        assert_eq!(statuses, ["verified", "skipped", "skipped"]);
+        assert_eq!(report[0].block_time, Some(header.time));
+        assert!(report[1].block_time.is_none() && report[2].block_time.is_none());
     }
 }
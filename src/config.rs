@@ -0,0 +1,578 @@
+use crate::error::{Error, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+
+/// Environment variable overriding the location of the configuration file
+pub const CONFIG_ENV: &str = "OTS_CONFIG";
+
+/// Client configuration, read from a TOML file
+///
+/// ```toml
+/// [calendars]
+/// allow = ["*.opentimestamps.org"]
+/// deny = ["a.pool.eternitywall.com"]
+///
+/// [calendars.batch]
+/// "calendar.example.org" = 100
+///
+/// [cache]
+/// capacity = 1000
+///
+/// [electrum]
+/// validate_certificates = true
+/// cross_check = true
+///
+/// [dns]
+/// resolver = "9.9.9.9:53"
+///
+/// [dns.pins]
+/// "a.pool.opentimestamps.org" = ["203.0.113.10"]
+///
+/// [profiles.tor-private]
+/// use_tor = true
+/// calendars = ["https://a.pool.opentimestamps.org"]
+/// timeout = 120
+///
+/// [profiles.multi.chains]
+/// bitcoin = ["https://a.pool.opentimestamps.org"]
+/// litecoin = ["https://ltc.calendar.example.org"]
+///
+/// [state]
+/// path = "/var/lib/ots/state.db"
+///
+/// [hooks]
+/// webhook = "https://alerts.example.org/ots"
+/// command = "notify-send \"$OTS_PROOF confirmed in block $OTS_BLOCK_HEIGHT\""
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Which calendar hosts may be contacted
+    pub calendars: CalendarPolicy,
+    /// How calendar hostnames are resolved
+    pub dns: DnsConfig,
+    /// Block header cache of the verifier
+    pub cache: CacheConfig,
+    /// Connection to the Electrum servers of the verifier
+    pub electrum: ElectrumConfig,
+    /// Named setups, selected with `--profile`
+    pub profiles: BTreeMap<String, Profile>,
+    /// Database recording every stamp
+    pub state: StateConfig,
+    /// Notifications fired when a proof is confirmed
+    pub hooks: HooksConfig,
+}
+
+/// A named setup selected with `--profile`
+///
+/// Every setting is a default that the matching command-line option
+/// overrides.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    /// Calendar server URLs to stamp with
+    pub calendars: Vec<String>,
+    /// Calendars to stamp with, grouped by the chain they anchor to
+    ///
+    /// Stamping submits to every group, so the proof gets a separate fork
+    /// for each chain. Within a group, calendars are tried in order until
+    /// one accepts the digest. Takes precedence over `calendars`.
+    pub chains: BTreeMap<String, Vec<String>>,
+    /// Server of the verification backend: an Electrum server, Esplora API
+    /// or Bitcoin Core RPC URL, depending on the build; several Electrum
+    /// servers or Esplora APIs separated by commas are tried in order
+    pub backend: Option<String>,
+    /// Server of the Litecoin verification backend: an Electrum-LTC server
+    /// (`tcp://` or `ssl://`) or Esplora-compatible API
+    pub litecoin_backend: Option<String>,
+    /// Route calendar and verifier traffic through Tor
+    pub use_tor: bool,
+    /// Tor SOCKS5 proxy address
+    pub tor_proxy: Option<String>,
+    /// Use a separate Tor circuit for every calendar request
+    pub tor_isolate: bool,
+    /// Timeout in seconds for calendar requests
+    pub timeout: Option<u64>,
+    /// Delay each calendar submission by a random 0..N seconds
+    pub jitter: Option<u64>,
+}
+
+impl Config {
+    /// Load the configuration file
+    ///
+    /// `path` is used if given, then the file named by `OTS_CONFIG`,
+    /// otherwise `$XDG_CONFIG_HOME/ots/config.toml` (or
+    /// `~/.config/ots/config.toml`). A missing default file yields the
+    /// default configuration.
+    ///
+    /// # Errors
+    /// Returns error if the file cannot be read or parsed, or if an
+    /// explicitly named file does not exist
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        match Self::source(path) {
+            Some(path) => Self::from_file(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// The file `load` reads, `None` if it uses the default configuration
+    #[must_use]
+    pub fn source(path: Option<&Path>) -> Option<PathBuf> {
+        if let Some(path) = path {
+            return Some(path.to_path_buf());
+        }
+        if let Some(path) = std::env::var_os(CONFIG_ENV) {
+            return Some(PathBuf::from(path));
+        }
+        default_path().filter(|path| path.exists())
+    }
+
+    /// Load the configuration from a specific file
+    ///
+    /// # Errors
+    /// Returns error if the file cannot be read or parsed
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| Error::Config(format!("{}: {e}", path.display())))?;
+        Self::parse(&contents).map_err(|e| Error::Config(format!("{}: {e}", path.display())))
+    }
+
+    /// Look up a named profile
+    ///
+    /// # Errors
+    /// Returns `Error::Config` if there is no such profile
+    pub fn profile(&self, name: &str) -> Result<&Profile> {
+        self.profiles.get(name).ok_or_else(|| Error::Config(format!("Unknown profile: {name}")))
+    }
+
+    /// Parse configuration from TOML text
+    ///
+    /// # Errors
+    /// Returns error if the text is not a valid configuration
+    pub fn parse(contents: &str) -> Result<Self> {
+        toml::from_str(contents).map_err(|e| Error::Config(e.message().to_string()))
+    }
+}
+
+/// Default location of the configuration file
+fn default_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("ots").join("config.toml"))
+}
+
+/// Read a list of calendar URLs from a file
+///
+/// The file holds one URL per line. Blank lines are skipped, and `#` starts
+/// a comment running to the end of the line.
+///
+/// # Errors
+/// Returns error if the file cannot be read or a line is not a URL with a host
+pub fn read_calendars_file(path: &Path) -> Result<Vec<String>> {
+    let contents = std::fs::read_to_string(path)?;
+    parse_calendars_list(&contents).map_err(|e| match e {
+        Error::Config(message) => Error::Config(format!("{} {message}", path.display())),
+        e => e,
+    })
+}
+
+/// Parse a list of calendar URLs, see `read_calendars_file`
+///
+/// # Errors
+/// Returns error if a line is not a URL with a host
+pub fn parse_calendars_list(contents: &str) -> Result<Vec<String>> {
+    let mut calendars = vec![];
+    for (number, line) in contents.lines().enumerate() {
+        let url = line.split('#').next().unwrap_or_default().trim();
+        if url.is_empty() {
+            continue;
+        }
+        let valid = reqwest::Url::parse(url).is_ok_and(|u| u.host_str().is_some());
+        if !valid {
+            return Err(Error::Config(format!("line {}: invalid calendar URL {url}", number + 1)));
+        }
+        calendars.push(url.to_string());
+    }
+    Ok(calendars)
+}
+
+/// Allowlist and denylist of calendar hosts
+///
+/// Entries are hostnames, optionally with a leading `*.` to match all
+/// subdomains. An empty allowlist allows every host that is not denied.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CalendarPolicy {
+    /// Hosts that may be contacted
+    pub allow: Vec<String>,
+    /// Hosts that must never be contacted
+    pub deny: Vec<String>,
+    /// Hosts running the non-standard batch endpoint (see
+    /// `CalendarClient::submit_many`), with their maximum batch size
+    pub batch: BTreeMap<String, usize>,
+    /// Calendars to query in place of dead or renamed ones, by URL
+    pub map: BTreeMap<String, String>,
+}
+
+impl CalendarPolicy {
+    /// Whether `host` may be contacted under this policy
+    #[must_use]
+    pub fn is_allowed(&self, host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        let matches = |pattern: &String| host_matches(pattern, &host);
+
+        (self.allow.is_empty() || self.allow.iter().any(matches)) && !self.deny.iter().any(matches)
+    }
+
+    /// Check that the calendar at `url` may be contacted
+    ///
+    /// # Errors
+    /// Returns `Error::BlockedCalendar` if the URL has no host or its host
+    /// is not allowed
+    pub fn check(&self, url: &str) -> Result<()> {
+        let host = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_string))
+            .ok_or_else(|| Error::BlockedCalendar(url.to_string()))?;
+
+        if self.is_allowed(&host) {
+            Ok(())
+        } else {
+            Err(Error::BlockedCalendar(host))
+        }
+    }
+
+    /// Narrow the policy to the hosts of the given calendar URLs
+    ///
+    /// Hosts this policy already blocks stay blocked.
+    ///
+    /// # Errors
+    /// Returns `Error::BlockedCalendar` if none of the calendars is allowed
+    pub fn restrict(&self, calendars: &[String]) -> Result<Self> {
+        let allow: Vec<String> = calendars
+            .iter()
+            .filter(|url| self.check(url).is_ok())
+            .filter_map(|url| reqwest::Url::parse(url).ok()?.host_str().map(str::to_string))
+            .collect();
+        if allow.is_empty() {
+            return Err(Error::BlockedCalendar(calendars.join(", ")));
+        }
+        Ok(Self {
+            allow,
+            deny: self.deny.clone(),
+            batch: self.batch.clone(),
+            map: self.map.clone(),
+        })
+    }
+
+    /// Calendar to query in place of the one at `url`, according to `map`
+    ///
+    /// A trailing `/` on either URL is ignored; unmapped URLs are returned as is.
+    #[must_use]
+    pub fn mirror<'a>(&'a self, url: &'a str) -> &'a str {
+        let wanted = url.trim_end_matches('/');
+        self.map
+            .iter()
+            .find(|(old, _)| old.trim_end_matches('/') == wanted)
+            .map_or(url, |(_, new)| new.trim_end_matches('/'))
+    }
+
+    /// Configured maximum batch size of the calendar at `url`, if any
+    #[must_use]
+    pub fn batch_limit(&self, url: &str) -> Option<usize> {
+        let host = reqwest::Url::parse(url).ok()?.host_str()?.to_ascii_lowercase();
+        self.batch.iter().find(|(pattern, _)| host_matches(pattern, &host)).map(|(_, &limit)| limit)
+    }
+}
+
+/// Name resolution settings for calendar hosts
+///
+/// Pinned hosts are never looked up. All other hosts are resolved through
+/// `resolver` if set, and through the system resolver otherwise.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DnsConfig {
+    /// Nameserver to send DNS queries to instead of the system resolver
+    pub resolver: Option<SocketAddr>,
+    /// Fixed addresses for specific hostnames
+    pub pins: BTreeMap<String, Vec<IpAddr>>,
+}
+
+/// Settings of the in-memory block header cache
+///
+/// Headers of blocks younger than `buried_after_secs` could still be
+/// reorganized away and expire after `ttl_secs`; older headers are kept until
+/// evicted as least recently used once `capacity` is reached.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Maximum number of cached headers
+    pub capacity: usize,
+    /// Lifetime in seconds of headers near the chain tip
+    pub ttl_secs: u64,
+    /// Age in seconds after which a block is considered deeply buried
+    pub buried_after_secs: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self { capacity: 1000, ttl_secs: 600, buried_after_secs: 24 * 60 * 60 }
+    }
+}
+
+/// Settings of the Electrum verification backend
+///
+/// Certificates of `ssl://` servers are checked against the bundled web
+/// roots unless `validate_certificates` is turned off, e.g. for a personal
+/// server with a self-signed certificate.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ElectrumConfig {
+    /// Check the certificate and hostname of `ssl://` servers
+    pub validate_certificates: bool,
+    /// Ask a second server for every header and fail if the answers differ
+    pub cross_check: bool,
+}
+
+impl Default for ElectrumConfig {
+    fn default() -> Self {
+        Self { validate_certificates: true, cross_check: false }
+    }
+}
+
+/// Settings of the database recording every stamp
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StateConfig {
+    /// Record stamps at all
+    pub enabled: bool,
+    /// Location of the database, see `state::default_path` for the default
+    pub path: Option<PathBuf>,
+}
+
+impl Default for StateConfig {
+    fn default() -> Self {
+        Self { enabled: true, path: None }
+    }
+}
+
+impl StateConfig {
+    /// Location of the database, if recording is enabled and a location is known
+    #[must_use]
+    pub fn path(&self) -> Option<PathBuf> {
+        if !self.enabled {
+            return None;
+        }
+        self.path.clone().or_else(crate::state::default_path)
+    }
+}
+
+/// Notifications fired when `upgrade` finds a proof has become confirmed
+///
+/// See `hooks::HookPayload` for what the webhook and command receive.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HooksConfig {
+    /// URL to POST a JSON description of the proof to
+    pub webhook: Option<String>,
+    /// Shell command to run, with the proof described in `OTS_*` variables
+    pub command: Option<String>,
+}
+
+/// Match a lowercase host against an allow/deny entry
+fn host_matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    pattern.strip_prefix("*.").map_or(host == pattern, |suffix| {
+        host.strip_suffix(suffix).is_some_and(|sub| sub.ends_with('.'))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::parse(
+            r#"
+            [calendars]
+            allow = ["*.opentimestamps.org"]
+            deny = ["b.pool.opentimestamps.org"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.calendars.allow, vec!["*.opentimestamps.org"]);
+        assert_eq!(config.calendars.deny, vec!["b.pool.opentimestamps.org"]);
+
+        assert!(Config::parse("").unwrap().calendars.allow.is_empty());
+        assert!(Config::parse("").unwrap().dns.resolver.is_none());
+        assert_eq!(Config::parse("[cache]\ncapacity = 5").unwrap().cache.capacity, 5);
+        assert_eq!(Config::parse("").unwrap().cache.ttl_secs, 600);
+        assert!(Config::parse("").unwrap().state.enabled);
+        assert!(Config::parse("").unwrap().electrum.validate_certificates);
+        assert!(Config::parse("[electrum]\ncross_check = true").unwrap().electrum.cross_check);
+        assert!(Config::parse("").unwrap().hooks.webhook.is_none());
+        assert_eq!(
+            Config::parse("[hooks]\ncommand = \"true\"").unwrap().hooks.command.as_deref(),
+            Some("true")
+        );
+        assert!(Config::parse("[state]\nenabled = false").unwrap().state.path().is_none());
+        assert!(Config::parse("[calendars]\nbogus = 1").is_err());
+    }
+
+    #[test]
+    fn test_batch_limit() {
+        let config = Config::parse(
+            r#"
+            [calendars.batch]
+            "*.example.org" = 100
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.calendars.batch_limit("https://cal.example.org"), Some(100));
+        assert_eq!(config.calendars.batch_limit("https://a.pool.opentimestamps.org"), None);
+        assert_eq!(config.calendars.batch_limit("not a url"), None);
+    }
+
+    #[test]
+    fn test_parse_dns_config() {
+        let config = Config::parse(
+            r#"
+            [dns]
+            resolver = "9.9.9.9:53"
+
+            [dns.pins]
+            "a.pool.opentimestamps.org" = ["203.0.113.10", "2001:db8::1"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.dns.resolver, Some("9.9.9.9:53".parse().unwrap()));
+        assert_eq!(config.dns.pins["a.pool.opentimestamps.org"].len(), 2);
+
+        assert!(Config::parse("[dns]\nresolver = \"not an address\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_profiles() {
+        let config = Config::parse(
+            r#"
+            [profiles.tor-private]
+            use_tor = true
+            tor_proxy = "127.0.0.1:9150"
+            calendars = ["https://a.pool.opentimestamps.org"]
+            timeout = 120
+
+            [profiles.multi.chains]
+            bitcoin = ["https://a.pool.opentimestamps.org", "https://b.pool.opentimestamps.org"]
+            litecoin = ["https://ltc.calendar.example.org"]
+            "#,
+        )
+        .unwrap();
+        let tor = config.profile("tor-private").unwrap();
+        assert!(tor.use_tor);
+        assert_eq!(tor.tor_proxy.as_deref(), Some("127.0.0.1:9150"));
+        assert_eq!(tor.timeout, Some(120));
+        assert!(tor.chains.is_empty());
+
+        let multi = config.profile("multi").unwrap();
+        assert_eq!(multi.chains.len(), 2);
+        assert_eq!(multi.chains["bitcoin"].len(), 2);
+        assert_eq!(multi.chains["litecoin"], vec!["https://ltc.calendar.example.org"]);
+        assert!(!multi.use_tor);
+
+        assert!(config.profile("missing").is_err());
+        assert!(Config::parse("[profiles.x]\nbogus = 1").is_err());
+        assert!(Config::parse("").unwrap().profiles.is_empty());
+    }
+
+    #[test]
+    fn test_policy_allow_and_deny() {
+        let policy = CalendarPolicy {
+            allow: vec!["*.opentimestamps.org".into()],
+            deny: vec!["b.pool.opentimestamps.org".into()],
+            ..CalendarPolicy::default()
+        };
+        assert!(policy.is_allowed("a.pool.opentimestamps.org"));
+        assert!(policy.is_allowed("A.Pool.OpenTimestamps.org"));
+        assert!(!policy.is_allowed("b.pool.opentimestamps.org"));
+        assert!(!policy.is_allowed("opentimestamps.org"));
+        assert!(!policy.is_allowed("evilopentimestamps.org"));
+        assert!(!policy.is_allowed("a.pool.eternitywall.com"));
+
+        assert!(CalendarPolicy::default().is_allowed("anything.example"));
+    }
+
+    #[test]
+    fn test_policy_check_url() {
+        let policy = CalendarPolicy {
+            deny: vec!["a.pool.eternitywall.com".into()],
+            ..CalendarPolicy::default()
+        };
+        assert!(policy.check("https://a.pool.opentimestamps.org").is_ok());
+        assert!(matches!(
+            policy.check("https://a.pool.eternitywall.com/digest"),
+            Err(Error::BlockedCalendar(host)) if host == "a.pool.eternitywall.com"
+        ));
+        assert!(policy.check("not a url").is_err());
+    }
+
+    #[test]
+    fn test_policy_restrict() {
+        let policy = CalendarPolicy {
+            deny: vec!["a.pool.eternitywall.com".into()],
+            ..CalendarPolicy::default()
+        };
+        let restricted = policy
+            .restrict(&[
+                "https://a.pool.opentimestamps.org".into(),
+                "https://a.pool.eternitywall.com".into(),
+            ])
+            .unwrap();
+        assert!(restricted.is_allowed("a.pool.opentimestamps.org"));
+        assert!(!restricted.is_allowed("b.pool.opentimestamps.org"));
+        assert!(!restricted.is_allowed("a.pool.eternitywall.com"));
+
+        assert!(policy.restrict(&["https://a.pool.eternitywall.com".into()]).is_err());
+    }
+
+    #[test]
+    fn test_policy_mirror() {
+        let config = Config::parse(
+            r#"
+            [calendars.map]
+            "https://old.example.org/" = "https://mirror.example.org/"
+            "#,
+        )
+        .unwrap();
+        let policy = &config.calendars;
+        assert_eq!(policy.mirror("https://old.example.org"), "https://mirror.example.org");
+        assert_eq!(policy.mirror("https://other.example.org/"), "https://other.example.org/");
+
+        let restricted = policy.restrict(&["https://mirror.example.org".into()]).unwrap();
+        assert_eq!(restricted.mirror("https://old.example.org/"), "https://mirror.example.org");
+    }
+
+    #[test]
+    fn test_parse_calendars_list() {
+        let calendars = parse_calendars_list(
+            "# Curated calendars\n\
+             https://a.pool.opentimestamps.org\n\
+             \n\
+             https://b.pool.opentimestamps.org  # backup\n",
+        )
+        .unwrap();
+        assert_eq!(
+            calendars,
+            vec!["https://a.pool.opentimestamps.org", "https://b.pool.opentimestamps.org"]
+        );
+
+        assert!(parse_calendars_list("").unwrap().is_empty());
+        assert!(matches!(
+            parse_calendars_list("https://a.pool.opentimestamps.org\nnot a url"),
+            Err(Error::Config(message)) if message.starts_with("line 2")
+        ));
+    }
+}
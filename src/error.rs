@@ -1,3 +1,4 @@
+use std::time::Duration;
 use thiserror::Error;
 
 /// Main error type for the `OpenTimestamps` client
@@ -20,6 +21,96 @@ pub enum Error {
     #[error("Calendar error: {0}")]
     Calendar(String),
 
+    /// Calendar failed with a server error and may recover later
+    #[error("Calendar {calendar} is unavailable (status {status})")]
+    CalendarUnavailable {
+        /// Calendar URL
+        calendar: String,
+        /// HTTP status code
+        status: u16,
+    },
+
+    /// Calendar is rate limiting this client
+    #[error(
+        "Calendar {calendar} is rate limiting requests{}",
+        .retry_after.map(|d| format!(" (retry after {}s)", d.as_secs())).unwrap_or_default()
+    )]
+    CalendarRateLimited {
+        /// Calendar URL
+        calendar: String,
+        /// How long the calendar asked to wait, from its `Retry-After` header
+        retry_after: Option<Duration>,
+    },
+
+    /// Calendar refused the request, so repeating it will not help
+    #[error("Calendar {calendar} rejected the request (status {status}): {body}")]
+    CalendarRejected {
+        /// Calendar URL
+        calendar: String,
+        /// HTTP status code
+        status: u16,
+        /// Start of the response body, usually the calendar's explanation
+        body: String,
+    },
+
+    /// Calendar returned data that is not a valid timestamp for the commitment
+    #[error("Invalid calendar response: {0}")]
+    InvalidCalendarResponse(String),
+
+    /// Calendar host is blocked by the configured allow/deny lists
+    #[error("Calendar host {0} is blocked by configuration")]
+    BlockedCalendar(String),
+
+    /// Configuration file is invalid
+    #[error("Configuration error: {0}")]
+    Config(String),
+
+    /// Hash-chained log is invalid
+    #[error("Log error: {0}")]
+    Log(String),
+
+    /// Proof bundle is invalid
+    #[error("Bundle error: {0}")]
+    Bundle(String),
+
+    /// State database could not be read or written
+    #[error("State database error: {0}")]
+    Database(#[from] rusqlite::Error),
+
+    /// State database holds invalid data
+    #[error("State error: {0}")]
+    State(String),
+
+    /// Notification hook failed
+    #[error("Hook error: {0}")]
+    Hook(String),
+
+    /// Output file exists and may not be overwritten
+    #[error("{} already exists (use --force to overwrite)", .0.display())]
+    OutputExists(std::path::PathBuf),
+
+    /// Some files of a batch run failed, see its summary
+    #[error("{failed} of {total} files failed")]
+    BatchFailed {
+        /// Number of files that failed
+        failed: usize,
+        /// Number of files processed
+        total: usize,
+    },
+
+    /// Some checks of `ots doctor` failed, see its report
+    #[error("{failed} of {total} checks failed")]
+    ChecksFailed {
+        /// Number of checks that failed
+        failed: usize,
+        /// Number of checks run
+        total: usize,
+    },
+
+    /// Signing key is missing or invalid
+    #[error("Signing error: {0}")]
+    Signing(String),
+
     /// Verification failed
     #[error("Verification failed: {0}")]
     Verification(String),
@@ -33,5 +124,30 @@ pub enum Error {
     PendingTimestamp,
 }
 
+impl Error {
+    /// Whether the failure is temporary, so the same request may succeed later
+    ///
+    /// Unavailable and rate-limiting calendars, timeouts and failed
+    /// connections are temporary; a calendar rejecting the request is not.
+    #[must_use]
+    #[allow(dead_code)]
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::CalendarUnavailable { .. } | Self::CalendarRateLimited { .. } => true,
+            Self::Http(e) => e.is_timeout() || e.is_connect(),
+            _ => false,
+        }
+    }
+
+    /// How long a rate-limiting calendar asked to wait before the next request
+    #[must_use]
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::CalendarRateLimited { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
 /// Result type alias for convenience
 pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,184 @@
+use crate::commands::info::collect_attestations;
+use crate::config::HooksConfig;
+use crate::error::{Error, Result};
+use crate::ots::{Attestation, DetachedTimestampFile};
+use log::{info, warn};
+use serde::Serialize;
+use std::path::Path;
+use std::time::Duration;
+
+/// Timeout for webhook requests
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Event name for a proof that gained a Bitcoin attestation
+pub const EVENT_CONFIRMED: &str = "confirmed";
+
+/// A block attestation in a hook payload
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct BlockInfo {
+    /// Chain of the block, e.g. `bitcoin`
+    pub chain: &'static str,
+    /// Height of the block
+    pub height: usize,
+}
+
+/// Description of a proof passed to hooks
+///
+/// Webhooks receive it as the JSON body; commands get the same fields as
+/// `OTS_*` environment variables.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct HookPayload {
+    /// What happened, e.g. `confirmed`
+    pub event: &'static str,
+    /// Path of the proof
+    pub proof: String,
+    /// Hash function of the stamped file
+    pub digest_type: String,
+    /// Hex-encoded digest of the stamped file
+    pub digest: String,
+    /// Every block attestation in the proof, lowest first
+    pub blocks: Vec<BlockInfo>,
+}
+
+impl HookPayload {
+    /// Describe `ots`, stored at `proof`
+    #[must_use]
+    pub fn new(event: &'static str, proof: &Path, ots: &DetachedTimestampFile) -> Self {
+        let mut attestations = vec![];
+        collect_attestations(&ots.timestamp.first_step(), &mut attestations);
+        let mut blocks: Vec<BlockInfo> = attestations
+            .iter()
+            .filter_map(|attestation| match *attestation {
+                Attestation::Bitcoin { height } => Some(BlockInfo { chain: "bitcoin", height }),
+                Attestation::Litecoin { height } => Some(BlockInfo { chain: "litecoin", height }),
+                _ => None,
+            })
+            .collect();
+        blocks.sort_by_key(|block| block.height);
+
+        Self {
+            event,
+            proof: proof.display().to_string(),
+            digest_type: ots.digest_type().to_string(),
+            digest: hex::encode(ots.timestamp.start_digest()),
+            blocks,
+        }
+    }
+
+    /// Environment variables describing the payload for hook commands
+    ///
+    /// `OTS_BLOCK_HEIGHT` is the lowest Bitcoin block attesting the proof.
+    #[must_use]
+    pub fn env(&self) -> Vec<(&'static str, String)> {
+        let mut env = vec![
+            ("OTS_EVENT", self.event.to_string()),
+            ("OTS_PROOF", self.proof.clone()),
+            ("OTS_DIGEST_TYPE", self.digest_type.clone()),
+            ("OTS_DIGEST", self.digest.clone()),
+        ];
+        if let Some(block) = self.blocks.iter().find(|block| block.chain == "bitcoin") {
+            env.push(("OTS_BLOCK_HEIGHT", block.height.to_string()));
+        }
+        env
+    }
+}
+
+/// Run the configured hooks for an event
+///
+/// Hooks run after the proof has been saved, so a failing hook only
+/// produces a warning.
+pub async fn fire(hooks: &HooksConfig, payload: &HookPayload) {
+    if let Some(url) = &hooks.webhook {
+        match post_webhook(url, payload).await {
+            Ok(()) => info!("Webhook {url} notified"),
+            Err(e) => warn!("Webhook {url} failed: {e}"),
+        }
+    }
+    if let Some(command) = &hooks.command {
+        match run_command(command, payload).await {
+            Ok(()) => info!("Hook command succeeded"),
+            Err(e) => warn!("Hook command failed: {e}"),
+        }
+    }
+}
+
+/// POST the payload as JSON to a webhook
+async fn post_webhook(url: &str, payload: &HookPayload) -> Result<()> {
+    let client = reqwest::Client::builder().timeout(WEBHOOK_TIMEOUT).build()?;
+    let body = serde_json::to_vec(payload).map_err(|e| Error::Hook(e.to_string()))?;
+    let response = client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(Error::Hook(format!("{url} returned {}", response.status())));
+    }
+    Ok(())
+}
+
+/// Run a hook command through the shell, describing the payload in its environment
+async fn run_command(command: &str, payload: &HookPayload) -> Result<()> {
+    #[cfg(windows)]
+    let mut cmd = {
+        let mut cmd = tokio::process::Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd
+    };
+    #[cfg(not(windows))]
+    let mut cmd = {
+        let mut cmd = tokio::process::Command::new("sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    };
+
+    let status = cmd.envs(payload.env()).status().await?;
+    if !status.success() {
+        return Err(Error::Hook(format!("`{command}` exited with {status}")));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ots::{Digest, Step, StepData, Timestamp};
+
+    fn attestation(attestation: Attestation) -> Step {
+        Step { data: StepData::Attestation(attestation), output: vec![0xab; 32], next: vec![] }
+    }
+
+    #[test]
+    fn test_payload() {
+        let ots = DetachedTimestampFile {
+            timestamp: Timestamp::new(
+                Digest::sha256([0xab; 32]),
+                &Step {
+                    data: StepData::Fork,
+                    output: vec![0xab; 32],
+                    next: vec![
+                        attestation(Attestation::Pending { uri: "https://example.com".into() }),
+                        attestation(Attestation::Bitcoin { height: 900 }),
+                        attestation(Attestation::Litecoin { height: 800 }),
+                    ],
+                },
+            )
+            .unwrap(),
+        };
+
+        let payload = HookPayload::new(EVENT_CONFIRMED, Path::new("file.txt.ots"), &ots);
+        assert_eq!(
+            payload.blocks,
+            vec![
+                BlockInfo { chain: "litecoin", height: 800 },
+                BlockInfo { chain: "bitcoin", height: 900 },
+            ]
+        );
+
+        let env = payload.env();
+        assert!(env.contains(&("OTS_PROOF", "file.txt.ots".to_string())));
+        assert!(env.contains(&("OTS_DIGEST", hex::encode([0xab; 32]))));
+        assert!(env.contains(&("OTS_BLOCK_HEIGHT", "900".to_string())));
+    }
+}
@@ -0,0 +1,108 @@
+//! Journal of the files a batch stamp has completed
+//!
+//! A batch appends every file to the journal as soon as its proof is saved,
+//! and removes the journal once every file succeeded. If the batch is
+//! interrupted, `stamp --resume` skips the files the journal lists instead
+//! of submitting them again.
+
+use crate::error::Result;
+use crate::state;
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// Journal of the batch stamp run from the current directory
+pub const JOURNAL_FILE: &str = ".ots-stamp-journal";
+
+/// Files a batch stamp has completed, one absolute path per line
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    done: HashSet<PathBuf>,
+}
+
+impl Journal {
+    /// Start a new journal at `path`, forgetting the files of an earlier one
+    ///
+    /// # Errors
+    /// Returns error if the journal cannot be created
+    pub fn start(path: &Path) -> Result<Self> {
+        std::fs::File::create(path)?;
+        Ok(Self { path: path.to_path_buf(), done: HashSet::new() })
+    }
+
+    /// Continue the journal at `path`, or start one if there is none
+    ///
+    /// # Errors
+    /// Returns error if the journal exists but cannot be read
+    pub fn resume(path: &Path) -> Result<Self> {
+        let file = match std::fs::File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Self::start(path),
+            Err(e) => return Err(e.into()),
+        };
+        let mut done = HashSet::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if !line.is_empty() {
+                done.insert(PathBuf::from(line));
+            }
+        }
+        Ok(Self { path: path.to_path_buf(), done })
+    }
+
+    /// Whether an earlier run already stamped `file`
+    #[must_use]
+    pub fn is_done(&self, file: &Path) -> bool {
+        self.done.contains(&state::absolute(file))
+    }
+
+    /// Record that `file` was stamped
+    ///
+    /// # Errors
+    /// Returns error if the journal cannot be written
+    pub fn record(&self, file: &Path) -> Result<()> {
+        let mut journal = OpenOptions::new().append(true).create(true).open(&self.path)?;
+        writeln!(journal, "{}", state::absolute(file).display())?;
+        Ok(())
+    }
+
+    /// Remove the journal once the batch is complete
+    ///
+    /// # Errors
+    /// Returns error if the journal cannot be removed
+    pub fn finish(self) -> Result<()> {
+        match std::fs::remove_file(&self.path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resume() {
+        let dir = std::env::temp_dir().join(format!("ots-journal-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(JOURNAL_FILE);
+
+        let journal = Journal::start(&path).unwrap();
+        journal.record(&dir.join("a.txt")).unwrap();
+        assert!(!journal.is_done(&dir.join("a.txt")));
+
+        let resumed = Journal::resume(&path).unwrap();
+        assert!(resumed.is_done(&dir.join("a.txt")));
+        assert!(!resumed.is_done(&dir.join("b.txt")));
+        resumed.finish().unwrap();
+        assert!(!path.exists());
+
+        // Starting again forgets what was done
+        Journal::start(&path).unwrap().record(&dir.join("a.txt")).unwrap();
+        assert!(!Journal::start(&path).unwrap().is_done(&dir.join("a.txt")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
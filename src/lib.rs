@@ -2,9 +2,21 @@
 
 pub mod calendar;
 pub mod commands;
+pub mod config;
 pub mod error;
+pub mod hooks;
+pub mod journal;
+pub mod merkle;
 pub mod ots;
+pub mod output;
+pub mod progress;
+pub mod report;
+pub mod state;
+pub mod summary;
+pub mod time_style;
+pub mod verbosity;
 pub mod verifier;
 
-pub use calendar::{CalendarClient, DEFAULT_CALENDARS};
+pub use calendar::{CalendarClient, TorConfig, DEFAULT_CALENDARS};
 pub use error::{Error, Result};
+pub use verifier::{verify_proof, VerificationResult};
@@ -1,41 +1,607 @@
 #![allow(clippy::multiple_crate_versions)]
 
 use clap::Parser;
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
 
 mod calendar;
 mod cli;
 mod commands;
+mod config;
 mod error;
+mod hooks;
+mod journal;
+mod merkle;
 mod ots;
+mod output;
+mod progress;
+mod report;
+mod state;
+mod summary;
+mod time_style;
+mod verbosity;
 mod verifier;
 
-use cli::{Cli, Command};
+use calendar::{CalendarClient, TorConfig};
+use cli::{BundleCommand, Cli, Command, LogCommand};
+use config::{Config, Profile};
+use output::Overwrite;
+use summary::Summary;
+use verbosity::Verbosity;
+
+/// Timeout in seconds for calendar requests
+const DEFAULT_TIMEOUT: u64 = 30;
+
+/// Timeout in seconds for calendar requests made while upgrading
+const UPGRADE_TIMEOUT: u64 = 30;
 
 #[tokio::main]
-async fn main() -> error::Result<()> {
+async fn main() -> ExitCode {
     let cli = Cli::parse();
 
-    // Initialize logger based on verbose flag
-    if cli.verbose {
-        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("debug")).init();
-    } else {
-        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+    // Initialize logger based on the -q and -v flags
+    let verbosity = Verbosity::from_flags(cli.quiet, cli.verbose);
+    env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or(verbosity.log_filter()),
+    )
+    .init();
+    verbosity.set_global();
+    cli.time_style.set_global();
+
+    match run(cli, verbosity).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprint!("{}", report::render(&e, verbosity >= Verbosity::Verbose));
+            ExitCode::FAILURE
+        }
     }
+}
+
+/// Run the command given on the command line
+async fn run(cli: Cli, verbosity: Verbosity) -> error::Result<()> {
+    let (config, config_error) = match Config::load(cli.config.as_deref()) {
+        Ok(config) => (config, None),
+        // doctor reports an invalid configuration instead of stopping at it
+        Err(e) if matches!(cli.command, Command::Doctor { .. }) => (Config::default(), Some(e)),
+        Err(e) => return Err(e),
+    };
+    let profile = match &cli.profile {
+        Some(name) if config_error.is_none() => config.profile(name)?.clone(),
+        _ => Profile::default(),
+    };
+    let tor = (cli.use_tor || profile.use_tor).then(|| TorConfig {
+        proxy: cli
+            .tor_proxy
+            .clone()
+            .or_else(|| profile.tor_proxy.clone())
+            .unwrap_or_else(|| calendar::DEFAULT_TOR_PROXY.to_string()),
+        isolate: cli.tor_isolate || profile.tor_isolate,
+    });
+    let backend = profile.backend.as_deref();
+    let litecoin_backend = profile.litecoin_backend.as_deref();
 
     match cli.command {
-        Command::Stamp { files, calendar, timeout } => {
-            commands::stamp::execute(&files, calendar, timeout).await?;
+        Command::Stamp {
+            mut files,
+            files_from,
+            include,
+            exclude,
+            digest,
+            algorithm,
+            nonce,
+            no_nonce,
+            nonce_per_calendar,
+            output,
+            calendar,
+            calendars_file,
+            timeout,
+            jitter,
+            batch_window,
+            min_responses,
+            attach,
+            compress,
+            merkle,
+            no_merkle,
+            audit,
+            metadata,
+            force,
+            json,
+            report,
+            emit_json_summary,
+            no_progress,
+            jobs,
+            resume,
+            buffer_size,
+            extra_digest,
+            #[cfg(feature = "mmap")]
+            mmap,
+        } => {
+            let mut client = calendar_client(timeout, tor, &config, &profile)?;
+            if let Some(secs) = jitter.or(profile.jitter) {
+                client = client.with_jitter(Duration::from_secs(secs));
+            }
+            if let Some(secs) = batch_window {
+                client = client.with_batch_window(Duration::from_secs(secs));
+            }
+            client = client.with_min_responses(min_responses);
+            let calendar = calendar_urls(calendar, calendars_file.as_deref())?;
+            let calendar_groups = calendar_groups(calendar, &profile);
+            let state = open_state(&config);
+            let overwrite = Overwrite::from_flags(force, cli.yes);
+            let format = commands::stamp::ProofFormat::from_flags(attach, compress);
+            let mut labels = ots::Metadata::default();
+            for (key, value) in &metadata {
+                labels.insert(key, value)?;
+            }
+            let labels = (!labels.is_empty()).then_some(&labels);
+            if let Some(list) = files_from {
+                files.extend(commands::stamp::read_file_list(&list)?);
+            }
+            let mut summary = start_summary("stamp", json, report.as_deref());
+            if files.iter().any(|file| file.is_dir()) {
+                summary = summary.keep_going();
+            }
+            let files = commands::stamp::expand_paths(&files, &include, &exclude)?;
+            let mut files = commands::stamp::targets(files, format, output.as_deref())?;
+            // Batches keep a journal of finished files, so an interrupted run can resume
+            let journal_path = Path::new(journal::JOURNAL_FILE);
+            let journal = match files.len() {
+                0 | 1 => None,
+                _ if resume => Some(journal::Journal::resume(journal_path)?),
+                _ => Some(journal::Journal::start(journal_path)?),
+            };
+            if let Some(journal) = &journal {
+                files = commands::stamp::skip_journaled(files, journal);
+            }
+            // Batches leave existing proofs alone, so they can simply be run again
+            if files.len() > 1 && !force {
+                files = commands::stamp::skip_stamped(files);
+            }
+            // Bulk runs aggregate into a Merkle tree unless told not to, or
+            // each calendar must get commitments of its own
+            let auto_merkle = !no_merkle
+                && !nonce_per_calendar
+                && files.len() >= commands::stamp::AUTO_MERKLE_FILES;
+            let bars = !no_progress && digest.is_none();
+            let hashes = files.len() * (1 + extra_digest.len());
+            let progress = progress::BatchProgress::new(hashes, bars);
+            let read_mode = commands::stamp::ReadMode::Buffered(buffer_size);
+            #[cfg(feature = "mmap")]
+            let read_mode = if mmap { commands::stamp::ReadMode::Mapped } else { read_mode };
+            let options = commands::stamp::StampOptions {
+                algorithm,
+                extra_algorithms: &extra_digest,
+                read_mode,
+                nonce: commands::stamp::Nonce::from_flags(nonce, no_nonce, nonce_per_calendar),
+                format,
+                metadata: labels,
+                overwrite,
+                progress: &progress,
+                jobs,
+                journal: journal.as_ref(),
+            };
+            if let Some(digest) = digest {
+                let name = std::path::PathBuf::from(hex::encode(digest));
+                let mut targets = commands::stamp::targets(vec![name], format, output.as_deref())?;
+                let output = targets.remove(0).proof;
+                let started = Instant::now();
+                let result = commands::stamp::execute_digest(
+                    digest,
+                    &output,
+                    &calendar_groups,
+                    &client,
+                    &options,
+                )
+                .await
+                .and_then(|()| write_json_summary(emit_json_summary, &output));
+                summary.record(&output, started, result)?;
+            } else if files.is_empty() {
+                verbosity::progress!("stamp: every file is already stamped");
+            } else if merkle || auto_merkle || !extra_digest.is_empty() {
+                // One submission covers every file, so they all share its outcome,
+                // unless a file failed on its own
+                let started = Instant::now();
+                let results = commands::stamp::execute_merkle(
+                    &files,
+                    &calendar_groups,
+                    &client,
+                    &options,
+                    audit.as_deref(),
+                    state.as_ref(),
+                )
+                .await;
+                match results {
+                    Ok(results) => {
+                        for (file, result) in files.iter().zip(results) {
+                            let result = result
+                                .and_then(|()| write_json_summary(emit_json_summary, &file.proof));
+                            summary.record(&file.file, started, result)?;
+                        }
+                    }
+                    Err(e) => summary.record_all(&files, started, Err(e))?,
+                }
+            } else {
+                // Commitments are submitted together, so files share the run's duration
+                let started = Instant::now();
+                let results = commands::stamp::execute(
+                    &files,
+                    &calendar_groups,
+                    &client,
+                    &options,
+                    state.as_ref(),
+                )
+                .await;
+                for (file, result) in files.iter().zip(results) {
+                    let result =
+                        result.and_then(|()| write_json_summary(emit_json_summary, &file.proof));
+                    summary.record(&file.file, started, result)?;
+                }
+            }
+            // Clear the bars before the summary is printed
+            drop(progress);
+            summary.finish(json, report.as_deref())?;
+            if let Some(journal) = journal {
+                journal.finish()?;
+            }
+        }
+        Command::Verify {
+            files,
+            target,
+            digest,
+            signer,
+            block_header,
+            headers_file,
+            headers_start,
+            strict,
+            upgrade,
+            save,
+            json,
+            report,
+        } => {
+            let files = commands::verify::expand_globs(files);
+            if (target.is_some() || digest.is_some()) && files.len() > 1 {
+                return Err(error::Error::Verification(
+                    "--target and --digest can only be used with a single proof".into(),
+                ));
+            }
+            let mut verifiers =
+                verifier::ChainVerifiers::new(tor.as_ref(), &config, backend, litecoin_backend)?;
+            if let Some(path) = headers_file {
+                let headers =
+                    verifier::HeadersFileVerifier::new(&path)?.with_start_height(headers_start);
+                verifiers =
+                    verifiers.with_bitcoin(Box::new(headers), verifier::HEADERS_FILE_BACKEND_NAME);
+            }
+            let block_header =
+                block_header.map(|header| verifier::read_header(&header)).transpose()?;
+            let timeout = profile.timeout.unwrap_or(UPGRADE_TIMEOUT);
+            let client = upgrade
+                .then(|| calendar_client(Some(timeout), tor.clone(), &config, &profile))
+                .transpose()?;
+            let options = commands::verify::VerifyOptions {
+                target: target.as_deref(),
+                digest: digest.as_deref(),
+                signer: signer.as_deref(),
+                block_header: block_header.as_ref(),
+                upgrade: client.as_ref(),
+                save,
+                strict,
+            };
+            let state = open_state(&config);
+            let mut summary = start_summary("verify", json, report.as_deref());
+            if files.len() > 1 {
+                summary = summary.keep_going();
+            }
+            for file in &files {
+                let started = Instant::now();
+                let mut attestations = vec![];
+                let result =
+                    commands::verify::execute(file, &options, &verifiers, &mut attestations).await;
+                if let Some(state) = state.as_ref().filter(|_| result.is_ok()) {
+                    if !commands::is_stdio(file) {
+                        if let Err(e) = state.set_status(file, state::StampStatus::Verified) {
+                            log::warn!("Failed to record verification of {}: {e}", file.display());
+                        }
+                    }
+                }
+                summary.record_verification(file, started, result, attestations)?;
+            }
+            if files.len() > 1 && !json {
+                print!("{}", summary.table());
+            }
+            summary.finish(json, report.as_deref())?;
+        }
+        Command::Upgrade {
+            files,
+            dry_run,
+            readiness,
+            accept_unknown,
+            prune_pending,
+            output,
+            force,
+            calendars_file,
+            calendar_map,
+            json,
+            report,
+            merge_response,
+            uri,
+            emit_json_summary,
+        } => {
+            let timeout = profile.timeout.unwrap_or(UPGRADE_TIMEOUT);
+            let mut client = calendar_client(Some(timeout), tor, &config, &profile)?;
+            let mut policy = config.calendars.clone();
+            if let Some(path) = &calendars_file {
+                let calendars = config::read_calendars_file(path)?;
+                policy = policy.restrict(&calendars)?;
+            }
+            if calendars_file.is_some() || !calendar_map.is_empty() {
+                policy.map.extend(calendar_map);
+                client = client.with_policy(policy);
+            }
+            let state = open_state(&config).filter(|_| !dry_run);
+            let merge = match (merge_response, uri) {
+                (Some(path), Some(uri)) => Some((uri, std::fs::read(path)?)),
+                _ => None,
+            };
+            let outputs = commands::upgrade::outputs(&files, output.as_deref())?;
+            let options = commands::upgrade::UpgradeOptions {
+                dry_run,
+                accept_unknown,
+                prune_pending,
+                overwrite: Overwrite::from_flags(force, cli.yes),
+                hooks: &config.hooks,
+            };
+            if readiness.as_deref().is_some_and(commands::is_stdio) {
+                verbosity::progress_to_stderr();
+            }
+            let mut proofs_readiness = vec![];
+            let mut summary = start_summary("upgrade", json, report.as_deref());
+            for (file, output) in files.iter().zip(&outputs) {
+                let started = Instant::now();
+                let output = output.as_deref();
+                let result = match &merge {
+                    Some((uri, response)) => {
+                        commands::upgrade::execute_merge(file, output, uri, response, &options)
+                            .await
+                    }
+                    None => commands::upgrade::execute(file, output, &client, &options).await.map(
+                        |attestations| {
+                            proofs_readiness.push(commands::upgrade::ProofReadiness {
+                                file: file.display().to_string(),
+                                attestations,
+                            });
+                        },
+                    ),
+                };
+                let proof = output.unwrap_or(file);
+                let result = result.and_then(|()| {
+                    let emit = emit_json_summary && !dry_run && !commands::is_stdio(proof);
+                    write_json_summary(emit, proof)
+                });
+                if let Some(state) = state.as_ref().filter(|_| result.is_ok()) {
+                    if !commands::is_stdio(proof) {
+                        if let Err(e) = commands::status::refresh_proof(state, proof) {
+                            log::warn!("Failed to record upgrade of {}: {e}", proof.display());
+                        }
+                    }
+                }
+                summary.record(file, started, result)?;
+            }
+            if let Some(path) = &readiness {
+                commands::upgrade::write_readiness(path, &proofs_readiness)?;
+            }
+            summary.finish(json, report.as_deref())?;
+        }
+        Command::Status { status, file, refresh } => {
+            let path = config
+                .state
+                .path()
+                .ok_or_else(|| error::Error::State("State database is disabled".into()))?;
+            let state = state::StateStore::open(&path)?;
+            let status = status.as_deref().map(str::parse).transpose()?;
+            commands::status::execute(&state, status, file.as_deref(), refresh)?;
+        }
+        Command::Info { file, detailed, lenient } => {
+            let detailed = detailed || verbosity >= Verbosity::Verbose;
+            commands::info::execute(&file, detailed, lenient)?;
         }
-        Command::Verify { file, target } => {
-            commands::verify::execute(&file, target.as_deref()).await?;
+        Command::Convert { file, to, output, canonical, force } => {
+            let overwrite = Overwrite::from_flags(force, cli.yes);
+            commands::convert::execute(&file, to, output.as_deref(), overwrite, canonical)?;
         }
-        Command::Upgrade { file, dry_run } => {
-            commands::upgrade::execute(&file, dry_run).await?;
+        Command::Diff { old, new } => {
+            commands::diff::execute(&old, &new)?;
         }
-        Command::Info { file, detailed } => {
-            commands::info::execute(&file, detailed)?;
+        Command::Doctor { timeout } => {
+            let config_source = match config_error {
+                Some(e) => Err(e),
+                None => Ok(Config::source(cli.config.as_deref())),
+            };
+            let client = calendar_client(timeout, tor.clone(), &config, &profile);
+            let timeout = timeout.or(profile.timeout).unwrap_or(DEFAULT_TIMEOUT);
+            commands::doctor::execute(commands::doctor::Environment {
+                config_source,
+                config: &config,
+                calendar_groups: calendar_groups(None, &profile),
+                client,
+                tor: tor.as_ref(),
+                backend,
+                litecoin_backend,
+                timeout: Duration::from_secs(timeout),
+            })
+            .await?;
         }
+        Command::Stats { dir } => {
+            let verifier = verifier::default_verifier(tor.as_ref(), &config, backend)?;
+            commands::stats::execute(&dir, verifier.as_ref()).await?;
+        }
+        Command::Extract { file, output, bitcoin, pending, force } => {
+            let attestation = match (bitcoin, pending) {
+                (Some(height), _) => ots::Attestation::Bitcoin { height },
+                (None, uri) => ots::Attestation::Pending { uri: uri.unwrap_or_default() },
+            };
+            let overwrite = Overwrite::from_flags(force, cli.yes);
+            commands::extract::execute(&file, &attestation, &output, overwrite)?;
+        }
+        Command::Rolling { file, history, interval, calendar, calendars_file, timeout } => {
+            let client = calendar_client(timeout, tor, &config, &profile)?;
+            let interval = interval.map(Duration::from_secs);
+            let calendar = calendar_urls(calendar, calendars_file.as_deref())?;
+            let calendar_groups = calendar_groups(calendar, &profile);
+            commands::rolling::execute(&file, &history, interval, &calendar_groups, &client)
+                .await?;
+        }
+        Command::Reanchor { file, calendar, calendars_file, timeout } => {
+            let client = calendar_client(timeout, tor, &config, &profile)?;
+            let calendar = calendar_urls(calendar, calendars_file.as_deref())?;
+            let calendar_groups = calendar_groups(calendar, &profile);
+            commands::reanchor::execute(&file, &calendar_groups, &client).await?;
+        }
+        Command::Log { command } => match command {
+            LogCommand::Append { logfile, entries, stamp_every, calendar, timeout } => {
+                let client = calendar_client(timeout, tor, &config, &profile)?;
+                let calendar_groups = calendar_groups(calendar, &profile);
+                commands::log_chain::append(
+                    &logfile,
+                    &entries,
+                    stamp_every,
+                    &calendar_groups,
+                    &client,
+                )
+                .await?;
+            }
+            LogCommand::Stamp { logfile, calendar, timeout } => {
+                let client = calendar_client(timeout, tor, &config, &profile)?;
+                let calendar_groups = calendar_groups(calendar, &profile);
+                commands::log_chain::stamp(&logfile, &calendar_groups, &client).await?;
+            }
+            LogCommand::Verify { logfile, line } => {
+                let verifiers = verifier::ChainVerifiers::new(
+                    tor.as_ref(),
+                    &config,
+                    backend,
+                    litecoin_backend,
+                )?;
+                commands::log_chain::verify(&logfile, line, &verifiers).await?;
+            }
+        },
+        Command::Bundle { command } => match command {
+            BundleCommand::Create { bundle, proofs, force } => {
+                commands::bundle::create(&bundle, &proofs, Overwrite::from_flags(force, cli.yes))?;
+            }
+            BundleCommand::Extract { bundle, output, force } => {
+                commands::bundle::extract(&bundle, &output, Overwrite::from_flags(force, cli.yes))?;
+            }
+            BundleCommand::Verify { bundle, data } => {
+                let verifiers = verifier::ChainVerifiers::new(
+                    tor.as_ref(),
+                    &config,
+                    backend,
+                    litecoin_backend,
+                )?;
+                commands::bundle::verify(&bundle, data.as_deref(), &verifiers).await?;
+            }
+        },
+        #[cfg(feature = "api")]
+        Command::Api { listen, calendar, timeout } => {
+            let client = calendar_client(timeout, tor.clone(), &config, &profile)?;
+            let verifiers =
+                verifier::ChainVerifiers::new(tor.as_ref(), &config, backend, litecoin_backend)?;
+            let calendar_groups = calendar_groups(calendar, &profile);
+            commands::api::execute(listen, client, verifiers, calendar_groups).await?;
+        }
+        Command::GenVectors { output, force } => {
+            commands::gen_vectors::execute(&output, Overwrite::from_flags(force, cli.yes))?;
+        }
+        Command::Sign { file, key, force } => {
+            commands::sign::execute(&file, &key, Overwrite::from_flags(force, cli.yes))?;
+        }
+        Command::Unpack { file, force } => {
+            commands::unpack::execute(&file, Overwrite::from_flags(force, cli.yes))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Build a calendar client from the command-line network options and configuration
+///
+/// A timeout given on the command line wins over the profile's.
+fn calendar_client(
+    timeout: Option<u64>,
+    tor: Option<TorConfig>,
+    config: &Config,
+    profile: &Profile,
+) -> error::Result<CalendarClient> {
+    let timeout = timeout.or(profile.timeout).unwrap_or(DEFAULT_TIMEOUT);
+    let mut client = CalendarClient::new(Duration::from_secs(timeout))?
+        .with_policy(config.calendars.clone())
+        .with_dns(config.dns.clone())?;
+    if let Some(tor) = tor {
+        client = client.with_tor(tor)?;
+    }
+    Ok(client)
+}
+
+/// Calendar server URLs given on the command line, adding those listed in a file
+fn calendar_urls(
+    calendar: Option<Vec<String>>,
+    calendars_file: Option<&Path>,
+) -> error::Result<Option<Vec<String>>> {
+    let Some(path) = calendars_file else {
+        return Ok(calendar);
+    };
+    let listed = config::read_calendars_file(path)?;
+    if listed.is_empty() {
+        return Err(error::Error::Config(format!("{} lists no calendars", path.display())));
     }
+    let mut calendars = calendar.unwrap_or_default();
+    calendars.extend(listed);
+    Ok(Some(calendars))
+}
 
+/// Calendar server URLs to stamp with, one group per chain
+///
+/// Calendars from the command line form a single group. Otherwise the
+/// profile's chains give one group each, falling back to its calendars (or
+/// the defaults) as a single group.
+fn calendar_groups(calendar: Option<Vec<String>>, profile: &Profile) -> Vec<Vec<String>> {
+    match calendar {
+        Some(calendar) => vec![calendar],
+        None if !profile.chains.is_empty() => profile.chains.values().cloned().collect(),
+        None => vec![profile.calendars.clone()],
+    }
+}
+
+/// Start the end-of-run summary of a batch command
+///
+/// Outcomes are only collected if a JSON summary was requested; printing it
+/// to stdout moves progress messages to stderr.
+fn start_summary(command: &'static str, json: bool, report: Option<&Path>) -> Summary {
+    if json {
+        verbosity::progress_to_stderr();
+    }
+    Summary::new(command, json || report.is_some())
+}
+
+/// Write the JSON summary next to a proof if `--emit-json-summary` is set
+fn write_json_summary(enabled: bool, proof: &Path) -> error::Result<()> {
+    if enabled {
+        let path = commands::info::write_json_summary(proof)?;
+        log::info!("Wrote proof summary {}", path.display());
+    }
     Ok(())
 }
+
+/// Open the state database, warning instead of failing if it is unavailable
+fn open_state(config: &Config) -> Option<state::StateStore> {
+    let path = config.state.path()?;
+    match state::StateStore::open(&path) {
+        Ok(state) => Some(state),
+        Err(e) => {
+            log::warn!("Cannot open state database {}: {e}", path.display());
+            None
+        }
+    }
+}
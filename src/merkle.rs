@@ -0,0 +1,201 @@
+use crate::ots::{Op, Step, StepData};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Which side of the running hash a sibling is concatenated on
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    /// The sibling comes first: `SHA256(sibling || hash)`
+    Left,
+    /// The sibling comes second: `SHA256(hash || sibling)`
+    Right,
+}
+
+/// One step of an inclusion path from a leaf to the root
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PathNode {
+    /// Side the sibling is on
+    pub side: Side,
+    /// Hash of the sibling
+    pub sibling: [u8; 32],
+}
+
+/// Merkle tree over SHA256 digests, used to stamp many digests with one
+/// calendar submission
+///
+/// Inner nodes are `SHA256(left || right)`. A node without a sibling is
+/// carried up to the next level unchanged.
+#[derive(Clone, Debug)]
+pub struct MerkleTree {
+    /// Every level of the tree, from the leaves up to the single root
+    levels: Vec<Vec<[u8; 32]>>,
+}
+
+impl MerkleTree {
+    /// Build a tree over `leaves`
+    ///
+    /// Returns `None` if there are no leaves.
+    #[must_use]
+    pub fn new(leaves: Vec<[u8; 32]>) -> Option<Self> {
+        if leaves.is_empty() {
+            return None;
+        }
+        let mut levels = vec![leaves];
+        while levels[levels.len() - 1].len() > 1 {
+            let level = levels[levels.len() - 1]
+                .chunks(2)
+                .map(|pair| match *pair {
+                    [left, right] => hash_pair(&left, &right),
+                    [single] => single,
+                    _ => unreachable!("chunks(2) yields one or two nodes"),
+                })
+                .collect();
+            levels.push(level);
+        }
+        Some(Self { levels })
+    }
+
+    /// The root of the tree
+    #[must_use]
+    pub fn root(&self) -> [u8; 32] {
+        self.levels[self.levels.len() - 1][0]
+    }
+
+    /// The leaves of the tree, in the order they were given
+    #[must_use]
+    pub fn leaves(&self) -> &[[u8; 32]] {
+        &self.levels[0]
+    }
+
+    /// The inclusion path of the leaf at `index`, from the leaf up
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of range
+    #[must_use]
+    pub fn path(&self, mut index: usize) -> Vec<PathNode> {
+        assert!(index < self.leaves().len(), "leaf index out of range");
+        let mut path = vec![];
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = index ^ 1;
+            if sibling < level.len() {
+                let side = if sibling < index { Side::Left } else { Side::Right };
+                path.push(PathNode { side, sibling: level[sibling] });
+            }
+            index /= 2;
+        }
+        path
+    }
+}
+
+/// Hash two sibling nodes into their parent
+fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Compute the root reached by following `path` from `leaf`
+#[must_use]
+#[allow(dead_code)]
+pub fn fold_path(leaf: [u8; 32], path: &[PathNode]) -> [u8; 32] {
+    path.iter().fold(leaf, |hash, node| match node.side {
+        Side::Left => hash_pair(&node.sibling, &hash),
+        Side::Right => hash_pair(&hash, &node.sibling),
+    })
+}
+
+/// Express an inclusion path as timestamp steps
+///
+/// Returns the chain of ops leading from `leaf` to the root, continuing with
+/// `next`, which must start at the root.
+#[must_use]
+pub fn path_steps(leaf: [u8; 32], path: &[PathNode], next: Step) -> Step {
+    let mut ops = vec![];
+    let mut hash = leaf;
+    for node in path {
+        let op = match node.side {
+            Side::Left => Op::Prepend(node.sibling.to_vec()),
+            Side::Right => Op::Append(node.sibling.to_vec()),
+        };
+        let concatenated = op.execute(&hash);
+        hash = Sha256::digest(&concatenated).into();
+        ops.push((op, concatenated));
+        ops.push((Op::Sha256, hash.to_vec()));
+    }
+    ops.into_iter().rev().fold(next, |next, (op, output)| Step {
+        data: StepData::Op(op),
+        output,
+        next: vec![next],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ots::Attestation;
+
+    fn leaf(n: u8) -> [u8; 32] {
+        [n; 32]
+    }
+
+    #[test]
+    fn test_empty_tree() {
+        assert!(MerkleTree::new(vec![]).is_none());
+    }
+
+    #[test]
+    fn test_single_leaf() {
+        let tree = MerkleTree::new(vec![leaf(1)]).unwrap();
+        assert_eq!(tree.root(), leaf(1));
+        assert!(tree.path(0).is_empty());
+    }
+
+    #[test]
+    fn test_paths_reach_root() {
+        for count in 1..=9 {
+            let leaves: Vec<[u8; 32]> = (0..count).map(leaf).collect();
+            let tree = MerkleTree::new(leaves.clone()).unwrap();
+            for (i, l) in leaves.iter().enumerate() {
+                assert_eq!(fold_path(*l, &tree.path(i)), tree.root(), "{count} leaves, leaf {i}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_three_leaves() {
+        let tree = MerkleTree::new(vec![leaf(1), leaf(2), leaf(3)]).unwrap();
+        let left = hash_pair(&leaf(1), &leaf(2));
+        assert_eq!(tree.root(), hash_pair(&left, &leaf(3)));
+        assert_eq!(tree.path(2), vec![PathNode { side: Side::Left, sibling: left }]);
+        assert_eq!(
+            tree.path(1),
+            vec![
+                PathNode { side: Side::Left, sibling: leaf(1) },
+                PathNode { side: Side::Right, sibling: leaf(3) },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_path_steps() {
+        let tree = MerkleTree::new(vec![leaf(1), leaf(2), leaf(3)]).unwrap();
+        let root = tree.root();
+        let attestation = Step {
+            data: StepData::Attestation(Attestation::Bitcoin { height: 1 }),
+            output: root.to_vec(),
+            next: vec![],
+        };
+
+        let mut step = &path_steps(leaf(2), &tree.path(1), attestation);
+        let mut digest = leaf(2).to_vec();
+        while let StepData::Op(op) = &step.data {
+            digest = op.execute(&digest);
+            assert_eq!(digest, step.output);
+            step = &step.next[0];
+        }
+        assert_eq!(digest, root.to_vec());
+    }
+}
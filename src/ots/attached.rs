@@ -0,0 +1,132 @@
+//! Attached timestamp files
+//!
+//! An attached file bundles the timestamped data together with its proof, so
+//! that a single self-contained artifact can be archived or sent around.
+
+use std::io::{Read, Write};
+
+use super::error::{OtsError, Result};
+use super::ser::{Deserializer, DetachedTimestampFile, Serializer};
+
+/// Magic bytes that every attached timestamp file starts with
+///
+/// Deliberately different from `MAGIC`, so that attached files are never
+/// mistaken for detached proofs.
+pub const ATTACHED_MAGIC: &[u8] = b"\x00OpenTimestamps\x00\x00Attached\x00\x8d\x2f\x51\x6e";
+
+/// Structure representing data together with its timestamp proof
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AttachedTimestampFile {
+    /// The timestamped data
+    pub data: Vec<u8>,
+    /// The proof for the data
+    pub proof: DetachedTimestampFile,
+}
+
+impl AttachedTimestampFile {
+    /// Deserialize an attached timestamp file from a reader
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - The magic bytes are invalid
+    /// - The embedded proof is invalid
+    /// - The digest of the data does not match the proof
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self> {
+        let mut deser = Deserializer::new(reader);
+
+        let magic = deser.read_fixed_bytes(ATTACHED_MAGIC.len())?;
+        if magic != ATTACHED_MAGIC {
+            return Err(OtsError::BadMagic(magic));
+        }
+        let len = deser.read_uint()?;
+        let data = deser.read_fixed_bytes(len)?;
+        let proof = DetachedTimestampFile::from_reader(deser.into_inner())?;
+
+        let attached = Self { data, proof };
+        attached.check_digest()?;
+        Ok(attached)
+    }
+
+    /// Serialize the attached timestamp file into a writer
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any I/O operation fails
+    pub fn to_writer<W: Write>(&self, writer: W) -> Result<()> {
+        let mut ser = Serializer::new(writer);
+        ser.write_fixed_bytes(ATTACHED_MAGIC)?;
+        ser.write_bytes(&self.data)?;
+        self.proof.to_writer(ser.into_inner())
+    }
+
+    /// Check that the proof's start digest is the digest of the data
+    ///
+    /// # Errors
+    ///
+    /// Returns `OtsError::DigestMismatch` if the digests differ
+    pub fn check_digest(&self) -> Result<()> {
+        let digest = self.proof.digest_type().digest_reader(&self.data[..])?;
+        if &digest == self.proof.timestamp.start_digest() {
+            Ok(())
+        } else {
+            Err(OtsError::DigestMismatch)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ots::attestation::Attestation;
+    use crate::ots::digest::{Digest, DigestType};
+    use crate::ots::op::Op;
+    use crate::ots::timestamp::{Step, StepData, Timestamp};
+
+    fn attached(data: &[u8]) -> AttachedTimestampFile {
+        let digest = Op::Sha256.execute(data);
+        AttachedTimestampFile {
+            data: data.to_vec(),
+            proof: DetachedTimestampFile {
+                timestamp: Timestamp::new(
+                    Digest::new(DigestType::Sha256, digest.clone()).unwrap(),
+                    &Step {
+                        data: StepData::Attestation(Attestation::Bitcoin { height: 1 }),
+                        output: digest,
+                        next: vec![],
+                    },
+                )
+                .unwrap(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_attached_round_trip() {
+        let file = attached(b"hello world");
+        let mut buf = Vec::new();
+        file.to_writer(&mut buf).unwrap();
+        assert!(buf.starts_with(ATTACHED_MAGIC));
+
+        let read = AttachedTimestampFile::from_reader(&buf[..]).unwrap();
+        assert_eq!(read, file);
+    }
+
+    #[test]
+    fn test_attached_digest_mismatch() {
+        let mut file = attached(b"hello world");
+        file.data = b"tampered".to_vec();
+        let mut buf = Vec::new();
+        file.to_writer(&mut buf).unwrap();
+
+        let result = AttachedTimestampFile::from_reader(&buf[..]);
+        assert!(matches!(result, Err(OtsError::DigestMismatch)));
+    }
+
+    #[test]
+    fn test_detached_is_not_attached() {
+        let mut buf = Vec::new();
+        attached(b"data").proof.to_writer(&mut buf).unwrap();
+        assert!(matches!(AttachedTimestampFile::from_reader(&buf[..]), Err(OtsError::BadMagic(_))));
+    }
+}
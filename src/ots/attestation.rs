@@ -2,11 +2,12 @@
 //!
 //! An attestation is a claim that some data existed at some time.
 
+use std::cmp::Ordering;
 use std::fmt;
 use std::io::{Read, Write};
 
-use super::error::{OtsError, Result, MAX_URI_LEN};
-use super::ser::{Deserializer, Serializer};
+use super::error::{OtsError, Result};
+use super::ser::{varint_len, Deserializer, Serializer};
 
 /// Size in bytes of the tag identifying the attestation type
 pub const TAG_SIZE: usize = 8;
@@ -14,6 +15,9 @@ pub const TAG_SIZE: usize = 8;
 /// Tag indicating a Bitcoin attestation
 pub const BITCOIN_TAG: &[u8] = b"\x05\x88\x96\x0d\x73\xd7\x19\x01";
 
+/// Tag indicating a Litecoin attestation
+pub const LITECOIN_TAG: &[u8] = b"\x06\x86\x9a\x0d\x73\xd7\x1b\x45";
+
 /// Tag indicating a pending attestation
 pub const PENDING_TAG: &[u8] = b"\x83\xdf\xe3\x0d\x2e\xf9\x0c\x8e";
 
@@ -26,6 +30,12 @@ pub enum Attestation {
         /// The Bitcoin block height
         height: usize,
     },
+    /// An attestation from a Litecoin blockheader.
+    /// This consists of a blockheight and nothing more.
+    Litecoin {
+        /// The Litecoin block height
+        height: usize,
+    },
     /// An attestation from some server.
     /// The server should be expected to keep anything it attests to forever.
     Pending {
@@ -42,24 +52,122 @@ pub enum Attestation {
 }
 
 impl Attestation {
+    /// Create an attestation of a type this client does not interpret
+    ///
+    /// Such attestations are passed through untouched, so custom schemes can
+    /// ride along in ordinary proofs.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - The tag is not exactly `TAG_SIZE` bytes
+    /// - The tag belongs to a known attestation type, which would not
+    ///   deserialize back into `Unknown`
+    #[allow(dead_code)]
+    pub fn unknown(tag: Vec<u8>, data: Vec<u8>) -> Result<Self> {
+        if tag.len() != TAG_SIZE {
+            return Err(OtsError::BadLength { min: TAG_SIZE, max: TAG_SIZE, val: tag.len() });
+        }
+        if tag == BITCOIN_TAG || tag == LITECOIN_TAG || tag == PENDING_TAG {
+            return Err(OtsError::ReservedAttestationTag(tag));
+        }
+        Ok(Self::Unknown { tag, data })
+    }
+
+    /// The tag identifying the type of the attestation
+    #[must_use]
+    pub fn tag(&self) -> &[u8] {
+        match self {
+            Self::Bitcoin { .. } => BITCOIN_TAG,
+            Self::Litecoin { .. } => LITECOIN_TAG,
+            Self::Pending { .. } => PENDING_TAG,
+            Self::Unknown { tag, .. } => tag,
+        }
+    }
+
+    /// Order attestations as python-opentimestamps sorts them: by tag, then
+    /// by height, URI or payload
+    #[must_use]
+    pub fn canonical_cmp(&self, other: &Self) -> Ordering {
+        self.tag().cmp(other.tag()).then_with(|| match (self, other) {
+            (Self::Bitcoin { height: a }, Self::Bitcoin { height: b })
+            | (Self::Litecoin { height: a }, Self::Litecoin { height: b }) => a.cmp(b),
+            (Self::Pending { uri: a }, Self::Pending { uri: b }) => a.cmp(b),
+            (Self::Unknown { data: a, .. }, Self::Unknown { data: b, .. }) => a.cmp(b),
+            _ => Ordering::Equal,
+        })
+    }
+
+    /// The tag of an unknown attestation, `None` for known types
+    #[must_use]
+    #[allow(dead_code)]
+    pub fn unknown_tag(&self) -> Option<&[u8]> {
+        match self {
+            Self::Unknown { tag, .. } => Some(tag),
+            _ => None,
+        }
+    }
+
+    /// The raw payload of an unknown attestation, `None` for known types
+    #[must_use]
+    #[allow(dead_code)]
+    pub fn unknown_data(&self) -> Option<&[u8]> {
+        match self {
+            Self::Unknown { data, .. } => Some(data),
+            _ => None,
+        }
+    }
+
+    /// Deserialize a single serialized attestation held in memory
+    ///
+    /// Guaranteed to return an error rather than panic or abort on any input,
+    /// however malformed; this is the entry point exercised by the fuzzer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if deserialization fails or data follows the attestation
+    #[allow(dead_code)]
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut deser = Deserializer::new(data);
+        let attestation = Self::deserialize(&mut deser)?;
+        deser.check_eof()?;
+        Ok(attestation)
+    }
+
     /// Deserialize an arbitrary attestation
     ///
+    /// The payload is read in full, by its length prefix, and parsed on its
+    /// own, so a prefix that disagrees with the payload is an error rather
+    /// than shifting the rest of the proof.
+    ///
     /// # Errors
     ///
     /// Returns an error if:
     /// - The tag is invalid
     /// - The URI contains invalid characters
+    /// - The URI or the payload exceeds the limit of the deserializer's options
+    /// - The payload has bytes left after its content, unless the deserializer
+    ///   is lenient
     /// - Deserialization fails
     pub fn deserialize<R: Read>(deser: &mut Deserializer<R>) -> Result<Self> {
         let tag = deser.read_fixed_bytes(TAG_SIZE)?;
         let len = deser.read_uint()?;
+        let options = *deser.options();
 
-        if tag == BITCOIN_TAG {
-            let height = deser.read_uint()?;
-            Ok(Self::Bitcoin { height })
+        let max = options.max_payload_len;
+        if len > max {
+            return Err(OtsError::BadLength { min: 0, max, val: len });
+        }
+        let payload = deser.read_fixed_bytes(len)?;
+
+        let mut payload_deser = Deserializer::with_options(&payload[..], options);
+        let attestation = if tag == BITCOIN_TAG {
+            Self::Bitcoin { height: payload_deser.read_uint()? }
+        } else if tag == LITECOIN_TAG {
+            Self::Litecoin { height: payload_deser.read_uint()? }
         } else if tag == PENDING_TAG {
             // This validation logic ensures URI contains only safe characters
-            let uri_bytes = deser.read_bytes(0, MAX_URI_LEN)?;
+            let uri_bytes = payload_deser.read_bytes(0, options.max_uri_len)?;
             let uri_string = String::from_utf8(uri_bytes)?;
             for ch in uri_string.chars() {
                 match ch {
@@ -67,34 +175,48 @@ impl Attestation {
                     x => return Err(OtsError::InvalidUriChar(x)),
                 }
             }
-            Ok(Self::Pending { uri: uri_string })
+            Self::Pending { uri: uri_string }
         } else {
-            Ok(Self::Unknown { tag, data: deser.read_fixed_bytes(len)? })
+            return Ok(Self::Unknown { tag, data: payload });
+        };
+        if !options.lenient {
+            payload_deser.check_eof()?;
         }
+        Ok(attestation)
+    }
+
+    /// Length of the payload that follows the tag and its length prefix
+    fn payload_len(&self) -> usize {
+        match self {
+            Self::Bitcoin { height } | Self::Litecoin { height } => varint_len(*height as u64),
+            Self::Pending { uri } => varint_len(uri.len() as u64) + uri.len(),
+            Self::Unknown { data, .. } => data.len(),
+        }
+    }
+
+    /// Number of bytes `serialize` writes for this attestation
+    #[must_use]
+    #[allow(dead_code)]
+    pub fn serialized_len(&self) -> usize {
+        let payload_len = self.payload_len();
+        TAG_SIZE + varint_len(payload_len as u64) + payload_len
     }
 
     /// Serialize an attestation
     ///
+    /// The payload length is computed up front, so the attestation is
+    /// written straight to the serializer without a temporary buffer.
+    ///
     /// # Errors
     ///
     /// Returns an error if the write operation fails
     pub fn serialize<W: Write>(&self, ser: &mut Serializer<W>) -> Result<()> {
-        let mut byte_ser = Serializer::new(Vec::new());
-        match *self {
-            Self::Bitcoin { height } => {
-                ser.write_fixed_bytes(BITCOIN_TAG)?;
-                byte_ser.write_uint(height)?;
-                ser.write_bytes(&byte_ser.into_inner())
-            }
-            Self::Pending { ref uri } => {
-                ser.write_fixed_bytes(PENDING_TAG)?;
-                byte_ser.write_bytes(uri.as_bytes())?;
-                ser.write_bytes(&byte_ser.into_inner())
-            }
-            Self::Unknown { ref tag, ref data } => {
-                ser.write_fixed_bytes(tag)?;
-                ser.write_bytes(data)
-            }
+        ser.write_fixed_bytes(self.tag())?;
+        ser.write_uint(self.payload_len() as u64)?;
+        match self {
+            Self::Bitcoin { height } | Self::Litecoin { height } => ser.write_uint(*height as u64),
+            Self::Pending { uri } => ser.write_bytes(uri.as_bytes()),
+            Self::Unknown { data, .. } => ser.write_fixed_bytes(data),
         }
     }
 }
@@ -103,6 +225,7 @@ impl fmt::Display for Attestation {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Bitcoin { height } => write!(f, "Bitcoin block {}", height),
+            Self::Litecoin { height } => write!(f, "Litecoin block {}", height),
             Self::Pending { uri } => write!(f, "Pending: update URI {}", uri),
             Self::Unknown { tag, data } => {
                 write!(f, "unknown attestation type {}: {}", hex::encode(tag), hex::encode(data))
@@ -114,6 +237,8 @@ impl fmt::Display for Attestation {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ots::error::MAX_URI_LEN;
+    use crate::ots::ser::DeserializerOptions;
 
     #[test]
     fn test_bitcoin_display() {
@@ -143,6 +268,7 @@ mod tests {
     fn test_constants() {
         assert_eq!(TAG_SIZE, 8);
         assert_eq!(BITCOIN_TAG.len(), 8);
+        assert_eq!(LITECOIN_TAG.len(), 8);
         assert_eq!(PENDING_TAG.len(), 8);
         assert_eq!(BITCOIN_TAG, b"\x05\x88\x96\x0d\x73\xd7\x19\x01");
         assert_eq!(PENDING_TAG, b"\x83\xdf\xe3\x0d\x2e\xf9\x0c\x8e");
@@ -173,6 +299,20 @@ mod tests {
         assert_eq!(attestation, deserialized);
     }
 
+    #[test]
+    fn test_serialize_deserialize_litecoin() {
+        let attestation = Attestation::Litecoin { height: 2_500_000 };
+        assert_eq!(format!("{}", attestation), "Litecoin block 2500000");
+        let mut buf = Vec::new();
+        let mut ser = Serializer::new(&mut buf);
+        attestation.serialize(&mut ser).unwrap();
+        assert!(buf.starts_with(LITECOIN_TAG));
+
+        let mut deser = Deserializer::new(&buf[..]);
+        let deserialized = Attestation::deserialize(&mut deser).unwrap();
+        assert_eq!(attestation, deserialized);
+    }
+
     #[test]
     fn test_serialize_deserialize_pending() {
         let attestation = Attestation::Pending {
@@ -202,6 +342,61 @@ mod tests {
         assert_eq!(attestation, deserialized);
     }
 
+    #[test]
+    fn test_serialized_len() {
+        let attestations = [
+            Attestation::Bitcoin { height: 0 },
+            Attestation::Bitcoin { height: 850_000 },
+            Attestation::Litecoin { height: 2_700_000 },
+            Attestation::Pending { uri: "https://a.pool.opentimestamps.org".to_string() },
+            Attestation::Pending { uri: "a".repeat(MAX_URI_LEN) },
+            Attestation::Unknown { tag: vec![1; TAG_SIZE], data: vec![0xaa; 300] },
+        ];
+        for attestation in &attestations {
+            let mut buf = Vec::new();
+            attestation.serialize(&mut Serializer::new(&mut buf)).unwrap();
+            assert_eq!(attestation.serialized_len(), buf.len(), "{attestation}");
+        }
+
+        // The payload is still length-prefixed as a whole
+        let mut buf = Vec::new();
+        Attestation::Bitcoin { height: 200 }.serialize(&mut Serializer::new(&mut buf)).unwrap();
+        assert_eq!(&buf[TAG_SIZE..], [0x02, 0xc8, 0x01]);
+    }
+
+    #[test]
+    fn test_unknown_constructor() {
+        let tag = vec![0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08];
+        let attestation = Attestation::unknown(tag.clone(), vec![0xaa, 0xbb]).unwrap();
+        assert_eq!(attestation.unknown_tag(), Some(&tag[..]));
+        assert_eq!(attestation.unknown_data(), Some(&[0xaa, 0xbb][..]));
+
+        let mut buf = Vec::new();
+        attestation.serialize(&mut Serializer::new(&mut buf)).unwrap();
+        let deserialized = Attestation::deserialize(&mut Deserializer::new(&buf[..])).unwrap();
+        assert_eq!(attestation, deserialized);
+
+        assert!(matches!(
+            Attestation::unknown(vec![0x01; 7], vec![]),
+            Err(OtsError::BadLength { min: 8, max: 8, val: 7 })
+        ));
+        assert!(matches!(
+            Attestation::unknown(BITCOIN_TAG.to_vec(), vec![]),
+            Err(OtsError::ReservedAttestationTag(_))
+        ));
+        assert!(matches!(
+            Attestation::unknown(PENDING_TAG.to_vec(), vec![]),
+            Err(OtsError::ReservedAttestationTag(_))
+        ));
+    }
+
+    #[test]
+    fn test_unknown_accessors_on_known_types() {
+        let attestation = Attestation::Bitcoin { height: 1 };
+        assert_eq!(attestation.unknown_tag(), None);
+        assert_eq!(attestation.unknown_data(), None);
+    }
+
     #[test]
     fn test_deserialize_invalid_uri_char() {
         // Create a pending attestation with invalid character
@@ -289,4 +484,63 @@ mod tests {
         let deserialized = Attestation::deserialize(&mut deser).unwrap();
         assert_eq!(attestation, deserialized);
     }
+
+    #[test]
+    fn test_from_bytes_malformed() {
+        // Unknown attestation claiming a huge payload
+        let mut buf = vec![0xaa; TAG_SIZE];
+        buf.extend_from_slice(b"\xff\xff\xff\xff\x0f");
+        assert!(matches!(Attestation::from_bytes(&buf), Err(OtsError::BadLength { .. })));
+
+        // Bitcoin height varint running past 64 bits
+        let mut buf = BITCOIN_TAG.to_vec();
+        buf.push(0x0b);
+        buf.extend_from_slice(&[0xff; 10]);
+        buf.push(0x01);
+        assert!(matches!(Attestation::from_bytes(&buf), Err(OtsError::VarintTooLong)));
+
+        // Trailing data after a valid attestation
+        let mut buf = Vec::new();
+        Attestation::Bitcoin { height: 1 }.serialize(&mut Serializer::new(&mut buf)).unwrap();
+        buf.push(0);
+        assert!(matches!(Attestation::from_bytes(&buf), Err(OtsError::TrailingBytes)));
+    }
+
+    #[test]
+    fn test_payload_length_mismatch() {
+        // Fuzz regression: a length prefix that disagrees with the payload
+        // must not shift the bytes that follow the attestation
+        let attestation = |tag: &[u8], len: u8, payload: &[u8]| {
+            let mut buf = tag.to_vec();
+            buf.push(len);
+            buf.extend_from_slice(payload);
+            buf
+        };
+
+        // Bitcoin height shorter than its payload, the rest read as the next step
+        let buf = attestation(BITCOIN_TAG, 2, &[0x01, 0x00]);
+        let mut deser = Deserializer::new(&buf[..]);
+        assert!(matches!(Attestation::deserialize(&mut deser), Err(OtsError::TrailingBytes)));
+
+        // Height running past its payload
+        let buf = attestation(LITECOIN_TAG, 1, &[0x81, 0x01]);
+        assert!(Attestation::from_bytes(&buf).is_err());
+
+        // URI shorter than its payload
+        let buf = attestation(PENDING_TAG, 4, b"\x02ab\x00");
+        assert!(matches!(Attestation::from_bytes(&buf), Err(OtsError::TrailingBytes)));
+
+        // URI running past its payload
+        let buf = attestation(PENDING_TAG, 2, b"\x02ab");
+        assert!(Attestation::from_bytes(&buf).is_err());
+
+        // A lenient deserializer skips what a newer client might have added
+        let buf = attestation(BITCOIN_TAG, 2, &[0x01, 0x00]);
+        let mut deser = Deserializer::with_options(&buf[..], DeserializerOptions::lenient());
+        assert_eq!(
+            Attestation::deserialize(&mut deser).unwrap(),
+            Attestation::Bitcoin { height: 1 }
+        );
+        deser.check_eof().unwrap();
+    }
 }
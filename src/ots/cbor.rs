@@ -0,0 +1,277 @@
+//! Compact CBOR encoding of timestamp files
+//!
+//! For protocols that already speak CBOR, such as IoT attestations and
+//! signed firmware manifests, a proof can travel as a CBOR item instead of
+//! an opaque `.ots` blob. The layout mirrors `ProofDocument`, with binary
+//! tags and byte strings instead of names and hex:
+//!
+//! ```text
+//! proof       = [1, digest-type: uint, digest: bstr, branch]
+//! branch      = [[* op], attestation / fork]
+//! op          = tag: uint / [tag: uint, argument: bstr]   ; append, prepend
+//! attestation = bstr                                      ; as in .ots files
+//! fork        = [+ branch]
+//! ```
+//!
+//! Tags are those of the binary format, and an attestation holds its binary
+//! serialization: the 8-byte type tag and the length-prefixed payload. Op
+//! outputs are left out, since decoding executes every op. Decoding goes
+//! through the binary form, so a decoded proof passes the same checks as a
+//! parsed one and encodes back to the same bytes.
+
+use ciborium::value::Value;
+
+use super::attestation::Attestation;
+use super::digest::{Digest, DigestType};
+use super::error::{OtsError, Result};
+use super::op::Op;
+use super::ser::{Deserializer, DetachedTimestampFile, Serializer};
+use super::timestamp::{Step, StepData};
+
+/// Version of the CBOR layout, the first item of every encoded proof
+const CBOR_VERSION: u64 = 1;
+
+impl DetachedTimestampFile {
+    /// Encode the timestamp file as CBOR
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an op is unknown, or an op or fork leads nowhere
+    pub fn to_cbor(&self) -> Result<Vec<u8>> {
+        let digest = self.digest();
+        let value = Value::Array(vec![
+            CBOR_VERSION.into(),
+            digest.digest_type().to_tag().into(),
+            Value::Bytes(digest.as_bytes().to_vec()),
+            encode_branch(&self.timestamp.first_step())?,
+        ]);
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&value, &mut bytes).map_err(|e| match e {
+            ciborium::ser::Error::Io(e) => OtsError::Io(e),
+            ciborium::ser::Error::Value(e) => OtsError::BadDocument(e),
+        })?;
+        Ok(bytes)
+    }
+
+    /// Decode a timestamp file encoded by `to_cbor`
+    ///
+    /// # Errors
+    ///
+    /// Returns `OtsError::BadDocument` if the data is not CBOR in the layout
+    /// of this module, or any error parsing the equivalent `.ots` file gives
+    pub fn from_cbor(data: &[u8]) -> Result<Self> {
+        let value: Value = ciborium::de::from_reader(data)
+            .map_err(|e| OtsError::BadDocument(format!("invalid CBOR: {e}")))?;
+        let [version, digest_type, digest, branch] = items(&value)? else {
+            return Err(invalid("a proof is an array of 4 items"));
+        };
+        if uint(version)? != CBOR_VERSION {
+            return Err(invalid("unsupported version"));
+        }
+        let digest_type = DigestType::from_tag(tag(digest_type)?)?;
+        let digest = Digest::new(digest_type, bytes(digest)?.to_vec())?;
+        let first_step = decode_branch(branch, digest.as_bytes())?;
+        let ots = Self::new(digest, &first_step)?;
+
+        // Round trip through the binary form for its limits and checks
+        let mut bytes = Vec::new();
+        ots.to_writer(&mut bytes)?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+/// The branch starting with `step`
+fn encode_branch(mut step: &Step) -> Result<Value> {
+    let mut ops = vec![];
+    loop {
+        let op = match &step.data {
+            StepData::Op(op) => op,
+            StepData::Attestation(attestation) => {
+                let mut ser = Serializer::new(Vec::new());
+                attestation.serialize(&mut ser)?;
+                return Ok(Value::Array(vec![Value::Array(ops), Value::Bytes(ser.into_inner())]));
+            }
+            StepData::Fork => {
+                if step.next.is_empty() {
+                    return Err(OtsError::IncompleteTimestamp(1));
+                }
+                let fork = step.next.iter().map(encode_branch).collect::<Result<_>>()?;
+                return Ok(Value::Array(vec![Value::Array(ops), Value::Array(fork)]));
+            }
+        };
+        ops.push(encode_op(op)?);
+        step = step.next.first().ok_or(OtsError::IncompleteTimestamp(1))?;
+    }
+}
+
+/// The encoding of `op`
+fn encode_op(op: &Op) -> Result<Value> {
+    match op {
+        Op::Unknown(tag) => Err(OtsError::BadOpTag(*tag)),
+        Op::Append(argument) | Op::Prepend(argument) => {
+            Ok(Value::Array(vec![op.tag().into(), Value::Bytes(argument.clone())]))
+        }
+        op => Ok(op.tag().into()),
+    }
+}
+
+/// The steps of an encoded branch, entered with the message `input`
+fn decode_branch(value: &Value, input: &[u8]) -> Result<Step> {
+    let [ops, end] = items(value)? else {
+        return Err(invalid("a branch is an array of 2 items"));
+    };
+    let mut chain = vec![];
+    let mut msg = input.to_vec();
+    for op in items(ops)? {
+        let op = decode_op(op)?;
+        msg = op.try_execute(&msg)?;
+        chain.push((op, msg.clone()));
+    }
+
+    let mut step = match end {
+        Value::Bytes(attestation) => {
+            let mut deser = Deserializer::new(attestation.as_slice());
+            let attestation = Attestation::deserialize(&mut deser)?;
+            deser.check_eof()?;
+            Step { data: StepData::Attestation(attestation), output: msg, next: vec![] }
+        }
+        Value::Array(fork) if !fork.is_empty() => Step {
+            data: StepData::Fork,
+            next: fork.iter().map(|branch| decode_branch(branch, &msg)).collect::<Result<_>>()?,
+            output: msg,
+        },
+        _ => return Err(invalid("a branch ends in an attestation or a non-empty fork")),
+    };
+    for (op, output) in chain.into_iter().rev() {
+        step = Step { data: StepData::Op(op), output, next: vec![step] };
+    }
+    Ok(step)
+}
+
+/// The op encoded as `value`
+fn decode_op(value: &Value) -> Result<Op> {
+    match value {
+        Value::Array(items) => match items.as_slice() {
+            [op, argument] => match tag(op)? {
+                0xf0 => Ok(Op::Append(bytes(argument)?.to_vec())),
+                0xf1 => Ok(Op::Prepend(bytes(argument)?.to_vec())),
+                other => Err(OtsError::BadOpTag(other)),
+            },
+            _ => Err(invalid("an op with an argument is an array of 2 items")),
+        },
+        value => match tag(value)? {
+            0xf0 | 0xf1 => Err(invalid("append and prepend need an argument")),
+            // Ops without an argument read nothing more
+            other => Op::deserialize_with_tag(&mut Deserializer::new(std::io::empty()), other),
+        },
+    }
+}
+
+/// The items of an array
+fn items(value: &Value) -> Result<&[Value]> {
+    value.as_array().map(Vec::as_slice).ok_or_else(|| invalid("expected an array"))
+}
+
+/// The value of an unsigned integer
+fn uint(value: &Value) -> Result<u64> {
+    value
+        .as_integer()
+        .and_then(|integer| u64::try_from(integer).ok())
+        .ok_or_else(|| invalid("expected an unsigned integer"))
+}
+
+/// The value of a one-byte tag
+fn tag(value: &Value) -> Result<u8> {
+    u8::try_from(uint(value)?).map_err(|_| invalid("expected a one-byte tag"))
+}
+
+/// The contents of a byte string
+fn bytes(value: &Value) -> Result<&[u8]> {
+    value.as_bytes().map(Vec::as_slice).ok_or_else(|| invalid("expected a byte string"))
+}
+
+/// Error for CBOR that does not follow the layout
+fn invalid(reason: &str) -> OtsError {
+    OtsError::BadDocument(format!("invalid CBOR proof: {reason}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ots::timestamp::Timestamp;
+
+    fn proof() -> DetachedTimestampFile {
+        let start = vec![0x01; 32];
+        let append = Op::Append(vec![0xaa]);
+        let appended = append.execute(&start);
+        let hashed = Op::Sha256.execute(&appended);
+        let leaf = |attestation: Attestation| Step {
+            data: StepData::Attestation(attestation),
+            output: hashed.clone(),
+            next: vec![],
+        };
+        let fork = Step {
+            data: StepData::Fork,
+            output: hashed.clone(),
+            next: vec![
+                leaf(Attestation::Pending { uri: "https://a.example".into() }),
+                leaf(Attestation::Bitcoin { height: 9 }),
+            ],
+        };
+        let sha256 = Step { data: StepData::Op(Op::Sha256), output: hashed, next: vec![fork] };
+        let first_step = Step { data: StepData::Op(append), output: appended, next: vec![sha256] };
+        DetachedTimestampFile {
+            timestamp: Timestamp::new(Digest::new(DigestType::Sha256, start).unwrap(), &first_step)
+                .unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let ots = proof();
+        let cbor = ots.to_cbor().unwrap();
+        assert_eq!(DetachedTimestampFile::from_cbor(&cbor).unwrap(), ots);
+        assert_eq!(DetachedTimestampFile::from_cbor(&cbor).unwrap().to_cbor().unwrap(), cbor);
+
+        // Smaller than the binary proof, which repeats nothing either
+        let mut binary = Vec::new();
+        ots.to_writer(&mut binary).unwrap();
+        assert!(cbor.len() < binary.len());
+    }
+
+    #[test]
+    fn test_invalid() {
+        let encode = |value: Value| {
+            let mut bytes = Vec::new();
+            ciborium::ser::into_writer(&value, &mut bytes).unwrap();
+            bytes
+        };
+        let attestation = {
+            let mut ser = Serializer::new(Vec::new());
+            Attestation::Bitcoin { height: 1 }.serialize(&mut ser).unwrap();
+            ser.into_inner()
+        };
+        let proof = |version: u64, digest: Vec<u8>, ops: Vec<Value>| {
+            encode(Value::Array(vec![
+                version.into(),
+                0x08u8.into(),
+                Value::Bytes(digest),
+                Value::Array(vec![Value::Array(ops), Value::Bytes(attestation.clone())]),
+            ]))
+        };
+
+        let valid = proof(1, vec![0; 32], vec![0x08u8.into()]);
+        assert!(DetachedTimestampFile::from_cbor(&valid).is_ok());
+
+        let cases = [
+            proof(2, vec![0; 32], vec![]),
+            proof(1, vec![0; 20], vec![]),
+            proof(1, vec![0; 32], vec![0xf0u8.into()]),
+            proof(1, vec![0; 32], vec![0x42u8.into()]),
+            b"not cbor".to_vec(),
+        ];
+        for cbor in cases {
+            assert!(DetachedTimestampFile::from_cbor(&cbor).is_err());
+        }
+    }
+}
@@ -0,0 +1,319 @@
+//! Structural comparison of timestamps
+//!
+//! Used to show what an upgrade, merge or re-anchor changed in a proof
+//! before it is saved. Positions in a tree are given as paths: the index
+//! into `Step::next` taken at each step, starting from the first step.
+
+use std::fmt;
+
+use super::attestation::Attestation;
+use super::timestamp::{Step, StepData, Timestamp};
+
+/// Position of a step in a timestamp tree
+///
+/// The empty path is the first step; `[1, 0]` is the step after the second
+/// branch of a fork at the start.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct StepPath(pub Vec<usize>);
+
+impl StepPath {
+    fn child(&self, index: usize) -> Self {
+        let mut path = self.0.clone();
+        path.push(index);
+        Self(path)
+    }
+}
+
+impl fmt::Display for StepPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("root");
+        }
+        for (i, index) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(".")?;
+            }
+            write!(f, "{index}")?;
+        }
+        Ok(())
+    }
+}
+
+/// An attestation found in only one of two timestamps
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AttestationChange {
+    /// Where the attestation is, in the timestamp holding it
+    pub path: StepPath,
+    /// The attestation
+    pub attestation: Attestation,
+    /// The digest it attests to
+    pub commitment: Vec<u8>,
+}
+
+/// A position at which two timestamps hold different steps
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Divergence {
+    /// Position in the new timestamp, or in the old one for a removed branch
+    pub path: StepPath,
+    /// Step of the old timestamp, `None` for an added branch
+    pub old: Option<StepData>,
+    /// Step of the new timestamp, `None` for a removed branch
+    pub new: Option<StepData>,
+}
+
+/// Differences between an old and a new version of a timestamp
+///
+/// An attestation counts as unchanged if the other timestamp has the same
+/// attestation on the same digest, wherever it sits in the tree.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct TimestampDiff {
+    /// Attestations only in the new timestamp
+    pub added: Vec<AttestationChange>,
+    /// Attestations only in the old timestamp
+    pub removed: Vec<AttestationChange>,
+    /// Topmost positions at which the trees differ
+    pub diverged: Vec<Divergence>,
+}
+
+impl TimestampDiff {
+    /// Whether the timestamps are identical
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.diverged.is_empty()
+    }
+}
+
+impl fmt::Display for TimestampDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.added {
+            writeln!(f, "+ {} at {}", change.attestation, change.path)?;
+        }
+        for change in &self.removed {
+            writeln!(f, "- {} at {}", change.attestation, change.path)?;
+        }
+        for divergence in &self.diverged {
+            writeln!(
+                f,
+                "~ {}: {} -> {}",
+                divergence.path,
+                describe(divergence.old.as_ref()),
+                describe(divergence.new.as_ref())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Short description of a step for `TimestampDiff`'s display
+fn describe(data: Option<&StepData>) -> String {
+    match data {
+        None => "(none)".to_string(),
+        Some(StepData::Fork) => "fork".to_string(),
+        Some(StepData::Op(op)) => op.to_string(),
+        Some(StepData::Attestation(attestation)) => attestation.to_string(),
+    }
+}
+
+/// Compare an old and a new version of a timestamp
+///
+/// Timestamps of different start digests diverge at the root.
+#[must_use]
+pub fn diff(old: &Timestamp, new: &Timestamp) -> TimestampDiff {
+    let mut old_attestations = vec![];
+    collect_attestations(&old.first_step(), &StepPath::default(), &mut old_attestations);
+    let mut new_attestations = vec![];
+    collect_attestations(&new.first_step(), &StepPath::default(), &mut new_attestations);
+
+    let missing_from = |others: &[AttestationChange], change: &AttestationChange| {
+        !others.iter().any(|other| {
+            other.attestation == change.attestation && other.commitment == change.commitment
+        })
+    };
+    let mut result = TimestampDiff {
+        added: new_attestations
+            .iter()
+            .filter(|change| missing_from(&old_attestations, change))
+            .cloned()
+            .collect(),
+        removed: old_attestations
+            .iter()
+            .filter(|change| missing_from(&new_attestations, change))
+            .cloned()
+            .collect(),
+        diverged: vec![],
+    };
+
+    if old.start_digest() == new.start_digest() {
+        diff_steps(
+            &old.first_step(),
+            &new.first_step(),
+            &StepPath::default(),
+            &mut result.diverged,
+        );
+    } else {
+        result.diverged.push(Divergence {
+            path: StepPath::default(),
+            old: Some(old.first_step().data.clone()),
+            new: Some(new.first_step().data.clone()),
+        });
+    }
+    result
+}
+
+/// Collect every attestation below `step` with its position
+fn collect_attestations(step: &Step, path: &StepPath, found: &mut Vec<AttestationChange>) {
+    if let StepData::Attestation(attestation) = &step.data {
+        found.push(AttestationChange {
+            path: path.clone(),
+            attestation: attestation.clone(),
+            commitment: step.output.clone(),
+        });
+    }
+    for (i, next) in step.next.iter().enumerate() {
+        collect_attestations(next, &path.child(i), found);
+    }
+}
+
+/// Record where two steps at the same position start to differ
+///
+/// Fork branches are matched regardless of their order: identical branches
+/// first, then branches starting with the same step, which are compared
+/// further. Branches left over on both sides are paired up in order.
+fn diff_steps(old: &Step, new: &Step, path: &StepPath, diverged: &mut Vec<Divergence>) {
+    if old == new {
+        return;
+    }
+    match (&old.data, &new.data) {
+        (StepData::Fork, StepData::Fork) => {
+            let mut old_left: Vec<usize> =
+                (0..old.next.len()).filter(|&i| !new.next.contains(&old.next[i])).collect();
+            let mut new_left: Vec<usize> =
+                (0..new.next.len()).filter(|&i| !old.next.contains(&new.next[i])).collect();
+
+            new_left.retain(|&n| {
+                let Some(pos) = old_left.iter().position(|&o| old.next[o].data == new.next[n].data)
+                else {
+                    return true;
+                };
+                let o = old_left.remove(pos);
+                diff_steps(&old.next[o], &new.next[n], &path.child(n), diverged);
+                false
+            });
+
+            let mut old_left = old_left.into_iter();
+            for n in new_left {
+                let old_data = old_left.next().map(|o| old.next[o].data.clone());
+                diverged.push(Divergence {
+                    path: path.child(n),
+                    old: old_data,
+                    new: Some(new.next[n].data.clone()),
+                });
+            }
+            for o in old_left {
+                diverged.push(Divergence {
+                    path: path.child(o),
+                    old: Some(old.next[o].data.clone()),
+                    new: None,
+                });
+            }
+        }
+        (StepData::Op(a), StepData::Op(b)) if a == b && !old.next.is_empty() => {
+            if let Some(next) = new.next.first() {
+                diff_steps(&old.next[0], next, &path.child(0), diverged);
+            }
+        }
+        _ => diverged.push(Divergence {
+            path: path.clone(),
+            old: Some(old.data.clone()),
+            new: Some(new.data.clone()),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ots::{Digest, DigestType, Op};
+
+    fn attestation(attestation: Attestation, output: &[u8]) -> Step {
+        Step { data: StepData::Attestation(attestation), output: output.to_vec(), next: vec![] }
+    }
+
+    fn pending(uri: &str) -> Step {
+        attestation(Attestation::Pending { uri: uri.to_string() }, &[1; 32])
+    }
+
+    fn fork(next: Vec<Step>) -> Timestamp {
+        Timestamp::new(
+            Digest::new(DigestType::Sha256, vec![1; 32]).unwrap(),
+            &Step { data: StepData::Fork, output: vec![1; 32], next },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_identical() {
+        let timestamp = fork(vec![pending("https://a.example"), pending("https://b.example")]);
+        assert!(diff(&timestamp, &timestamp).is_empty());
+
+        // Branch order does not matter
+        let swapped = fork(vec![pending("https://b.example"), pending("https://a.example")]);
+        let result = diff(&timestamp, &swapped);
+        assert!(result.is_empty(), "{result}");
+    }
+
+    #[test]
+    fn test_upgrade() {
+        let old = fork(vec![pending("https://a.example"), pending("https://b.example")]);
+        let digest = Op::Sha256.execute(&[1; 32]);
+        let upgraded = Step {
+            data: StepData::Op(Op::Sha256),
+            output: digest.clone(),
+            next: vec![attestation(Attestation::Bitcoin { height: 850_000 }, &digest)],
+        };
+        let new = fork(vec![pending("https://a.example"), upgraded]);
+
+        let result = diff(&old, &new);
+        assert_eq!(result.added.len(), 1);
+        assert_eq!(result.added[0].attestation, Attestation::Bitcoin { height: 850_000 });
+        assert_eq!(result.added[0].path, StepPath(vec![1, 0]));
+        assert_eq!(result.removed.len(), 1);
+        assert_eq!(result.removed[0].path, StepPath(vec![1]));
+        assert_eq!(
+            result.diverged,
+            vec![Divergence {
+                path: StepPath(vec![1]),
+                old: Some(StepData::Attestation(Attestation::Pending {
+                    uri: "https://b.example".into()
+                })),
+                new: Some(StepData::Op(Op::Sha256)),
+            }]
+        );
+        assert_eq!(
+            result.to_string(),
+            "+ Bitcoin block 850000 at 1.0\n\
+             - Pending: update URI https://b.example at 1\n\
+             ~ 1: Pending: update URI https://b.example -> SHA256()\n"
+        );
+    }
+
+    #[test]
+    fn test_added_branch() {
+        let old = fork(vec![pending("https://a.example"), pending("https://b.example")]);
+        let new = fork(vec![
+            pending("https://a.example"),
+            pending("https://b.example"),
+            pending("https://c.example"),
+        ]);
+        let result = diff(&old, &new);
+        assert_eq!(result.added.len(), 1);
+        assert!(result.removed.is_empty());
+        assert_eq!(result.diverged[0].path, StepPath(vec![2]));
+        assert_eq!(result.diverged[0].old, None);
+
+        // And the other way round
+        let result = diff(&new, &old);
+        assert_eq!(result.removed.len(), 1);
+        assert_eq!(result.diverged[0].new, None);
+    }
+}
@@ -1,6 +1,10 @@
 //! Digest types supported by OpenTimestamps
 
 use std::fmt;
+use std::io::Read;
+
+use bitcoin_hashes::{ripemd160, sha1, sha256, Hash, HashEngine};
+use sha3::{Digest as _, Keccak256};
 
 use super::error::{OtsError, Result};
 
@@ -13,6 +17,11 @@ pub enum DigestType {
     Sha256,
     /// RIPEMD-160 hash (20 bytes)
     Ripemd160,
+    /// Keccak-256 hash (32 bytes), as used by Ethereum
+    ///
+    /// Not SHA3-256: the standardized SHA-3 pads its input differently, and
+    /// has no tag in the `OpenTimestamps` format.
+    Keccak256,
 }
 
 impl DigestType {
@@ -26,6 +35,7 @@ impl DigestType {
             0x02 => Ok(Self::Sha1),
             0x03 => Ok(Self::Ripemd160),
             0x08 => Ok(Self::Sha256),
+            0x67 => Ok(Self::Keccak256),
             _ => Err(OtsError::BadDigestTag(tag)),
         }
     }
@@ -37,6 +47,7 @@ impl DigestType {
             Self::Sha1 => 0x02,
             Self::Ripemd160 => 0x03,
             Self::Sha256 => 0x08,
+            Self::Keccak256 => 0x67,
         }
     }
 
@@ -45,17 +56,163 @@ impl DigestType {
     pub const fn digest_len(self) -> usize {
         match self {
             Self::Sha1 | Self::Ripemd160 => 20,
-            Self::Sha256 => 32,
+            Self::Sha256 | Self::Keccak256 => 32,
+        }
+    }
+
+    /// Hash everything read from `reader` with this digest algorithm
+    ///
+    /// Reads in chunks so large files need not fit in memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns `OtsError::Io` if reading fails
+    pub fn hash_reader<R: Read>(self, reader: R) -> Result<Vec<u8>> {
+        let mut hasher = self.hasher();
+        read_chunks(reader, |chunk| hasher.update(chunk))?;
+        Ok(hasher.finish())
+    }
+
+    /// Hash everything read from `reader` into a typed `Digest`
+    ///
+    /// # Errors
+    ///
+    /// Returns `OtsError::Io` if reading fails
+    pub fn digest_reader<R: Read>(self, reader: R) -> Result<Digest> {
+        Digest::new(self, self.hash_reader(reader)?)
+    }
+
+    /// Start hashing data that arrives piece by piece
+    #[must_use]
+    pub fn hasher(self) -> Hasher {
+        match self {
+            Self::Sha1 => Hasher::Sha1(sha1::Hash::engine()),
+            Self::Sha256 => Hasher::Sha256(sha256::Hash::engine()),
+            Self::Ripemd160 => Hasher::Ripemd160(ripemd160::Hash::engine()),
+            Self::Keccak256 => Hasher::Keccak256(Box::new(Keccak256::new())),
+        }
+    }
+}
+
+/// A document digest, with the algorithm that produced it
+///
+/// The bytes always have the length of the digest type, so a proof built
+/// from a `Digest` cannot claim one algorithm and carry the digest of
+/// another.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Digest {
+    digest_type: DigestType,
+    bytes: Vec<u8>,
+}
+
+impl Digest {
+    /// A digest of type `digest_type`
+    ///
+    /// # Errors
+    ///
+    /// Returns `OtsError::BadLength` if `bytes` does not have the length of
+    /// `digest_type`
+    pub fn new(digest_type: DigestType, bytes: Vec<u8>) -> Result<Self> {
+        let len = digest_type.digest_len();
+        if bytes.len() != len {
+            return Err(OtsError::BadLength { min: len, max: len, val: bytes.len() });
+        }
+        Ok(Self { digest_type, bytes })
+    }
+
+    /// A SHA-256 digest, such as a calendar commitment or a Merkle tree node
+    #[must_use]
+    pub fn sha256(bytes: [u8; 32]) -> Self {
+        Self { digest_type: DigestType::Sha256, bytes: bytes.to_vec() }
+    }
+
+    /// The algorithm that produced the digest
+    #[must_use]
+    pub const fn digest_type(&self) -> DigestType {
+        self.digest_type
+    }
+
+    /// The digest bytes
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The digest bytes, consuming the digest
+    #[must_use]
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+impl AsRef<[u8]> for Digest {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.digest_type, hex::encode(&self.bytes))
+    }
+}
+
+/// Incremental hasher for one of the `DigestType`s
+pub enum Hasher {
+    /// SHA-1 state
+    Sha1(sha1::HashEngine),
+    /// SHA-256 state
+    Sha256(sha256::HashEngine),
+    /// RIPEMD-160 state
+    Ripemd160(ripemd160::HashEngine),
+    /// Keccak-256 state, boxed as it is several times larger than the others
+    Keccak256(Box<Keccak256>),
+}
+
+impl Hasher {
+    /// Hash the next piece of data
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha1(engine) => engine.input(data),
+            Self::Sha256(engine) => engine.input(data),
+            Self::Ripemd160(engine) => engine.input(data),
+            Self::Keccak256(hasher) => hasher.update(data),
+        }
+    }
+
+    /// The digest of everything hashed
+    #[must_use]
+    pub fn finish(self) -> Vec<u8> {
+        match self {
+            Self::Sha1(engine) => sha1::Hash::from_engine(engine).to_byte_array().to_vec(),
+            Self::Sha256(engine) => sha256::Hash::from_engine(engine).to_byte_array().to_vec(),
+            Self::Ripemd160(engine) => {
+                ripemd160::Hash::from_engine(engine).to_byte_array().to_vec()
+            }
+            Self::Keccak256(hasher) => hasher.finalize().to_vec(),
         }
     }
 }
 
+/// Feed `reader` to `update` in fixed-size chunks until EOF
+fn read_chunks<R: Read>(mut reader: R, mut update: impl FnMut(&[u8])) -> Result<()> {
+    let mut buffer = [0u8; 8192];
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            return Ok(());
+        }
+        update(&buffer[..n]);
+    }
+}
+
 impl fmt::Display for DigestType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Sha1 => f.write_str("SHA1"),
             Self::Sha256 => f.write_str("SHA256"),
             Self::Ripemd160 => f.write_str("RIPEMD160"),
+            Self::Keccak256 => f.write_str("KECCAK256"),
         }
     }
 }
@@ -69,6 +226,7 @@ mod tests {
         assert_eq!(DigestType::from_tag(0x02).unwrap(), DigestType::Sha1);
         assert_eq!(DigestType::from_tag(0x03).unwrap(), DigestType::Ripemd160);
         assert_eq!(DigestType::from_tag(0x08).unwrap(), DigestType::Sha256);
+        assert_eq!(DigestType::from_tag(0x67).unwrap(), DigestType::Keccak256);
         assert!(DigestType::from_tag(0xFF).is_err());
     }
 
@@ -77,6 +235,7 @@ mod tests {
         assert_eq!(DigestType::Sha1.to_tag(), 0x02);
         assert_eq!(DigestType::Ripemd160.to_tag(), 0x03);
         assert_eq!(DigestType::Sha256.to_tag(), 0x08);
+        assert_eq!(DigestType::Keccak256.to_tag(), 0x67);
     }
 
     #[test]
@@ -84,6 +243,7 @@ mod tests {
         assert_eq!(DigestType::Sha1.digest_len(), 20);
         assert_eq!(DigestType::Ripemd160.digest_len(), 20);
         assert_eq!(DigestType::Sha256.digest_len(), 32);
+        assert_eq!(DigestType::Keccak256.digest_len(), 32);
     }
 
     #[test]
@@ -91,20 +251,55 @@ mod tests {
         assert_eq!(format!("{}", DigestType::Sha1), "SHA1");
         assert_eq!(format!("{}", DigestType::Sha256), "SHA256");
         assert_eq!(format!("{}", DigestType::Ripemd160), "RIPEMD160");
+        assert_eq!(format!("{}", DigestType::Keccak256), "KECCAK256");
     }
 
     #[test]
     fn test_round_trip() {
-        for digest_type in [DigestType::Sha1, DigestType::Sha256, DigestType::Ripemd160] {
+        for digest_type in
+            [DigestType::Sha1, DigestType::Sha256, DigestType::Ripemd160, DigestType::Keccak256]
+        {
             let tag = digest_type.to_tag();
             assert_eq!(DigestType::from_tag(tag).unwrap(), digest_type);
         }
     }
 
+    #[test]
+    fn test_hash_reader() {
+        let cases = [
+            (DigestType::Sha1, "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d"),
+            (
+                DigestType::Sha256,
+                "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+            ),
+            (DigestType::Ripemd160, "108f07b8382412612c048d07d13f814118445acd"),
+            (
+                DigestType::Keccak256,
+                "1c8aff950685c2ed4bc3174f3472287b56d9517b9c948127319a09a7a36deac8",
+            ),
+        ];
+        for (digest_type, expected) in cases {
+            let hash = digest_type.hash_reader(&b"hello"[..]).unwrap();
+            assert_eq!(hex::encode(&hash), expected);
+            assert_eq!(hash.len(), digest_type.digest_len());
+
+            let mut hasher = digest_type.hasher();
+            hasher.update(b"he");
+            hasher.update(b"llo");
+            assert_eq!(hasher.finish(), hash);
+        }
+        // Inputs spanning several chunks hash the same as a one-shot hash
+        let data = vec![0x5a; 20_000];
+        assert_eq!(
+            DigestType::Sha256.hash_reader(&data[..]).unwrap(),
+            sha256::Hash::hash(&data).to_byte_array().to_vec()
+        );
+    }
+
     #[test]
     fn test_from_tag_all_invalid() {
         // Test various invalid tags
-        for invalid_tag in [0x00, 0x01, 0x04, 0x05, 0x06, 0x07, 0x09, 0xFF] {
+        for invalid_tag in [0x00, 0x01, 0x04, 0x05, 0x06, 0x07, 0x09, 0x66, 0xFF] {
             assert!(DigestType::from_tag(invalid_tag).is_err());
         }
     }
@@ -148,4 +343,22 @@ mod tests {
             _ => panic!("Expected BadDigestTag error"),
         }
     }
+
+    #[test]
+    fn test_digest_length() {
+        let digest = Digest::new(DigestType::Sha1, vec![0xab; 20]).unwrap();
+        assert_eq!(digest.digest_type(), DigestType::Sha1);
+        assert_eq!(digest.as_bytes(), &[0xab; 20][..]);
+        assert_eq!(digest.to_string(), format!("SHA1 {}", "ab".repeat(20)));
+
+        // A SHA256-sized digest is not a valid SHA1 digest
+        assert!(matches!(
+            Digest::new(DigestType::Sha1, vec![0xab; 32]),
+            Err(OtsError::BadLength { min: 20, max: 20, val: 32 })
+        ));
+
+        let hashed = DigestType::Keccak256.digest_reader(&b"hello"[..]).unwrap();
+        assert_eq!(hashed.digest_type(), DigestType::Keccak256);
+        assert_eq!(hashed.into_bytes(), DigestType::Keccak256.hash_reader(&b"hello"[..]).unwrap());
+    }
 }
@@ -0,0 +1,419 @@
+//! Proofs as plain documents for JSON and other serde formats
+//!
+//! The binary format is compact but needs a parser; a `ProofDocument` holds
+//! the same tree with names instead of tags and hex instead of raw bytes, so
+//! web frontends and auditors can read proofs with any JSON library. The
+//! layout is stable: it only changes with `DOCUMENT_VERSION`.
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "digest_type": "sha256",
+//!   "digest": "9f86d081...0f00a08",
+//!   "timestamp": {
+//!     "ops": [
+//!       { "op": "append", "argument": "6d2e4b8c...", "output": "9f86d081..." },
+//!       { "op": "sha256", "output": "1c9b2e8f..." }
+//!     ],
+//!     "fork": [
+//!       { "attestation": { "type": "pending", "uri": "https://alice.btc.calendar.opentimestamps.org" } },
+//!       { "ops": [...], "attestation": { "type": "bitcoin", "height": 358391 } }
+//!     ]
+//!   }
+//! }
+//! ```
+//!
+//! A branch applies its `ops` in order, then ends in either an attestation
+//! of the result or a fork into further branches. Chains of ops are lists
+//! rather than nested objects, so long proofs stay shallow for parsers with
+//! a nesting limit. Every op gives its `output` when written; on reading it
+//! may be left out, and is checked against the op's result when present.
+
+use serde::{Deserialize, Serialize};
+
+use super::attestation::Attestation;
+use super::digest::{Digest, DigestType};
+use super::error::{OtsError, Result};
+use super::op::Op;
+use super::ser::DetachedTimestampFile;
+use super::timestamp::{Step, StepData};
+
+/// Version of the document layout written in `ProofDocument::version`
+pub const DOCUMENT_VERSION: u32 = 1;
+
+/// A detached timestamp file as a document
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProofDocument {
+    /// Layout version, `DOCUMENT_VERSION`
+    pub version: u32,
+    /// Hash function of the document digest: sha1, sha256, ripemd160 or keccak256
+    pub digest_type: String,
+    /// Hex-encoded document digest
+    pub digest: String,
+    /// The steps from the digest to its attestations
+    pub timestamp: BranchDocument,
+}
+
+/// A chain of ops ending in an attestation or a fork
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BranchDocument {
+    /// Ops applied in order, starting from the message of the branch
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ops: Vec<OpDocument>,
+    /// Attestation of the result of the ops
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation: Option<AttestationDocument>,
+    /// Branches continuing from the result of the ops
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fork: Vec<BranchDocument>,
+}
+
+/// One op of a branch
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OpDocument {
+    /// sha1, sha256, ripemd160, keccak256, hexlify, reverse, append or prepend
+    pub op: String,
+    /// Hex-encoded argument of append and prepend
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub argument: Option<String>,
+    /// Hex-encoded result of the op
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+}
+
+/// An attestation, tagged by its `type`
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum AttestationDocument {
+    /// Bitcoin block header attestation
+    Bitcoin {
+        /// Block height
+        height: usize,
+    },
+    /// Litecoin block header attestation
+    Litecoin {
+        /// Block height
+        height: usize,
+    },
+    /// Calendar that will complete the proof
+    Pending {
+        /// Calendar URI
+        uri: String,
+    },
+    /// Attestation of a type this client does not interpret
+    Unknown {
+        /// Hex-encoded 8-byte tag
+        tag: String,
+        /// Hex-encoded payload
+        payload: String,
+    },
+}
+
+impl ProofDocument {
+    /// The document of a timestamp file
+    ///
+    /// # Errors
+    ///
+    /// Returns `OtsError::BadOpTag` for an op this client does not know,
+    /// which only lenient parsing lets into a proof, or
+    /// `OtsError::IncompleteTimestamp` for an op or fork leading nowhere
+    pub fn from_file(ots: &DetachedTimestampFile) -> Result<Self> {
+        Ok(Self {
+            version: DOCUMENT_VERSION,
+            digest_type: digest_type_name(ots.digest_type()).to_string(),
+            digest: hex::encode(ots.timestamp.start_digest()),
+            timestamp: BranchDocument::from_step(&ots.timestamp.first_step())?,
+        })
+    }
+
+    /// The timestamp file this document describes
+    ///
+    /// Ops are executed to find each message, and the result passes the
+    /// same checks as a proof read from its binary form.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - The version, a name or a hex string is invalid (`OtsError::BadDocument`)
+    /// - The digest does not have the length of its type
+    /// - A given `output` is not the result of its op (`OtsError::OutputMismatch`)
+    /// - The tree would not be a valid proof in binary form
+    pub fn to_file(&self) -> Result<DetachedTimestampFile> {
+        if self.version != DOCUMENT_VERSION {
+            return Err(OtsError::BadDocument(format!(
+                "unsupported version {}, expected {DOCUMENT_VERSION}",
+                self.version
+            )));
+        }
+        let digest = Digest::new(parse_digest_type(&self.digest_type)?, decode(&self.digest)?)?;
+        let first_step = self.timestamp.to_step(digest.as_bytes())?;
+        let ots = DetachedTimestampFile::new(digest, &first_step)?;
+
+        // Round trip through the binary form for its limits and checks
+        let mut bytes = Vec::new();
+        ots.to_writer(&mut bytes)?;
+        DetachedTimestampFile::from_bytes(&bytes)
+    }
+}
+
+impl BranchDocument {
+    /// The branch starting with `step`
+    fn from_step(mut step: &Step) -> Result<Self> {
+        let mut ops = vec![];
+        loop {
+            match &step.data {
+                StepData::Op(op) => {
+                    ops.push(OpDocument::new(op, &step.output)?);
+                    step = step.next.first().ok_or(OtsError::IncompleteTimestamp(1))?;
+                }
+                StepData::Attestation(attestation) => {
+                    let attestation = Some(AttestationDocument::from(attestation));
+                    return Ok(Self { ops, attestation, fork: vec![] });
+                }
+                StepData::Fork => {
+                    if step.next.is_empty() {
+                        return Err(OtsError::IncompleteTimestamp(1));
+                    }
+                    let fork = step.next.iter().map(Self::from_step).collect::<Result<_>>()?;
+                    return Ok(Self { ops, attestation: None, fork });
+                }
+            }
+        }
+    }
+
+    /// The steps of the branch, entered with the message `input`
+    fn to_step(&self, input: &[u8]) -> Result<Step> {
+        let mut chain = Vec::with_capacity(self.ops.len());
+        let mut msg = input.to_vec();
+        for document in &self.ops {
+            let op = document.to_op()?;
+            msg = op.try_execute(&msg)?;
+            if let Some(output) = &document.output {
+                if decode(output)? != msg {
+                    return Err(OtsError::OutputMismatch);
+                }
+            }
+            chain.push((op, msg.clone()));
+        }
+
+        let mut step = match (&self.attestation, self.fork.is_empty()) {
+            (Some(attestation), true) => Step {
+                data: StepData::Attestation(attestation.to_attestation()?),
+                output: msg,
+                next: vec![],
+            },
+            (None, false) => Step {
+                data: StepData::Fork,
+                next: self.fork.iter().map(|branch| branch.to_step(&msg)).collect::<Result<_>>()?,
+                output: msg,
+            },
+            _ => {
+                return Err(OtsError::BadDocument(
+                    "a branch must end in either an attestation or a fork".into(),
+                ))
+            }
+        };
+        for (op, output) in chain.into_iter().rev() {
+            step = Step { data: StepData::Op(op), output, next: vec![step] };
+        }
+        Ok(step)
+    }
+}
+
+impl OpDocument {
+    /// The document of `op`, which gave `output`
+    fn new(op: &Op, output: &[u8]) -> Result<Self> {
+        let (name, argument) = match op {
+            Op::Sha1 => ("sha1", None),
+            Op::Sha256 => ("sha256", None),
+            Op::Ripemd160 => ("ripemd160", None),
+            Op::Keccak256 => ("keccak256", None),
+            Op::Hexlify => ("hexlify", None),
+            Op::Reverse => ("reverse", None),
+            Op::Append(data) => ("append", Some(hex::encode(data))),
+            Op::Prepend(data) => ("prepend", Some(hex::encode(data))),
+            Op::Unknown(tag) => return Err(OtsError::BadOpTag(*tag)),
+        };
+        Ok(Self { op: name.to_string(), argument, output: Some(hex::encode(output)) })
+    }
+
+    /// The op this document names
+    fn to_op(&self) -> Result<Op> {
+        let op = match self.op.as_str() {
+            "append" => return Ok(Op::Append(decode(self.argument()?)?)),
+            "prepend" => return Ok(Op::Prepend(decode(self.argument()?)?)),
+            "sha1" => Op::Sha1,
+            "sha256" => Op::Sha256,
+            "ripemd160" => Op::Ripemd160,
+            "keccak256" => Op::Keccak256,
+            "hexlify" => Op::Hexlify,
+            "reverse" => Op::Reverse,
+            other => return Err(OtsError::BadDocument(format!("unknown op \"{other}\""))),
+        };
+        match self.argument {
+            Some(_) => Err(OtsError::BadDocument(format!("{} takes no argument", self.op))),
+            None => Ok(op),
+        }
+    }
+
+    /// The argument of an append or prepend
+    fn argument(&self) -> Result<&str> {
+        self.argument
+            .as_deref()
+            .ok_or_else(|| OtsError::BadDocument(format!("{} needs an argument", self.op)))
+    }
+}
+
+impl AttestationDocument {
+    /// The attestation this document describes
+    fn to_attestation(&self) -> Result<Attestation> {
+        Ok(match self {
+            Self::Bitcoin { height } => Attestation::Bitcoin { height: *height },
+            Self::Litecoin { height } => Attestation::Litecoin { height: *height },
+            Self::Pending { uri } => Attestation::Pending { uri: uri.clone() },
+            Self::Unknown { tag, payload } => Attestation::unknown(decode(tag)?, decode(payload)?)?,
+        })
+    }
+}
+
+impl From<&Attestation> for AttestationDocument {
+    fn from(attestation: &Attestation) -> Self {
+        match attestation {
+            Attestation::Bitcoin { height } => Self::Bitcoin { height: *height },
+            Attestation::Litecoin { height } => Self::Litecoin { height: *height },
+            Attestation::Pending { uri } => Self::Pending { uri: uri.clone() },
+            Attestation::Unknown { tag, data } => {
+                Self::Unknown { tag: hex::encode(tag), payload: hex::encode(data) }
+            }
+        }
+    }
+}
+
+/// Name of a digest type in documents
+fn digest_type_name(digest_type: DigestType) -> &'static str {
+    match digest_type {
+        DigestType::Sha1 => "sha1",
+        DigestType::Sha256 => "sha256",
+        DigestType::Ripemd160 => "ripemd160",
+        DigestType::Keccak256 => "keccak256",
+    }
+}
+
+/// The digest type named `name` in a document
+fn parse_digest_type(name: &str) -> Result<DigestType> {
+    match name {
+        "sha1" => Ok(DigestType::Sha1),
+        "sha256" => Ok(DigestType::Sha256),
+        "ripemd160" => Ok(DigestType::Ripemd160),
+        "keccak256" => Ok(DigestType::Keccak256),
+        other => Err(OtsError::BadDocument(format!("unknown digest type \"{other}\""))),
+    }
+}
+
+/// Decode a hex string of a document
+fn decode(hex: &str) -> Result<Vec<u8>> {
+    hex::decode(hex).map_err(|e| OtsError::BadDocument(format!("invalid hex \"{hex}\": {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ots::timestamp::Timestamp;
+
+    /// A proof forking into a pending and a Bitcoin-confirmed path
+    fn proof() -> DetachedTimestampFile {
+        let start = vec![0x01; 32];
+        let append = Op::Append(vec![0xaa]);
+        let appended = append.execute(&start);
+        let hashed = Op::Sha256.execute(&appended);
+        let leaf = |attestation: Attestation| Step {
+            data: StepData::Attestation(attestation),
+            output: hashed.clone(),
+            next: vec![],
+        };
+        let fork = Step {
+            data: StepData::Fork,
+            output: hashed.clone(),
+            next: vec![
+                leaf(Attestation::Pending { uri: "https://a.example".into() }),
+                leaf(Attestation::Bitcoin { height: 9 }),
+            ],
+        };
+        let sha256 = Step { data: StepData::Op(Op::Sha256), output: hashed, next: vec![fork] };
+        let first_step = Step { data: StepData::Op(append), output: appended, next: vec![sha256] };
+        DetachedTimestampFile {
+            timestamp: Timestamp::new(Digest::new(DigestType::Sha256, start).unwrap(), &first_step)
+                .unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let ots = proof();
+        let document = ProofDocument::from_file(&ots).unwrap();
+        assert_eq!(document.timestamp.ops.len(), 2);
+        assert_eq!(document.timestamp.fork.len(), 2);
+        assert_eq!(document.to_file().unwrap(), ots);
+
+        let json = serde_json::to_value(&document).unwrap();
+        assert_eq!(json["digest_type"], "sha256");
+        assert_eq!(json["timestamp"]["ops"][0]["argument"], "aa");
+        assert_eq!(
+            json["timestamp"]["fork"][1]["attestation"],
+            serde_json::json!({ "type": "bitcoin", "height": 9 })
+        );
+        let parsed: ProofDocument = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, document);
+    }
+
+    #[test]
+    fn test_outputs_optional_but_checked() {
+        let ots = proof();
+        let mut document = ProofDocument::from_file(&ots).unwrap();
+        for op in &mut document.timestamp.ops {
+            op.output = None;
+        }
+        assert_eq!(document.to_file().unwrap(), ots);
+
+        document.timestamp.ops[1].output = Some(hex::encode([0u8; 32]));
+        assert!(matches!(document.to_file(), Err(OtsError::OutputMismatch)));
+    }
+
+    #[test]
+    fn test_invalid_documents() {
+        let document = ProofDocument::from_file(&proof()).unwrap();
+        let invalid = |change: fn(&mut ProofDocument)| {
+            let mut document = document.clone();
+            change(&mut document);
+            document.to_file().unwrap_err()
+        };
+
+        assert!(matches!(invalid(|d| d.version = 2), OtsError::BadDocument(_)));
+        assert!(matches!(invalid(|d| d.digest_type = "md5".into()), OtsError::BadDocument(_)));
+        assert!(matches!(invalid(|d| d.digest.push('0')), OtsError::BadDocument(_)));
+        assert!(matches!(invalid(|d| d.digest = "00".into()), OtsError::BadLength { .. }));
+        assert!(matches!(
+            invalid(|d| d.timestamp.ops[1].op = "sha512".into()),
+            OtsError::BadDocument(_)
+        ));
+        assert!(matches!(
+            invalid(|d| d.timestamp.ops[0].argument = None),
+            OtsError::BadDocument(_)
+        ));
+        assert!(matches!(invalid(|d| d.timestamp.fork.clear()), OtsError::BadDocument(_)));
+        assert!(matches!(
+            invalid(|d| {
+                d.timestamp.fork[0].attestation =
+                    Some(AttestationDocument::Pending { uri: "https://a.example/$".into() });
+            }),
+            OtsError::InvalidUriChar('$')
+        ));
+
+        let mut json = serde_json::to_value(&document).unwrap();
+        json["extra"] = serde_json::json!(1);
+        assert!(serde_json::from_value::<ProofDocument>(json).is_err());
+    }
+}
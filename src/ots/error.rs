@@ -11,9 +11,21 @@ pub const RECURSION_LIMIT: usize = 256;
 /// Maximum length of a pending attestation URI
 pub const MAX_URI_LEN: usize = 1000;
 
+/// Maximum length in bytes of a variable-length integer, enough for 63 bits
+pub const MAX_VARINT_LEN: usize = 9;
+
+/// Maximum length of the payload of an unknown attestation
+pub const MAX_PAYLOAD_LEN: usize = 8192;
+
 /// Maximum length of operation data
 pub const MAX_OP_LENGTH: usize = 4096;
 
+/// Maximum length of the input message of an operation
+pub const MAX_MSG_LENGTH: usize = 4096;
+
+/// Maximum length of the result of an operation
+pub const MAX_RESULT_LENGTH: usize = 4096;
+
 /// Error type for OTS module operations
 #[derive(Debug)]
 pub enum OtsError {
@@ -21,6 +33,8 @@ pub enum OtsError {
     StackOverflow,
     /// Invalid character in pending attestation URI
     InvalidUriChar(char),
+    /// Invalid character in a metadata key
+    InvalidMetadataKey(char),
     /// Unrecognized digest type tag
     BadDigestTag(u8),
     /// Unrecognized operation tag
@@ -40,6 +54,42 @@ pub enum OtsError {
     },
     /// Unexpected data after end of timestamp
     TrailingBytes,
+    /// Variable-length integer does not fit in a `usize`, or is too large to encode
+    VarintOverflow,
+    /// Variable-length integer has more than `MAX_VARINT_LEN` bytes
+    VarintTooLong,
+    /// Variable-length integer padded with redundant zero bytes
+    NonMinimalVarint,
+    /// Digest of attached data does not match the timestamp
+    DigestMismatch,
+    /// Operation input has a length the operation does not accept
+    BadOpInput {
+        /// Tag of the operation
+        tag: u8,
+        /// Length of the input
+        len: usize,
+    },
+    /// Operation produced a result of invalid length
+    BadOpResult {
+        /// Tag of the operation
+        tag: u8,
+        /// Length of the result
+        len: usize,
+    },
+    /// Attestation tag belongs to a known attestation type
+    ReservedAttestationTag(Vec<u8>),
+    /// Step output differs from the result of executing the step on its input
+    OutputMismatch,
+    /// Timestamp ended with paths not terminated by an attestation
+    IncompleteTimestamp(usize),
+    /// Step written after every path was terminated by an attestation
+    TimestampComplete,
+    /// Proof document is not a valid description of a proof
+    BadDocument(String),
+    /// ASCII-armored proof is missing a marker line or is not valid base64
+    BadArmor(String),
+    /// Signature of a signed timestamp file is invalid
+    BadSignature,
     /// UTF-8 decoding error
     Utf8(FromUtf8Error),
     /// I/O error
@@ -51,6 +101,7 @@ impl fmt::Display for OtsError {
         match self {
             Self::StackOverflow => write!(f, "recursion limit of {} exceeded", RECURSION_LIMIT),
             Self::InvalidUriChar(c) => write!(f, "invalid character '{}' in URI", c),
+            Self::InvalidMetadataKey(c) => write!(f, "invalid character '{}' in metadata key", c),
             Self::BadDigestTag(tag) => write!(f, "unrecognized digest type tag: 0x{:02x}", tag),
             Self::BadOpTag(tag) => write!(f, "unrecognized operation tag: 0x{:02x}", tag),
             Self::BadMagic(bytes) => {
@@ -63,6 +114,31 @@ impl fmt::Display for OtsError {
                 write!(f, "length {} is out of range (expected {}-{} inclusive)", val, min, max)
             }
             Self::TrailingBytes => write!(f, "unexpected data after end of timestamp"),
+            Self::VarintOverflow => write!(f, "variable-length integer is too large"),
+            Self::VarintTooLong => {
+                write!(f, "variable-length integer is longer than {} bytes", MAX_VARINT_LEN)
+            }
+            Self::NonMinimalVarint => {
+                write!(f, "variable-length integer is not minimally encoded")
+            }
+            Self::DigestMismatch => write!(f, "digest of attached data does not match timestamp"),
+            Self::BadOpInput { tag, len } => {
+                write!(f, "invalid input length {} for operation 0x{:02x}", len, tag)
+            }
+            Self::BadOpResult { tag, len } => {
+                write!(f, "invalid result length {} for operation 0x{:02x}", len, tag)
+            }
+            Self::ReservedAttestationTag(tag) => {
+                write!(f, "attestation tag {} belongs to a known type", hex::encode(tag))
+            }
+            Self::OutputMismatch => write!(f, "step output does not match its input"),
+            Self::IncompleteTimestamp(open) => {
+                write!(f, "timestamp has {} paths without an attestation", open)
+            }
+            Self::TimestampComplete => write!(f, "step written after end of timestamp"),
+            Self::BadDocument(reason) => write!(f, "invalid proof document: {}", reason),
+            Self::BadArmor(reason) => write!(f, "invalid armored proof: {}", reason),
+            Self::BadSignature => write!(f, "invalid signature on timestamp file"),
             Self::Utf8(e) => write!(f, "UTF-8 decoding error: {}", e),
             Self::Io(e) => write!(f, "I/O error: {}", e),
         }
@@ -144,7 +220,11 @@ mod tests {
     fn test_constants() {
         assert_eq!(RECURSION_LIMIT, 256);
         assert_eq!(MAX_URI_LEN, 1000);
+        assert_eq!(MAX_VARINT_LEN, 9);
+        assert_eq!(MAX_PAYLOAD_LEN, 8192);
         assert_eq!(MAX_OP_LENGTH, 4096);
+        assert_eq!(MAX_MSG_LENGTH, 4096);
+        assert_eq!(MAX_RESULT_LENGTH, 4096);
     }
 
     #[test]
@@ -188,12 +268,23 @@ mod tests {
         let errors = vec![
             OtsError::StackOverflow,
             OtsError::InvalidUriChar('#'),
+            OtsError::InvalidMetadataKey(' '),
             OtsError::BadDigestTag(0x42),
             OtsError::BadOpTag(0x43),
             OtsError::BadMagic(vec![1, 2, 3]),
             OtsError::BadVersion(99),
             OtsError::BadLength { min: 5, max: 10, val: 3 },
             OtsError::TrailingBytes,
+            OtsError::VarintOverflow,
+            OtsError::VarintTooLong,
+            OtsError::NonMinimalVarint,
+            OtsError::DigestMismatch,
+            OtsError::BadOpInput { tag: 0xf3, len: 0 },
+            OtsError::BadOpResult { tag: 0xf0, len: 5000 },
+            OtsError::ReservedAttestationTag(vec![0x05; 8]),
+            OtsError::IncompleteTimestamp(2),
+            OtsError::TimestampComplete,
+            OtsError::BadSignature,
             OtsError::Utf8(String::from_utf8(vec![0xFF]).unwrap_err()),
             OtsError::Io(io::Error::new(io::ErrorKind::Other, "test")),
         ];
@@ -0,0 +1,437 @@
+//! Timestamps as a map from messages to ops and attestations
+//!
+//! python-opentimestamps models a timestamp as a message with the set of
+//! attestations of that message and a map from ops to the timestamps of
+//! their results, and so does `Timestamp`, which holds the `TimestampMap` of
+//! its start digest. Merging, deduplicating and pruning are simple in that
+//! form, since branches that apply the same op are the same entry. The
+//! `Step` tree stays as a view for code following a proof path by path:
+//! `from_step` reads a tree into a map, and `to_step` converts back without
+//! losing anything but fork layout and duplicates.
+
+use std::io::{Read, Write};
+
+use super::attestation::Attestation;
+use super::error::{OtsError, Result};
+use super::op::Op;
+use super::ser::{Deserializer, Serializer};
+use super::timestamp::{Step, StepData};
+
+/// A message, its attestations, and the ops applied to it
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct TimestampMap {
+    /// The message this node timestamps
+    pub msg: Vec<u8>,
+    /// Attestations of `msg`, without duplicates
+    pub attestations: Vec<Attestation>,
+    /// Ops applied to `msg`, each with the timestamp of its result; every op
+    /// appears once
+    pub ops: Vec<(Op, TimestampMap)>,
+}
+
+impl TimestampMap {
+    /// An empty timestamp of `msg`
+    #[must_use]
+    pub fn new(msg: Vec<u8>) -> Self {
+        Self { msg, attestations: vec![], ops: vec![] }
+    }
+
+    /// Build the timestamp of `msg` from a step tree starting at it
+    ///
+    /// The outputs in the tree must be what its ops compute from `msg`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - An op cannot be executed, or its output differs from its result
+    ///   (`OtsError::OutputMismatch`, also for forks and attestations, which
+    ///   keep their input)
+    /// - A fork or op has no continuation (`OtsError::IncompleteTimestamp`)
+    /// - An attestation or op has more than it allows (`OtsError::TimestampComplete`)
+    pub fn from_step(msg: Vec<u8>, step: &Step) -> Result<Self> {
+        let mut map = Self::new(msg);
+        map.add_step(step)?;
+        Ok(map)
+    }
+
+    /// Add a step that starts from `msg`, with everything after it
+    fn add_step(&mut self, step: &Step) -> Result<()> {
+        let expected = match &step.data {
+            // Unknown ops, read leniently, pass their input through
+            StepData::Op(op @ Op::Unknown(_)) => op.execute(&self.msg),
+            StepData::Op(op) => op.try_execute(&self.msg)?,
+            StepData::Fork | StepData::Attestation(_) => self.msg.clone(),
+        };
+        if step.output != expected {
+            return Err(OtsError::OutputMismatch);
+        }
+        match &step.data {
+            StepData::Attestation(_) if !step.next.is_empty() => Err(OtsError::TimestampComplete),
+            StepData::Op(_) if step.next.len() > 1 => Err(OtsError::TimestampComplete),
+            StepData::Fork | StepData::Op(_) if step.next.is_empty() => {
+                Err(OtsError::IncompleteTimestamp(1))
+            }
+            StepData::Attestation(attestation) => {
+                self.add_attestation(attestation.clone());
+                Ok(())
+            }
+            StepData::Fork => step.next.iter().try_for_each(|next| self.add_step(next)),
+            StepData::Op(op) => self.op_entry(op.clone(), expected).add_step(&step.next[0]),
+        }
+    }
+
+    /// Deserialize the timestamp of `msg`
+    ///
+    /// As in python-opentimestamps, the branches of a fork become the
+    /// attestations and ops of one message, so duplicated branches are
+    /// merged and nested forks flattened.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - The recursion limit of the deserializer's options is exceeded
+    /// - Deserialization of any component fails
+    pub fn deserialize<R: Read>(deser: &mut Deserializer<R>, msg: Vec<u8>) -> Result<Self> {
+        let mut map = Self::new(msg);
+        let limit = deser.options().max_recursion;
+        map.deserialize_recurse(deser, limit)?;
+        Ok(map)
+    }
+
+    /// Deserialize the steps starting from `msg` into this timestamp
+    fn deserialize_recurse<R: Read>(
+        &mut self,
+        deser: &mut Deserializer<R>,
+        recursion_limit: usize,
+    ) -> Result<()> {
+        if recursion_limit == 0 {
+            return Err(OtsError::StackOverflow);
+        }
+
+        // A tag typically indicates an op to execute, but the two special values
+        // 0xff (fork) and 0x00 (read attestation and terminate path) are used to
+        // provide multiple attestations
+        let mut tag = deser.read_byte()?;
+        while tag == 0xff {
+            self.deserialize_recurse(deser, recursion_limit - 1)?;
+            tag = deser.read_byte()?;
+        }
+        if tag == 0x00 {
+            let attestation = Attestation::deserialize(deser)?;
+            self.add_attestation(attestation);
+            return Ok(());
+        }
+        let op = Op::deserialize_with_tag(deser, tag)?;
+        // Unknown ops, read leniently, pass their input through
+        let output = match op {
+            Op::Unknown(_) => op.execute(&self.msg),
+            _ => op.try_execute(&self.msg)?,
+        };
+        self.op_entry(op, output).deserialize_recurse(deser, recursion_limit - 1)
+    }
+
+    /// Serialize the timestamp: the attestations of `msg`, then its ops,
+    /// each followed by the timestamp of its result, all but the last
+    /// prefixed with a fork
+    ///
+    /// # Errors
+    ///
+    /// Returns `OtsError::IncompleteTimestamp` if a message has neither
+    /// attestations nor ops, or an error of the write operation
+    pub fn serialize<W: Write>(&self, ser: &mut Serializer<W>) -> Result<()> {
+        let count = self.attestations.len() + self.ops.len();
+        if count == 0 {
+            return Err(OtsError::IncompleteTimestamp(1));
+        }
+        for (i, attestation) in self.attestations.iter().enumerate() {
+            if i + 1 < count {
+                ser.write_byte(0xff)?;
+            }
+            ser.write_byte(0x00)?;
+            attestation.serialize(ser)?;
+        }
+        for (i, (op, child)) in self.ops.iter().enumerate() {
+            if self.attestations.len() + i + 1 < count {
+                ser.write_byte(0xff)?;
+            }
+            op.serialize(ser)?;
+            child.serialize(ser)?;
+        }
+        Ok(())
+    }
+
+    /// Attest to `msg`, unless the attestation is already there
+    pub fn add_attestation(&mut self, attestation: Attestation) {
+        if !self.attestations.contains(&attestation) {
+            self.attestations.push(attestation);
+        }
+    }
+
+    /// The timestamp of the result of `op`, added with message `output` if
+    /// `op` was not applied yet
+    pub fn op_entry(&mut self, op: Op, output: Vec<u8>) -> &mut Self {
+        let index = match self.ops.iter().position(|(existing, _)| *existing == op) {
+            Some(index) => index,
+            None => {
+                self.ops.push((op, Self::new(output)));
+                self.ops.len() - 1
+            }
+        };
+        &mut self.ops[index].1
+    }
+
+    /// The timestamp of `msg`, this one or one reached through the ops
+    pub fn find_mut(&mut self, msg: &[u8]) -> Option<&mut Self> {
+        if self.msg == msg {
+            return Some(self);
+        }
+        self.ops.iter_mut().find_map(|(_, child)| child.find_mut(msg))
+    }
+
+    /// Merge every attestation and op of `other` into this timestamp
+    ///
+    /// # Errors
+    ///
+    /// Returns `OtsError::DigestMismatch` if `other` timestamps a different message
+    pub fn merge(&mut self, other: Self) -> Result<()> {
+        if other.msg != self.msg {
+            return Err(OtsError::DigestMismatch);
+        }
+        for attestation in other.attestations {
+            self.add_attestation(attestation);
+        }
+        for (op, child) in other.ops {
+            self.op_entry(op, child.msg.clone()).merge(child)?;
+        }
+        Ok(())
+    }
+
+    /// Remove the pending attestations, and the ops left leading to no attestation
+    ///
+    /// Returns true if anything was removed.
+    pub fn prune_pending(&mut self) -> bool {
+        let attestations = self.attestations.len();
+        self.attestations.retain(|attestation| !matches!(attestation, Attestation::Pending { .. }));
+        let mut pruned = self.attestations.len() != attestations;
+        for (_, child) in &mut self.ops {
+            pruned |= child.prune_pending();
+        }
+        let ops = self.ops.len();
+        self.ops.retain(|(_, child)| !child.is_empty());
+        pruned || self.ops.len() != ops
+    }
+
+    /// Sort attestations and ops at every message into canonical order, see
+    /// `Attestation::canonical_cmp` and `Op::canonical_cmp`
+    pub fn sort(&mut self) {
+        self.attestations.sort_by(Attestation::canonical_cmp);
+        self.ops.sort_by(|(a, _), (b, _)| a.canonical_cmp(b));
+        for (_, child) in &mut self.ops {
+            child.sort();
+        }
+    }
+
+    /// Whether no attestation is reachable from this timestamp
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.attestations.is_empty() && self.ops.iter().all(|(_, child)| child.is_empty())
+    }
+
+    /// Every attestation, each with the message it attests to
+    #[must_use]
+    pub fn all_attestations(&self) -> Vec<(&[u8], &Attestation)> {
+        let mut found: Vec<(&[u8], &Attestation)> = self
+            .attestations
+            .iter()
+            .map(|attestation| (self.msg.as_slice(), attestation))
+            .collect();
+        for (_, child) in &self.ops {
+            found.extend(child.all_attestations());
+        }
+        found
+    }
+
+    /// The step tree starting from `msg`
+    ///
+    /// Attestations come first at each message, then ops, in the order they
+    /// were added; a message with several of them becomes a single fork.
+    ///
+    /// # Errors
+    ///
+    /// Returns `OtsError::IncompleteTimestamp` if a message has neither
+    /// attestations nor ops, since a tree cannot end without an attestation
+    pub fn to_step(&self) -> Result<Step> {
+        let mut branches: Vec<Step> = self
+            .attestations
+            .iter()
+            .map(|attestation| Step {
+                data: StepData::Attestation(attestation.clone()),
+                output: self.msg.clone(),
+                next: vec![],
+            })
+            .collect();
+        for (op, child) in &self.ops {
+            branches.push(Step {
+                data: StepData::Op(op.clone()),
+                output: child.msg.clone(),
+                next: vec![child.to_step()?],
+            });
+        }
+        match branches.len() {
+            0 => Err(OtsError::IncompleteTimestamp(1)),
+            1 => Ok(branches.remove(0)),
+            _ => Ok(Step { data: StepData::Fork, output: self.msg.clone(), next: branches }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attested(output: &[u8], attestation: Attestation) -> Step {
+        Step { data: StepData::Attestation(attestation), output: output.to_vec(), next: vec![] }
+    }
+
+    fn op(input: &[u8], op: Op, next: impl FnOnce(&[u8]) -> Step) -> Step {
+        let output = op.execute(input);
+        let next = next(&output);
+        Step { data: StepData::Op(op), output, next: vec![next] }
+    }
+
+    fn pending(uri: &str) -> Attestation {
+        Attestation::Pending { uri: uri.to_string() }
+    }
+
+    /// The message timestamped in these tests
+    fn start() -> Vec<u8> {
+        vec![0x01; 32]
+    }
+
+    /// A proof forking into a pending and a Bitcoin-confirmed calendar path
+    fn tree() -> Step {
+        let start = start();
+        Step {
+            data: StepData::Fork,
+            output: start.clone(),
+            next: vec![
+                op(&start, Op::Append(vec![0xaa]), |msg| {
+                    op(msg, Op::Sha256, |msg| attested(msg, pending("https://a.example")))
+                }),
+                op(&start, Op::Prepend(vec![0xbb]), |msg| {
+                    op(msg, Op::Sha256, |msg| attested(msg, Attestation::Bitcoin { height: 9 }))
+                }),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let tree = tree();
+        let map = TimestampMap::from_step(start(), &tree).unwrap();
+        assert_eq!(map.ops.len(), 2);
+        assert_eq!(map.all_attestations().len(), 2);
+        assert_eq!(map.to_step().unwrap(), tree);
+
+        let mut bytes = Vec::new();
+        map.serialize(&mut Serializer::new(&mut bytes)).unwrap();
+        let parsed =
+            TimestampMap::deserialize(&mut Deserializer::new(&bytes[..]), start()).unwrap();
+        assert_eq!(parsed, map);
+
+        assert!(matches!(
+            TimestampMap::new(start()).to_step(),
+            Err(OtsError::IncompleteTimestamp(_))
+        ));
+        assert!(matches!(
+            TimestampMap::new(start()).serialize(&mut Serializer::new(Vec::new())),
+            Err(OtsError::IncompleteTimestamp(_))
+        ));
+    }
+
+    #[test]
+    fn test_from_step_checks_tree() {
+        let mut corrupted = tree();
+        corrupted.next[1].next[0].output[0] ^= 1;
+        assert!(matches!(
+            TimestampMap::from_step(start(), &corrupted),
+            Err(OtsError::OutputMismatch)
+        ));
+
+        let mut dangling = tree();
+        dangling.next[0].next.clear();
+        assert!(matches!(
+            TimestampMap::from_step(start(), &dangling),
+            Err(OtsError::IncompleteTimestamp(_))
+        ));
+
+        let attestation = attested(&start(), pending("https://a.example"));
+        let mut continued = attestation.clone();
+        continued.next.push(attestation);
+        assert!(matches!(
+            TimestampMap::from_step(start(), &continued),
+            Err(OtsError::TimestampComplete)
+        ));
+    }
+
+    #[test]
+    fn test_deserialize_merges_branches() {
+        // Two branches applying the same ops, as a sloppy merge would write them
+        let mut bytes = Vec::new();
+        let mut ser = Serializer::new(&mut bytes);
+        for (i, attestation) in
+            [pending("https://a.example"), Attestation::Bitcoin { height: 9 }].iter().enumerate()
+        {
+            if i == 0 {
+                ser.write_byte(0xff).unwrap();
+            }
+            Op::Append(vec![0xaa]).serialize(&mut ser).unwrap();
+            Op::Sha256.serialize(&mut ser).unwrap();
+            ser.write_byte(0x00).unwrap();
+            attestation.serialize(&mut ser).unwrap();
+        }
+
+        let map = TimestampMap::deserialize(&mut Deserializer::new(&bytes[..]), start()).unwrap();
+        assert_eq!(map.ops.len(), 1);
+        let hashed = &map.ops[0].1.ops[0].1;
+        assert_eq!(hashed.msg, Op::Sha256.execute(&Op::Append(vec![0xaa]).execute(&start())));
+        assert_eq!(hashed.attestations.len(), 2);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut map = TimestampMap::from_step(start(), &tree()).unwrap();
+
+        // Merging a proof with itself changes nothing
+        map.merge(TimestampMap::from_step(start(), &tree()).unwrap()).unwrap();
+        assert_eq!(map.to_step().unwrap(), tree());
+
+        // A second calendar on the same path joins the existing ops
+        let other = op(&start(), Op::Append(vec![0xaa]), |msg| {
+            op(msg, Op::Sha256, |msg| attested(msg, pending("https://b.example")))
+        });
+        map.merge(TimestampMap::from_step(start(), &other).unwrap()).unwrap();
+        assert_eq!(map.ops.len(), 2);
+        assert_eq!(map.ops[0].1.ops[0].1.attestations.len(), 2);
+
+        let unrelated = TimestampMap::new(vec![0x02; 32]);
+        assert!(matches!(map.merge(unrelated), Err(OtsError::DigestMismatch)));
+
+        // find_mut reaches the message a calendar response starts from
+        let commitment = map.ops[1].1.msg.clone();
+        assert_eq!(map.find_mut(&commitment).unwrap().msg, commitment);
+        assert!(map.find_mut(&[0x02; 32]).is_none());
+    }
+
+    #[test]
+    fn test_prune_pending() {
+        let mut map = TimestampMap::from_step(start(), &tree()).unwrap();
+        assert!(map.prune_pending());
+        assert_eq!(map.ops.len(), 1);
+        assert_eq!(map.all_attestations()[0].1, &Attestation::Bitcoin { height: 9 });
+        assert!(!map.prune_pending());
+
+        // Only the confirmed calendar path is left
+        assert_eq!(map.to_step().unwrap(), tree().next.remove(1));
+    }
+}
@@ -0,0 +1,207 @@
+//! Key-value metadata carried in a vendor attestation
+//!
+//! Metadata such as a document ID or retention class travels inside the
+//! proof as an attestation with a private tag. Other OpenTimestamps clients
+//! see it as an unknown attestation and keep it untouched. Nobody attests to
+//! the metadata: it is a label on the proof, not part of what is proven.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::attestation::Attestation;
+use super::error::{OtsError, Result, MAX_PAYLOAD_LEN};
+use super::ser::{Deserializer, Serializer};
+use super::timestamp::{Step, StepData};
+
+/// Tag of the metadata attestation
+pub const METADATA_TAG: &[u8] = b"\x6f\x74\x73\x6d\x65\x74\x61\x01";
+
+/// Maximum length of a metadata key
+pub const MAX_METADATA_KEY_LEN: usize = 64;
+
+/// Maximum length of a metadata value
+pub const MAX_METADATA_VALUE_LEN: usize = 1024;
+
+/// Key-value metadata attached to a proof
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Metadata {
+    entries: BTreeMap<String, String>,
+}
+
+impl Metadata {
+    /// Add an entry, replacing any earlier value of `key`
+    ///
+    /// Keys are made of ASCII letters, digits, `.`, `-` and `_`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key is empty or has other characters, or
+    /// the key or value is too long
+    pub fn insert(&mut self, key: &str, value: &str) -> Result<()> {
+        if key.is_empty() || key.len() > MAX_METADATA_KEY_LEN {
+            return Err(OtsError::BadLength { min: 1, max: MAX_METADATA_KEY_LEN, val: key.len() });
+        }
+        if let Some(c) = key.chars().find(|c| !(c.is_ascii_alphanumeric() || "._-".contains(*c))) {
+            return Err(OtsError::InvalidMetadataKey(c));
+        }
+        if value.len() > MAX_METADATA_VALUE_LEN {
+            return Err(OtsError::BadLength {
+                min: 0,
+                max: MAX_METADATA_VALUE_LEN,
+                val: value.len(),
+            });
+        }
+        self.entries.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    /// Value of `key`, if set
+    #[must_use]
+    #[allow(dead_code)]
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.get(key).map(String::as_str)
+    }
+
+    /// Whether there are no entries
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Entries in key order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Encode the metadata as an attestation
+    ///
+    /// The payload is the number of entries followed by each key and value
+    /// as length-prefixed bytes, in key order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the payload exceeds `MAX_PAYLOAD_LEN`
+    pub fn to_attestation(&self) -> Result<Attestation> {
+        let mut ser = Serializer::new(Vec::new());
+        ser.write_uint(self.entries.len() as u64)?;
+        for (key, value) in &self.entries {
+            ser.write_bytes(key.as_bytes())?;
+            ser.write_bytes(value.as_bytes())?;
+        }
+        let data = ser.into_inner();
+        if data.len() > MAX_PAYLOAD_LEN {
+            return Err(OtsError::BadLength { min: 0, max: MAX_PAYLOAD_LEN, val: data.len() });
+        }
+        Ok(Attestation::Unknown { tag: METADATA_TAG.to_vec(), data })
+    }
+
+    /// Decode the metadata in an attestation
+    ///
+    /// Returns `None` if the attestation is not a metadata attestation.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the payload is malformed
+    pub fn from_attestation(attestation: &Attestation) -> Option<Result<Self>> {
+        match attestation {
+            Attestation::Unknown { tag, data } if tag == METADATA_TAG => Some(Self::decode(data)),
+            _ => None,
+        }
+    }
+
+    /// Whether an attestation carries metadata rather than a time claim
+    #[must_use]
+    pub fn is_metadata(attestation: &Attestation) -> bool {
+        attestation.unknown_tag() == Some(METADATA_TAG)
+    }
+
+    /// Collect the metadata of every metadata attestation in a timestamp tree
+    ///
+    /// Later attestations win when a key is set more than once.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a metadata attestation is malformed
+    pub fn collect(step: &Step) -> Result<Self> {
+        let mut metadata = Self::default();
+        Self::collect_recurse(step, &mut metadata)?;
+        Ok(metadata)
+    }
+
+    fn collect_recurse(step: &Step, metadata: &mut Self) -> Result<()> {
+        if let StepData::Attestation(attestation) = &step.data {
+            if let Some(found) = Self::from_attestation(attestation) {
+                metadata.entries.extend(found?.entries);
+            }
+        }
+        step.next.iter().try_for_each(|next| Self::collect_recurse(next, metadata))
+    }
+
+    fn decode(data: &[u8]) -> Result<Self> {
+        let mut deser = Deserializer::new(data);
+        let mut metadata = Self::default();
+        let count = deser.read_uint()?;
+        for _ in 0..count {
+            let key = String::from_utf8(deser.read_bytes(1, MAX_METADATA_KEY_LEN)?)?;
+            let value = String::from_utf8(deser.read_bytes(0, MAX_METADATA_VALUE_LEN)?)?;
+            metadata.insert(&key, &value)?;
+        }
+        deser.check_eof()?;
+        Ok(metadata)
+    }
+}
+
+impl fmt::Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (key, value)) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{key}={value}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut metadata = Metadata::default();
+        metadata.insert("doc-id", "INV-2024-0042").unwrap();
+        metadata.insert("retention", "10y").unwrap();
+
+        let attestation = metadata.to_attestation().unwrap();
+        assert!(Metadata::is_metadata(&attestation));
+        let decoded = Metadata::from_attestation(&attestation).unwrap().unwrap();
+        assert_eq!(decoded, metadata);
+        assert_eq!(decoded.get("retention"), Some("10y"));
+        assert_eq!(decoded.to_string(), "doc-id=INV-2024-0042, retention=10y");
+
+        // Other clients read it back as an ordinary unknown attestation
+        let mut buf = Vec::new();
+        attestation.serialize(&mut Serializer::new(&mut buf)).unwrap();
+        assert_eq!(Attestation::from_bytes(&buf).unwrap(), attestation);
+    }
+
+    #[test]
+    fn test_invalid_entries() {
+        let mut metadata = Metadata::default();
+        assert!(matches!(metadata.insert("", "x"), Err(OtsError::BadLength { .. })));
+        assert!(matches!(metadata.insert("doc id", "x"), Err(OtsError::InvalidMetadataKey(' '))));
+        assert!(metadata.insert("key", &"x".repeat(MAX_METADATA_VALUE_LEN + 1)).is_err());
+        assert!(metadata.is_empty());
+    }
+
+    #[test]
+    fn test_other_attestations() {
+        assert!(Metadata::from_attestation(&Attestation::Bitcoin { height: 1 }).is_none());
+        let other = Attestation::Unknown { tag: vec![1; 8], data: vec![] };
+        assert!(Metadata::from_attestation(&other).is_none());
+
+        let malformed = Attestation::Unknown { tag: METADATA_TAG.to_vec(), data: vec![0x05] };
+        assert!(Metadata::from_attestation(&malformed).unwrap().is_err());
+    }
+}
@@ -3,16 +3,32 @@
 //! This module contains all the core types needed for OTS file parsing,
 //! timestamp verification, and attestation handling.
 
+mod attached;
 mod attestation;
+#[cfg(feature = "cbor")]
+mod cbor;
+mod diff;
 mod digest;
+mod document;
 mod error;
+mod map;
+mod metadata;
 mod op;
 mod ser;
+mod signed;
+mod stream;
 mod timestamp;
 
+pub use attached::*;
 pub use attestation::*;
+pub use diff::*;
 pub use digest::*;
+pub use document::*;
 pub use error::*;
+pub use map::*;
+pub use metadata::*;
 pub use op::*;
 pub use ser::*;
+pub use signed::*;
+pub use stream::*;
 pub use timestamp::*;
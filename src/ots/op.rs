@@ -3,13 +3,15 @@
 //! Operations that can be performed on data in an OpenTimestamps proof.
 //! Each operation takes input bytes and produces output bytes.
 
+use std::cmp::Ordering;
 use std::fmt;
 use std::io::{Read, Write};
 
-use bitcoin_hashes::{ripemd160, sha1, sha256, Hash};
+use bitcoin_hashes::{ripemd160, sha1, Hash};
+use sha3::{Digest, Keccak256};
 
-use super::error::{OtsError, Result, MAX_OP_LENGTH};
-use super::ser::{Deserializer, Serializer};
+use super::error::{OtsError, Result, MAX_MSG_LENGTH, MAX_RESULT_LENGTH};
+use super::ser::{varint_len, Deserializer, Serializer};
 
 /// All the types of operations supported
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -21,6 +23,8 @@ pub enum Op {
     Sha256,
     /// RIPEMD-160 hash operation
     Ripemd160,
+    /// Keccak-256 hash operation
+    Keccak256,
     /// Convert bytes to hexadecimal string
     Hexlify,
     /// Reverse byte order
@@ -29,6 +33,11 @@ pub enum Op {
     Append(Vec<u8>),
     /// Prepend data to the beginning
     Prepend(Vec<u8>),
+    /// Op with a tag this client does not know, only read in lenient mode
+    ///
+    /// Passes its input through unchanged, so the rest of the proof can be
+    /// shown; `try_execute` refuses it, so it never verifies.
+    Unknown(u8),
 }
 
 impl Op {
@@ -38,19 +47,31 @@ impl Op {
             Op::Sha1 => 0x02,
             Op::Sha256 => 0x08,
             Op::Ripemd160 => 0x03,
+            Op::Keccak256 => 0x67,
             Op::Hexlify => 0xf3,
             Op::Reverse => 0xf2,
             Op::Append(_) => 0xf0,
             Op::Prepend(_) => 0xf1,
+            Op::Unknown(tag) => tag,
         }
     }
 
+    /// Order ops as python-opentimestamps sorts them: by tag, then by argument
+    #[must_use]
+    pub fn canonical_cmp(&self, other: &Self) -> Ordering {
+        self.tag().cmp(&other.tag()).then_with(|| match (self, other) {
+            (Op::Append(a), Op::Append(b)) | (Op::Prepend(a), Op::Prepend(b)) => a.cmp(b),
+            _ => Ordering::Equal,
+        })
+    }
+
     /// Execute the operation on the given input data
     pub fn execute(&self, input: &[u8]) -> Vec<u8> {
         match *self {
             Op::Sha1 => sha1::Hash::hash(input).to_byte_array().to_vec(),
-            Op::Sha256 => sha256::Hash::hash(input).to_byte_array().to_vec(),
+            Op::Sha256 => sha256_digest(input).to_vec(),
             Op::Ripemd160 => ripemd160::Hash::hash(input).to_byte_array().to_vec(),
+            Op::Keccak256 => Keccak256::digest(input).to_vec(),
             Op::Hexlify => hex::encode(input).into_bytes(),
             Op::Reverse => input.iter().copied().rev().collect(),
             Op::Append(ref data) => {
@@ -63,9 +84,91 @@ impl Op {
                 vec.extend(input);
                 vec
             }
+            Op::Unknown(_) => input.to_vec(),
+        }
+    }
+
+    /// Execute the operation, replacing `data` with the result
+    ///
+    /// Hashes, `Append` and `Prepend` reuse the buffer of `data`, so running
+    /// many ops does not allocate a new result for each.
+    pub fn execute_in_place(&self, data: &mut Vec<u8>) {
+        match *self {
+            Op::Sha1 => {
+                let digest = sha1::Hash::hash(data).to_byte_array();
+                replace(data, &digest);
+            }
+            Op::Sha256 => {
+                let digest = sha256_digest(data);
+                replace(data, &digest);
+            }
+            Op::Ripemd160 => {
+                let digest = ripemd160::Hash::hash(data).to_byte_array();
+                replace(data, &digest);
+            }
+            Op::Keccak256 => {
+                let digest = Keccak256::digest(&data[..]);
+                replace(data, &digest);
+            }
+            Op::Hexlify => *data = hex::encode(&data[..]).into_bytes(),
+            Op::Reverse => data.reverse(),
+            Op::Append(ref extra) => data.extend_from_slice(extra),
+            Op::Prepend(ref extra) => {
+                data.splice(0..0, extra.iter().copied());
+            }
+            Op::Unknown(_) => {}
         }
     }
 
+    /// Execute the operation, enforcing the length rules of the specification
+    ///
+    /// - Inputs may be at most `MAX_MSG_LENGTH` bytes, and at most half of
+    ///   that for `Hexlify`, whose result is twice as long
+    /// - `Hexlify` and `Reverse` reject empty inputs
+    /// - Results may be at most `MAX_RESULT_LENGTH` bytes
+    /// - Hash operations produce exactly their digest length
+    ///
+    /// # Errors
+    ///
+    /// Returns `OtsError::BadOpInput` or `OtsError::BadOpResult` if a rule is violated,
+    /// or `OtsError::BadOpTag` for an `Unknown` op
+    pub fn try_execute(&self, input: &[u8]) -> Result<Vec<u8>> {
+        let mut output = input.to_vec();
+        self.try_execute_in_place(&mut output)?;
+        Ok(output)
+    }
+
+    /// Execute the operation like `try_execute`, replacing `data` with the result
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as `try_execute`; `data` is left unchanged if
+    /// the input is rejected
+    pub fn try_execute_in_place(&self, data: &mut Vec<u8>) -> Result<()> {
+        if let Op::Unknown(tag) = *self {
+            return Err(OtsError::BadOpTag(tag));
+        }
+        let max_input = match *self {
+            Op::Hexlify => MAX_RESULT_LENGTH / 2,
+            _ => MAX_MSG_LENGTH,
+        };
+        let empty_forbidden = matches!(*self, Op::Hexlify | Op::Reverse);
+        if data.len() > max_input || (empty_forbidden && data.is_empty()) {
+            return Err(OtsError::BadOpInput { tag: self.tag(), len: data.len() });
+        }
+
+        self.execute_in_place(data);
+        let valid_len = match *self {
+            Op::Sha1 | Op::Ripemd160 => data.len() == 20,
+            Op::Sha256 | Op::Keccak256 => data.len() == 32,
+            _ => data.len() <= MAX_RESULT_LENGTH,
+        };
+        if !valid_len {
+            return Err(OtsError::BadOpResult { tag: self.tag(), len: data.len() });
+        }
+        Ok(())
+    }
+
     /// Deserialize an arbitrary op
     ///
     /// # Errors
@@ -81,22 +184,37 @@ impl Op {
     ///
     /// # Errors
     ///
-    /// Returns `OtsError::BadOpTag` if the tag is not recognized
+    /// Returns `OtsError::BadOpTag` if the tag is not recognized, unless the
+    /// deserializer is lenient
     pub fn deserialize_with_tag<R: Read>(deser: &mut Deserializer<R>, tag: u8) -> Result<Self> {
         match tag {
             // unary ops are trivial
             0x02 => Ok(Self::Sha1),
             0x08 => Ok(Self::Sha256),
             0x03 => Ok(Self::Ripemd160),
+            0x67 => Ok(Self::Keccak256),
             0xf3 => Ok(Self::Hexlify),
             0xf2 => Ok(Self::Reverse),
             // binary ops need to read data
-            0xf0 => Ok(Self::Append(deser.read_bytes(1, MAX_OP_LENGTH)?)),
-            0xf1 => Ok(Self::Prepend(deser.read_bytes(1, MAX_OP_LENGTH)?)),
+            0xf0 => Ok(Self::Append(deser.read_bytes(1, deser.options().max_op_len)?)),
+            0xf1 => Ok(Self::Prepend(deser.read_bytes(1, deser.options().max_op_len)?)),
+            x if deser.options().lenient => Ok(Self::Unknown(x)),
             x => Err(OtsError::BadOpTag(x)),
         }
     }
 
+    /// Number of bytes `serialize` writes for this op
+    #[must_use]
+    #[allow(dead_code)]
+    pub fn serialized_len(&self) -> usize {
+        match self {
+            Self::Append(data) | Self::Prepend(data) => {
+                1 + varint_len(data.len() as u64) + data.len()
+            }
+            _ => 1,
+        }
+    }
+
     /// Serialize the op into a serializer
     ///
     /// # Errors
@@ -104,26 +222,46 @@ impl Op {
     /// Returns an error if the write operation fails
     pub fn serialize<W: Write>(&self, ser: &mut Serializer<W>) -> Result<()> {
         ser.write_byte(self.tag())?;
-        if let Self::Append(ref data) = *self {
-            ser.write_bytes(data)?;
-        }
-        if let Self::Prepend(ref data) = *self {
+        if let Self::Append(data) | Self::Prepend(data) = self {
             ser.write_bytes(data)?;
         }
         Ok(())
     }
 }
 
+/// SHA-256 digest of `input`
+///
+/// With the `asm` feature this uses the sha2 crate with its assembly
+/// backend, which also picks the CPU's SHA extensions at runtime.
+#[cfg(feature = "asm")]
+fn sha256_digest(input: &[u8]) -> [u8; 32] {
+    sha2::Sha256::digest(input).into()
+}
+
+/// SHA-256 digest of `input`
+#[cfg(not(feature = "asm"))]
+fn sha256_digest(input: &[u8]) -> [u8; 32] {
+    bitcoin_hashes::sha256::Hash::hash(input).to_byte_array()
+}
+
+/// Replace the contents of `data` with `digest`, keeping its allocation
+fn replace(data: &mut Vec<u8>, digest: &[u8]) {
+    data.clear();
+    data.extend_from_slice(digest);
+}
+
 impl fmt::Display for Op {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Op::Sha1 => f.write_str("SHA1()"),
             Op::Sha256 => f.write_str("SHA256()"),
             Op::Ripemd160 => f.write_str("RIPEMD160()"),
+            Op::Keccak256 => f.write_str("KECCAK256()"),
             Op::Hexlify => f.write_str("Hexlify()"),
             Op::Reverse => f.write_str("Reverse()"),
             Op::Append(ref data) => write!(f, "Append({})", hex::encode(data)),
             Op::Prepend(ref data) => write!(f, "Prepend({})", hex::encode(data)),
+            Op::Unknown(tag) => write!(f, "Unknown(0x{tag:02x})"),
         }
     }
 }
@@ -137,6 +275,7 @@ mod tests {
         assert_eq!(Op::Sha1.tag(), 0x02);
         assert_eq!(Op::Sha256.tag(), 0x08);
         assert_eq!(Op::Ripemd160.tag(), 0x03);
+        assert_eq!(Op::Keccak256.tag(), 0x67);
         assert_eq!(Op::Hexlify.tag(), 0xf3);
         assert_eq!(Op::Reverse.tag(), 0xf2);
         assert_eq!(Op::Append(vec![]).tag(), 0xf0);
@@ -172,6 +311,17 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn test_keccak256_execute() {
+        let input = b"hello";
+        let result = Op::Keccak256.execute(input);
+        // Expected Keccak-256 hash of "hello"
+        let expected =
+            hex::decode("1c8aff950685c2ed4bc3174f3472287b56d9517b9c948127319a09a7a36deac8")
+                .unwrap();
+        assert_eq!(result, expected);
+    }
+
     #[test]
     fn test_append_execute() {
         let input = b"hello";
@@ -202,11 +352,42 @@ mod tests {
         assert_eq!(result, b"010203ff");
     }
 
+    #[test]
+    fn test_execute_in_place() {
+        let ops = vec![
+            Op::Append(vec![0x01, 0x02]),
+            Op::Sha256,
+            Op::Prepend(vec![0xff]),
+            Op::Reverse,
+            Op::Sha1,
+            Op::Hexlify,
+            Op::Ripemd160,
+            Op::Keccak256,
+        ];
+        let expected = ops.iter().fold(b"hello".to_vec(), |data, op| op.execute(&data));
+        let mut data = b"hello".to_vec();
+        for op in &ops {
+            op.execute_in_place(&mut data);
+        }
+        assert_eq!(data, expected);
+
+        let mut data = b"hello".to_vec();
+        for op in &ops {
+            op.try_execute_in_place(&mut data).unwrap();
+        }
+        assert_eq!(data, expected);
+
+        let mut data = vec![];
+        assert!(Op::Reverse.try_execute_in_place(&mut data).is_err());
+        assert!(data.is_empty());
+    }
+
     #[test]
     fn test_display() {
         assert_eq!(format!("{}", Op::Sha1), "SHA1()");
         assert_eq!(format!("{}", Op::Sha256), "SHA256()");
         assert_eq!(format!("{}", Op::Ripemd160), "RIPEMD160()");
+        assert_eq!(format!("{}", Op::Keccak256), "KECCAK256()");
         assert_eq!(format!("{}", Op::Hexlify), "Hexlify()");
         assert_eq!(format!("{}", Op::Reverse), "Reverse()");
         assert_eq!(format!("{}", Op::Append(vec![0x01, 0x02, 0x03])), "Append(010203)");
@@ -226,7 +407,8 @@ mod tests {
 
     #[test]
     fn test_serialize_deserialize_unary_ops() {
-        let ops = vec![Op::Sha1, Op::Sha256, Op::Ripemd160, Op::Hexlify, Op::Reverse];
+        let ops =
+            vec![Op::Sha1, Op::Sha256, Op::Ripemd160, Op::Keccak256, Op::Hexlify, Op::Reverse];
 
         for op in ops {
             let mut buf = Vec::new();
@@ -251,6 +433,15 @@ mod tests {
         assert_eq!(op, deserialized);
     }
 
+    #[test]
+    fn test_serialized_len() {
+        for op in [Op::Sha256, Op::Append(vec![0xaa; 3]), Op::Prepend(vec![0x11; 200])] {
+            let mut buf = Vec::new();
+            op.serialize(&mut Serializer::new(&mut buf)).unwrap();
+            assert_eq!(op.serialized_len(), buf.len(), "{op}");
+        }
+    }
+
     #[test]
     fn test_serialize_deserialize_prepend() {
         let op = Op::Prepend(vec![0x01, 0x02, 0x03, 0x04]);
@@ -279,6 +470,7 @@ mod tests {
             (0x02, Op::Sha1),
             (0x08, Op::Sha256),
             (0x03, Op::Ripemd160),
+            (0x67, Op::Keccak256),
             (0xf3, Op::Hexlify),
             (0xf2, Op::Reverse),
         ] {
@@ -337,4 +529,36 @@ mod tests {
         let result = Op::Prepend(vec![]).execute(input);
         assert_eq!(result, input);
     }
+
+    #[test]
+    fn test_try_execute_valid() {
+        assert_eq!(Op::Sha256.try_execute(b"hello").unwrap(), Op::Sha256.execute(b"hello"));
+        assert_eq!(Op::Append(vec![1]).try_execute(b"").unwrap(), vec![1]);
+        assert_eq!(Op::Sha256.try_execute(&[0u8; MAX_MSG_LENGTH]).unwrap().len(), 32);
+    }
+
+    #[test]
+    fn test_try_execute_input_limits() {
+        assert!(matches!(
+            Op::Sha256.try_execute(&[0u8; MAX_MSG_LENGTH + 1]),
+            Err(OtsError::BadOpInput { tag: 0x08, len }) if len == MAX_MSG_LENGTH + 1
+        ));
+        assert!(Op::Hexlify.try_execute(&[0u8; MAX_RESULT_LENGTH / 2]).is_ok());
+        assert!(matches!(
+            Op::Hexlify.try_execute(&[0u8; MAX_RESULT_LENGTH / 2 + 1]),
+            Err(OtsError::BadOpInput { .. })
+        ));
+        assert!(matches!(Op::Hexlify.try_execute(b""), Err(OtsError::BadOpInput { .. })));
+        assert!(matches!(Op::Reverse.try_execute(b""), Err(OtsError::BadOpInput { .. })));
+    }
+
+    #[test]
+    fn test_try_execute_result_limit() {
+        let op = Op::Append(vec![0u8; 100]);
+        assert!(op.try_execute(&[0u8; MAX_RESULT_LENGTH - 100]).is_ok());
+        assert!(matches!(
+            op.try_execute(&[0u8; MAX_RESULT_LENGTH - 99]),
+            Err(OtsError::BadOpResult { tag: 0xf0, len }) if len == MAX_RESULT_LENGTH + 1
+        ));
+    }
 }
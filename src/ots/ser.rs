@@ -3,11 +3,16 @@
 //! This module provides tools for reading and writing OTS timestamp files.
 
 use std::fmt;
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
 
-use super::digest::DigestType;
-use super::error::{OtsError, Result};
-use super::timestamp::Timestamp;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+
+use super::digest::{Digest, DigestType};
+use super::error::{
+    OtsError, Result, MAX_OP_LENGTH, MAX_PAYLOAD_LEN, MAX_URI_LEN, MAX_VARINT_LEN, RECURSION_LIMIT,
+};
+use super::timestamp::{Step, Timestamp};
 
 /// Magic bytes that every OTS proof must start with
 pub const MAGIC: &[u8] = b"\x00OpenTimestamps\x00\x00Proof\x00\xbf\x89\xe2\xe8\x84\xe8\x92\x94";
@@ -15,16 +20,165 @@ pub const MAGIC: &[u8] = b"\x00OpenTimestamps\x00\x00Proof\x00\xbf\x89\xe2\xe8\x
 /// Major version of timestamp files we understand
 pub const VERSION: usize = 1;
 
+/// Largest integer that fits in `MAX_VARINT_LEN` bytes of LEB128
+pub const MAX_VARINT: u64 = (1 << (7 * MAX_VARINT_LEN)) - 1;
+
+/// Number of bytes `Serializer::write_uint` takes to encode `n`
+#[must_use]
+pub const fn varint_len(mut n: u64) -> usize {
+    let mut len = 1;
+    while n > 0x7f {
+        n >>= 7;
+        len += 1;
+    }
+    len
+}
+
+/// First line of an ASCII-armored proof
+pub const ARMOR_BEGIN: &str = "-----BEGIN OPENTIMESTAMPS PROOF-----";
+
+/// Last line of an ASCII-armored proof
+pub const ARMOR_END: &str = "-----END OPENTIMESTAMPS PROOF-----";
+
+/// Base64 characters per line of an armored proof, as in PEM
+const ARMOR_LINE_LEN: usize = 64;
+
+/// Wrap a proof in base64 between `ARMOR_BEGIN` and `ARMOR_END` lines
+///
+/// The result is plain text that survives being pasted into an email, a
+/// ticket or a commit message.
+#[must_use]
+pub fn armor(bytes: &[u8]) -> String {
+    let encoded = BASE64.encode(bytes);
+    let mut text = String::with_capacity(encoded.len() + encoded.len() / ARMOR_LINE_LEN + 80);
+    text.push_str(ARMOR_BEGIN);
+    text.push('\n');
+    for line in encoded.as_bytes().chunks(ARMOR_LINE_LEN) {
+        text.push_str(&String::from_utf8_lossy(line));
+        text.push('\n');
+    }
+    text.push_str(ARMOR_END);
+    text.push('\n');
+    text
+}
+
+/// Whether `data` holds an armored proof rather than a binary one
+///
+/// Text around the armor, such as the rest of an email, is allowed.
+#[must_use]
+pub fn is_armored(data: &[u8]) -> bool {
+    !data.starts_with(MAGIC) && find(data, ARMOR_BEGIN.as_bytes()).is_some()
+}
+
+/// The proof inside the first armor of `text`
+///
+/// Anything before `ARMOR_BEGIN` and after `ARMOR_END` is ignored, as is
+/// whitespace, so line endings and indentation may change in transit.
+///
+/// # Errors
+///
+/// Returns `OtsError::BadArmor` if either line is missing or the text
+/// between them is not base64
+pub fn dearmor(text: &[u8]) -> Result<Vec<u8>> {
+    let start = find(text, ARMOR_BEGIN.as_bytes())
+        .ok_or_else(|| OtsError::BadArmor(format!("no {ARMOR_BEGIN} line")))?
+        + ARMOR_BEGIN.len();
+    let len = find(&text[start..], ARMOR_END.as_bytes())
+        .ok_or_else(|| OtsError::BadArmor(format!("no {ARMOR_END} line")))?;
+    let encoded: Vec<u8> =
+        text[start..start + len].iter().copied().filter(|c| !c.is_ascii_whitespace()).collect();
+    BASE64.decode(encoded).map_err(|e| OtsError::BadArmor(e.to_string()))
+}
+
+/// Position of the first occurrence of `needle` in `haystack`
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Largest buffer `read_fixed_bytes` allocates before seeing any data
+const PREALLOC_LIMIT: usize = 4096;
+
+/// Limits applied while parsing a proof
+///
+/// The defaults are those of the specification, which every proof made by a
+/// conforming client stays within. A server parsing untrusted proofs can
+/// tighten them, and an archival tool reading unusual proofs loosen them.
+/// Results of ops stay bounded by `MAX_RESULT_LENGTH` either way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeserializerOptions {
+    /// Longest URI of a pending attestation
+    pub max_uri_len: usize,
+    /// Longest argument of an `Append` or `Prepend` op
+    pub max_op_len: usize,
+    /// Deepest nesting of steps
+    pub max_recursion: usize,
+    /// Longest payload of an unknown attestation
+    pub max_payload_len: usize,
+    /// Reject integers padded with redundant zero bytes, see `Deserializer::strict`
+    pub strict: bool,
+    /// Tolerate what a newer or sloppy client might write, for inspecting
+    /// proofs rather than verifying them
+    ///
+    /// Trailing bytes after the proof are ignored, versions newer than
+    /// `VERSION` are read as if they were it, and unknown op tags become
+    /// `Op::Unknown` nodes, assumed to take no argument. Such ops cannot be
+    /// executed, so proofs containing them never verify.
+    pub lenient: bool,
+}
+
+impl Default for DeserializerOptions {
+    fn default() -> Self {
+        Self {
+            max_uri_len: MAX_URI_LEN,
+            max_op_len: MAX_OP_LENGTH,
+            max_recursion: RECURSION_LIMIT,
+            max_payload_len: MAX_PAYLOAD_LEN,
+            strict: false,
+            lenient: false,
+        }
+    }
+}
+
+impl DeserializerOptions {
+    /// The default limits in lenient mode, see `lenient`
+    #[must_use]
+    pub fn lenient() -> Self {
+        Self { lenient: true, ..Self::default() }
+    }
+}
+
 /// Structure representing a detached timestamp file
+///
+/// The document digest is the start digest of `timestamp`, which carries
+/// the hash function that produced it.
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct DetachedTimestampFile {
-    /// The claimed hash function used to produce the document digest
-    pub digest_type: DigestType,
     /// The actual timestamp data
     pub timestamp: Timestamp,
 }
 
 impl DetachedTimestampFile {
+    /// A timestamp file for `digest`, verified by the steps from `first_step`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `Timestamp::new` if the steps do not follow from `digest`
+    pub fn new(digest: Digest, first_step: &Step) -> Result<Self> {
+        Ok(Self { timestamp: Timestamp::new(digest, first_step)? })
+    }
+
+    /// The document digest the timestamp starts from
+    #[must_use]
+    pub const fn digest(&self) -> &Digest {
+        self.timestamp.start_digest()
+    }
+
+    /// The claimed hash function used to produce the document digest
+    #[must_use]
+    pub const fn digest_type(&self) -> DigestType {
+        self.timestamp.start_digest().digest_type()
+    }
+
     /// Deserialize a timestamp file from a reader
     ///
     /// # Errors
@@ -35,17 +189,45 @@ impl DetachedTimestampFile {
     /// - The file format is invalid
     /// - There is trailing data after the timestamp
     pub fn from_reader<R: Read>(reader: R) -> Result<Self> {
-        let mut deser = Deserializer::new(reader);
+        Self::from_reader_with_options(reader, DeserializerOptions::default())
+    }
+
+    /// Deserialize a timestamp file from a reader within custom limits
+    ///
+    /// # Errors
+    ///
+    /// Returns an error under the same conditions as `from_reader`, or if
+    /// the proof exceeds one of the `options` limits
+    pub fn from_reader_with_options<R: Read>(
+        reader: R,
+        options: DeserializerOptions,
+    ) -> Result<Self> {
+        let mut deser = Deserializer::with_options(reader, options);
 
         deser.read_magic()?;
         deser.read_version()?;
         let digest_type = DigestType::from_tag(deser.read_byte()?)?;
-        let digest = deser.read_fixed_bytes(digest_type.digest_len())?;
+        let digest = Digest::new(digest_type, deser.read_fixed_bytes(digest_type.digest_len())?)?;
         let timestamp = Timestamp::deserialize(&mut deser, digest)?;
 
-        deser.check_eof()?;
+        if !options.lenient {
+            deser.check_eof()?;
+        }
 
-        Ok(Self { digest_type, timestamp })
+        Ok(Self { timestamp })
+    }
+
+    /// Deserialize a timestamp file held in memory
+    ///
+    /// Guaranteed to return an error rather than panic or abort on any input,
+    /// however malformed; this is the entry point exercised by the fuzzer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error under the same conditions as `from_reader`
+    #[allow(dead_code)]
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        Self::from_reader(data)
     }
 
     /// Serialize the timestamp file into a writer
@@ -54,20 +236,49 @@ impl DetachedTimestampFile {
     ///
     /// Returns an error if any I/O operation fails
     pub fn to_writer<W: Write>(&self, writer: W) -> Result<()> {
+        let digest = self.digest();
         let mut ser = Serializer::new(writer);
         ser.write_magic()?;
         ser.write_version()?;
-        ser.write_byte(self.digest_type.to_tag())?;
-        // We write timestamp.start_digest here and not in `Timestamp::serialize`
+        ser.write_byte(digest.digest_type().to_tag())?;
+        // We write timestamp.start_digest() here and not in `Timestamp::serialize`
         // to copy the way that python-opentimestamps is structured
-        ser.write_fixed_bytes(&self.timestamp.start_digest)?;
+        ser.write_fixed_bytes(digest.as_bytes())?;
         self.timestamp.serialize(&mut ser)
     }
+
+    /// Serialize the timestamp file as an ASCII-armored proof, see `armor`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error under the same conditions as `to_writer`
+    pub fn to_armored(&self) -> Result<String> {
+        let mut bytes = Vec::new();
+        self.to_writer(&mut bytes)?;
+        Ok(armor(&bytes))
+    }
+
+    /// Serialize the timestamp file into a writer in canonical form
+    ///
+    /// See `Timestamp::serialize_canonical`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error under the same conditions as `to_writer`
+    pub fn to_writer_canonical<W: Write>(&self, writer: W) -> Result<()> {
+        let digest = self.digest();
+        let mut ser = Serializer::new(writer);
+        ser.write_magic()?;
+        ser.write_version()?;
+        ser.write_byte(digest.digest_type().to_tag())?;
+        ser.write_fixed_bytes(digest.as_bytes())?;
+        self.timestamp.serialize_canonical(&mut ser)
+    }
 }
 
 impl fmt::Display for DetachedTimestampFile {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(f, "{} digest of some data.", self.digest_type)?;
+        writeln!(f, "{} digest of some data.", self.digest_type())?;
         write!(f, "{}", self.timestamp)
     }
 }
@@ -75,13 +286,37 @@ impl fmt::Display for DetachedTimestampFile {
 /// Standard deserializer for OTS timestamp files
 pub struct Deserializer<R: Read> {
     reader: R,
+    options: DeserializerOptions,
 }
 
 impl<R: Read> Deserializer<R> {
     /// Constructs a new deserializer from a reader
     #[must_use]
     pub fn new(reader: R) -> Self {
-        Self { reader }
+        Self::with_options(reader, DeserializerOptions::default())
+    }
+
+    /// Constructs a new deserializer applying custom limits
+    #[must_use]
+    pub fn with_options(reader: R, options: DeserializerOptions) -> Self {
+        Self { reader, options }
+    }
+
+    /// The limits this deserializer applies
+    #[must_use]
+    pub const fn options(&self) -> &DeserializerOptions {
+        &self.options
+    }
+
+    /// Reject encodings other implementations might read differently
+    ///
+    /// In strict mode, integers padded with redundant zero bytes are an error
+    /// instead of being accepted.
+    #[must_use]
+    #[allow(dead_code)]
+    pub fn strict(mut self) -> Self {
+        self.options.strict = true;
+        self
     }
 
     /// Extracts the underlying reader from the deserializer
@@ -107,12 +342,14 @@ impl<R: Read> Deserializer<R> {
 
     /// Reads the version and checks that it matches the expected value
     ///
+    /// Newer versions are accepted in lenient mode.
+    ///
     /// # Errors
     ///
     /// Returns `OtsError::BadVersion` if the version is not supported
     pub fn read_version(&mut self) -> Result<()> {
         let recv_version = self.read_uint()?;
-        if recv_version == VERSION {
+        if recv_version == VERSION || (self.options.lenient && recv_version > VERSION) {
             Ok(())
         } else {
             Err(OtsError::BadVersion(recv_version))
@@ -134,33 +371,58 @@ impl<R: Read> Deserializer<R> {
     ///
     /// # Errors
     ///
-    /// Returns an error if the read operation fails
+    /// Returns an error if the integer is malformed (see `read_u64`) or does
+    /// not fit in a `usize`
     pub fn read_uint(&mut self) -> Result<usize> {
+        usize::try_from(self.read_u64()?).map_err(|_| OtsError::VarintOverflow)
+    }
+
+    /// Deserializes a 64-bit unsigned integer using LEB128 variable-length encoding
+    ///
+    /// At most `MAX_VARINT_LEN` bytes are read, so the value never needs
+    /// more than 63 bits and cannot wrap, whatever the input.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - The integer is longer than `MAX_VARINT_LEN` bytes (`OtsError::VarintTooLong`)
+    /// - The encoding is not minimal and the deserializer is strict
+    /// - The read operation fails
+    pub fn read_u64(&mut self) -> Result<u64> {
         let mut ret = 0;
-        let mut shift = 0;
 
-        loop {
+        for i in 0..MAX_VARINT_LEN {
             // Bottom 7 bits are value bits
             let byte = self.read_byte()?;
-            ret |= ((byte & 0x7f) as usize) << shift;
+            ret |= u64::from(byte & 0x7f) << (7 * i);
             // Top bit is a continue bit
             if byte & 0x80 == 0 {
-                break;
+                // A zero final byte only pads out a shorter encoding
+                if self.options.strict && byte == 0 && i > 0 {
+                    return Err(OtsError::NonMinimalVarint);
+                }
+                return Ok(ret);
             }
-            shift += 7;
         }
 
-        Ok(ret)
+        Err(OtsError::VarintTooLong)
     }
 
     /// Deserializes a fixed number of bytes
     ///
+    /// The buffer grows as data arrives rather than being allocated up front,
+    /// so a bogus length in a malformed file cannot exhaust memory.
+    ///
     /// # Errors
     ///
-    /// Returns an error if the read operation fails
+    /// Returns an error if the read operation fails or the input ends early
     pub fn read_fixed_bytes(&mut self, n: usize) -> Result<Vec<u8>> {
-        let mut ret = vec![0; n];
-        self.reader.read_exact(&mut ret)?;
+        let mut ret = Vec::with_capacity(n.min(PREALLOC_LIMIT));
+        let limit = u64::try_from(n).unwrap_or(u64::MAX);
+        self.reader.by_ref().take(limit).read_to_end(&mut ret)?;
+        if ret.len() != n {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
         Ok(ret)
     }
 
@@ -226,7 +488,7 @@ impl<W: Write> Serializer<W> {
     ///
     /// Returns an error if the write operation fails
     pub fn write_version(&mut self) -> Result<()> {
-        self.write_uint(VERSION)
+        self.write_uint(VERSION as u64)
     }
 
     /// Writes a single byte to the writer
@@ -241,22 +503,23 @@ impl<W: Write> Serializer<W> {
 
     /// Write an unsigned integer using LEB128 variable-length encoding
     ///
+    /// Takes a `u64` so proofs come out the same on 32- and 64-bit targets.
+    ///
     /// # Errors
     ///
-    /// Returns an error if the write operation fails
-    pub fn write_uint(&mut self, mut n: usize) -> Result<()> {
-        if n == 0 {
-            self.write_byte(0x00)
-        } else {
-            while n > 0 {
-                if n > 0x7f {
-                    self.write_byte((n as u8) | 0x80)?;
-                } else {
-                    self.write_byte(n as u8)?;
-                }
-                n >>= 7;
+    /// Returns `OtsError::VarintOverflow` if `n` needs more than `MAX_VARINT_LEN`
+    /// bytes, or an error if the write operation fails
+    pub fn write_uint(&mut self, mut n: u64) -> Result<()> {
+        if n > MAX_VARINT {
+            return Err(OtsError::VarintOverflow);
+        }
+        loop {
+            if n > 0x7f {
+                self.write_byte((n as u8) | 0x80)?;
+            } else {
+                return self.write_byte(n as u8);
             }
-            Ok(())
+            n >>= 7;
         }
     }
 
@@ -276,7 +539,7 @@ impl<W: Write> Serializer<W> {
     ///
     /// Returns an error if the write operation fails
     pub fn write_bytes(&mut self, data: &[u8]) -> Result<()> {
-        self.write_uint(data.len())?;
+        self.write_uint(data.len() as u64)?;
         self.write_fixed_bytes(data)
     }
 }
@@ -368,7 +631,7 @@ mod tests {
             ser.write_uint(val).unwrap();
 
             let mut deser = Deserializer::new(&buf[..]);
-            let result = deser.read_uint().unwrap();
+            let result = deser.read_u64().unwrap();
             assert_eq!(result, val, "Round-trip failed for value {}", val);
         }
     }
@@ -392,7 +655,7 @@ mod tests {
     fn test_version_ok() {
         let mut buf = Vec::new();
         let mut ser = Serializer::new(&mut buf);
-        ser.write_uint(VERSION).unwrap();
+        ser.write_uint(VERSION as u64).unwrap();
 
         let mut deser = Deserializer::new(&buf[..]);
         assert!(deser.read_version().is_ok());
@@ -492,7 +755,7 @@ mod tests {
             ser.write_uint(val).unwrap();
 
             let mut deser = Deserializer::new(&buf[..]);
-            let result = deser.read_uint().unwrap();
+            let result = deser.read_u64().unwrap();
             assert_eq!(result, val, "Failed round-trip for value {}", val);
         }
     }
@@ -551,51 +814,96 @@ mod tests {
 
     #[test]
     fn test_detached_timestamp_display() {
-        use crate::ots::digest::DigestType;
         use crate::ots::timestamp::*;
 
-        let file = DetachedTimestampFile {
-            digest_type: DigestType::Sha256,
-            timestamp: Timestamp {
-                start_digest: vec![0xaa, 0xbb],
-                first_step: Step {
-                    data: StepData::Attestation(crate::ots::attestation::Attestation::Bitcoin {
-                        height: 100,
-                    }),
-                    output: vec![0xaa, 0xbb],
-                    next: vec![],
-                },
+        let file = DetachedTimestampFile::new(
+            Digest::sha256([0xaa; 32]),
+            &Step {
+                data: StepData::Attestation(crate::ots::attestation::Attestation::Bitcoin {
+                    height: 100,
+                }),
+                output: vec![0xaa; 32],
+                next: vec![],
             },
-        };
+        )
+        .unwrap();
 
         let display = format!("{}", file);
         assert!(display.contains("SHA256 digest"));
-        assert!(display.contains("Starting digest: aabb"));
+        assert!(display.contains(&format!("Starting digest: {}", "aa".repeat(32))));
     }
 
     #[test]
     fn test_detached_timestamp_clone() {
-        use crate::ots::digest::DigestType;
         use crate::ots::timestamp::*;
 
-        let file1 = DetachedTimestampFile {
-            digest_type: DigestType::Sha256,
-            timestamp: Timestamp {
-                start_digest: vec![0x01],
-                first_step: Step {
-                    data: StepData::Attestation(crate::ots::attestation::Attestation::Bitcoin {
-                        height: 42,
-                    }),
-                    output: vec![0x01],
-                    next: vec![],
-                },
+        let file1 = DetachedTimestampFile::new(
+            Digest::sha256([0x01; 32]),
+            &Step {
+                data: StepData::Attestation(crate::ots::attestation::Attestation::Bitcoin {
+                    height: 42,
+                }),
+                output: vec![0x01; 32],
+                next: vec![],
             },
-        };
+        )
+        .unwrap();
 
         let file2 = file1.clone();
         assert_eq!(file1, file2);
     }
 
+    #[test]
+    fn test_digest_length_mismatch() {
+        use crate::ots::attestation::Attestation;
+        use crate::ots::timestamp::StepData;
+
+        let digest = Digest::new(DigestType::Sha1, vec![0x01; 20]).unwrap();
+        let step = |output: &[u8]| Step {
+            data: StepData::Attestation(Attestation::Bitcoin { height: 1 }),
+            output: output.to_vec(),
+            next: vec![],
+        };
+        let ots = DetachedTimestampFile::new(digest.clone(), &step(digest.as_bytes())).unwrap();
+        assert_eq!(ots.digest(), &digest);
+        assert_eq!(ots.digest_type(), DigestType::Sha1);
+        let mut buf = Vec::new();
+        ots.to_writer(&mut buf).unwrap();
+        assert_eq!(DetachedTimestampFile::from_reader(&buf[..]).unwrap(), ots);
+
+        // A SHA256 digest claimed to be SHA1 can't be built, let alone written
+        assert!(matches!(
+            Digest::new(DigestType::Sha1, vec![0x01; 32]),
+            Err(OtsError::BadLength { min: 20, max: 20, val: 32 })
+        ));
+    }
+
+    #[test]
+    fn test_armor() {
+        let armored = armor(SMALL_TEST);
+        let ots = DetachedTimestampFile::from_bytes(SMALL_TEST).unwrap();
+        let mut bytes = Vec::new();
+        ots.to_writer(&mut bytes).unwrap();
+        assert_eq!(ots.to_armored().unwrap(), armor(&bytes));
+        assert!(armored.starts_with(ARMOR_BEGIN));
+        assert!(armored.lines().all(|line| line.len() <= ARMOR_LINE_LEN.max(ARMOR_BEGIN.len())));
+        assert!(is_armored(armored.as_bytes()));
+        assert!(!is_armored(SMALL_TEST));
+        assert_eq!(dearmor(armored.as_bytes()).unwrap(), SMALL_TEST);
+
+        // Quoted in a message with CRLF line endings and indentation
+        let pasted =
+            format!("Proof of the release:\r\n\r\n{}\r\nThanks", armored).replace('\n', "\r\n  ");
+        assert!(is_armored(pasted.as_bytes()));
+        assert_eq!(dearmor(pasted.as_bytes()).unwrap(), SMALL_TEST);
+
+        assert!(matches!(dearmor(b"no armor here"), Err(OtsError::BadArmor(_))));
+        let truncated = &armored[..armored.len() - ARMOR_END.len() - 1];
+        assert!(matches!(dearmor(truncated.as_bytes()), Err(OtsError::BadArmor(_))));
+        let corrupted = armored.replacen('\n', "\n!", 1);
+        assert!(matches!(dearmor(corrupted.as_bytes()), Err(OtsError::BadArmor(_))));
+    }
+
     #[test]
     fn test_from_reader_with_trailing_bytes() {
         // Create a valid OTS file with extra bytes at the end
@@ -625,4 +933,201 @@ mod tests {
         assert_eq!(MAGIC.len(), 31);
         assert_eq!(VERSION, 1);
     }
+
+    #[test]
+    fn test_read_uint_overflow() {
+        let mut huge = vec![0xff; 20];
+        huge.push(0x01);
+        let mut deser = Deserializer::new(&huge[..]);
+        assert!(matches!(deser.read_uint(), Err(OtsError::VarintTooLong)));
+
+        // Padding with zero continuation bytes is harmless up to the length limit
+        let padded = [0x81, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00];
+        assert_eq!(Deserializer::new(&padded[..]).read_uint().unwrap(), 1);
+        let mut deser =
+            Deserializer::new(&[0x81, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00][..]);
+        assert!(matches!(deser.read_uint(), Err(OtsError::VarintTooLong)));
+    }
+
+    #[test]
+    fn test_adversarial_varints() {
+        // Every mix of continuation and final bytes up to a few bytes past the
+        // limit either decodes within 63 bits or fails, without panicking
+        let continuations = [0x80, 0x81, 0xfe, 0xff];
+        let finals = [0x00, 0x01, 0x40, 0x7f];
+        for len in 0..=MAX_VARINT_LEN + 3 {
+            for &continuation in &continuations {
+                for &last in &finals {
+                    let mut buf = vec![continuation; len];
+                    buf.push(last);
+                    let mut deser = Deserializer::new(&buf[..]);
+                    match deser.read_u64() {
+                        Ok(n) => {
+                            assert!(buf.len() <= MAX_VARINT_LEN, "{buf:02x?}");
+                            assert!(n <= MAX_VARINT, "{buf:02x?} gave {n}");
+                        }
+                        Err(OtsError::VarintTooLong) => assert!(buf.len() > MAX_VARINT_LEN),
+                        Err(e) => panic!("{buf:02x?} failed with {e}"),
+                    }
+                    // Decoding stops at the limit instead of reading on
+                    let consumed = buf.len() - deser.into_inner().len();
+                    assert_eq!(consumed, buf.len().min(MAX_VARINT_LEN), "{buf:02x?}");
+                }
+            }
+        }
+
+        // The widest value sets every bit the limit allows
+        let widest = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f];
+        assert_eq!(Deserializer::new(&widest[..]).read_u64().unwrap(), MAX_VARINT);
+
+        // Input ending inside a varint is an I/O error, not a value
+        for len in 1..MAX_VARINT_LEN {
+            let truncated = vec![0xff; len];
+            assert!(matches!(Deserializer::new(&truncated[..]).read_u64(), Err(OtsError::Io(_))));
+        }
+
+        // Values spread over the whole range survive a round trip
+        let mut n: u64 = 1;
+        while n <= MAX_VARINT {
+            for value in [n - 1, n, n | (n >> 1)] {
+                let mut buf = Vec::new();
+                Serializer::new(&mut buf).write_uint(value).unwrap();
+                let mut deser = Deserializer::new(&buf[..]).strict();
+                assert_eq!(deser.read_u64().unwrap(), value);
+            }
+            n <<= 1;
+        }
+    }
+
+    #[test]
+    fn test_varint_len() {
+        for n in [0, 0x7f, 0x80, 0x3fff, 0x4000, u64::from(u32::MAX), MAX_VARINT] {
+            let mut ser = Serializer::new(Vec::new());
+            ser.write_uint(n).unwrap();
+            assert_eq!(varint_len(n), ser.into_inner().len(), "length of {n}");
+        }
+    }
+
+    #[test]
+    fn test_varint_limits() {
+        let mut buf = Vec::new();
+        Serializer::new(&mut buf).write_uint(MAX_VARINT).unwrap();
+        assert_eq!(buf.len(), MAX_VARINT_LEN);
+        assert_eq!(Deserializer::new(&buf[..]).read_u64().unwrap(), MAX_VARINT);
+
+        assert!(matches!(
+            Serializer::new(Vec::new()).write_uint(MAX_VARINT + 1),
+            Err(OtsError::VarintOverflow)
+        ));
+        assert!(matches!(
+            Serializer::new(Vec::new()).write_uint(u64::MAX),
+            Err(OtsError::VarintOverflow)
+        ));
+    }
+
+    #[test]
+    fn test_deserializer_options() {
+        use crate::ots::{Attestation, Op, Step, StepData};
+
+        let uri = "https://alice.btc.calendar.opentimestamps.org";
+        let ots = DetachedTimestampFile::new(
+            Digest::sha256([0; 32]),
+            &Step {
+                data: StepData::Op(Op::Append(vec![0xaa; 100])),
+                output: [vec![0; 32], vec![0xaa; 100]].concat(),
+                next: vec![Step {
+                    data: StepData::Attestation(Attestation::Pending { uri: uri.into() }),
+                    output: [vec![0; 32], vec![0xaa; 100]].concat(),
+                    next: vec![],
+                }],
+            },
+        )
+        .unwrap();
+        let mut buf = Vec::new();
+        ots.to_writer(&mut buf).unwrap();
+        let parse = |options| DetachedTimestampFile::from_reader_with_options(&buf[..], options);
+
+        assert_eq!(parse(DeserializerOptions::default()).unwrap(), ots);
+        let defaults = DeserializerOptions::default();
+        assert!(matches!(
+            parse(DeserializerOptions { max_op_len: 99, ..defaults }),
+            Err(OtsError::BadLength { max: 99, val: 100, .. })
+        ));
+        assert!(matches!(
+            parse(DeserializerOptions { max_uri_len: 10, ..defaults }),
+            Err(OtsError::BadLength { max: 10, .. })
+        ));
+        // The op and the attestation are two levels deep
+        assert!(parse(DeserializerOptions { max_recursion: 2, ..defaults }).is_ok());
+        assert!(matches!(
+            parse(DeserializerOptions { max_recursion: 1, ..defaults }),
+            Err(OtsError::StackOverflow)
+        ));
+    }
+
+    #[test]
+    fn test_lenient() {
+        use crate::ots::{Attestation, Op, StepData};
+
+        let mut buf = MAGIC.to_vec();
+        buf.push(0x02); // A future version
+        buf.push(DigestType::Sha256.to_tag());
+        buf.extend_from_slice(&[0x11; 32]);
+        buf.push(0x42); // An unknown op
+        buf.push(0x00);
+        let mut ser = Serializer::new(&mut buf);
+        Attestation::Bitcoin { height: 1 }.serialize(&mut ser).unwrap();
+        buf.extend_from_slice(b"trailing");
+
+        assert!(matches!(DetachedTimestampFile::from_bytes(&buf), Err(OtsError::BadVersion(2))));
+        let ots = DetachedTimestampFile::from_reader_with_options(
+            &buf[..],
+            DeserializerOptions::lenient(),
+        )
+        .unwrap();
+        let step = ots.timestamp.first_step();
+        assert_eq!(step.data, StepData::Op(Op::Unknown(0x42)));
+        assert_eq!(step.output, vec![0x11; 32]);
+        assert!(matches!(
+            Op::Unknown(0x42).try_execute(&step.output),
+            Err(OtsError::BadOpTag(0x42))
+        ));
+
+        // Each leniency is needed on its own
+        buf[MAGIC.len()] = 0x01;
+        assert!(matches!(DetachedTimestampFile::from_bytes(&buf), Err(OtsError::BadOpTag(0x42))));
+    }
+
+    #[test]
+    fn test_strict_non_minimal_varint() {
+        let padded = [0x81, 0x00];
+        assert_eq!(Deserializer::new(&padded[..]).read_uint().unwrap(), 1);
+        assert!(matches!(
+            Deserializer::new(&padded[..]).strict().read_uint(),
+            Err(OtsError::NonMinimalVarint)
+        ));
+
+        // Zero itself and ordinary values are minimal
+        assert_eq!(Deserializer::new(&[0x00][..]).strict().read_uint().unwrap(), 0);
+        assert_eq!(Deserializer::new(&[0x80, 0x01][..]).strict().read_uint().unwrap(), 128);
+    }
+
+    #[test]
+    fn test_read_fixed_bytes_short_input() {
+        let mut deser = Deserializer::new(&[1, 2, 3][..]);
+        assert!(matches!(deser.read_fixed_bytes(usize::MAX), Err(OtsError::Io(_))));
+    }
+
+    #[test]
+    fn test_from_bytes_never_panics() {
+        assert!(DetachedTimestampFile::from_bytes(SMALL_TEST).is_ok());
+        for len in 0..SMALL_TEST.len() {
+            assert!(DetachedTimestampFile::from_bytes(&SMALL_TEST[..len]).is_err());
+        }
+        let mut corrupt = SMALL_TEST.to_vec();
+        for byte in corrupt.iter_mut().skip(MAGIC.len()) {
+            *byte = 0xff;
+        }
+        assert!(DetachedTimestampFile::from_bytes(&corrupt).is_err());
+    }
 }
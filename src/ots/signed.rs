@@ -0,0 +1,168 @@
+//! Signed timestamp files
+//!
+//! A signed file wraps a detached proof together with an ed25519 signature
+//! over its bytes, so recipients can tell who produced or curated the proof.
+//! The signature says nothing about the timestamp itself, which still has to
+//! be verified as usual.
+
+use std::io::{Read, Write};
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+
+use super::error::{OtsError, Result};
+use super::ser::{Deserializer, DetachedTimestampFile, Serializer};
+
+/// Magic bytes that every signed timestamp file starts with
+///
+/// Deliberately different from `MAGIC`, so that signed files are never
+/// mistaken for detached proofs.
+pub const SIGNED_MAGIC: &[u8] = b"\x00OpenTimestamps\x00\x00Signed\x00\x3c\x91\x07\xd4";
+
+/// Length in bytes of an ed25519 public key
+pub const PUBLIC_KEY_LEN: usize = 32;
+
+/// Length in bytes of an ed25519 signature
+pub const SIGNATURE_LEN: usize = 64;
+
+/// Structure representing a proof signed by its producer
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SignedTimestampFile {
+    /// The signed proof
+    pub proof: DetachedTimestampFile,
+    /// The exact proof bytes covered by the signature
+    proof_bytes: Vec<u8>,
+    /// Public key of the signer
+    signer: VerifyingKey,
+    /// Signature over `SIGNED_MAGIC` followed by the proof bytes
+    signature: Signature,
+}
+
+impl SignedTimestampFile {
+    /// Sign a proof with the given key
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the proof cannot be serialized
+    pub fn sign(proof: DetachedTimestampFile, key: &SigningKey) -> Result<Self> {
+        let mut proof_bytes = Vec::new();
+        proof.to_writer(&mut proof_bytes)?;
+        let signature = key.sign(&signed_message(&proof_bytes));
+        Ok(Self { proof, proof_bytes, signer: key.verifying_key(), signature })
+    }
+
+    /// Public key of the signer
+    #[must_use]
+    pub const fn signer(&self) -> &VerifyingKey {
+        &self.signer
+    }
+
+    /// Deserialize a signed timestamp file from a reader
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - The magic bytes are invalid
+    /// - The embedded proof is invalid
+    /// - The signature does not match the proof
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self> {
+        let mut deser = Deserializer::new(reader);
+
+        let magic = deser.read_fixed_bytes(SIGNED_MAGIC.len())?;
+        if magic != SIGNED_MAGIC {
+            return Err(OtsError::BadMagic(magic));
+        }
+        let len = deser.read_uint()?;
+        let proof_bytes = deser.read_fixed_bytes(len)?;
+        let signer = deser.read_fixed_bytes(PUBLIC_KEY_LEN)?;
+        let signature = deser.read_fixed_bytes(SIGNATURE_LEN)?;
+        deser.check_eof()?;
+
+        let signer = VerifyingKey::try_from(&signer[..]).map_err(|_| OtsError::BadSignature)?;
+        let signature = Signature::from_slice(&signature).map_err(|_| OtsError::BadSignature)?;
+        signer
+            .verify_strict(&signed_message(&proof_bytes), &signature)
+            .map_err(|_| OtsError::BadSignature)?;
+
+        let proof = DetachedTimestampFile::from_reader(&proof_bytes[..])?;
+        Ok(Self { proof, proof_bytes, signer, signature })
+    }
+
+    /// Serialize the signed timestamp file into a writer
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any I/O operation fails
+    pub fn to_writer<W: Write>(&self, writer: W) -> Result<()> {
+        let mut ser = Serializer::new(writer);
+        ser.write_fixed_bytes(SIGNED_MAGIC)?;
+        ser.write_bytes(&self.proof_bytes)?;
+        ser.write_fixed_bytes(self.signer.as_bytes())?;
+        ser.write_fixed_bytes(&self.signature.to_bytes())
+    }
+}
+
+/// The message that is actually signed
+///
+/// Prefixing the magic keeps these signatures from being valid in any other
+/// protocol that signs raw bytes with the same key.
+fn signed_message(proof_bytes: &[u8]) -> Vec<u8> {
+    let mut message = SIGNED_MAGIC.to_vec();
+    message.extend_from_slice(proof_bytes);
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ots::attestation::Attestation;
+    use crate::ots::digest::Digest;
+    use crate::ots::timestamp::{Step, StepData, Timestamp};
+
+    fn proof() -> DetachedTimestampFile {
+        DetachedTimestampFile {
+            timestamp: Timestamp::new(
+                Digest::sha256([0x11; 32]),
+                &Step {
+                    data: StepData::Attestation(Attestation::Bitcoin { height: 1 }),
+                    output: vec![0x11; 32],
+                    next: vec![],
+                },
+            )
+            .unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_signed_round_trip() {
+        let key = SigningKey::from_bytes(&[0x42; 32]);
+        let signed = SignedTimestampFile::sign(proof(), &key).unwrap();
+        let mut buf = Vec::new();
+        signed.to_writer(&mut buf).unwrap();
+        assert!(buf.starts_with(SIGNED_MAGIC));
+
+        let read = SignedTimestampFile::from_reader(&buf[..]).unwrap();
+        assert_eq!(read, signed);
+        assert_eq!(read.signer(), &key.verifying_key());
+        assert_eq!(read.proof, proof());
+    }
+
+    #[test]
+    fn test_signed_tampered() {
+        let key = SigningKey::from_bytes(&[0x42; 32]);
+        let mut buf = Vec::new();
+        SignedTimestampFile::sign(proof(), &key).unwrap().to_writer(&mut buf).unwrap();
+
+        // Flip a bit inside the start digest of the embedded proof
+        let pos = buf.len() - PUBLIC_KEY_LEN - SIGNATURE_LEN - 20;
+        buf[pos] ^= 0x01;
+        let result = SignedTimestampFile::from_reader(&buf[..]);
+        assert!(matches!(result, Err(OtsError::BadSignature)));
+    }
+
+    #[test]
+    fn test_detached_is_not_signed() {
+        let mut buf = Vec::new();
+        proof().to_writer(&mut buf).unwrap();
+        assert!(matches!(SignedTimestampFile::from_reader(&buf[..]), Err(OtsError::BadMagic(_))));
+    }
+}
@@ -0,0 +1,210 @@
+//! Streaming timestamp serialization
+//!
+//! Writes a timestamp one step at a time, so that very large trees never
+//! have to be held in memory as a `Step` tree.
+
+use std::io::Write;
+
+use super::attestation::Attestation;
+use super::digest::Digest;
+use super::error::{OtsError, Result};
+use super::op::Op;
+use super::ser::Serializer;
+
+/// Push-style writer for the steps of a timestamp
+///
+/// Steps are written in the order they appear in the binary format: a path
+/// is a sequence of ops ending in an attestation. Calling `begin_fork`
+/// starts a branch that will be followed by at least one more branch from
+/// the same point, so a fork with `n` branches takes `n - 1` calls, one
+/// before each branch but the last.
+pub struct TimestampWriter<W: Write> {
+    ser: Serializer<W>,
+    /// Number of paths that still need to be terminated by an attestation
+    open: usize,
+}
+
+impl<W: Write> TimestampWriter<W> {
+    /// Start writing a bare timestamp into a serializer
+    #[must_use]
+    pub fn new(ser: Serializer<W>) -> Self {
+        Self { ser, open: 1 }
+    }
+
+    /// Start writing a detached timestamp file
+    ///
+    /// Writes the file header, so the steps that follow form a complete
+    /// `.ots` file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the write operation fails
+    pub fn new_file(writer: W, start_digest: &Digest) -> Result<Self> {
+        let mut ser = Serializer::new(writer);
+        ser.write_magic()?;
+        ser.write_version()?;
+        ser.write_byte(start_digest.digest_type().to_tag())?;
+        ser.write_fixed_bytes(start_digest.as_bytes())?;
+        Ok(Self::new(ser))
+    }
+
+    /// Begin a branch that will be followed by another branch
+    ///
+    /// # Errors
+    ///
+    /// Returns `OtsError::TimestampComplete` if every path has already been
+    /// terminated, or an error if the write operation fails
+    pub fn begin_fork(&mut self) -> Result<()> {
+        self.check_open()?;
+        self.ser.write_byte(0xff)?;
+        self.open += 1;
+        Ok(())
+    }
+
+    /// Write an op on the current path
+    ///
+    /// # Errors
+    ///
+    /// Returns `OtsError::TimestampComplete` if every path has already been
+    /// terminated, or an error if the write operation fails
+    pub fn op(&mut self, op: &Op) -> Result<()> {
+        self.check_open()?;
+        op.serialize(&mut self.ser)
+    }
+
+    /// Terminate the current path with an attestation
+    ///
+    /// # Errors
+    ///
+    /// Returns `OtsError::TimestampComplete` if every path has already been
+    /// terminated, or an error if the write operation fails
+    pub fn attestation(&mut self, attestation: &Attestation) -> Result<()> {
+        self.check_open()?;
+        self.ser.write_byte(0x00)?;
+        attestation.serialize(&mut self.ser)?;
+        self.open -= 1;
+        Ok(())
+    }
+
+    /// Whether every path has been terminated by an attestation
+    #[must_use]
+    pub const fn is_complete(&self) -> bool {
+        self.open == 0
+    }
+
+    /// Finish writing, returning the underlying serializer
+    ///
+    /// # Errors
+    ///
+    /// Returns `OtsError::IncompleteTimestamp` if some paths were not
+    /// terminated by an attestation
+    pub fn finish(self) -> Result<Serializer<W>> {
+        if self.open > 0 {
+            return Err(OtsError::IncompleteTimestamp(self.open));
+        }
+        Ok(self.ser)
+    }
+
+    fn check_open(&self) -> Result<()> {
+        if self.is_complete() {
+            Err(OtsError::TimestampComplete)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ots::digest::DigestType;
+    use crate::ots::ser::{Deserializer, DetachedTimestampFile};
+    use crate::ots::timestamp::{Step, StepData, Timestamp};
+
+    #[test]
+    fn test_matches_tree_serialization() {
+        let start_digest = Op::Sha256.execute(b"hello");
+        let appended = Op::Append(vec![0xaa]).execute(&start_digest);
+        let hashed = Op::Sha256.execute(&appended);
+        let bitcoin = Attestation::Bitcoin { height: 100 };
+        let pending = Attestation::Pending { uri: "https://example.com".to_string() };
+
+        let leaf = |attestation: &Attestation, output: &[u8]| Step {
+            data: StepData::Attestation(attestation.clone()),
+            output: output.to_vec(),
+            next: vec![],
+        };
+        let digest = Digest::new(DigestType::Sha256, start_digest.clone()).unwrap();
+        let ots = DetachedTimestampFile {
+            timestamp: Timestamp::new(
+                digest.clone(),
+                &Step {
+                    data: StepData::Fork,
+                    output: start_digest.clone(),
+                    next: vec![
+                        leaf(&pending, &start_digest),
+                        leaf(&bitcoin, &start_digest),
+                        Step {
+                            data: StepData::Op(Op::Append(vec![0xaa])),
+                            output: appended.clone(),
+                            next: vec![Step {
+                                data: StepData::Op(Op::Sha256),
+                                output: hashed.clone(),
+                                next: vec![leaf(&bitcoin, &hashed)],
+                            }],
+                        },
+                    ],
+                },
+            )
+            .unwrap(),
+        };
+        let mut expected = Vec::new();
+        ots.to_writer(&mut expected).unwrap();
+
+        let mut buf = Vec::new();
+        let mut writer = TimestampWriter::new_file(&mut buf, &digest).unwrap();
+        writer.begin_fork().unwrap();
+        writer.attestation(&pending).unwrap();
+        writer.begin_fork().unwrap();
+        writer.attestation(&bitcoin).unwrap();
+        assert!(!writer.is_complete());
+        writer.op(&Op::Append(vec![0xaa])).unwrap();
+        writer.op(&Op::Sha256).unwrap();
+        writer.attestation(&bitcoin).unwrap();
+        assert!(writer.is_complete());
+        writer.finish().unwrap();
+
+        assert_eq!(buf, expected);
+        assert_eq!(DetachedTimestampFile::from_reader(&buf[..]).unwrap(), ots);
+    }
+
+    #[test]
+    fn test_bare_timestamp() {
+        let mut writer = TimestampWriter::new(Serializer::new(Vec::new()));
+        writer.op(&Op::Sha256).unwrap();
+        writer.attestation(&Attestation::Bitcoin { height: 1 }).unwrap();
+        let buf = writer.finish().unwrap().into_inner();
+
+        let timestamp =
+            Timestamp::deserialize(&mut Deserializer::new(&buf[..]), Digest::sha256([0x01; 32]));
+        assert!(timestamp.is_ok());
+    }
+
+    #[test]
+    fn test_structure_errors() {
+        let mut writer = TimestampWriter::new(Serializer::new(Vec::new()));
+        writer.begin_fork().unwrap();
+        writer.attestation(&Attestation::Bitcoin { height: 1 }).unwrap();
+        assert!(matches!(writer.finish(), Err(OtsError::IncompleteTimestamp(1))));
+
+        let mut writer = TimestampWriter::new(Serializer::new(Vec::new()));
+        writer.attestation(&Attestation::Bitcoin { height: 1 }).unwrap();
+        assert!(matches!(writer.op(&Op::Sha256), Err(OtsError::TimestampComplete)));
+        assert!(matches!(writer.begin_fork(), Err(OtsError::TimestampComplete)));
+
+        assert!(matches!(
+            Digest::new(DigestType::Sha256, vec![0u8; 20]),
+            Err(OtsError::BadLength { min: 32, max: 32, val: 20 })
+        ));
+    }
+}
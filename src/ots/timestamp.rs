@@ -6,7 +6,9 @@ use std::fmt;
 use std::io::{Read, Write};
 
 use super::attestation::Attestation;
-use super::error::{OtsError, Result, RECURSION_LIMIT};
+use super::digest::Digest;
+use super::error::{OtsError, Result, MAX_RESULT_LENGTH};
+use super::map::TimestampMap;
 use super::op::Op;
 use super::ser::{Deserializer, Serializer};
 
@@ -32,78 +34,68 @@ pub struct Step {
     pub next: Vec<Step>,
 }
 
+impl Step {
+    /// Deserialize the steps of a timestamp of `msg`
+    ///
+    /// `msg` need not be a document digest: calendar responses are
+    /// timestamps of the commitment a pending attestation was made on. The
+    /// timestamp is read into a `TimestampMap`, so the tree has the layout
+    /// of `TimestampMap::to_step`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - The recursion limit of the deserializer's options is exceeded
+    /// - Deserialization of any component fails
+    pub fn deserialize<R: Read>(deser: &mut Deserializer<R>, msg: &[u8]) -> Result<Self> {
+        TimestampMap::deserialize(deser, msg.to_vec())?.to_step()
+    }
+}
+
 /// Main structure representing a timestamp
+///
+/// As in python-opentimestamps, a timestamp is the `TimestampMap` of its
+/// start digest: every message reached from it, with its attestations and
+/// the ops applied to it. `first_step` is the same proof as a tree of
+/// `Step`s, and `new` builds a timestamp from such a tree.
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Timestamp {
-    /// The starting document digest
-    pub start_digest: Vec<u8>,
-    /// The first execution step in verifying it
-    pub first_step: Step,
+    /// The starting document digest, or the commitment a calendar or
+    /// Merkle sub-timestamp starts from
+    start_digest: Digest,
+    /// The timestamp of the bytes of `start_digest`; every message in it
+    /// has an attestation or an op
+    map: TimestampMap,
 }
 
 impl Timestamp {
-    /// Deserialize one step in a timestamp
-    fn deserialize_step_recurse<R: Read>(
-        deser: &mut Deserializer<R>,
-        input_digest: Vec<u8>,
-        tag: Option<u8>,
-        recursion_limit: usize,
-    ) -> Result<Step> {
-        if recursion_limit == 0 {
-            return Err(OtsError::StackOverflow);
-        }
+    /// Build the timestamp of `start_digest` from a step tree starting at it
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `TimestampMap::from_step` if the tree does not
+    /// follow from `start_digest`
+    pub fn new(start_digest: Digest, first_step: &Step) -> Result<Self> {
+        let map = TimestampMap::from_step(start_digest.as_bytes().to_vec(), first_step)?;
+        Ok(Self { start_digest, map })
+    }
 
-        // Read next tag if we weren't given one
-        let tag = match tag {
-            Some(tag) => tag,
-            None => deser.read_byte()?,
-        };
+    /// The digest the timestamp starts from
+    #[must_use]
+    pub const fn start_digest(&self) -> &Digest {
+        &self.start_digest
+    }
 
-        // A tag typically indicates an op to execute, but the two special values
-        // 0xff (fork) and 0x00 (read attestation and terminate path) are used to
-        // provide multiple attestations
-        match tag {
-            // Attestation
-            0x00 => {
-                let attest = Attestation::deserialize(deser)?;
-                Ok(Step { data: StepData::Attestation(attest), output: input_digest, next: vec![] })
-            }
-            // Fork
-            0xff => {
-                let mut forks = vec![];
-                let mut next_tag = 0xff;
-                while next_tag == 0xff {
-                    forks.push(Self::deserialize_step_recurse(
-                        deser,
-                        input_digest.clone(),
-                        None,
-                        recursion_limit - 1,
-                    )?);
-                    next_tag = deser.read_byte()?;
-                }
-                forks.push(Self::deserialize_step_recurse(
-                    deser,
-                    input_digest.clone(),
-                    Some(next_tag),
-                    recursion_limit - 1,
-                )?);
-                Ok(Step { data: StepData::Fork, output: input_digest, next: forks })
-            }
-            // An actual op tag
-            tag => {
-                // parse tag
-                let op = Op::deserialize_with_tag(deser, tag)?;
-                let output_digest = op.execute(&input_digest);
-                // recurse
-                let next = vec![Self::deserialize_step_recurse(
-                    deser,
-                    output_digest.clone(),
-                    None,
-                    recursion_limit - 1,
-                )?];
-                Ok(Step { data: StepData::Op(op), output: output_digest, next })
-            }
-        }
+    /// Every message of the timestamp, with its attestations and ops
+    #[must_use]
+    pub const fn map(&self) -> &TimestampMap {
+        &self.map
+    }
+
+    /// The timestamp as a tree of steps, in the layout of `TimestampMap::to_step`
+    #[must_use]
+    pub fn first_step(&self) -> Step {
+        self.map.to_step().expect("every message of a timestamp has an attestation or op")
     }
 
     /// Deserialize a timestamp
@@ -111,43 +103,164 @@ impl Timestamp {
     /// # Errors
     ///
     /// Returns an error if:
-    /// - The recursion limit is exceeded
+    /// - The recursion limit of the deserializer's options is exceeded
     /// - Deserialization of any component fails
-    pub fn deserialize<R: Read>(deser: &mut Deserializer<R>, digest: Vec<u8>) -> Result<Self> {
-        let first_step =
-            Self::deserialize_step_recurse(deser, digest.clone(), None, RECURSION_LIMIT)?;
-
-        Ok(Self { start_digest: digest, first_step })
-    }
-
-    /// Serialize one step in a timestamp recursively
-    fn serialize_step_recurse<W: Write>(ser: &mut Serializer<W>, step: &Step) -> Result<()> {
-        match step.data {
-            StepData::Fork => {
-                for i in 0..step.next.len() - 1 {
-                    ser.write_byte(0xff)?;
-                    Self::serialize_step_recurse(ser, &step.next[i])?;
-                }
-                Self::serialize_step_recurse(ser, &step.next[step.next.len() - 1])
-            }
-            StepData::Op(ref op) => {
-                op.serialize(ser)?;
-                Self::serialize_step_recurse(ser, &step.next[0])
-            }
-            StepData::Attestation(ref attest) => {
-                ser.write_byte(0x00)?;
-                attest.serialize(ser)
-            }
-        }
+    pub fn deserialize<R: Read>(deser: &mut Deserializer<R>, digest: Digest) -> Result<Self> {
+        let map = TimestampMap::deserialize(deser, digest.as_bytes().to_vec())?;
+        Ok(Self { start_digest: digest, map })
     }
 
     /// Serialize a timestamp
     ///
+    /// At every message the attestations come first and then the ops, each
+    /// in the order they were added; see `normalize` for the order of
+    /// python-opentimestamps.
+    ///
     /// # Errors
     ///
     /// Returns an error if the write operation fails
     pub fn serialize<W: Write>(&self, ser: &mut Serializer<W>) -> Result<()> {
-        Self::serialize_step_recurse(ser, &self.first_step)
+        self.map.serialize(ser)
+    }
+
+    /// Serialize a timestamp in canonical form
+    ///
+    /// Writes the timestamp in the order `normalize` sorts it into, without
+    /// changing it. Two timestamps holding the same attestations and ops
+    /// therefore serialize identically, regardless of the order in which the
+    /// branches were merged.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the write operation fails
+    pub fn serialize_canonical<W: Write>(&self, ser: &mut Serializer<W>) -> Result<()> {
+        let mut map = self.map.clone();
+        map.sort();
+        map.serialize(ser)
+    }
+
+    /// Sort the timestamp into the order python-opentimestamps serializes
+    ///
+    /// Each message gets its attestations, sorted by tag and then value,
+    /// followed by its ops, sorted by tag and then argument, so semantically
+    /// identical proofs serialize to identical bytes, as they would from the
+    /// reference client. `serialize_canonical` writes the same order without
+    /// sorting the timestamp itself.
+    pub fn normalize(&mut self) {
+        self.map.sort();
+    }
+
+    /// Check that every attestation is on a message it can attest to
+    ///
+    /// Every message follows from the start digest by construction, but a
+    /// block attestation must also be on a 32-byte merkle root, and no
+    /// attestation may be on an empty or over-long message.
+    ///
+    /// # Errors
+    ///
+    /// Returns `OtsError::BadLength` if an attested message has an invalid length
+    pub fn validate(&self) -> Result<()> {
+        for (msg, attestation) in self.map.all_attestations() {
+            let (min, max) = match attestation {
+                Attestation::Bitcoin { .. } | Attestation::Litecoin { .. } => (32, 32),
+                _ => (1, MAX_RESULT_LENGTH),
+            };
+            if !(min..=max).contains(&msg.len()) {
+                return Err(OtsError::BadLength { min, max, val: msg.len() });
+            }
+        }
+        Ok(())
+    }
+
+    /// Check whether two timestamps prove the same thing
+    ///
+    /// The timestamps are compared as normalized maps, so unlike `==`, this
+    /// ignores the order of attestations and ops at each message. Proofs that
+    /// differ only in how they were merged compare equal: the order and nesting
+    /// of fork branches, duplicated branches, and whether branches starting
+    /// with the same op share it do not end up in the map.
+    #[must_use]
+    pub fn equivalent(&self, other: &Self) -> bool {
+        if self.start_digest != other.start_digest {
+            return false;
+        }
+        let (mut ours, mut theirs) = (self.map.clone(), other.map.clone());
+        ours.sort();
+        theirs.sort();
+        ours == theirs
+    }
+
+    /// Merge a timestamp of `msg`, a message of this one, into it
+    ///
+    /// This is how the timestamp a calendar returned for the commitment of a
+    /// pending attestation is added: identical attestations are kept once,
+    /// identical ops have their timestamps merged, and everything else is
+    /// added next to what `msg` already has.
+    ///
+    /// # Errors
+    ///
+    /// Returns `OtsError::DigestMismatch` if this timestamp never reaches
+    /// `msg`, or an error of `TimestampMap::from_step` if `step` does not
+    /// follow from it
+    pub fn merge_at(&mut self, msg: &[u8], step: &Step) -> Result<()> {
+        let other = TimestampMap::from_step(msg.to_vec(), step)?;
+        let node = self.map.find_mut(msg).ok_or(OtsError::DigestMismatch)?;
+        node.merge(other)
+    }
+
+    /// Attest directly to the start digest, alongside the existing attestations and ops
+    pub fn add_attestation(&mut self, attestation: Attestation) {
+        self.map.add_attestation(attestation);
+    }
+
+    /// Find the shortest path through `map` to `attestation`
+    ///
+    /// Returns the number of ops on the path along with the path itself.
+    fn extract_map(map: &TimestampMap, attestation: &Attestation) -> Option<(usize, TimestampMap)> {
+        if map.attestations.contains(attestation) {
+            let mut path = TimestampMap::new(map.msg.clone());
+            path.add_attestation(attestation.clone());
+            return Some((0, path));
+        }
+        map.ops
+            .iter()
+            .filter_map(|(op, child)| {
+                Self::extract_map(child, attestation).map(|(len, next)| {
+                    let mut path = TimestampMap::new(map.msg.clone());
+                    path.ops.push((op.clone(), next));
+                    (len + 1, path)
+                })
+            })
+            .min_by_key(|(len, _)| *len)
+    }
+
+    /// Extract a minimal proof for a single attestation
+    ///
+    /// The result is a linear timestamp holding only the ops leading from the
+    /// start digest to `attestation`. If the attestation is reachable along
+    /// several paths, the one with the fewest ops is chosen.
+    ///
+    /// Returns `None` if the timestamp does not contain the attestation.
+    #[must_use]
+    pub fn extract_path(&self, attestation: &Attestation) -> Option<Self> {
+        Self::extract_map(&self.map, attestation)
+            .map(|(_, map)| Self { start_digest: self.start_digest.clone(), map })
+    }
+
+    /// Remove every pending attestation, and the ops only leading to one
+    ///
+    /// Meant for proofs that already have a block attestation, whose pending
+    /// attestations are redundant. A timestamp holding nothing but pending
+    /// attestations is left unchanged.
+    ///
+    /// Returns true if anything was removed.
+    pub fn prune_pending(&mut self) -> bool {
+        let mut map = self.map.clone();
+        if !map.prune_pending() || map.is_empty() {
+            return false;
+        }
+        self.map = map;
+        true
     }
 }
 
@@ -201,13 +314,28 @@ fn fmt_recurse(
 impl fmt::Display for Timestamp {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "Starting digest: {}", hex::encode(&self.start_digest))?;
-        fmt_recurse(&self.first_step, f, 0, false)
+        fmt_recurse(&self.first_step(), f, 0, false)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ots::digest::DigestType;
+
+    fn attested(msg: &[u8], attestation: Attestation) -> Step {
+        Step { data: StepData::Attestation(attestation), output: msg.to_vec(), next: vec![] }
+    }
+
+    fn op(input: &[u8], op: Op, next: impl FnOnce(&[u8]) -> Step) -> Step {
+        let output = op.execute(input);
+        let next = next(&output);
+        Step { data: StepData::Op(op), output, next: vec![next] }
+    }
+
+    fn fork(msg: &[u8], next: Vec<Step>) -> Step {
+        Step { data: StepData::Fork, output: msg.to_vec(), next }
+    }
 
     #[test]
     fn test_step_data_variants() {
@@ -229,63 +357,46 @@ mod tests {
 
     #[test]
     fn test_timestamp_display_simple() {
-        let timestamp = Timestamp {
-            start_digest: vec![0xaa, 0xbb, 0xcc],
-            first_step: Step {
-                data: StepData::Attestation(Attestation::Bitcoin { height: 100 }),
-                output: vec![0xaa, 0xbb, 0xcc],
-                next: vec![],
-            },
-        };
+        let timestamp = Timestamp::new(
+            Digest::sha256([0xaa; 32]),
+            &attested(&[0xaa; 32], Attestation::Bitcoin { height: 100 }),
+        )
+        .unwrap();
 
         let display = format!("{}", timestamp);
-        assert!(display.contains("Starting digest: aabbcc"));
+        assert!(display.contains(&format!("Starting digest: {}", "aa".repeat(32))));
         assert!(display.contains("Bitcoin block 100"));
     }
 
     #[test]
     fn test_timestamp_display_with_op() {
-        let timestamp = Timestamp {
-            start_digest: vec![0x00, 0x01],
-            first_step: Step {
-                data: StepData::Op(Op::Sha256),
-                output: vec![0x02, 0x03],
-                next: vec![Step {
-                    data: StepData::Attestation(Attestation::Bitcoin { height: 200 }),
-                    output: vec![0x02, 0x03],
-                    next: vec![],
-                }],
-            },
-        };
+        let timestamp = Timestamp::new(
+            Digest::sha256([0x01; 32]),
+            &op(&[0x01; 32], Op::Sha256, |msg| attested(msg, Attestation::Bitcoin { height: 200 })),
+        )
+        .unwrap();
 
         let display = format!("{}", timestamp);
-        assert!(display.contains("Starting digest: 0001"));
+        assert!(display.contains(&format!("Starting digest: {}", "01".repeat(32))));
         assert!(display.contains("execute SHA256()"));
-        assert!(display.contains("result 0203"));
+        assert!(display.contains(&format!("result {}", hex::encode(Op::Sha256.execute(&[1; 32])))));
         assert!(display.contains("Bitcoin block 200"));
     }
 
     #[test]
     fn test_timestamp_display_with_fork() {
-        let timestamp = Timestamp {
-            start_digest: vec![0xff],
-            first_step: Step {
-                data: StepData::Fork,
-                output: vec![0xff],
-                next: vec![
-                    Step {
-                        data: StepData::Attestation(Attestation::Bitcoin { height: 100 }),
-                        output: vec![0xff],
-                        next: vec![],
-                    },
-                    Step {
-                        data: StepData::Attestation(Attestation::Bitcoin { height: 200 }),
-                        output: vec![0xff],
-                        next: vec![],
-                    },
+        let digest = [0xff; 32];
+        let timestamp = Timestamp::new(
+            Digest::sha256(digest),
+            &fork(
+                &digest,
+                vec![
+                    attested(&digest, Attestation::Bitcoin { height: 100 }),
+                    attested(&digest, Attestation::Bitcoin { height: 200 }),
                 ],
-            },
-        };
+            ),
+        )
+        .unwrap();
 
         let display = format!("{}", timestamp);
         assert!(display.contains("(fork 2 ways)"));
@@ -297,52 +408,41 @@ mod tests {
     fn test_serialize_deserialize_simple_timestamp() {
         use crate::ots::ser::*;
 
-        let timestamp = Timestamp {
-            start_digest: vec![0xaa, 0xbb, 0xcc],
-            first_step: Step {
-                data: StepData::Attestation(Attestation::Bitcoin { height: 12345 }),
-                output: vec![0xaa, 0xbb, 0xcc],
-                next: vec![],
-            },
-        };
+        let timestamp = Timestamp::new(
+            Digest::sha256([0xaa; 32]),
+            &attested(&[0xaa; 32], Attestation::Bitcoin { height: 12345 }),
+        )
+        .unwrap();
 
         let mut buf = Vec::new();
         let mut ser = Serializer::new(&mut buf);
         timestamp.serialize(&mut ser).unwrap();
 
         let mut deser = Deserializer::new(&buf[..]);
-        let deserialized = Timestamp::deserialize(&mut deser, vec![0xaa, 0xbb, 0xcc]).unwrap();
+        let deserialized = Timestamp::deserialize(&mut deser, Digest::sha256([0xaa; 32])).unwrap();
         assert_eq!(timestamp, deserialized);
     }
 
     #[test]
     fn test_serialize_deserialize_timestamp_with_op() {
-        use crate::ots::op::Op;
         use crate::ots::ser::*;
 
-        let input_digest = vec![0x01, 0x02];
-        let op = Op::Sha256;
-        let output_digest = op.execute(&input_digest);
-
-        let timestamp = Timestamp {
-            start_digest: input_digest.clone(),
-            first_step: Step {
-                data: StepData::Op(op.clone()),
-                output: output_digest.clone(),
-                next: vec![Step {
-                    data: StepData::Attestation(Attestation::Bitcoin { height: 500 }),
-                    output: output_digest.clone(),
-                    next: vec![],
-                }],
-            },
-        };
+        let input_digest = vec![0x01; 32];
+        let first_step = op(&input_digest, Op::Sha256, |msg| {
+            attested(msg, Attestation::Bitcoin { height: 500 })
+        });
+        let timestamp =
+            Timestamp::new(Digest::new(DigestType::Sha256, input_digest).unwrap(), &first_step)
+                .unwrap();
+        assert_eq!(timestamp.first_step(), first_step);
 
         let mut buf = Vec::new();
         let mut ser = Serializer::new(&mut buf);
         timestamp.serialize(&mut ser).unwrap();
 
         let mut deser = Deserializer::new(&buf[..]);
-        let deserialized = Timestamp::deserialize(&mut deser, input_digest).unwrap();
+        let deserialized =
+            Timestamp::deserialize(&mut deser, timestamp.start_digest().clone()).unwrap();
         assert_eq!(timestamp, deserialized);
     }
 
@@ -350,42 +450,32 @@ mod tests {
     fn test_serialize_deserialize_timestamp_with_fork() {
         use crate::ots::ser::*;
 
-        let digest = vec![0xff];
-        let timestamp = Timestamp {
-            start_digest: digest.clone(),
-            first_step: Step {
-                data: StepData::Fork,
-                output: digest.clone(),
-                next: vec![
-                    Step {
-                        data: StepData::Attestation(Attestation::Bitcoin { height: 100 }),
-                        output: digest.clone(),
-                        next: vec![],
-                    },
-                    Step {
-                        data: StepData::Attestation(Attestation::Pending {
-                            uri: "https://example.com".to_string(),
-                        }),
-                        output: digest.clone(),
-                        next: vec![],
-                    },
+        let digest = vec![0xff; 32];
+        let timestamp = Timestamp::new(
+            Digest::new(DigestType::Sha256, digest.clone()).unwrap(),
+            &fork(
+                &digest,
+                vec![
+                    attested(&digest, Attestation::Bitcoin { height: 100 }),
+                    attested(&digest, Attestation::Pending { uri: "https://example.com".into() }),
                 ],
-            },
-        };
+            ),
+        )
+        .unwrap();
 
         let mut buf = Vec::new();
         let mut ser = Serializer::new(&mut buf);
         timestamp.serialize(&mut ser).unwrap();
 
         let mut deser = Deserializer::new(&buf[..]);
-        let deserialized = Timestamp::deserialize(&mut deser, digest.clone()).unwrap();
+        let deserialized =
+            Timestamp::deserialize(&mut deser, timestamp.start_digest().clone()).unwrap();
         assert_eq!(timestamp, deserialized);
     }
 
     #[test]
     fn test_deserialize_stack_overflow() {
         use crate::ots::error::RECURSION_LIMIT;
-        use crate::ots::op::Op;
         use crate::ots::ser::*;
 
         // Create a deeply nested timestamp that exceeds recursion limit
@@ -405,7 +495,7 @@ mod tests {
         ser.write_bytes(inner_ser.into_inner()).unwrap();
 
         let mut deser = Deserializer::new(&buf[..]);
-        let result = Timestamp::deserialize(&mut deser, vec![0x00]);
+        let result = Timestamp::deserialize(&mut deser, Digest::sha256([0x00; 32]));
         assert!(result.is_err());
         assert!(matches!(result.unwrap_err(), OtsError::StackOverflow));
     }
@@ -414,51 +504,53 @@ mod tests {
     fn test_serialize_fork_with_multiple_branches() {
         use crate::ots::ser::*;
 
-        let digest = vec![0xaa];
-        let timestamp = Timestamp {
-            start_digest: digest.clone(),
-            first_step: Step {
-                data: StepData::Fork,
-                output: digest.clone(),
-                next: vec![
-                    Step {
-                        data: StepData::Attestation(Attestation::Bitcoin { height: 1 }),
-                        output: digest.clone(),
-                        next: vec![],
-                    },
-                    Step {
-                        data: StepData::Attestation(Attestation::Bitcoin { height: 2 }),
-                        output: digest.clone(),
-                        next: vec![],
-                    },
-                    Step {
-                        data: StepData::Attestation(Attestation::Bitcoin { height: 3 }),
-                        output: digest.clone(),
-                        next: vec![],
-                    },
-                ],
-            },
-        };
+        let digest = vec![0xaa; 32];
+        let timestamp = Timestamp::new(
+            Digest::new(DigestType::Sha256, digest.clone()).unwrap(),
+            &fork(
+                &digest,
+                (1..=3).map(|height| attested(&digest, Attestation::Bitcoin { height })).collect(),
+            ),
+        )
+        .unwrap();
 
         let mut buf = Vec::new();
         let mut ser = Serializer::new(&mut buf);
         timestamp.serialize(&mut ser).unwrap();
 
         let mut deser = Deserializer::new(&buf[..]);
-        let deserialized = Timestamp::deserialize(&mut deser, digest.clone()).unwrap();
+        let deserialized =
+            Timestamp::deserialize(&mut deser, timestamp.start_digest().clone()).unwrap();
         assert_eq!(timestamp, deserialized);
+        assert_eq!(deserialized.first_step().next.len(), 3);
+    }
+
+    #[test]
+    fn test_new_checks_tree() {
+        let digest = [0x01; 32];
+        let mut corrupted =
+            op(&digest, Op::Sha256, |msg| attested(msg, Attestation::Bitcoin { height: 7 }));
+        corrupted.output[0] ^= 1;
+        assert!(matches!(
+            Timestamp::new(Digest::sha256(digest), &corrupted),
+            Err(OtsError::OutputMismatch)
+        ));
+        let mut dangling =
+            op(&digest, Op::Sha256, |msg| attested(msg, Attestation::Bitcoin { height: 7 }));
+        dangling.next.clear();
+        assert!(matches!(
+            Timestamp::new(Digest::sha256(digest), &dangling),
+            Err(OtsError::IncompleteTimestamp(_))
+        ));
     }
 
     #[test]
     fn test_timestamp_clone() {
-        let timestamp = Timestamp {
-            start_digest: vec![0x01],
-            first_step: Step {
-                data: StepData::Attestation(Attestation::Bitcoin { height: 42 }),
-                output: vec![0x01],
-                next: vec![],
-            },
-        };
+        let timestamp = Timestamp::new(
+            Digest::sha256([0x01; 32]),
+            &attested(&[0x01; 32], Attestation::Bitcoin { height: 42 }),
+        )
+        .unwrap();
 
         let cloned = timestamp.clone();
         assert_eq!(timestamp, cloned);
@@ -466,8 +558,6 @@ mod tests {
 
     #[test]
     fn test_step_data_debug() {
-        use crate::ots::op::Op;
-
         let fork = StepData::Fork;
         let op = StepData::Op(Op::Sha256);
         let attestation = StepData::Attestation(Attestation::Bitcoin { height: 100 });
@@ -477,4 +567,317 @@ mod tests {
         assert!(format!("{:?}", op).contains("Op"));
         assert!(format!("{:?}", attestation).contains("Attestation"));
     }
+
+    #[test]
+    fn test_serialize_canonical_order_independent() {
+        let digest = vec![0xaa; 32];
+        let bitcoin = attested(&digest, Attestation::Bitcoin { height: 7 });
+        let pending_a = attested(&digest, Attestation::Pending { uri: "https://a.example".into() });
+        let pending_b = attested(&digest, Attestation::Pending { uri: "https://b.example".into() });
+
+        let start_digest = Digest::new(DigestType::Sha256, digest.clone()).unwrap();
+        let first = Timestamp::new(
+            start_digest.clone(),
+            &fork(&digest, vec![pending_a.clone(), pending_b.clone(), bitcoin.clone()]),
+        )
+        .unwrap();
+        // Same branches, merged in a different order, nested and duplicated
+        let second = Timestamp::new(
+            start_digest,
+            &fork(
+                &digest,
+                vec![bitcoin.clone(), fork(&digest, vec![pending_b, pending_a]), bitcoin],
+            ),
+        )
+        .unwrap();
+
+        let canonical = |ts: &Timestamp| {
+            let mut ser = Serializer::new(Vec::new());
+            ts.serialize_canonical(&mut ser).unwrap();
+            ser.into_inner()
+        };
+        assert_eq!(canonical(&first), canonical(&second));
+
+        // It is the serialization of the normalized timestamp
+        let mut normalized = second.clone();
+        normalized.normalize();
+        let mut ser = Serializer::new(Vec::new());
+        normalized.serialize(&mut ser).unwrap();
+        assert_eq!(ser.into_inner(), canonical(&second));
+
+        // The canonical form is a valid timestamp with all three branches
+        let bytes = canonical(&first);
+        let mut deser = Deserializer::new(&bytes[..]);
+        let parsed = Timestamp::deserialize(&mut deser, first.start_digest().clone()).unwrap();
+        assert_eq!(parsed.first_step().next.len(), 3);
+    }
+
+    #[test]
+    fn test_merge_at() {
+        let start = [0x01; 32];
+        let pending = Attestation::Pending { uri: "https://a.example".into() };
+        let mut timestamp = Timestamp::new(
+            Digest::sha256(start),
+            &op(&start, Op::Sha256, |msg| attested(msg, pending.clone())),
+        )
+        .unwrap();
+        let commitment = Op::Sha256.execute(&start);
+
+        // The pending attestation stays, with the calendar's chain next to it
+        let bitcoin = op(&commitment, Op::Append(vec![2]), |msg| {
+            attested(msg, Attestation::Bitcoin { height: 7 })
+        });
+        timestamp.merge_at(&commitment, &bitcoin).unwrap();
+        let node = &timestamp.map().ops[0].1;
+        assert_eq!(node.attestations, vec![pending]);
+        assert_eq!(node.ops.len(), 1);
+
+        // Merging the same steps again changes nothing
+        let merged = timestamp.clone();
+        timestamp.merge_at(&commitment, &bitcoin).unwrap();
+        assert_eq!(timestamp, merged);
+
+        // A chain starting with the same op is merged into the existing one
+        let litecoin = op(&commitment, Op::Append(vec![2]), |msg| {
+            attested(msg, Attestation::Litecoin { height: 9 })
+        });
+        timestamp.merge_at(&commitment, &litecoin).unwrap();
+        assert_eq!(timestamp.map().ops[0].1.ops.len(), 1);
+        assert_eq!(timestamp.map().ops[0].1.ops[0].1.attestations.len(), 2);
+
+        // Only messages of the timestamp can be merged into
+        assert!(matches!(
+            timestamp
+                .merge_at(&[0x02; 32], &attested(&[0x02; 32], Attestation::Bitcoin { height: 1 })),
+            Err(OtsError::DigestMismatch)
+        ));
+        assert!(matches!(
+            timestamp.merge_at(&commitment, &attested(&start, Attestation::Bitcoin { height: 1 })),
+            Err(OtsError::OutputMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_normalize() {
+        let start = vec![0x01; 32];
+        let leaf_op = |kind: Op, height: usize| {
+            op(&start, kind, |msg| attested(msg, Attestation::Bitcoin { height }))
+        };
+        let pending = attested(&start, Attestation::Pending { uri: "https://a.example".into() });
+        let bitcoin = attested(&start, Attestation::Bitcoin { height: 7 });
+        let prepend = leaf_op(Op::Prepend(vec![2]), 8);
+        let append_b = leaf_op(Op::Append(vec![2]), 9);
+        let append_a = leaf_op(Op::Append(vec![1]), 9);
+
+        let start_digest = Digest::new(DigestType::Sha256, start.clone()).unwrap();
+        let mut a = Timestamp::new(
+            start_digest.clone(),
+            &fork(
+                &start,
+                vec![
+                    prepend.clone(),
+                    fork(&start, vec![pending.clone(), append_b.clone()]),
+                    bitcoin.clone(),
+                    append_a.clone(),
+                ],
+            ),
+        )
+        .unwrap();
+        let mut b = Timestamp::new(
+            start_digest,
+            &fork(
+                &start,
+                vec![
+                    append_a.clone(),
+                    bitcoin.clone(),
+                    append_b.clone(),
+                    pending.clone(),
+                    prepend.clone(),
+                    bitcoin.clone(),
+                ],
+            ),
+        )
+        .unwrap();
+        assert_ne!(a, b);
+        a.normalize();
+        b.normalize();
+        assert_eq!(a, b);
+
+        // Attestations by tag first, then ops by tag and argument
+        assert_eq!(a.first_step().next, vec![bitcoin, pending, append_a, append_b, prepend]);
+
+        let (mut bytes_a, mut bytes_b) = (Vec::new(), Vec::new());
+        a.serialize(&mut Serializer::new(&mut bytes_a)).unwrap();
+        b.serialize(&mut Serializer::new(&mut bytes_b)).unwrap();
+        assert_eq!(bytes_a, bytes_b);
+    }
+
+    #[test]
+    fn test_validate() {
+        let start = vec![0x01; 32];
+        let start_digest = Digest::new(DigestType::Sha256, start.clone()).unwrap();
+        let hashed =
+            op(&start, Op::Sha256, |msg| attested(msg, Attestation::Bitcoin { height: 7 }));
+        Timestamp::new(start_digest.clone(), &hashed).unwrap().validate().unwrap();
+
+        // A block attestation must be on a 32-byte merkle root
+        let appended = op(&start, Op::Append(vec![0]), |msg| {
+            attested(msg, Attestation::Bitcoin { height: 7 })
+        });
+        let timestamp = Timestamp::new(start_digest, &appended).unwrap();
+        assert!(matches!(timestamp.validate(), Err(OtsError::BadLength { val: 33, .. })));
+    }
+
+    #[test]
+    fn test_prune_pending() {
+        let start = [1; 32];
+        let pending = |uri: &'static str| {
+            move |msg: &[u8]| attested(msg, Attestation::Pending { uri: uri.into() })
+        };
+        let bitcoin = |msg: &[u8]| {
+            op(msg, Op::Sha256, |msg| attested(msg, Attestation::Bitcoin { height: 7 }))
+        };
+
+        // A calendar branch still pending goes, and so does the attestation next to the upgraded one
+        let mut timestamp = Timestamp::new(
+            Digest::sha256(start),
+            &fork(
+                &start,
+                vec![
+                    op(&start, Op::Append(vec![1]), pending("https://a.example")),
+                    op(&start, Op::Sha256, |msg| {
+                        fork(msg, vec![pending("https://b.example")(msg), bitcoin(msg)])
+                    }),
+                ],
+            ),
+        )
+        .unwrap();
+        assert!(timestamp.prune_pending());
+        assert_eq!(timestamp.first_step(), op(&start, Op::Sha256, bitcoin));
+        assert!(!timestamp.prune_pending());
+
+        // Nothing but pending attestations: keep them
+        let pending_only = op(&start, Op::Sha256, pending("https://a.example"));
+        let mut timestamp = Timestamp::new(Digest::sha256(start), &pending_only).unwrap();
+        assert!(!timestamp.prune_pending());
+        assert_eq!(timestamp.first_step(), pending_only);
+    }
+
+    #[test]
+    fn test_add_attestation() {
+        let digest = vec![7; 32];
+        let pending = Attestation::Pending { uri: "https://a.example".into() };
+        let mut timestamp = Timestamp::new(
+            Digest::new(DigestType::Sha256, digest.clone()).unwrap(),
+            &attested(&digest, pending.clone()),
+        )
+        .unwrap();
+        let extra = Attestation::Unknown { tag: vec![1; 8], data: vec![2] };
+        timestamp.add_attestation(extra.clone());
+        let first_step = timestamp.first_step();
+        assert_eq!(first_step.data, StepData::Fork);
+        assert_eq!(first_step.next.len(), 2);
+        assert_eq!(first_step.next[1].data, StepData::Attestation(extra.clone()));
+
+        timestamp.add_attestation(Attestation::Bitcoin { height: 1 });
+        assert_eq!(timestamp.first_step().next.len(), 3);
+
+        let mut buf = Vec::new();
+        timestamp.serialize(&mut Serializer::new(&mut buf)).unwrap();
+        let parsed = Timestamp::deserialize(
+            &mut Deserializer::new(&buf[..]),
+            timestamp.start_digest().clone(),
+        )
+        .unwrap();
+        assert_eq!(parsed, timestamp);
+        assert!(parsed.extract_path(&pending).is_some());
+    }
+
+    #[test]
+    fn test_extract_path_shortest() {
+        let start = [0x01; 32];
+        let bitcoin = Attestation::Bitcoin { height: 100 };
+        let pending = Attestation::Pending { uri: "https://example.com".to_string() };
+        let shortcut = |msg: &[u8]| {
+            op(msg, Op::Append(vec![0xff]), |msg| {
+                attested(msg, Attestation::Bitcoin { height: 100 })
+            })
+        };
+        let timestamp = Timestamp::new(
+            Digest::sha256(start),
+            &fork(
+                &start,
+                vec![
+                    op(&start, Op::Sha256, |msg| {
+                        op(msg, Op::Sha256, |msg| attested(msg, bitcoin.clone()))
+                    }),
+                    op(&start, Op::Prepend(vec![0xff]), |msg| {
+                        fork(msg, vec![attested(msg, pending.clone()), shortcut(msg)])
+                    }),
+                    op(&start, Op::Append(vec![0xff]), |msg| attested(msg, bitcoin.clone())),
+                ],
+            ),
+        )
+        .unwrap();
+
+        let extracted = timestamp.extract_path(&bitcoin).unwrap();
+        let expected = Timestamp::new(
+            Digest::sha256(start),
+            &op(&start, Op::Append(vec![0xff]), |msg| attested(msg, bitcoin.clone())),
+        )
+        .unwrap();
+        assert_eq!(extracted, expected);
+
+        assert!(timestamp.extract_path(&pending).is_some());
+        assert!(timestamp.extract_path(&Attestation::Bitcoin { height: 101 }).is_none());
+    }
+
+    #[test]
+    fn test_equivalent() {
+        let digest = [0x01; 32];
+        let leaf = |height: usize| attested(&digest, Attestation::Bitcoin { height });
+        let timestamp =
+            |first_step: Step| Timestamp::new(Digest::sha256(digest), &first_step).unwrap();
+        let forked = |next: Vec<Step>| fork(&digest, next);
+
+        let a = timestamp(forked(vec![leaf(1), leaf(2)]));
+        let reordered = timestamp(forked(vec![leaf(2), leaf(1)]));
+        let duplicated = timestamp(forked(vec![leaf(1), leaf(2), leaf(1)]));
+        let nested = timestamp(forked(vec![forked(vec![leaf(2), leaf(1)]), leaf(2)]));
+        assert_ne!(a, reordered);
+        assert!(a.equivalent(&reordered));
+        assert!(a.equivalent(&duplicated));
+        assert!(a.equivalent(&nested));
+        assert!(timestamp(leaf(1)).equivalent(&timestamp(forked(vec![leaf(1), leaf(1)]))));
+
+        // Branches starting with the same op are that op followed by a fork
+        let bitcoin =
+            |height: usize| move |msg: &[u8]| attested(msg, Attestation::Bitcoin { height });
+        let append = || Op::Append(vec![0xaa]);
+        let shared = timestamp(forked(vec![
+            op(&digest, append(), bitcoin(1)),
+            op(&digest, append(), bitcoin(2)),
+        ]));
+        let prefixed = timestamp(op(&digest, append(), |msg| {
+            fork(msg, vec![bitcoin(2)(msg), bitcoin(1)(msg)])
+        }));
+        assert!(shared.equivalent(&prefixed));
+        assert!(!shared.equivalent(&a));
+
+        assert!(!a.equivalent(&timestamp(forked(vec![leaf(1), leaf(3)]))));
+        assert!(!a.equivalent(&timestamp(leaf(1))));
+        let other = [0x02; 32];
+        let other_digest = Timestamp::new(
+            Digest::sha256(other),
+            &fork(
+                &other,
+                vec![
+                    attested(&other, Attestation::Bitcoin { height: 1 }),
+                    attested(&other, Attestation::Bitcoin { height: 2 }),
+                ],
+            ),
+        )
+        .unwrap();
+        assert!(!a.equivalent(&other_digest));
+    }
 }
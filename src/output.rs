@@ -0,0 +1,200 @@
+use crate::error::{Error, Result};
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+/// What to do when an output file already exists
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overwrite {
+    /// Fail with `Error::OutputExists`
+    #[allow(dead_code)]
+    Never,
+    /// Ask on the terminal, failing like `Never` when there is no terminal
+    Ask,
+    /// Replace the file
+    Always,
+}
+
+impl Overwrite {
+    /// Policy for a command: `--force` or the global `--yes` replace files
+    /// without asking
+    #[must_use]
+    pub const fn from_flags(force: bool, yes: bool) -> Self {
+        if force || yes {
+            Self::Always
+        } else {
+            Self::Ask
+        }
+    }
+}
+
+/// Create an output file, handling an existing one according to `overwrite`
+///
+/// # Errors
+/// Returns `Error::OutputExists` if the file exists and may not be replaced,
+/// or an IO error if it cannot be created
+pub fn create_file(path: &Path, overwrite: Overwrite) -> Result<File> {
+    match OpenOptions::new().write(true).create_new(true).open(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            if confirm(path, overwrite)? {
+                Ok(File::create(path)?)
+            } else {
+                Err(Error::OutputExists(path.to_path_buf()))
+            }
+        }
+        result => Ok(result?),
+    }
+}
+
+/// A file written under a temporary name and renamed over its path once complete
+///
+/// Until `commit`, any existing file at the path stays intact, so a crash
+/// while writing cannot leave a truncated file behind. The temporary file
+/// sits in the same directory, as a rename cannot cross file systems, and is
+/// removed if the `AtomicFile` is dropped without `commit`.
+pub struct AtomicFile {
+    path: PathBuf,
+    temp: PathBuf,
+    file: File,
+    committed: bool,
+}
+
+impl AtomicFile {
+    /// Start writing `path`, handling an existing file according to `overwrite`
+    ///
+    /// The new file gets the permissions of the one it replaces.
+    ///
+    /// # Errors
+    /// Returns `Error::OutputExists` if the file exists and may not be replaced,
+    /// or an IO error if the temporary file cannot be created
+    pub fn create(path: &Path, overwrite: Overwrite) -> Result<Self> {
+        let existing = std::fs::metadata(path).ok();
+        if existing.is_some() && !confirm(path, overwrite)? {
+            return Err(Error::OutputExists(path.to_path_buf()));
+        }
+        let temp = temp_path(path);
+        let file = OpenOptions::new().write(true).create_new(true).open(&temp)?;
+        let atomic = Self { path: path.to_path_buf(), temp, file, committed: false };
+        if let Some(existing) = existing {
+            atomic.file.set_permissions(existing.permissions())?;
+        }
+        Ok(atomic)
+    }
+
+    /// Replace the file at the path with everything written so far
+    ///
+    /// # Errors
+    /// Returns error if the data cannot be synced to disk or the rename fails
+    pub fn commit(mut self) -> Result<()> {
+        self.file.sync_all()?;
+        std::fs::rename(&self.temp, &self.path)?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = std::fs::remove_file(&self.temp);
+        }
+    }
+}
+
+/// Temporary name for writing `path`: `dir/.name.<pid>.tmp`
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".{}.tmp", std::process::id()));
+    path.with_file_name(name)
+}
+
+/// Whether an existing file at `path` may be replaced
+fn confirm(path: &Path, overwrite: Overwrite) -> Result<bool> {
+    match overwrite {
+        Overwrite::Never => Ok(false),
+        Overwrite::Always => Ok(true),
+        Overwrite::Ask => {
+            let stdin = std::io::stdin();
+            if !stdin.is_terminal() || !std::io::stderr().is_terminal() {
+                return Ok(false);
+            }
+            eprint!("{} already exists. Overwrite? [y/N] ", path.display());
+            std::io::stderr().flush()?;
+            let mut answer = String::new();
+            stdin.lock().read_line(&mut answer)?;
+            Ok(is_yes(&answer))
+        }
+    }
+}
+
+/// Whether a prompt answer means yes
+fn is_yes(answer: &str) -> bool {
+    matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create() {
+        let path = std::env::temp_dir().join(format!("ots_output_{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        create_file(&path, Overwrite::Never).unwrap().write_all(b"first").unwrap();
+        assert!(matches!(create_file(&path, Overwrite::Never), Err(Error::OutputExists(_))));
+        assert_eq!(std::fs::read(&path).unwrap(), b"first");
+
+        create_file(&path, Overwrite::Always).unwrap().write_all(b"second").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"second");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_atomic_file() {
+        let dir = std::env::temp_dir().join(format!("ots_atomic_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file.txt.ots");
+
+        let mut file = AtomicFile::create(&path, Overwrite::Never).unwrap();
+        file.write_all(b"first").unwrap();
+        assert!(!path.exists());
+        file.commit().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"first");
+        assert!(matches!(AtomicFile::create(&path, Overwrite::Never), Err(Error::OutputExists(_))));
+
+        // Dropped before commit: the original stays and nothing is left behind
+        let mut file = AtomicFile::create(&path, Overwrite::Always).unwrap();
+        file.write_all(b"partial").unwrap();
+        drop(file);
+        assert_eq!(std::fs::read(&path).unwrap(), b"first");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        let mut file = AtomicFile::create(&path, Overwrite::Always).unwrap();
+        file.write_all(b"second").unwrap();
+        file.commit().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"second");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_is_yes() {
+        assert!(is_yes("y\n"));
+        assert!(is_yes(" YES "));
+        assert!(!is_yes("\n"));
+        assert!(!is_yes("no"));
+    }
+}
@@ -0,0 +1,138 @@
+//! Progress bars for batch commands
+//!
+//! Bars are drawn on stderr, and only when it is a terminal and the command
+//! is neither quiet nor told not to. Messages printed with `progress!` while
+//! bars are shown appear above them.
+
+use crate::verbosity::is_quiet;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::io::{IsTerminal, Read};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Bars currently drawn, hidden while `suspend` prints
+static ACTIVE: Mutex<Option<MultiProgress>> = Mutex::new(None);
+
+/// Layout of the bar counting the files of a batch
+const BATCH_TEMPLATE: &str = "{spinner} [{elapsed_precise}] {bar:30} {pos}/{len} files {msg}";
+
+/// Layout of the bar counting the bytes of the file being hashed
+const FILE_TEMPLATE: &str = "  {bar:30} {bytes}/{total_bytes} {wide_msg}";
+
+/// How often the batch spinner moves while waiting for calendars
+const TICK: Duration = Duration::from_millis(120);
+
+/// Progress of a batch of files, hidden when bars are not wanted
+pub struct BatchProgress {
+    multi: Option<MultiProgress>,
+    batch: ProgressBar,
+}
+
+impl BatchProgress {
+    /// Track a batch of `files` files, drawing bars if `enabled` and stderr
+    /// is a terminal
+    #[must_use]
+    pub fn new(files: usize, enabled: bool) -> Self {
+        if !enabled || is_quiet() || !std::io::stderr().is_terminal() {
+            return Self::hidden();
+        }
+        let multi = MultiProgress::new();
+        let batch = multi.add(ProgressBar::new(files as u64).with_style(style(BATCH_TEMPLATE)));
+        if let Ok(mut active) = ACTIVE.lock() {
+            *active = Some(multi.clone());
+        }
+        Self { multi: Some(multi), batch }
+    }
+
+    /// Progress that draws nothing
+    #[must_use]
+    pub fn hidden() -> Self {
+        Self { multi: None, batch: ProgressBar::hidden() }
+    }
+
+    /// Start hashing a file of `len` bytes
+    ///
+    /// The file counts as done once the returned `FileProgress` is dropped.
+    #[must_use]
+    pub fn file(&self, path: &Path, len: u64) -> FileProgress {
+        let bar = match &self.multi {
+            Some(multi) => multi.add(
+                ProgressBar::new(len)
+                    .with_style(style(FILE_TEMPLATE))
+                    .with_message(path.display().to_string()),
+            ),
+            None => ProgressBar::hidden(),
+        };
+        FileProgress { bar, batch: self.batch.clone() }
+    }
+
+    /// Show what the batch is waiting for, keeping the spinner moving
+    pub fn stage(&self, message: impl Into<String>) {
+        self.batch.set_message(message.into());
+        self.batch.enable_steady_tick(TICK);
+    }
+}
+
+impl Drop for BatchProgress {
+    fn drop(&mut self) {
+        if self.multi.is_some() {
+            self.batch.finish_and_clear();
+            if let Ok(mut active) = ACTIVE.lock() {
+                *active = None;
+            }
+        }
+    }
+}
+
+/// Progress of hashing one file of a batch
+pub struct FileProgress {
+    bar: ProgressBar,
+    batch: ProgressBar,
+}
+
+impl FileProgress {
+    /// Count the bytes read through `reader`
+    pub fn wrap<R: Read>(&self, reader: R) -> impl Read {
+        self.bar.wrap_read(reader)
+    }
+}
+
+impl Drop for FileProgress {
+    fn drop(&mut self) {
+        self.bar.finish_and_clear();
+        self.batch.inc(1);
+    }
+}
+
+/// Run `print` with any bars hidden, so its output is not drawn over
+pub fn suspend<T>(print: impl FnOnce() -> T) -> T {
+    let active = ACTIVE.lock().ok().and_then(|active| active.clone());
+    match active {
+        Some(multi) => multi.suspend(print),
+        None => print(),
+    }
+}
+
+/// Style from a template known to be valid
+fn style(template: &str) -> ProgressStyle {
+    ProgressStyle::with_template(template).unwrap_or_else(|_| ProgressStyle::default_bar())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hidden() {
+        let progress = BatchProgress::new(2, false);
+        assert!(progress.multi.is_none());
+
+        let file = progress.file(Path::new("a.txt"), 5);
+        let mut read = String::new();
+        file.wrap(&b"hello"[..]).read_to_string(&mut read).unwrap();
+        assert_eq!(read, "hello");
+        drop(file);
+        assert_eq!(suspend(|| 42), 42);
+    }
+}
@@ -0,0 +1,209 @@
+use crate::error::Error;
+use crate::ots::OtsError;
+use std::io::ErrorKind;
+
+/// Hint for proofs that are damaged rather than misused
+const CORRUPT_HINT: &str = "the proof may be corrupted; get a fresh copy from wherever it was made";
+
+/// A short explanation of an error and what to do about it
+#[derive(Debug, PartialEq, Eq)]
+pub struct Explanation {
+    /// What went wrong, in one line
+    pub summary: String,
+    /// Suggested next step, if there is a useful one
+    pub hint: Option<&'static str>,
+}
+
+impl Explanation {
+    fn new(summary: impl Into<String>, hint: &'static str) -> Self {
+        Self { summary: summary.into(), hint: Some(hint) }
+    }
+
+    fn bare(summary: impl Into<String>) -> Self {
+        Self { summary: summary.into(), hint: None }
+    }
+}
+
+/// Explain an error to the user
+#[must_use]
+pub fn explain(error: &Error) -> Explanation {
+    match error {
+        Error::Io(e) => explain_io(e),
+        Error::InvalidOts(e) => explain_ots(e),
+        Error::Http(e) if e.is_timeout() => Explanation::new(
+            "a network request timed out",
+            "try again later, or allow more time with --timeout",
+        ),
+        Error::Http(e) if e.is_connect() => Explanation::new(
+            "could not connect to the server",
+            "check your network connection, and that Tor is running if --use-tor is set",
+        ),
+        Error::Http(_) => {
+            Explanation::new("a network request failed", "try again later; run with -v for details")
+        }
+        Error::Calendar(msg) => Explanation::new(
+            format!("calendar server error: {msg}"),
+            "try again later, or use other calendars with --calendar",
+        ),
+        Error::CalendarUnavailable { calendar, status } => Explanation::new(
+            format!("calendar {calendar} is unavailable (HTTP {status})"),
+            "try again later, or use other calendars with --calendar",
+        ),
+        Error::CalendarRateLimited { calendar, .. } => Explanation::new(
+            format!("calendar {calendar} is rate limiting requests"),
+            "wait a while before trying again, or use other calendars with --calendar",
+        ),
+        Error::CalendarRejected { calendar, status, .. } => Explanation::new(
+            format!("calendar {calendar} rejected the request (HTTP {status})"),
+            "repeating the request will not help; use other calendars with --calendar",
+        ),
+        Error::InvalidCalendarResponse(msg) => Explanation::new(
+            format!("a calendar sent an invalid timestamp: {msg}"),
+            "the calendar may be misbehaving; use other calendars with --calendar",
+        ),
+        Error::BlockedCalendar(host) => Explanation::new(
+            format!("calendar {host} is blocked by the configuration"),
+            "allow it under [calendars] in the configuration file, or use other calendars",
+        ),
+        Error::Config(msg) => Explanation::new(
+            format!("invalid configuration: {msg}"),
+            "fix the configuration file (see --config and OTS_CONFIG)",
+        ),
+        Error::Log(msg) => Explanation::new(
+            format!("invalid log: {msg}"),
+            "the log may have been edited outside `ots log append`",
+        ),
+        Error::Bundle(msg) => Explanation::new(
+            format!("invalid bundle: {msg}"),
+            "bundles are .otsz files made with `ots bundle create`",
+        ),
+        Error::Database(_) => Explanation::new(
+            "the state database cannot be used",
+            "check the path under [state], or set `enabled = false` there to stop recording",
+        ),
+        Error::State(msg) => Explanation::new(
+            format!("the state database holds invalid data: {msg}"),
+            "move the database aside to start a fresh one",
+        ),
+        Error::Hook(msg) => {
+            Explanation::new(format!("hook failed: {msg}"), "check the [hooks] configuration")
+        }
+        Error::OutputExists(path) => Explanation::new(
+            format!("{} already exists", path.display()),
+            "pass --force (or the global --yes) to overwrite it",
+        ),
+        Error::BatchFailed { failed, total } => Explanation::new(
+            format!("{failed} of {total} files failed"),
+            "the summary lists the error of each file",
+        ),
+        Error::ChecksFailed { failed, total } => Explanation::new(
+            format!("{failed} of {total} checks failed"),
+            "follow the hint under each failed check",
+        ),
+        Error::Signing(msg) => Explanation::new(
+            format!("invalid signing key: {msg}"),
+            "the key file must hold 32 random bytes, hex-encoded, e.g. from `openssl rand -hex 32`",
+        ),
+        Error::Verification(msg) => Explanation::bare(format!("verification failed: {msg}")),
+        Error::NoBitcoinAttestation => Explanation::new(
+            "the proof has no Bitcoin attestation yet",
+            "run `ots upgrade` on the proof once the calendar has anchored it",
+        ),
+        Error::PendingTimestamp => Explanation::new(
+            "the timestamp is still pending",
+            "run `ots upgrade` later; confirmation usually takes a few hours",
+        ),
+    }
+}
+
+/// Explain an IO error
+fn explain_io(error: &std::io::Error) -> Explanation {
+    match error.kind() {
+        ErrorKind::NotFound => Explanation::new(
+            "file not found",
+            "check the path; proofs are usually named after the file with .ots appended",
+        ),
+        ErrorKind::PermissionDenied => {
+            Explanation::new("permission denied", "check the permissions of the file")
+        }
+        _ => Explanation::bare(format!("I/O error: {error}")),
+    }
+}
+
+/// Explain why a proof could not be read
+fn explain_ots(error: &OtsError) -> Explanation {
+    match error {
+        OtsError::BadMagic(_) => Explanation::new(
+            "this is not an .ots file",
+            "did you pass the original file instead of its proof?",
+        ),
+        OtsError::BadVersion(version) => Explanation::new(
+            format!("unsupported proof format version {version}"),
+            "the proof was probably made by a newer client; update this one, \
+             or look inside with `ots info --lenient`",
+        ),
+        OtsError::Io(e) if e.kind() == ErrorKind::UnexpectedEof => Explanation::new(
+            "the proof is truncated",
+            "the file was cut short, e.g. by an interrupted download or copy",
+        ),
+        OtsError::Io(e) => explain_io(e),
+        OtsError::TrailingBytes => Explanation::new(
+            "unexpected data after the end of the proof",
+            "the file may have been appended to or corrupted",
+        ),
+        OtsError::DigestMismatch => Explanation::new(
+            "the attached data does not match its timestamp",
+            "the .otsa file was modified after it was stamped",
+        ),
+        OtsError::BadSignature => Explanation::new(
+            "the signature of the proof is invalid",
+            "the signed file was modified after signing",
+        ),
+        _ => Explanation::new(format!("invalid proof: {error}"), CORRUPT_HINT),
+    }
+}
+
+/// Render an error for stderr
+///
+/// With `verbose`, the raw error follows the explanation.
+#[must_use]
+pub fn render(error: &Error, verbose: bool) -> String {
+    let explanation = explain(error);
+    let mut out = format!("error: {}\n", explanation.summary);
+    if let Some(hint) = explanation.hint {
+        out.push_str(&format!("hint: {hint}\n"));
+    }
+    if verbose {
+        out.push_str(&format!("details: {error:?}\n"));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_explain() {
+        let bad_magic = Error::InvalidOts(OtsError::BadMagic(b"hello".to_vec()));
+        assert_eq!(explain(&bad_magic).summary, "this is not an .ots file");
+
+        let truncated = Error::InvalidOts(OtsError::Io(ErrorKind::UnexpectedEof.into()));
+        assert_eq!(explain(&truncated).summary, "the proof is truncated");
+
+        let bad_op = Error::InvalidOts(OtsError::BadOpTag(0x04));
+        assert_eq!(explain(&bad_op).hint, Some(CORRUPT_HINT));
+
+        assert!(explain(&Error::PendingTimestamp).hint.unwrap().contains("ots upgrade"));
+        assert!(explain(&Error::Verification("mismatch".into())).hint.is_none());
+    }
+
+    #[test]
+    fn test_render() {
+        let error = Error::Io(ErrorKind::NotFound.into());
+        let rendered = render(&error, false);
+        assert!(rendered.starts_with("error: file not found\nhint: "));
+        assert!(!rendered.contains("details"));
+        assert!(render(&error, true).contains("details: Io("));
+    }
+}
@@ -0,0 +1,318 @@
+use crate::error::{Error, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Schema of the state database
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS stamps (
+    id INTEGER PRIMARY KEY,
+    file TEXT NOT NULL,
+    digest TEXT NOT NULL,
+    nonce_policy TEXT NOT NULL,
+    calendars TEXT NOT NULL,
+    proof TEXT NOT NULL UNIQUE,
+    status TEXT NOT NULL,
+    created INTEGER NOT NULL,
+    updated INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS stamps_file ON stamps (file);
+";
+
+/// How far along a recorded stamp is
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum StampStatus {
+    /// Only pending calendar attestations so far
+    Pending,
+    /// The proof contains a block attestation
+    Confirmed,
+    /// The block attestation was checked against the blockchain
+    Verified,
+}
+
+impl StampStatus {
+    /// Name of the status as stored and displayed
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Confirmed => "confirmed",
+            Self::Verified => "verified",
+        }
+    }
+}
+
+impl fmt::Display for StampStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for StampStatus {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pending" => Ok(Self::Pending),
+            "confirmed" => Ok(Self::Confirmed),
+            "verified" => Ok(Self::Verified),
+            _ => Err(Error::State(format!("Unknown stamp status: {s}"))),
+        }
+    }
+}
+
+/// A stamp recorded in the state store
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StampRecord {
+    /// Absolute path of the stamped file
+    pub file: PathBuf,
+    /// Hex-encoded digest of the file
+    pub digest: String,
+    /// How the digest was committed to, e.g. `per-file` or `merkle`
+    pub nonce_policy: String,
+    /// Calendar URLs submitted to, one group per chain (empty for the defaults)
+    pub calendars: Vec<Vec<String>>,
+    /// Path of the proof
+    pub proof: PathBuf,
+    /// Current status
+    pub status: StampStatus,
+    /// When the file was stamped (Unix epoch)
+    pub created: u64,
+    /// When the record last changed (Unix epoch)
+    pub updated: u64,
+}
+
+/// Embedded database recording every stamp made by this client
+///
+/// Without it, the only state is the `.ots` files themselves, scattered
+/// wherever the stamped files live.
+pub struct StateStore {
+    conn: Connection,
+}
+
+impl StateStore {
+    /// Open the store at `path`, creating it if needed
+    ///
+    /// # Errors
+    /// Returns error if the database cannot be opened or initialized
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Self::init(Connection::open(path)?)
+    }
+
+    /// Open a store that only lives in memory
+    ///
+    /// # Errors
+    /// Returns error if the database cannot be initialized
+    #[allow(dead_code)]
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    /// Record a new stamp, replacing any earlier record for the same proof
+    ///
+    /// # Errors
+    /// Returns error if the record cannot be written
+    pub fn record(&self, record: &StampRecord) -> Result<()> {
+        let calendars = serde_json::to_string(&record.calendars)
+            .map_err(|e| Error::State(format!("Cannot encode calendars: {e}")))?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO stamps
+             (file, digest, nonce_policy, calendars, proof, status, created, updated)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                path_key(&record.file),
+                record.digest,
+                record.nonce_policy,
+                calendars,
+                path_key(&record.proof),
+                record.status.as_str(),
+                record.created,
+                record.updated,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Update the status of the stamp with this proof
+    ///
+    /// Returns whether a record was found. The status only moves forward:
+    /// verifying a proof does not make it pending again.
+    ///
+    /// # Errors
+    /// Returns error if the record cannot be read or written
+    pub fn set_status(&self, proof: &Path, status: StampStatus) -> Result<bool> {
+        let key = path_key(proof);
+        let current: Option<String> = self
+            .conn
+            .query_row("SELECT status FROM stamps WHERE proof = ?1", params![key], |row| row.get(0))
+            .optional()?;
+        let Some(current) = current else {
+            return Ok(false);
+        };
+        if current.parse::<StampStatus>()? < status {
+            self.conn.execute(
+                "UPDATE stamps SET status = ?1, updated = ?2 WHERE proof = ?3",
+                params![status.as_str(), now(), key],
+            )?;
+        }
+        Ok(true)
+    }
+
+    /// List recorded stamps, oldest first
+    ///
+    /// # Arguments
+    /// * `status` - Only list stamps with this status
+    /// * `file` - Only list stamps whose file path contains this text
+    ///
+    /// # Errors
+    /// Returns error if the records cannot be read
+    pub fn list(
+        &self,
+        status: Option<StampStatus>,
+        file: Option<&str>,
+    ) -> Result<Vec<StampRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT file, digest, nonce_policy, calendars, proof, status, created, updated
+             FROM stamps
+             WHERE (?1 IS NULL OR status = ?1) AND (?2 IS NULL OR instr(file, ?2) > 0)
+             ORDER BY created, id",
+        )?;
+        let rows = stmt.query_map(params![status.map(StampStatus::as_str), file], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, String>(5)?,
+                row.get::<_, u64>(6)?,
+                row.get::<_, u64>(7)?,
+            ))
+        })?;
+
+        let mut records = vec![];
+        for row in rows {
+            let (file, digest, nonce_policy, calendars, proof, status, created, updated) = row?;
+            records.push(StampRecord {
+                file: PathBuf::from(file),
+                digest,
+                nonce_policy,
+                calendars: serde_json::from_str(&calendars)
+                    .map_err(|e| Error::State(format!("Invalid calendars for {proof}: {e}")))?,
+                proof: PathBuf::from(proof),
+                status: status.parse()?,
+                created,
+                updated,
+            });
+        }
+        Ok(records)
+    }
+}
+
+/// Default location of the state database
+///
+/// `$XDG_DATA_HOME/ots/state.db`, or `~/.local/share/ots/state.db`.
+#[must_use]
+pub fn default_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_DATA_HOME").map(PathBuf::from).or_else(|| {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share"))
+    })?;
+    Some(base.join("ots").join("state.db"))
+}
+
+/// Current time as seconds since the Unix epoch
+#[must_use]
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Absolute form of a path, so records match whatever directory the client runs in
+fn path_key(path: &Path) -> String {
+    absolute(path).display().to_string()
+}
+
+/// Make a path absolute by joining it to the current directory
+#[must_use]
+pub fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+    std::env::current_dir().map_or_else(|_| path.to_path_buf(), |dir| dir.join(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(file: &str, status: StampStatus, created: u64) -> StampRecord {
+        StampRecord {
+            file: PathBuf::from(format!("/data/{file}")),
+            digest: "ab".repeat(32),
+            nonce_policy: "per-file".to_string(),
+            calendars: vec![vec!["https://a.pool.opentimestamps.org".to_string()]],
+            proof: PathBuf::from(format!("/data/{file}.ots")),
+            status,
+            created,
+            updated: created,
+        }
+    }
+
+    #[test]
+    fn test_record_and_list() {
+        let store = StateStore::open_in_memory().unwrap();
+        store.record(&record("b.txt", StampStatus::Confirmed, 2)).unwrap();
+        store.record(&record("a.txt", StampStatus::Pending, 1)).unwrap();
+
+        let all = store.list(None, None).unwrap();
+        assert_eq!(
+            all,
+            vec![
+                record("a.txt", StampStatus::Pending, 1),
+                record("b.txt", StampStatus::Confirmed, 2)
+            ]
+        );
+
+        let pending = store.list(Some(StampStatus::Pending), None).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(store.list(None, Some("b.t")).unwrap().len(), 1);
+        assert!(store.list(Some(StampStatus::Verified), None).unwrap().is_empty());
+
+        // Stamping the same file again replaces its record
+        store.record(&record("a.txt", StampStatus::Pending, 3)).unwrap();
+        assert_eq!(store.list(None, None).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_set_status() {
+        let store = StateStore::open_in_memory().unwrap();
+        store.record(&record("a.txt", StampStatus::Pending, 1)).unwrap();
+        let proof = Path::new("/data/a.txt.ots");
+
+        assert!(store.set_status(proof, StampStatus::Verified).unwrap());
+        assert_eq!(store.list(None, None).unwrap()[0].status, StampStatus::Verified);
+
+        // Status never moves backwards
+        assert!(store.set_status(proof, StampStatus::Confirmed).unwrap());
+        assert_eq!(store.list(None, None).unwrap()[0].status, StampStatus::Verified);
+
+        assert!(!store.set_status(Path::new("/data/missing.ots"), StampStatus::Confirmed).unwrap());
+    }
+
+    #[test]
+    fn test_status_names() {
+        for status in [StampStatus::Pending, StampStatus::Confirmed, StampStatus::Verified] {
+            assert_eq!(status.as_str().parse::<StampStatus>().unwrap(), status);
+        }
+        assert!("done".parse::<StampStatus>().is_err());
+    }
+}
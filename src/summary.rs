@@ -0,0 +1,239 @@
+use crate::commands::verify::AttestationReport;
+use crate::error::{Error, Result};
+use crate::verbosity::progress;
+use serde::Serialize;
+use std::fmt::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Outcome of one file in a batch run
+#[derive(Debug, Serialize)]
+pub struct FileOutcome {
+    /// File as given on the command line
+    pub file: String,
+    /// `ok` or `failed`
+    pub status: &'static str,
+    /// What went wrong, if the file failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Time spent on the file in milliseconds
+    pub duration_ms: u64,
+    /// What `verify` found for each block attestation of the proof
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attestations: Vec<AttestationReport>,
+}
+
+/// End-of-run summary of a batch stamp, verify or upgrade
+///
+/// Without `--json` or `--report`, nothing is collected and the first
+/// failing file stops the run, as usual. With either, every file is
+/// processed and the summary lists each one.
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    /// Command that ran
+    pub command: &'static str,
+    /// Number of files processed
+    pub total: usize,
+    /// Number of files that succeeded
+    pub succeeded: usize,
+    /// Number of files that failed
+    pub failed: usize,
+    /// Duration of the whole run in milliseconds
+    pub duration_ms: u64,
+    /// Outcome of every file, in order
+    pub files: Vec<FileOutcome>,
+    #[serde(skip)]
+    collect: bool,
+    #[serde(skip)]
+    started: Instant,
+}
+
+impl Summary {
+    /// Start a summary, collecting outcomes only if `collect` is set
+    #[must_use]
+    pub fn new(command: &'static str, collect: bool) -> Self {
+        Self {
+            command,
+            total: 0,
+            succeeded: 0,
+            failed: 0,
+            duration_ms: 0,
+            files: vec![],
+            collect,
+            started: Instant::now(),
+        }
+    }
+
+    /// Collect outcomes even without `--json` or `--report`
+    ///
+    /// Used for runs over whole directories, where one failing file should
+    /// not stop the others.
+    #[must_use]
+    pub const fn keep_going(mut self) -> Self {
+        self.collect = true;
+        self
+    }
+
+    /// Record the result of one file, which took since `started`
+    ///
+    /// # Errors
+    /// Returns the file's error if outcomes are not being collected
+    pub fn record(&mut self, file: &Path, started: Instant, result: Result<()>) -> Result<()> {
+        self.record_all(&[file], started, result)
+    }
+
+    /// Record the result of verifying one proof, with what each attestation gave
+    ///
+    /// # Errors
+    /// Returns the file's error if outcomes are not being collected
+    pub fn record_verification(
+        &mut self,
+        file: &Path,
+        started: Instant,
+        result: Result<()>,
+        attestations: Vec<AttestationReport>,
+    ) -> Result<()> {
+        self.record(file, started, result)?;
+        if self.collect {
+            if let Some(outcome) = self.files.last_mut() {
+                outcome.attestations = attestations;
+            }
+        }
+        Ok(())
+    }
+
+    /// Record one result shared by several files, e.g. a single Merkle submission
+    ///
+    /// # Errors
+    /// Returns the error if outcomes are not being collected
+    pub fn record_all(
+        &mut self,
+        files: &[impl AsRef<Path>],
+        started: Instant,
+        result: Result<()>,
+    ) -> Result<()> {
+        if !self.collect {
+            return result;
+        }
+        let error = result.err().map(|e| e.to_string());
+        let duration_ms = millis(started.elapsed());
+        for file in files {
+            let file = file.as_ref();
+            let status = if let Some(e) = &error {
+                log::error!("{}: {e}", file.display());
+                self.failed += 1;
+                "failed"
+            } else {
+                self.succeeded += 1;
+                "ok"
+            };
+            self.total += 1;
+            self.files.push(FileOutcome {
+                file: file.display().to_string(),
+                status,
+                error: error.clone(),
+                duration_ms,
+                attestations: vec![],
+            });
+        }
+        Ok(())
+    }
+
+    /// One line per file with its status and any error, the file names aligned
+    #[must_use]
+    pub fn table(&self) -> String {
+        let width = self.files.iter().map(|outcome| outcome.file.len()).max().unwrap_or(0);
+        let mut table = String::new();
+        for outcome in &self.files {
+            let status = if outcome.error.is_some() { "FAILED" } else { "ok" };
+            let line = format!("{:<width$}  {status}", outcome.file);
+            let _ = match &outcome.error {
+                Some(e) => writeln!(table, "{line}  {e}"),
+                None => writeln!(table, "{line}"),
+            };
+        }
+        table
+    }
+
+    /// Print the summary to stdout if `json` is set and write it to `report`
+    ///
+    /// # Errors
+    /// Returns error if the report cannot be written, or `Error::BatchFailed`
+    /// if any file failed
+    pub fn finish(mut self, json: bool, report: Option<&Path>) -> Result<()> {
+        if !self.collect {
+            return Ok(());
+        }
+        self.duration_ms = millis(self.started.elapsed());
+        let text = serde_json::to_string_pretty(&self).map_err(std::io::Error::from)?;
+        if json {
+            println!("{text}");
+        }
+        if let Some(path) = report {
+            std::fs::write(path, format!("{text}\n"))?;
+        }
+        if self.failed > 0 {
+            return Err(Error::BatchFailed { failed: self.failed, total: self.total });
+        }
+        progress!("{}: all {} files succeeded", self.command, self.total);
+        Ok(())
+    }
+}
+
+/// Whole milliseconds in a duration
+fn millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect() {
+        let mut summary = Summary::new("verify", true);
+        summary.record(Path::new("a.ots"), Instant::now(), Ok(())).unwrap();
+        summary.record(Path::new("b.ots"), Instant::now(), Err(Error::PendingTimestamp)).unwrap();
+        assert_eq!((summary.total, summary.succeeded, summary.failed), (2, 1, 1));
+        assert_eq!(summary.files[1].status, "failed");
+        assert!(summary.files[1].error.as_deref().unwrap().contains("pending"));
+
+        let attestation = AttestationReport {
+            chain: "Bitcoin",
+            height: 100,
+            status: "verified",
+            merkle_root: "ab".repeat(32),
+            txid: None,
+            block_time: Some(1_231_006_505),
+            checked_at: 0,
+            backend: "Electrum",
+            error: None,
+        };
+        summary
+            .record_verification(Path::new("c.ots"), Instant::now(), Ok(()), vec![attestation])
+            .unwrap();
+        let json = serde_json::to_value(&summary).unwrap();
+        assert!(json["files"][0].get("attestations").is_none());
+        assert_eq!(json["files"][2]["attestations"][0]["height"], 100);
+        assert_eq!(json["files"][2]["attestations"][0]["block_time"], 1_231_006_505);
+
+        let table = summary.table();
+        assert!(table.starts_with("a.ots  ok\nb.ots  FAILED  "));
+        assert!(table.ends_with('\n'));
+
+        assert!(matches!(
+            summary.finish(false, None),
+            Err(Error::BatchFailed { failed: 1, total: 3 })
+        ));
+    }
+
+    #[test]
+    fn test_fail_fast() {
+        let mut summary = Summary::new("stamp", false);
+        assert!(summary
+            .record(Path::new("a"), Instant::now(), Err(Error::PendingTimestamp))
+            .is_err());
+        assert!(summary.files.is_empty());
+        summary.finish(true, None).unwrap();
+    }
+}
@@ -0,0 +1,90 @@
+//! How attested block times are printed
+
+use serde::Serializer;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Style of attested times, set once at startup
+static STYLE: AtomicU8 = AtomicU8::new(TimeStyle::Human as u8);
+
+/// How attested block times are printed, from `--time-style`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimeStyle {
+    /// RFC 3339 profile of ISO 8601 in UTC, `2009-01-03T18:15:05Z`
+    Iso8601,
+    /// Seconds since the Unix epoch, `1231006505`
+    Unix,
+    /// Readable UTC time, `2009-01-03 18:15:05 UTC`
+    #[default]
+    Human,
+}
+
+impl TimeStyle {
+    /// Format a block time, in seconds since the Unix epoch
+    #[must_use]
+    pub fn format(self, time: u32) -> String {
+        let Some(datetime) = chrono::DateTime::from_timestamp(i64::from(time), 0) else {
+            return time.to_string();
+        };
+        match self {
+            Self::Iso8601 => datetime.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            Self::Unix => time.to_string(),
+            Self::Human => datetime.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+        }
+    }
+
+    /// Make this the style of `format_time` and JSON block times
+    pub fn set_global(self) {
+        STYLE.store(self as u8, Ordering::Relaxed);
+    }
+
+    /// The style set with `set_global`
+    #[must_use]
+    pub fn global() -> Self {
+        match STYLE.load(Ordering::Relaxed) {
+            x if x == Self::Iso8601 as u8 => Self::Iso8601,
+            x if x == Self::Unix as u8 => Self::Unix,
+            _ => Self::Human,
+        }
+    }
+}
+
+/// Format a block time in the global style
+#[must_use]
+pub fn format_time(time: u32) -> String {
+    TimeStyle::global().format(time)
+}
+
+/// Serialize a block time for JSON output
+///
+/// Times stay numbers of seconds since the Unix epoch, as parsers expect,
+/// unless the global style is ISO 8601, which gives a string.
+///
+/// # Errors
+/// Returns the serializer's error
+#[allow(clippy::trivially_copy_pass_by_ref)]
+pub fn serialize_block_time<S: Serializer>(
+    time: &Option<u32>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match (time, TimeStyle::global()) {
+        (Some(time), TimeStyle::Iso8601) => {
+            serializer.serialize_str(&TimeStyle::Iso8601.format(*time))
+        }
+        (Some(time), _) => serializer.serialize_u32(*time),
+        (None, _) => serializer.serialize_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let genesis = 1_231_006_505;
+        assert_eq!(TimeStyle::Iso8601.format(genesis), "2009-01-03T18:15:05Z");
+        assert_eq!(TimeStyle::Unix.format(genesis), "1231006505");
+        assert_eq!(TimeStyle::Human.format(genesis), "2009-01-03 18:15:05 UTC");
+        assert_eq!(TimeStyle::default(), TimeStyle::Human);
+    }
+}
@@ -0,0 +1,112 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether progress messages are suppressed, set once at startup
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Whether progress messages go to stderr because stdout carries data
+static TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// How much the client prints, from `-q` to `-vvv`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Nothing but errors
+    Quiet,
+    /// Progress messages and warnings
+    Normal,
+    /// Also informational log messages and detailed output
+    Verbose,
+    /// Also HTTP requests and digests
+    Debug,
+    /// Everything, including dependency internals
+    Trace,
+}
+
+impl Verbosity {
+    /// Verbosity for the `-q` flag and the number of `-v` flags
+    #[must_use]
+    pub const fn from_flags(quiet: bool, verbose: u8) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Self::Quiet,
+            (false, 0) => Self::Normal,
+            (false, 1) => Self::Verbose,
+            (false, 2) => Self::Debug,
+            (false, _) => Self::Trace,
+        }
+    }
+
+    /// Default `env_logger` filter for this verbosity
+    #[must_use]
+    pub const fn log_filter(self) -> &'static str {
+        match self {
+            Self::Quiet => "error",
+            Self::Normal => "warn",
+            Self::Verbose => "info",
+            Self::Debug => "debug",
+            Self::Trace => "trace",
+        }
+    }
+
+    /// Make this the verbosity of progress messages printed with `progress!`
+    pub fn set_global(self) {
+        QUIET.store(self == Self::Quiet, Ordering::Relaxed);
+    }
+}
+
+/// Whether progress messages are suppressed
+#[must_use]
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Send progress messages to stderr, for commands writing data to stdout
+pub fn progress_to_stderr() {
+    TO_STDERR.store(true, Ordering::Relaxed);
+}
+
+/// Print a progress message, see `progress!`
+pub fn print_progress(args: std::fmt::Arguments<'_>) {
+    if is_quiet() {
+        return;
+    }
+    crate::progress::suspend(|| {
+        if TO_STDERR.load(Ordering::Relaxed) {
+            eprintln!("{args}");
+        } else {
+            println!("{args}");
+        }
+    });
+}
+
+/// Print a message to stderr, see `eprogress!`
+pub fn print_error_progress(args: std::fmt::Arguments<'_>) {
+    crate::progress::suspend(|| eprintln!("{args}"));
+}
+
+/// Print a progress message unless running with `-q`
+macro_rules! progress {
+    ($($arg:tt)*) => {
+        $crate::verbosity::print_progress(format_args!($($arg)*))
+    };
+}
+pub(crate) use progress;
+
+/// Print a message about a failure to stderr, even with `-q`
+macro_rules! eprogress {
+    ($($arg:tt)*) => {
+        $crate::verbosity::print_error_progress(format_args!($($arg)*))
+    };
+}
+pub(crate) use eprogress;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_flags() {
+        assert_eq!(Verbosity::from_flags(false, 0), Verbosity::Normal);
+        assert_eq!(Verbosity::from_flags(false, 2).log_filter(), "debug");
+        assert_eq!(Verbosity::from_flags(false, 7), Verbosity::Trace);
+        assert_eq!(Verbosity::from_flags(true, 1), Verbosity::Quiet);
+    }
+}
@@ -1,12 +1,354 @@
+use crate::calendar::TorConfig;
+use crate::config::{CacheConfig, Config, ElectrumConfig};
 use crate::error::{Error, Result};
+use crate::ots::{Attestation, DetachedTimestampFile, Op, Step, StepData, Timestamp};
 use async_trait::async_trait;
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Default Electrum server
+#[cfg(feature = "electrum")]
+pub const DEFAULT_ELECTRUM_SERVER: &str = "tcp://electrum.blockstream.info:50001";
+
+/// Default Esplora API
+#[cfg(feature = "esplora")]
+pub const DEFAULT_ESPLORA_URL: &str = "https://blockstream.info/api";
+
+/// Esplora APIs tried in order when no backend is configured
+#[cfg(feature = "esplora")]
+pub const DEFAULT_ESPLORA_URLS: &[&str] = &[DEFAULT_ESPLORA_URL, "https://mempool.space/api"];
+
+/// Tor hidden service of the default Esplora API, used when Tor is enabled
+#[cfg(feature = "esplora")]
+pub const DEFAULT_ESPLORA_ONION_URL: &str =
+    "http://explorerzydxu5ecjrkwceayqybizmpjjznk5izmitf2modhcusuqlid.onion/api";
+
+/// Default Esplora-compatible API for Litecoin
+pub const DEFAULT_LITECOIN_ESPLORA_URL: &str = "https://litecoinspace.org/api";
+
+/// Name of the Bitcoin backend selected at compile time, see `default_verifier`
+#[cfg(feature = "electrum")]
+pub const BACKEND_NAME: &str = "Electrum";
+
+/// Name of the Bitcoin backend selected at compile time, see `default_verifier`
+#[cfg(all(feature = "esplora", not(feature = "electrum")))]
+pub const BACKEND_NAME: &str = "Esplora";
+
+/// Name of the Bitcoin backend selected at compile time, see `default_verifier`
+#[cfg(all(feature = "rpc", not(feature = "electrum"), not(feature = "esplora")))]
+pub const BACKEND_NAME: &str = "Bitcoin Core RPC";
+
+/// Name of the default Litecoin backend, see `ChainVerifiers::new`
+pub const LITECOIN_BACKEND_NAME: &str = "Esplora (litecoinspace.org)";
+
+/// Name of a configured Esplora-compatible Litecoin backend
+pub const LITECOIN_ESPLORA_BACKEND_NAME: &str = "Esplora";
+
+/// Name of a configured Electrum-LTC backend
+#[cfg(feature = "electrum")]
+pub const LITECOIN_ELECTRUM_BACKEND_NAME: &str = "Electrum-LTC";
+
+/// Name of the local headers file backend, see `HeadersFileVerifier`
+pub const HEADERS_FILE_BACKEND_NAME: &str = "headers file";
+
+/// Blocks known to be in the Bitcoin chain, as (height, hash in display order)
+///
+/// The genesis block and Bitcoin Core's historical checkpoints, the points
+/// `validate_header_chain` starts from.
+const CHECKPOINTS: &[(u32, &str)] = &[
+    (0, "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"),
+    (11_111, "0000000069e244f73d78e8fd29ba2fd2ed618bd6fa2ee92559f542fdb26e7c1d"),
+    (33_333, "000000002dd5588a74784eaa7ab0507a18ad16a236e7b1ce69f00d7ddfb5d0a6"),
+    (74_000, "0000000000573993a3c9e41ce34471c079dcf5f52a0e824a81e7f953b8661a20"),
+    (105_000, "00000000000291ce28027faea320c8d2b054b2e0fe44a773f3eefb151d6bdc97"),
+    (134_444, "00000000000005b12ffd4cd315cd34ffd4a594f430ac814c91184a0d42d2b0fe"),
+    (168_000, "000000000000099e61ea72015e79632f216fe6cb33d7899acb35b75c8303b763"),
+    (193_000, "000000000000059f452a5f7340de6682a977387c17010ff6e6c3bd83ca8b1317"),
+    (210_000, "000000000000048b95347e83192f69cf0366076336c639f9b7228e9ba171342e"),
+    (216_116, "00000000000001b4f4b433e81ee46494af945cf96014816a4e2370f11b23df4e"),
+    (225_430, "00000000000001c108384350f74090433e7fcf79a606b8e797f065b130575932"),
+    (250_000, "000000000000003887df1f29024b06fc2200b55f8af8f35453d7be294df2d214"),
+    (279_000, "0000000000000001ae8c72a0b0c301f67e3afca10e819efa9041e458e9bd7e40"),
+    (295_000, "00000000000000004d9b4ef50f0f9d686fd69db2e03af35a100370c64632a983"),
+];
+
+/// Blocks between two difficulty adjustments
+const RETARGET_INTERVAL: u32 = 2016;
+
+/// Most headers requested at once while validating a header chain
+const HEADER_BATCH: u32 = 2016;
+
+/// Size of a serialized block header
+const HEADER_SIZE: usize = 80;
+
+/// Compact encoding of the easiest target Bitcoin allows
+const POW_LIMIT_BITS: u32 = 0x1d00_ffff;
 
 /// Block header information needed for timestamp verification
+///
+/// Every backend builds it from the serialized 80-byte header, so the hash
+/// and fields are derived the same way wherever the header came from. Hashes
+/// are in internal byte order, the reverse of how block explorers show them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct BlockHeader {
+    /// Hash of the block
+    pub hash: [u8; 32],
+    /// Height of the block
+    pub height: u32,
+    /// Hash of the previous block
+    pub prev_hash: [u8; 32],
     /// Merkle root of the block
     pub merkle_root: [u8; 32],
     /// Block timestamp (Unix epoch)
     pub time: u32,
+    /// Serialized header
+    pub raw: [u8; HEADER_SIZE],
+}
+
+impl BlockHeader {
+    /// Build a header from its serialized form
+    ///
+    /// # Arguments
+    /// * `raw` - Serialized header
+    /// * `height` - Height of the block, which the header does not contain
+    #[must_use]
+    pub fn from_raw(raw: [u8; HEADER_SIZE], height: u32) -> Self {
+        use bitcoin_hashes::{sha256d, Hash};
+
+        let mut prev_hash = [0u8; 32];
+        prev_hash.copy_from_slice(&raw[4..36]);
+        let mut merkle_root = [0u8; 32];
+        merkle_root.copy_from_slice(&raw[36..68]);
+        let time = u32::from_le_bytes([raw[68], raw[69], raw[70], raw[71]]);
+        let hash = sha256d::Hash::hash(&raw).to_byte_array();
+        Self { hash, height, prev_hash, merkle_root, time, raw }
+    }
+
+    /// Compact encoding of the proof-of-work target (`nBits`)
+    #[must_use]
+    pub fn bits(&self) -> u32 {
+        u32::from_le_bytes([self.raw[72], self.raw[73], self.raw[74], self.raw[75]])
+    }
+
+    /// Check that the block hash meets the target encoded in the header
+    ///
+    /// This is Bitcoin's proof of work, over the double SHA-256 block hash.
+    /// Targets easier than Bitcoin's proof-of-work limit are rejected, but
+    /// the target is not checked to be the right one for the height. Does
+    /// not apply to Litecoin, whose proof of work uses scrypt.
+    ///
+    /// # Errors
+    /// Returns error if the target is malformed or too easy, or the hash is above it
+    pub fn validate_pow(&self) -> Result<()> {
+        let invalid = || {
+            Error::Verification(format!(
+                "Block {} has an invalid target {:#010x}",
+                self.height,
+                self.bits()
+            ))
+        };
+        let target = target_from_bits(self.bits()).ok_or_else(invalid)?;
+        let limit = target_from_bits(POW_LIMIT_BITS).ok_or_else(invalid)?;
+        if le_cmp(&target, &limit) == Ordering::Greater {
+            return Err(invalid());
+        }
+        if le_cmp(&self.hash, &target) == Ordering::Greater {
+            return Err(Error::Verification(format!(
+                "Block {} hash does not meet its proof-of-work target",
+                self.height
+            )));
+        }
+        Ok(())
+    }
+
+    /// Whether this header directly follows `prev` in the chain
+    #[must_use]
+    pub fn links_to(&self, prev: &Self) -> bool {
+        self.prev_hash == prev.hash && prev.height.checked_add(1) == Some(self.height)
+    }
+}
+
+/// A header chain checked by `validate_header_chain`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HeaderChain {
+    /// Height of the checkpoint the chain was validated from
+    pub checkpoint: u32,
+    /// The last header of the chain, the attested block
+    pub tip: BlockHeader,
+    /// Expected number of hashes it took to mine the blocks after the checkpoint
+    pub work: f64,
+}
+
+/// Validate the Bitcoin header chain from the nearest checkpoint up to `header`
+///
+/// Fetches every header from the highest checkpoint at or below the block
+/// and checks that each links to the one before and meets its proof-of-work
+/// target, and that the target only changes at a difficulty adjustment, by
+/// at most a factor of four. The chain must end at `header`. Forging the
+/// block then means redoing all the work since the checkpoint, which gives
+/// SPV assurance without a full node. Does not apply to Litecoin.
+///
+/// # Errors
+/// Returns error if a header cannot be fetched, or the chain breaks any rule
+pub async fn validate_header_chain(
+    verifier: &dyn BlockVerifier,
+    header: &BlockHeader,
+) -> Result<HeaderChain> {
+    let (checkpoint, hash) = CHECKPOINTS
+        .iter()
+        .rev()
+        .find(|(height, _)| *height <= header.height)
+        .copied()
+        .unwrap_or(CHECKPOINTS[0]);
+    let mut prev = verifier.get_block_header(checkpoint).await?;
+    if prev.hash != display_hash_bytes(hash)? {
+        return Err(Error::Verification(format!(
+            "Block {checkpoint} is not the checkpoint {hash}"
+        )));
+    }
+
+    let mut work = 0.0;
+    while prev.height < header.height {
+        let count = (header.height - prev.height).min(HEADER_BATCH);
+        let batch = verifier.get_block_headers(prev.height + 1, count).await?;
+        if batch.is_empty() {
+            return Err(Error::Verification(format!(
+                "No headers returned after block {}",
+                prev.height
+            )));
+        }
+        for next in batch {
+            check_successor(&prev, &next)?;
+            work += block_work(&next);
+            prev = next;
+        }
+    }
+    if prev.hash != header.hash {
+        return Err(Error::Verification(format!(
+            "Block {} is not on the header chain from checkpoint {checkpoint}",
+            header.height
+        )));
+    }
+    Ok(HeaderChain { checkpoint, tip: prev, work })
+}
+
+/// Check that `next` may follow `prev` in the Bitcoin header chain
+fn check_successor(prev: &BlockHeader, next: &BlockHeader) -> Result<()> {
+    if !next.links_to(prev) {
+        return Err(Error::Verification(format!(
+            "Header of block {} does not link to block {}",
+            next.height, prev.height
+        )));
+    }
+    if next.height % RETARGET_INTERVAL != 0 {
+        if next.bits() != prev.bits() {
+            return Err(Error::Verification(format!(
+                "Block {} changes the difficulty between adjustments",
+                next.height
+            )));
+        }
+    } else {
+        // Compact encoding rounds targets down, so allow a little slack
+        let ratio = target_value(next.bits()) / target_value(prev.bits());
+        if !(0.249..=4.001).contains(&ratio) {
+            return Err(Error::Verification(format!(
+                "Block {} adjusts the difficulty by more than a factor of four",
+                next.height
+            )));
+        }
+    }
+    next.validate_pow()
+}
+
+/// Expected number of hashes to mine a block, 2^256 / target
+fn block_work(header: &BlockHeader) -> f64 {
+    2f64.powi(256) / target_value(header.bits())
+}
+
+/// A compact target as a float, infinite for an invalid one so it means no work
+fn target_value(bits: u32) -> f64 {
+    target_from_bits(bits).map_or(f64::INFINITY, |target| {
+        target.iter().rev().fold(0.0, |value, byte| value * 256.0 + f64::from(*byte))
+    })
+}
+
+/// Compare two little-endian 256-bit numbers
+fn le_cmp(a: &[u8; 32], b: &[u8; 32]) -> Ordering {
+    a.iter().rev().cmp(b.iter().rev())
+}
+
+/// Expand a compact target into a little-endian 256-bit number
+///
+/// Returns `None` for negative, zero or overflowing targets.
+fn target_from_bits(bits: u32) -> Option<[u8; 32]> {
+    let exponent = (bits >> 24) as usize;
+    let mantissa = bits & 0x007f_ffff;
+    if bits & 0x0080_0000 != 0 {
+        return None;
+    }
+    let mut target = [0u8; 32];
+    if exponent <= 3 {
+        let value = mantissa >> (8 * (3 - exponent));
+        target[..4].copy_from_slice(&value.to_le_bytes());
+    } else {
+        for (i, byte) in mantissa.to_le_bytes()[..3].iter().enumerate() {
+            match target.get_mut(exponent - 3 + i) {
+                Some(slot) => *slot = *byte,
+                None if *byte != 0 => return None,
+                None => {}
+            }
+        }
+    }
+    if target == [0u8; 32] {
+        return None;
+    }
+    Some(target)
+}
+
+/// Check a serialized header has the right size
+fn raw_header(bytes: &[u8]) -> Result<[u8; HEADER_SIZE]> {
+    bytes.try_into().map_err(|_| {
+        Error::Verification(format!(
+            "Block header is {} bytes, expected {HEADER_SIZE}",
+            bytes.len()
+        ))
+    })
+}
+
+/// Check a header hashes to the block hash the backend reported for it
+fn check_hash(header: &BlockHeader, expected: &[u8; 32]) -> Result<()> {
+    if header.hash != *expected {
+        return Err(Error::Verification(format!(
+            "Header of block {} does not match its block hash",
+            header.height
+        )));
+    }
+    Ok(())
+}
+
+/// Serialize header fields as an 80-byte header
+fn encode_header(
+    version: i32,
+    prev_hash: &[u8; 32],
+    merkle_root: &[u8; 32],
+    time: u32,
+    bits: u32,
+    nonce: u32,
+) -> [u8; HEADER_SIZE] {
+    let mut raw = [0u8; HEADER_SIZE];
+    raw[..4].copy_from_slice(&version.to_le_bytes());
+    raw[4..36].copy_from_slice(prev_hash);
+    raw[36..68].copy_from_slice(merkle_root);
+    raw[68..72].copy_from_slice(&time.to_le_bytes());
+    raw[72..76].copy_from_slice(&bits.to_le_bytes());
+    raw[76..].copy_from_slice(&nonce.to_le_bytes());
+    raw
 }
 
 /// Trait for Bitcoin block verification backends
@@ -23,14 +365,396 @@ pub trait BlockVerifier: Send + Sync {
     /// # Errors
     /// Returns error if block cannot be fetched or parsed
     async fn get_block_header(&self, height: u32) -> Result<BlockHeader>;
+
+    /// Fetch `count` consecutive block headers starting at `start`
+    ///
+    /// Fetches them one at a time unless the backend can do better.
+    ///
+    /// # Errors
+    /// Returns error if a block cannot be fetched or parsed
+    async fn get_block_headers(&self, start: u32, count: u32) -> Result<Vec<BlockHeader>> {
+        let mut headers = Vec::with_capacity(count as usize);
+        for height in start..start.saturating_add(count) {
+            headers.push(self.get_block_header(height).await?);
+        }
+        Ok(headers)
+    }
+}
+
+/// Create the block verifier for the backends selected at compile time
+///
+/// Asks Electrum, then Esplora, then Bitcoin Core RPC, whichever the binary
+/// was built with, falling back to the next when one fails; see
+/// `FallbackVerifier`.
+///
+/// With `tor` set, public backends are reached through the Tor proxy, using
+/// their hidden-service endpoint where one is known. The RPC backend talks
+/// to a local node and is never proxied.
+///
+/// `backend` overrides the server of the preferred backend: an Electrum
+/// server, Esplora API or RPC URL. The cache and Electrum settings come from
+/// `config`.
+///
+/// # Errors
+/// Returns error if the backend client cannot be created
+pub fn default_verifier(
+    tor: Option<&TorConfig>,
+    config: &Config,
+    backend: Option<&str>,
+) -> Result<Box<dyn BlockVerifier>> {
+    let verifier = backend_verifier(tor, &config.electrum, backend)?;
+    Ok(Box::new(CachingVerifier::new(verifier, config.cache.clone())))
+}
+
+/// Block verifiers for every chain a block attestation can refer to
+pub struct ChainVerifiers {
+    /// Verifier for Bitcoin block attestations
+    pub bitcoin: Box<dyn BlockVerifier>,
+    /// Verifier for Litecoin block attestations
+    pub litecoin: Box<dyn BlockVerifier>,
+    /// Name of the Bitcoin backend, for reports
+    pub bitcoin_backend: &'static str,
+    /// Name of the Litecoin backend, for reports
+    pub litecoin_backend: &'static str,
+}
+
+impl ChainVerifiers {
+    /// Create the default verifier for each chain
+    ///
+    /// Bitcoin uses the backend selected at compile time, see
+    /// `default_verifier`. Litecoin uses `litecoin_backend`, an Electrum-LTC
+    /// server (`tcp://` or `ssl://`) or Esplora-compatible API, defaulting to
+    /// the public API at litecoinspace.org.
+    ///
+    /// # Errors
+    /// Returns error if a backend client cannot be created, or an Electrum-LTC
+    /// server is given to a build without Electrum support
+    pub fn new(
+        tor: Option<&TorConfig>,
+        config: &Config,
+        backend: Option<&str>,
+        litecoin_backend: Option<&str>,
+    ) -> Result<Self> {
+        let (litecoin, litecoin_name) = litecoin_verifier(tor, config, litecoin_backend)?;
+        Ok(Self {
+            bitcoin: default_verifier(tor, config, backend)?,
+            litecoin: Box::new(CachingVerifier::new(litecoin, config.cache.clone())),
+            bitcoin_backend: BACKEND_NAME,
+            litecoin_backend: litecoin_name,
+        })
+    }
+
+    /// Check Bitcoin attestations with `verifier`, named `name` in reports
+    #[must_use]
+    pub fn with_bitcoin(mut self, verifier: Box<dyn BlockVerifier>, name: &'static str) -> Self {
+        self.bitcoin = verifier;
+        self.bitcoin_backend = name;
+        self
+    }
+
+    /// Name of the backend checking a block attestation, for reports
+    #[must_use]
+    pub const fn backend_name(&self, attestation: &Attestation) -> &'static str {
+        match attestation {
+            Attestation::Litecoin { .. } => self.litecoin_backend,
+            _ => self.bitcoin_backend,
+        }
+    }
+
+    /// The verifier for a block attestation, `None` for any other attestation
+    #[must_use]
+    pub fn for_attestation(&self, attestation: &Attestation) -> Option<&dyn BlockVerifier> {
+        match attestation {
+            Attestation::Bitcoin { .. } => Some(self.bitcoin.as_ref()),
+            Attestation::Litecoin { .. } => Some(self.litecoin.as_ref()),
+            _ => None,
+        }
+    }
+
+    /// Verify every Bitcoin and Litecoin attestation of a timestamp on its own chain
+    pub async fn verify(&self, timestamp: &Timestamp) -> VerificationResult {
+        verify_attestations(timestamp, |attestation| self.for_attestation(attestation)).await
+    }
+}
+
+/// Create the uncached Litecoin verifier and its name, see `ChainVerifiers::new`
+///
+/// Electrum-LTC speaks the Electrum protocol and serves headers in Bitcoin's
+/// layout, so `ElectrumVerifier` works with it unchanged.
+#[allow(unused_variables)]
+fn litecoin_verifier(
+    tor: Option<&TorConfig>,
+    config: &Config,
+    backend: Option<&str>,
+) -> Result<(Box<dyn BlockVerifier>, &'static str)> {
+    match backend {
+        Some(url) if is_electrum_url(url) => {
+            #[cfg(feature = "electrum")]
+            {
+                // Cross-checking is left to Bitcoin, where a pool is configured
+                let verifier = ElectrumVerifier::new(Some(url.to_string()))
+                    .with_certificate_validation(config.electrum.validate_certificates);
+                let verifier = match tor {
+                    Some(tor) => verifier.with_proxy(&tor.proxy),
+                    None => verifier,
+                };
+                Ok((Box::new(verifier), LITECOIN_ELECTRUM_BACKEND_NAME))
+            }
+            #[cfg(not(feature = "electrum"))]
+            {
+                Err(Error::Verification(format!(
+                    "{url} is an Electrum server, but this build has no Electrum support"
+                )))
+            }
+        }
+        Some(url) => Ok((
+            Box::new(LitecoinVerifier::new(Some(url.to_string()), tor)?),
+            LITECOIN_ESPLORA_BACKEND_NAME,
+        )),
+        None => Ok((Box::new(LitecoinVerifier::new(None, tor)?), LITECOIN_BACKEND_NAME)),
+    }
+}
+
+/// Whether a backend URL names an Electrum server rather than an HTTP API
+fn is_electrum_url(url: &str) -> bool {
+    url.starts_with("tcp://") || url.starts_with("ssl://")
+}
+
+/// Create the uncached backend verifier, see `default_verifier`
+#[allow(unused_variables, unused_mut)]
+fn backend_verifier(
+    tor: Option<&TorConfig>,
+    electrum: &ElectrumConfig,
+    backend: Option<&str>,
+) -> Result<Box<dyn BlockVerifier>> {
+    // The configured server is for the preferred backend; fallbacks use their defaults
+    let mut backend = backend.map(str::to_string);
+    let mut backends: Vec<(&'static str, Box<dyn BlockVerifier>)> = vec![];
+    #[cfg(feature = "electrum")]
+    {
+        let verifier = ElectrumVerifier::new(backend.take())
+            .with_certificate_validation(electrum.validate_certificates)
+            .with_cross_check(electrum.cross_check);
+        let verifier = match tor {
+            Some(tor) => verifier.with_proxy(&tor.proxy),
+            None => verifier,
+        };
+        backends.push(("Electrum", Box::new(verifier)));
+    }
+    #[cfg(feature = "esplora")]
+    {
+        let verifier = match tor {
+            Some(tor) => EsploraVerifier::with_proxy(
+                backend.take().or_else(|| Some(DEFAULT_ESPLORA_ONION_URL.to_string())),
+                &format!("socks5h://{}", tor.proxy),
+            )?,
+            None => EsploraVerifier::new(backend.take())?,
+        };
+        backends.push(("Esplora", Box::new(verifier)));
+    }
+    #[cfg(feature = "rpc")]
+    {
+        backends.push(("Bitcoin Core RPC", Box::new(RpcVerifier::new(backend.take(), None, None))));
+    }
+    if backends.len() > 1 {
+        return Ok(Box::new(FallbackVerifier::new(backends)));
+    }
+    backends
+        .pop()
+        .map(|(_, verifier)| verifier)
+        .ok_or_else(|| Error::Verification("No verification backend was built in".into()))
+}
+
+/// Block verifier trying several backends in order until one answers
+///
+/// A backend that fails is logged and the next one asked, so verification
+/// keeps working while any one of them is down.
+pub struct FallbackVerifier {
+    backends: Vec<(&'static str, Box<dyn BlockVerifier>)>,
+}
+
+impl FallbackVerifier {
+    /// Try `backends` in order, each named for logging
+    #[must_use]
+    pub fn new(backends: Vec<(&'static str, Box<dyn BlockVerifier>)>) -> Self {
+        Self { backends }
+    }
+
+    /// Log a failed backend, keeping its error in case every backend fails
+    fn failed(name: &str, e: Error, last_error: &mut Option<Error>) {
+        log::warn!("{name} verifier failed: {e}");
+        *last_error = Some(e);
+    }
+}
+
+#[async_trait]
+impl BlockVerifier for FallbackVerifier {
+    async fn get_block_header(&self, height: u32) -> Result<BlockHeader> {
+        let mut last_error = None;
+        for (name, backend) in &self.backends {
+            match backend.get_block_header(height).await {
+                Ok(header) => {
+                    log::debug!("Block {height} from {name}");
+                    return Ok(header);
+                }
+                Err(e) => Self::failed(name, e, &mut last_error),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| Error::Verification("No verifier to ask".into())))
+    }
+
+    async fn get_block_headers(&self, start: u32, count: u32) -> Result<Vec<BlockHeader>> {
+        let mut last_error = None;
+        for (name, backend) in &self.backends {
+            match backend.get_block_headers(start, count).await {
+                Ok(headers) => {
+                    log::debug!("Blocks from {start} from {name}");
+                    return Ok(headers);
+                }
+                Err(e) => Self::failed(name, e, &mut last_error),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| Error::Verification("No verifier to ask".into())))
+    }
+}
+
+/// A cached block header
+struct CacheEntry {
+    header: BlockHeader,
+    /// When the header was fetched
+    fetched: Instant,
+    /// Value of the use counter at the last access, for LRU eviction
+    last_used: u64,
+}
+
+/// Bounded in-memory cache of block headers in front of another verifier
+///
+/// Holds at most `capacity` headers, evicting the least recently used one when
+/// full. Headers of recent blocks, which may still be reorganized away, expire
+/// after the configured TTL; headers of deeply buried blocks never expire.
+pub struct CachingVerifier<V> {
+    inner: V,
+    config: CacheConfig,
+    entries: Mutex<(HashMap<u32, CacheEntry>, u64)>,
+}
+
+impl<V: BlockVerifier> CachingVerifier<V> {
+    /// Wrap `inner` in a header cache
+    #[must_use]
+    pub fn new(inner: V, config: CacheConfig) -> Self {
+        Self { inner, config, entries: Mutex::new((HashMap::new(), 0)) }
+    }
+
+    /// Number of headers currently cached
+    #[must_use]
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.entries.lock().map_or(0, |entries| entries.0.len())
+    }
+
+    /// Whether the cache is empty
+    #[must_use]
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether a header is old enough to be safe from reorganizations
+    fn is_buried(&self, header: &BlockHeader) -> bool {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        now.saturating_sub(u64::from(header.time)) >= self.config.buried_after_secs
+    }
+
+    /// Look up a cached header, dropping it if it has expired
+    fn lookup(&self, height: u32) -> Option<BlockHeader> {
+        let mut guard = self.entries.lock().ok()?;
+        let (entries, counter) = &mut *guard;
+        let entry = entries.get_mut(&height)?;
+
+        let ttl = Duration::from_secs(self.config.ttl_secs);
+        if !self.is_buried(&entry.header) && entry.fetched.elapsed() >= ttl {
+            entries.remove(&height);
+            return None;
+        }
+
+        *counter += 1;
+        entry.last_used = *counter;
+        Some(entry.header)
+    }
+
+    /// Cache a freshly fetched header, evicting the least recently used if full
+    fn store(&self, height: u32, header: BlockHeader) {
+        if self.config.capacity == 0 {
+            return;
+        }
+        let Ok(mut guard) = self.entries.lock() else {
+            return;
+        };
+        let (entries, counter) = &mut *guard;
+
+        if entries.len() >= self.config.capacity && !entries.contains_key(&height) {
+            if let Some(lru) =
+                entries.iter().min_by_key(|(_, entry)| entry.last_used).map(|(height, _)| *height)
+            {
+                entries.remove(&lru);
+            }
+        }
+
+        *counter += 1;
+        entries.insert(height, CacheEntry { header, fetched: Instant::now(), last_used: *counter });
+    }
+}
+
+#[async_trait]
+impl<V: BlockVerifier> BlockVerifier for CachingVerifier<V> {
+    async fn get_block_header(&self, height: u32) -> Result<BlockHeader> {
+        if let Some(header) = self.lookup(height) {
+            return Ok(header);
+        }
+        let header = self.inner.get_block_header(height).await?;
+        self.store(height, header);
+        Ok(header)
+    }
+
+    async fn get_block_headers(&self, start: u32, count: u32) -> Result<Vec<BlockHeader>> {
+        // Header chains are read once, so they would only evict useful entries
+        self.inner.get_block_headers(start, count).await
+    }
+}
+
+#[async_trait]
+impl BlockVerifier for Box<dyn BlockVerifier> {
+    async fn get_block_header(&self, height: u32) -> Result<BlockHeader> {
+        (**self).get_block_header(height).await
+    }
+
+    async fn get_block_headers(&self, start: u32, count: u32) -> Result<Vec<BlockHeader>> {
+        (**self).get_block_headers(start, count).await
+    }
 }
 
 /// Electrum-based block verifier (default backend)
 ///
 /// Uses Electrum protocol to verify timestamps against Bitcoin blockchain.
+/// Servers are `tcp://` or `ssl://` URLs, tried in order until one answers.
+/// Each connection is opened on the first request and reused for later ones,
+/// so verifying many proofs does not reconnect for each.
+///
+/// With cross-checking on, every answer is compared with that of a second
+/// server, so a single lying or compromised server cannot fake a block.
 #[cfg(feature = "electrum")]
 pub struct ElectrumVerifier {
-    server: String,
+    servers: Vec<ElectrumServer>,
+    socks5: Option<String>,
+    validate_certificates: bool,
+    cross_check: bool,
+}
+
+/// One Electrum server and its connection, once opened
+#[cfg(feature = "electrum")]
+struct ElectrumServer {
+    url: String,
+    client: std::sync::Arc<Mutex<Option<std::sync::Arc<electrum_client::Client>>>>,
 }
 
 #[cfg(feature = "electrum")]
@@ -38,93 +762,334 @@ impl ElectrumVerifier {
     /// Create new Electrum verifier
     ///
     /// # Arguments
-    /// * `server` - Optional Electrum server URL. Defaults to Blockstream's public server.
+    /// * `server` - Optional Electrum server URL, or several separated by
+    ///   commas. Defaults to Blockstream's public server.
     ///
     /// # Example
     /// ```rust,ignore
     /// let verifier = ElectrumVerifier::new(None); // Use default
     /// let verifier = ElectrumVerifier::new(Some("tcp://localhost:50001".to_string())); // Custom
+    /// let verifier = ElectrumVerifier::new(Some("ssl://a:50002,ssl://b:50002".to_string())); // Pool
     /// ```
     #[must_use]
     pub fn new(server: Option<String>) -> Self {
-        Self {
-            server: server.unwrap_or_else(|| "tcp://electrum.blockstream.info:50001".to_string()),
-        }
+        let urls = server.unwrap_or_else(|| DEFAULT_ELECTRUM_SERVER.to_string());
+        let servers = urls
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(|url| ElectrumServer { url: url.to_string(), client: std::sync::Arc::default() })
+            .collect();
+        Self { servers, socks5: None, validate_certificates: true, cross_check: false }
+    }
+
+    /// Connect to the server through a SOCKS5 proxy such as Tor
+    ///
+    /// # Arguments
+    /// * `proxy` - Proxy address (`host:port`)
+    #[must_use]
+    pub fn with_proxy(mut self, proxy: &str) -> Self {
+        self.socks5 = Some(proxy.to_string());
+        self
+    }
+
+    /// Check the certificate and hostname of `ssl://` servers (the default)
+    ///
+    /// Turning this off accepts self-signed certificates, and any server
+    /// that intercepts the connection.
+    #[must_use]
+    pub const fn with_certificate_validation(mut self, validate: bool) -> Self {
+        self.validate_certificates = validate;
+        self
+    }
+
+    /// Compare every answer with that of a second server
+    ///
+    /// Requests fail if the servers disagree or no second server answers.
+    #[must_use]
+    pub const fn with_cross_check(mut self, cross_check: bool) -> Self {
+        self.cross_check = cross_check;
+        self
+    }
+
+    /// URLs of the servers, in the order they are tried
+    #[must_use]
+    pub fn servers(&self) -> Vec<&str> {
+        self.servers.iter().map(|server| server.url.as_str()).collect()
     }
 }
 
 #[cfg(feature = "electrum")]
-#[async_trait]
-impl BlockVerifier for ElectrumVerifier {
-    async fn get_block_header(&self, height: u32) -> Result<BlockHeader> {
-        use electrum_client::ElectrumApi;
+impl ElectrumVerifier {
+    /// Fetch raw data with `call` from the first server that answers,
+    /// cross-checked with a second server if enabled
+    ///
+    /// Header lists may differ in length, since servers can return fewer
+    /// headers than asked for; only the headers both returned are kept.
+    async fn request(
+        &self,
+        what: &'static str,
+        call: impl Fn(&electrum_client::Client) -> std::result::Result<Vec<u8>, electrum_client::Error>
+            + Clone
+            + Send
+            + 'static,
+    ) -> Result<Vec<u8>> {
+        let (first, mut answer) = self.first_answer(what, call.clone(), None).await?;
+        if self.cross_check {
+            let servers = self.servers();
+            let (second, check) =
+                self.first_answer(what, call, Some(first)).await.map_err(|e| {
+                    Error::Verification(format!(
+                        "No second Electrum server to cross-check {} with (configured: {}): {e}",
+                        servers[first],
+                        servers.join(", ")
+                    ))
+                })?;
+            let common = answer.len().min(check.len());
+            if answer[..common] != check[..common] {
+                return Err(Error::Verification(format!(
+                    "Electrum servers {} and {} disagree on the {what}",
+                    servers[first], servers[second]
+                )));
+            }
+            answer.truncate(common);
+        }
+        Ok(answer)
+    }
 
-        // electrum-client is synchronous, wrap in spawn_blocking for async context
-        let server = self.server.clone();
-        let header = tokio::task::spawn_blocking(move || {
-            let client = electrum_client::Client::new(&server)
-                .map_err(|e| Error::Verification(format!("Failed to connect to Electrum: {e}")))?;
+    /// Ask each server but `skip` in turn, returning the index of the first
+    /// that answered and its answer, or the last error
+    async fn first_answer<T: Send + 'static>(
+        &self,
+        what: &'static str,
+        call: impl Fn(&electrum_client::Client) -> std::result::Result<T, electrum_client::Error>
+            + Clone
+            + Send
+            + 'static,
+        skip: Option<usize>,
+    ) -> Result<(usize, T)> {
+        let mut last_error = None;
+        for (index, server) in self.servers.iter().enumerate() {
+            if Some(index) == skip {
+                continue;
+            }
+            match self.request_server(server, what, call.clone()).await {
+                Ok(answer) => return Ok((index, answer)),
+                Err(e) => {
+                    log::debug!("Electrum server {} failed: {e}", server.url);
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error
+            .unwrap_or_else(|| Error::Verification("No Electrum server configured".into())))
+    }
+
+    /// Run `call` on the shared connection to `server`, opening it first if needed
+    ///
+    /// electrum-client is synchronous, so the call runs in `spawn_blocking`.
+    /// A failed call drops the connection, so the next request reconnects in
+    /// case it broke.
+    async fn request_server<T: Send + 'static>(
+        &self,
+        server: &ElectrumServer,
+        what: &'static str,
+        call: impl FnOnce(&electrum_client::Client) -> std::result::Result<T, electrum_client::Error>
+            + Send
+            + 'static,
+    ) -> Result<T> {
+        let url = server.url.clone();
+        let config = electrum_client::ConfigBuilder::new()
+            .socks5(self.socks5.clone().map(electrum_client::Socks5Config::new))
+            .validate_domain(self.validate_certificates)
+            .build();
+        let cached = std::sync::Arc::clone(&server.client);
+        tokio::task::spawn_blocking(move || {
+            let connected = cached.lock().ok().and_then(|client| client.clone());
+            let client = match connected {
+                Some(client) => client,
+                None => {
+                    let client =
+                        electrum_client::Client::from_config(&url, config).map_err(|e| {
+                            Error::Verification(format!("Failed to connect to Electrum: {e}"))
+                        })?;
+                    let client = std::sync::Arc::new(client);
+                    if let Ok(mut cached) = cached.lock() {
+                        *cached = Some(std::sync::Arc::clone(&client));
+                    }
+                    client
+                }
+            };
 
-            client
-                .block_header(height as usize)
-                .map_err(|e| Error::Verification(format!("Failed to fetch block header: {e}")))
+            call(&client).map_err(|e| {
+                if let Ok(mut cached) = cached.lock() {
+                    *cached = None;
+                }
+                Error::Verification(format!("Failed to fetch {what}: {e}"))
+            })
         })
         .await
-        .map_err(|e| Error::Verification(format!("Task join error: {e}")))??;
+        .map_err(|e| Error::Verification(format!("Task join error: {e}")))?
+    }
+}
 
-        // Convert merkle root to byte array
-        // Bitcoin displays hashes in reverse (little-endian display, internal big-endian)
-        let merkle_root_bytes = header.merkle_root.to_string();
-        let mut merkle_root = [0u8; 32];
+#[cfg(feature = "electrum")]
+#[async_trait]
+impl BlockVerifier for ElectrumVerifier {
+    async fn get_block_header(&self, height: u32) -> Result<BlockHeader> {
+        use electrum_client::ElectrumApi;
 
-        // Parse hex string to bytes
-        hex::decode_to_slice(merkle_root_bytes.as_bytes(), &mut merkle_root)
-            .map_err(|e| Error::Verification(format!("Failed to decode merkle root: {e}")))?;
+        let raw = self
+            .request("block header", move |client| client.block_header_raw(height as usize))
+            .await?;
+        Ok(BlockHeader::from_raw(raw_header(&raw)?, height))
+    }
 
-        // Reverse bytes for internal representation (Bitcoin internal byte order)
-        merkle_root.reverse();
+    async fn get_block_headers(&self, start: u32, count: u32) -> Result<Vec<BlockHeader>> {
+        use electrum_client::ElectrumApi;
 
-        Ok(BlockHeader { merkle_root, time: header.time })
+        let raw_headers = self
+            .request("block headers", move |client| {
+                Ok(client.block_headers(start as usize, count as usize)?.raw_headers)
+            })
+            .await?;
+        // Servers may return fewer headers than asked for; the caller asks again
+        (0..)
+            .zip(raw_headers.chunks(HEADER_SIZE))
+            .map(|(offset, raw)| Ok(BlockHeader::from_raw(raw_header(raw)?, start + offset)))
+            .collect()
     }
 }
 
 /// Esplora-based block verifier
 ///
-/// Uses Esplora HTTP API to verify timestamps against Bitcoin blockchain.
+/// Uses Esplora HTTP APIs to verify timestamps against Bitcoin blockchain.
+/// Endpoints are tried in order until one answers. An endpoint that failed
+/// is tried after the others until `ENDPOINT_RETRY` has passed, so a server
+/// that is down does not slow down every request.
 #[cfg(feature = "esplora")]
 #[allow(dead_code)]
 pub struct EsploraVerifier {
+    endpoints: Vec<EsploraEndpoint>,
+}
+
+/// One Esplora API and how it has been answering
+#[cfg(feature = "esplora")]
+struct EsploraEndpoint {
+    url: String,
     client: esplora_client::r#async::AsyncClient<esplora_client::r#async::DefaultSleeper>,
+    health: Mutex<EndpointHealth>,
 }
 
+/// Failures of an endpoint since it last answered
+#[cfg(feature = "esplora")]
+#[derive(Clone, Copy, Debug, Default)]
+struct EndpointHealth {
+    failures: u32,
+    last_failure: Option<Instant>,
+}
+
+#[cfg(feature = "esplora")]
+impl EndpointHealth {
+    /// Whether the endpoint failed within `ENDPOINT_RETRY` of `now`
+    fn is_down(&self, now: Instant) -> bool {
+        self.last_failure.is_some_and(|failed| now.duration_since(failed) < ENDPOINT_RETRY)
+    }
+}
+
+/// How long an Esplora endpoint that failed is tried after the others
+#[cfg(feature = "esplora")]
+const ENDPOINT_RETRY: Duration = Duration::from_secs(300);
+
 #[cfg(feature = "esplora")]
 #[allow(dead_code)]
 impl EsploraVerifier {
     /// Create new Esplora verifier
     ///
     /// # Arguments
-    /// * `url` - Optional Esplora server URL. Defaults to Blockstream's public API.
+    /// * `url` - Optional Esplora server URL, or several separated by commas.
+    ///   Defaults to `DEFAULT_ESPLORA_URLS`.
     ///
     /// # Example
     /// ```rust,ignore
-    /// let verifier = EsploraVerifier::new(None); // Use default
-    /// let verifier = EsploraVerifier::new(Some("https://blockstream.info/api".to_string())); // Custom
+    /// let verifier = EsploraVerifier::new(None); // Use defaults
+    /// let verifier = EsploraVerifier::new(Some("https://mempool.space/api".to_string())); // Custom
     /// ```
     ///
     /// # Errors
     /// Returns error if client cannot be created
     pub fn new(url: Option<String>) -> Result<Self> {
-        let base_url = url.unwrap_or_else(|| "https://blockstream.info/api".to_string());
-        let builder = esplora_client::Builder::new(&base_url);
-        let client = esplora_client::r#async::AsyncClient::from_builder(builder)
-            .map_err(|e| Error::Verification(format!("Failed to create Esplora client: {e}")))?;
-        Ok(Self { client })
+        Self::build(url, None)
+    }
+
+    /// Create new Esplora verifier that connects through a proxy such as Tor
+    ///
+    /// # Arguments
+    /// * `url` - Optional Esplora server URL, or several separated by commas.
+    ///   Defaults to `DEFAULT_ESPLORA_URLS`.
+    /// * `proxy` - Proxy URL, e.g. `socks5h://127.0.0.1:9050`
+    ///
+    /// # Errors
+    /// Returns error if client cannot be created
+    pub fn with_proxy(url: Option<String>, proxy: &str) -> Result<Self> {
+        Self::build(url, Some(proxy))
+    }
+
+    fn build(url: Option<String>, proxy: Option<&str>) -> Result<Self> {
+        let urls: Vec<String> = match url {
+            Some(urls) => urls.split(',').map(|url| url.trim().to_string()).collect(),
+            None => DEFAULT_ESPLORA_URLS.iter().map(ToString::to_string).collect(),
+        };
+        let endpoints = urls
+            .into_iter()
+            .filter(|url| !url.is_empty())
+            .map(|url| {
+                let mut builder = esplora_client::Builder::new(&url);
+                if let Some(proxy) = proxy {
+                    builder = builder.proxy(proxy);
+                }
+                let client =
+                    esplora_client::r#async::AsyncClient::from_builder(builder).map_err(|e| {
+                        Error::Verification(format!("Failed to create Esplora client: {e}"))
+                    })?;
+                Ok(EsploraEndpoint { url, client, health: Mutex::default() })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if endpoints.is_empty() {
+            return Err(Error::Verification("No Esplora endpoint given".into()));
+        }
+        Ok(Self { endpoints })
+    }
+
+    /// URL and consecutive failures of every endpoint, in the configured order
+    #[must_use]
+    pub fn health(&self) -> Vec<(&str, u32)> {
+        self.endpoints
+            .iter()
+            .map(|endpoint| (endpoint.url.as_str(), endpoint.health().failures))
+            .collect()
     }
 }
 
 #[cfg(feature = "esplora")]
-#[async_trait]
-impl BlockVerifier for EsploraVerifier {
+impl EsploraEndpoint {
+    fn health(&self) -> EndpointHealth {
+        self.health.lock().map(|health| *health).unwrap_or_default()
+    }
+
+    fn record(&self, answered: bool) {
+        if let Ok(mut health) = self.health.lock() {
+            *health = if answered {
+                EndpointHealth::default()
+            } else {
+                EndpointHealth {
+                    failures: health.failures.saturating_add(1),
+                    last_failure: Some(Instant::now()),
+                }
+            };
+        }
+    }
+
     async fn get_block_header(&self, height: u32) -> Result<BlockHeader> {
         use bitcoin_hashes::Hash;
 
@@ -142,10 +1107,585 @@ impl BlockVerifier for EsploraVerifier {
             .await
             .map_err(|e| Error::Verification(format!("Failed to fetch block header: {e}")))?;
 
-        // Extract merkle root bytes
-        let merkle_root = *header.merkle_root.as_byte_array();
+        let raw = encode_header(
+            header.version.to_consensus(),
+            header.prev_blockhash.as_byte_array(),
+            header.merkle_root.as_byte_array(),
+            header.time,
+            header.bits.to_consensus(),
+            header.nonce,
+        );
+        let header = BlockHeader::from_raw(raw, height);
+        check_hash(&header, block_hash.as_byte_array())?;
+        Ok(header)
+    }
+}
+
+/// Order in which to try endpoints: those up in configured order, then those down
+#[cfg(feature = "esplora")]
+fn endpoint_order(health: &[EndpointHealth], now: Instant) -> Vec<usize> {
+    let (up, down): (Vec<usize>, Vec<usize>) =
+        (0..health.len()).partition(|&i| !health[i].is_down(now));
+    up.into_iter().chain(down).collect()
+}
+
+#[cfg(feature = "esplora")]
+#[async_trait]
+impl BlockVerifier for EsploraVerifier {
+    async fn get_block_header(&self, height: u32) -> Result<BlockHeader> {
+        let health: Vec<EndpointHealth> =
+            self.endpoints.iter().map(EsploraEndpoint::health).collect();
+        let mut last_error = None;
+        for i in endpoint_order(&health, Instant::now()) {
+            let endpoint = &self.endpoints[i];
+            match endpoint.get_block_header(height).await {
+                Ok(header) => {
+                    endpoint.record(true);
+                    log::debug!("Block {height} from {}", endpoint.url);
+                    return Ok(header);
+                }
+                Err(e) => {
+                    endpoint.record(false);
+                    log::debug!("{} failed: {e}", endpoint.url);
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| Error::Verification("No Esplora endpoint".into())))
+    }
+}
+
+/// Litecoin block verifier using an Esplora-compatible HTTP API
+pub struct LitecoinVerifier {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl LitecoinVerifier {
+    /// Create new Litecoin verifier
+    ///
+    /// # Arguments
+    /// * `url` - Optional API URL. Defaults to `DEFAULT_LITECOIN_ESPLORA_URL`.
+    /// * `tor` - Reach the API through this Tor proxy
+    ///
+    /// # Errors
+    /// Returns error if client cannot be created
+    pub fn new(url: Option<String>, tor: Option<&TorConfig>) -> Result<Self> {
+        let mut builder = reqwest::Client::builder();
+        if let Some(tor) = tor {
+            builder = builder.proxy(reqwest::Proxy::all(format!("socks5h://{}", tor.proxy))?);
+        }
+        let base_url = url.unwrap_or_else(|| DEFAULT_LITECOIN_ESPLORA_URL.to_string());
+        Ok(Self { client: builder.build()?, base_url: base_url.trim_end_matches('/').to_string() })
+    }
+
+    /// GET a plain-text resource from the API
+    async fn get_text(&self, path: &str) -> Result<String> {
+        let url = format!("{}/{path}", self.base_url);
+        let response = self.client.get(&url).send().await?;
+        if !response.status().is_success() {
+            return Err(Error::Verification(format!(
+                "Litecoin API returned {} for {url}",
+                response.status()
+            )));
+        }
+        Ok(response.text().await?.trim().to_string())
+    }
+}
+
+#[async_trait]
+impl BlockVerifier for LitecoinVerifier {
+    async fn get_block_header(&self, height: u32) -> Result<BlockHeader> {
+        let block_hash = self.get_text(&format!("block-height/{height}")).await?;
+        let header =
+            parse_header(&self.get_text(&format!("block/{block_hash}/header")).await?, height)?;
+        // The API returns the hash in display order
+        check_hash(&header, &display_hash_bytes(&block_hash)?)?;
+        Ok(header)
+    }
+}
+
+/// Parse a hex-encoded 80-byte block header of the block at `height`
+///
+/// Litecoin headers share Bitcoin's layout and double SHA-256 block hash.
+///
+/// # Errors
+/// Returns error if the header is not hex or not 80 bytes long
+pub fn parse_header(header: &str, height: u32) -> Result<BlockHeader> {
+    let bytes = hex::decode(header)
+        .map_err(|e| Error::Verification(format!("Failed to decode block header: {e}")))?;
+    Ok(BlockHeader::from_raw(raw_header(&bytes)?, height))
+}
+
+/// A block header as JSON, from Bitcoin Core's `getblockheader` or Esplora's `/block/:hash`
+///
+/// Hashes are in display order, as both print them.
+#[derive(Deserialize)]
+struct JsonHeader {
+    #[serde(default, alias = "id")]
+    hash: Option<String>,
+    #[serde(default)]
+    height: Option<u32>,
+    version: i32,
+    #[serde(default)]
+    previousblockhash: Option<String>,
+    #[serde(alias = "merkleroot")]
+    merkle_root: String,
+    #[serde(alias = "timestamp")]
+    time: u32,
+    bits: JsonBits,
+    nonce: u32,
+}
+
+/// `nBits` as a number (Esplora) or a hex string (Bitcoin Core)
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonBits {
+    Number(u32),
+    Hex(String),
+}
+
+/// Read a block header given on the command line
+///
+/// `header` is either the hex-encoded 80-byte header, or a file holding the
+/// hex or a JSON header from Bitcoin Core or Esplora. A JSON header's `hash`
+/// must match the header, and its `height` is kept; otherwise the height is 0.
+///
+/// # Errors
+/// Returns error if the file cannot be read, or the header is malformed
+pub fn read_header(header: &str) -> Result<BlockHeader> {
+    let text = if Path::new(header).is_file() {
+        std::fs::read_to_string(header)?
+    } else {
+        header.to_string()
+    };
+    let text = text.trim();
+    if text.starts_with('{') {
+        parse_json_header(text)
+    } else {
+        parse_header(text, 0)
+    }
+}
+
+/// Parse a JSON block header, see `read_header`
+///
+/// # Errors
+/// Returns error if the JSON is malformed or its hash does not match the header
+pub fn parse_json_header(json: &str) -> Result<BlockHeader> {
+    let json: JsonHeader = serde_json::from_str(json)
+        .map_err(|e| Error::Verification(format!("Failed to parse block header JSON: {e}")))?;
+    let bits = match &json.bits {
+        JsonBits::Number(bits) => *bits,
+        JsonBits::Hex(bits) => u32::from_str_radix(bits, 16)
+            .map_err(|e| Error::Verification(format!("Failed to decode block bits: {e}")))?,
+    };
+    let prev_hash = match &json.previousblockhash {
+        Some(hash) => display_hash_bytes(hash)?,
+        None => [0u8; 32],
+    };
+    let raw = encode_header(
+        json.version,
+        &prev_hash,
+        &display_hash_bytes(&json.merkle_root)?,
+        json.time,
+        bits,
+        json.nonce,
+    );
+    let header = BlockHeader::from_raw(raw, json.height.unwrap_or(0));
+    if let Some(hash) = &json.hash {
+        check_hash(&header, &display_hash_bytes(hash)?)?;
+    }
+    Ok(header)
+}
+
+/// Decode a hash printed in display order into internal byte order
+fn display_hash_bytes(hash: &str) -> Result<[u8; 32]> {
+    let mut bytes = [0u8; 32];
+    hex::decode_to_slice(hash, &mut bytes)
+        .map_err(|e| Error::Verification(format!("Failed to decode hash {hash}: {e}")))?;
+    bytes.reverse();
+    Ok(bytes)
+}
+
+/// Transaction id of `bytes` if they are exactly one serialized transaction
+///
+/// Calendars commit to a block through a transaction whose serialization
+/// (without witness data) appears in the proof as the output of an op, so
+/// its double SHA-256 is the txid. Returned in internal byte order, like
+/// `BlockHeader::merkle_root`.
+#[must_use]
+pub fn transaction_id(bytes: &[u8]) -> Option<[u8; 32]> {
+    use bitcoin_hashes::{sha256d, Hash};
+
+    /// Split `n` bytes off the front of `rest`
+    fn take<'a>(rest: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+        if rest.len() < n {
+            return None;
+        }
+        let (head, tail) = rest.split_at(n);
+        *rest = tail;
+        Some(head)
+    }
+    /// Read a `CompactSize` integer
+    fn compact_size(rest: &mut &[u8]) -> Option<usize> {
+        let value = match take(rest, 1)?[0] {
+            0xfd => u64::from(u16::from_le_bytes(take(rest, 2)?.try_into().ok()?)),
+            0xfe => u64::from(u32::from_le_bytes(take(rest, 4)?.try_into().ok()?)),
+            0xff => u64::from_le_bytes(take(rest, 8)?.try_into().ok()?),
+            n => u64::from(n),
+        };
+        usize::try_from(value).ok()
+    }
+
+    let mut rest = bytes;
+    take(&mut rest, 4)?; // version
+                         // No inputs would be the marker of a serialization with witness data
+    let inputs = compact_size(&mut rest).filter(|&n| n > 0)?;
+    for _ in 0..inputs {
+        take(&mut rest, 36)?; // previous output
+        let script = compact_size(&mut rest)?;
+        take(&mut rest, script)?;
+        take(&mut rest, 4)?; // sequence
+    }
+    let outputs = compact_size(&mut rest).filter(|&n| n > 0)?;
+    for _ in 0..outputs {
+        take(&mut rest, 8)?; // value
+        let script = compact_size(&mut rest)?;
+        take(&mut rest, script)?;
+    }
+    take(&mut rest, 4)?; // lock time
+    rest.is_empty().then(|| sha256d::Hash::hash(bytes).to_byte_array())
+}
+
+/// A block attestation of a proof, with what replaying the proof up to it gave
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockAttestation {
+    /// The Bitcoin or Litecoin attestation
+    pub attestation: Attestation,
+    /// Ops leading from the document digest to the attestation, in order
+    pub path: Vec<Op>,
+    /// Digest the ops produce from the start digest, which must be the block's merkle root
+    pub merkle_root: [u8; 32],
+    /// Id of the last transaction the path goes through, in internal byte order
+    pub txid: Option<[u8; 32]>,
+}
+
+impl BlockAttestation {
+    /// Name of the chain the attestation is on
+    #[must_use]
+    pub const fn chain(&self) -> &'static str {
+        match self.attestation {
+            Attestation::Litecoin { .. } => "Litecoin",
+            _ => "Bitcoin",
+        }
+    }
+
+    /// Height of the attested block
+    #[must_use]
+    pub fn height(&self) -> u32 {
+        match self.attestation {
+            Attestation::Bitcoin { height } | Attestation::Litecoin { height } => {
+                u32::try_from(height).unwrap_or(u32::MAX)
+            }
+            _ => 0,
+        }
+    }
+}
+
+/// Every Bitcoin and Litecoin attestation of a timestamp, in proof order
+///
+/// Executes the ops from the start digest, so each attestation is paired
+/// with the merkle root the proof commits to. An op output that is a whole transaction
+/// gives the txid of the path below it.
+///
+/// # Errors
+/// Returns error if an op cannot be executed, or a block attestation is on a
+/// digest that is not 32 bytes long and so cannot be a merkle root
+pub fn block_attestations(timestamp: &Timestamp) -> Result<Vec<BlockAttestation>> {
+    let mut found = vec![];
+    find_block_attestations(
+        &timestamp.first_step(),
+        timestamp.start_digest().as_bytes().to_vec(),
+        &mut vec![],
+        None,
+        &mut found,
+    )?;
+    Ok(found)
+}
+
+/// Collect the block attestations below `step`, entered with the digest `input`
+///
+/// A chain of ops runs in place on `input`; it is only copied where the
+/// proof forks.
+fn find_block_attestations(
+    step: &Step,
+    mut input: Vec<u8>,
+    path: &mut Vec<Op>,
+    txid: Option<[u8; 32]>,
+    found: &mut Vec<BlockAttestation>,
+) -> Result<()> {
+    match &step.data {
+        StepData::Attestation(
+            attestation @ (Attestation::Bitcoin { .. } | Attestation::Litecoin { .. }),
+        ) => {
+            let merkle_root = input.as_slice().try_into().map_err(|_| {
+                Error::Verification(format!(
+                    "{attestation} is on a {}-byte digest, not a 32-byte merkle root",
+                    input.len()
+                ))
+            })?;
+            found.push(BlockAttestation {
+                attestation: attestation.clone(),
+                path: path.clone(),
+                merkle_root,
+                txid,
+            });
+        }
+        StepData::Attestation(_) => {}
+        StepData::Fork => {
+            find_in_branches(&step.next, input, path, txid, found)?;
+        }
+        StepData::Op(op) => {
+            op.try_execute_in_place(&mut input)?;
+            let txid = transaction_id(&input).or(txid);
+            path.push(op.clone());
+            find_in_branches(&step.next, input, path, txid, found)?;
+            path.pop();
+        }
+    }
+    Ok(())
+}
+
+/// Collect the block attestations below each of `branches`, copying `input`
+/// for all but the last
+fn find_in_branches(
+    branches: &[Step],
+    input: Vec<u8>,
+    path: &mut Vec<Op>,
+    txid: Option<[u8; 32]>,
+    found: &mut Vec<BlockAttestation>,
+) -> Result<()> {
+    if let Some((last, rest)) = branches.split_last() {
+        for next in rest {
+            find_block_attestations(next, input.clone(), path, txid, found)?;
+        }
+        find_block_attestations(last, input, path, txid, found)?;
+    }
+    Ok(())
+}
+
+/// Outcome of checking one block attestation against its chain
+#[derive(Debug)]
+pub enum AttestationStatus {
+    /// The block's merkle root is the one the proof commits to
+    Verified,
+    /// The block has a different merkle root
+    MerkleRootMismatch,
+    /// The block header could not be fetched
+    Unavailable(Error),
+    /// No verifier was given for the attestation's chain
+    Skipped,
+}
+
+/// A block attestation and what its chain said about it
+#[derive(Debug)]
+pub struct AttestationVerification {
+    /// The attestation, with its path and the merkle root it commits to
+    pub attestation: BlockAttestation,
+    /// Header of the attested block, if it was fetched
+    pub header: Option<BlockHeader>,
+    /// Whether the block matches
+    pub status: AttestationStatus,
+}
+
+impl AttestationVerification {
+    /// Time of the attested block (Unix epoch), if its header was fetched
+    #[must_use]
+    pub fn block_time(&self) -> Option<u32> {
+        self.header.map(|header| header.time)
+    }
+}
+
+/// Structured outcome of verifying a proof, for applications embedding the crate
+///
+/// Only the attestations are checked: comparing the proof's start digest
+/// with the document is up to the caller.
+#[derive(Debug, Default)]
+pub struct VerificationResult {
+    /// Every block attestation of the proof, in proof order
+    pub attestations: Vec<AttestationVerification>,
+    /// Why the proof could not be replayed, in which case nothing was checked
+    pub error: Option<Error>,
+}
+
+impl VerificationResult {
+    /// Whether at least one attestation was verified and none failed
+    #[must_use]
+    pub fn is_verified(&self) -> bool {
+        self.error.is_none()
+            && self
+                .attestations
+                .iter()
+                .any(|verification| matches!(verification.status, AttestationStatus::Verified))
+            && self.attestations.iter().all(|verification| {
+                matches!(
+                    verification.status,
+                    AttestationStatus::Verified | AttestationStatus::Skipped
+                )
+            })
+    }
 
-        Ok(BlockHeader { merkle_root, time: header.time })
+    /// The verified attestation with the earliest block time
+    #[must_use]
+    pub fn earliest(&self) -> Option<&AttestationVerification> {
+        self.attestations
+            .iter()
+            .filter(|verification| matches!(verification.status, AttestationStatus::Verified))
+            .min_by_key(|verification| verification.block_time())
+    }
+}
+
+/// Verify the Bitcoin attestations of a proof with `verifier`
+///
+/// Litecoin attestations are reported as `AttestationStatus::Skipped`; use
+/// `ChainVerifiers::verify` to check them too.
+pub async fn verify_proof(
+    ots: &DetachedTimestampFile,
+    verifier: &dyn BlockVerifier,
+) -> VerificationResult {
+    verify_attestations(&ots.timestamp, |attestation| {
+        matches!(attestation, Attestation::Bitcoin { .. }).then_some(verifier)
+    })
+    .await
+}
+
+/// Check every block attestation of a timestamp with the verifier `verifier_for` picks
+async fn verify_attestations<'a>(
+    timestamp: &Timestamp,
+    verifier_for: impl Fn(&Attestation) -> Option<&'a dyn BlockVerifier>,
+) -> VerificationResult {
+    let found = match block_attestations(timestamp) {
+        Ok(found) => found,
+        Err(e) => return VerificationResult { attestations: vec![], error: Some(e) },
+    };
+    let mut attestations = vec![];
+    for attestation in found {
+        let (header, status) = match verifier_for(&attestation.attestation) {
+            None => (None, AttestationStatus::Skipped),
+            Some(verifier) => match verifier.get_block_header(attestation.height()).await {
+                Ok(header) if header.merkle_root == attestation.merkle_root => {
+                    (Some(header), AttestationStatus::Verified)
+                }
+                Ok(header) => (Some(header), AttestationStatus::MerkleRootMismatch),
+                Err(e) => (None, AttestationStatus::Unavailable(e)),
+            },
+        };
+        attestations.push(AttestationVerification { attestation, header, status });
+    }
+    VerificationResult { attestations, error: None }
+}
+
+/// Block verifier reading a local file of concatenated 80-byte headers
+///
+/// The file holds the raw headers of consecutive blocks, the first at the
+/// start height (0 unless set), as exported by Bitcoin Core or electrs, so
+/// proofs can be verified offline against a pre-synced header chain. Each
+/// header read must link to the one before it.
+pub struct HeadersFileVerifier {
+    path: PathBuf,
+    start: u32,
+    count: u32,
+}
+
+impl HeadersFileVerifier {
+    /// Use the headers in the file at `path`, starting from the genesis block
+    ///
+    /// # Errors
+    /// Returns error if the file cannot be read or its size is not a multiple of 80 bytes
+    pub fn new(path: &Path) -> Result<Self> {
+        let len = std::fs::metadata(path)?.len();
+        if len % HEADER_SIZE as u64 != 0 {
+            return Err(Error::Verification(format!(
+                "{} is {len} bytes, not a whole number of {HEADER_SIZE}-byte headers",
+                path.display()
+            )));
+        }
+        let count = u32::try_from(len / HEADER_SIZE as u64).map_err(|_| {
+            Error::Verification(format!("{} holds too many headers", path.display()))
+        })?;
+        Ok(Self { path: path.to_path_buf(), start: 0, count })
+    }
+
+    /// The first header of the file is the block at `height`
+    #[must_use]
+    pub const fn with_start_height(mut self, height: u32) -> Self {
+        self.start = height;
+        self
+    }
+
+    /// Height of the last header in the file, `None` if it is empty
+    #[must_use]
+    pub fn tip_height(&self) -> Option<u32> {
+        self.count.checked_sub(1).map(|last| self.start.saturating_add(last))
+    }
+
+    /// Position in the file of the header of the block at `height`
+    fn index(&self, height: u32) -> Result<u32> {
+        height.checked_sub(self.start).filter(|&index| index < self.count).ok_or_else(|| {
+            Error::Verification(format!(
+                "Block {height} is not in {} (blocks {} to {})",
+                self.path.display(),
+                self.start,
+                self.tip_height().map_or_else(|| "none".to_string(), |tip| tip.to_string())
+            ))
+        })
+    }
+
+    /// Read the header at `index` in the file, and the one before it unless it is the first
+    fn read(&self, index: u32) -> Result<(Option<[u8; HEADER_SIZE]>, [u8; HEADER_SIZE])> {
+        let mut file = File::open(&self.path)?;
+        let first = index.saturating_sub(1);
+        file.seek(SeekFrom::Start(u64::from(first) * HEADER_SIZE as u64))?;
+        let mut prev = [0u8; HEADER_SIZE];
+        let prev = if first < index {
+            file.read_exact(&mut prev)?;
+            Some(prev)
+        } else {
+            None
+        };
+        let mut raw = [0u8; HEADER_SIZE];
+        file.read_exact(&mut raw)?;
+        Ok((prev, raw))
+    }
+}
+
+#[async_trait]
+impl BlockVerifier for HeadersFileVerifier {
+    async fn get_block_header(&self, height: u32) -> Result<BlockHeader> {
+        let (prev, raw) = self.read(self.index(height)?)?;
+        let header = BlockHeader::from_raw(raw, height);
+        if let Some(prev) = prev {
+            if !header.links_to(&BlockHeader::from_raw(prev, height - 1)) {
+                return Err(Error::Verification(format!(
+                    "Header of block {height} in {} does not follow the one before it",
+                    self.path.display()
+                )));
+            }
+        }
+        Ok(header)
+    }
+
+    async fn get_block_headers(&self, start: u32, count: u32) -> Result<Vec<BlockHeader>> {
+        let first = self.index(start)?;
+        let count = count.min(self.count - first);
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(u64::from(first) * HEADER_SIZE as u64))?;
+        let mut raw = vec![0u8; count as usize * HEADER_SIZE];
+        file.read_exact(&mut raw)?;
+        (0..)
+            .zip(raw.chunks(HEADER_SIZE))
+            .map(|(offset, raw)| Ok(BlockHeader::from_raw(raw_header(raw)?, start + offset)))
+            .collect()
     }
 }
 
@@ -214,13 +1754,406 @@ impl BlockVerifier for RpcVerifier {
                 .get_block_header(&block_hash)
                 .map_err(|e| Error::Verification(format!("Failed to fetch block header: {e}")))?;
 
-            Ok::<_, Error>((header.merkle_root, header.time))
+            Ok::<_, Error>((block_hash, header))
         })
         .await
         .map_err(|e| Error::Verification(format!("Task join error: {e}")))?;
 
-        let (merkle_root, time) = header?;
+        let (block_hash, header) = header?;
+        let raw = encode_header(
+            header.version.to_consensus(),
+            header.prev_blockhash.as_byte_array(),
+            header.merkle_root.as_byte_array(),
+            header.time,
+            header.bits.to_consensus(),
+            header.nonce,
+        );
+        let header = BlockHeader::from_raw(raw, height);
+        check_hash(&header, block_hash.as_byte_array())?;
+        Ok(header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Verifier returning synthetic headers and counting backend calls
+    struct CountingVerifier {
+        calls: AtomicUsize,
+        time: u32,
+    }
+
+    #[async_trait]
+    impl BlockVerifier for CountingVerifier {
+        async fn get_block_header(&self, height: u32) -> Result<BlockHeader> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let mut raw = [0u8; HEADER_SIZE];
+            raw[36..40].copy_from_slice(&height.to_le_bytes());
+            raw[68..72].copy_from_slice(&self.time.to_le_bytes());
+            Ok(BlockHeader::from_raw(raw, height))
+        }
+    }
+
+    const GENESIS_HEADER: &str = "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c";
+    const BLOCK1_HEADER: &str = "010000006fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000982051fd1e4ba744bbbe680e1fee14677ba1a3c3540bf7b1cdb606e857233e0e61bc6649ffff001d01e36299";
+    /// The only transaction of the genesis block
+    const GENESIS_COINBASE: &str = "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff4d04ffff001d0104455468652054696d65732030332f4a616e2f32303039204368616e63656c6c6f72206f6e206272696e6b206f66207365636f6e64206261696c6f757420666f722062616e6b73ffffffff0100f2052a01000000434104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac00000000";
+
+    fn cached(time: u32, config: CacheConfig) -> CachingVerifier<CountingVerifier> {
+        CachingVerifier::new(CountingVerifier { calls: AtomicUsize::new(0), time }, config)
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let mut header = vec![0u8; HEADER_SIZE];
+        header[36..68].copy_from_slice(&[0xab; 32]);
+        header[68..72].copy_from_slice(&1_317_972_665u32.to_le_bytes());
+        let parsed = parse_header(&hex::encode(&header), 7).unwrap();
+        assert_eq!(parsed.merkle_root, [0xab; 32]);
+        assert_eq!(parsed.time, 1_317_972_665);
+        assert_eq!(parsed.height, 7);
+
+        assert!(parse_header(&hex::encode(&header[..79]), 7).is_err());
+        assert!(parse_header("not hex", 7).is_err());
+    }
+
+    #[test]
+    fn test_parse_json_header() {
+        let genesis = parse_header(GENESIS_HEADER, 0).unwrap();
+        let core = r#"{
+            "hash": "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f",
+            "height": 0,
+            "version": 1,
+            "merkleroot": "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b",
+            "time": 1231006505,
+            "nonce": 2083236893,
+            "bits": "1d00ffff"
+        }"#;
+        assert_eq!(parse_json_header(core).unwrap(), genesis);
+
+        let esplora = r#"{
+            "id": "00000000839a8e6886ab5951d76f411475428afc90947ee320161bbf18eb6048",
+            "height": 1,
+            "version": 1,
+            "timestamp": 1231469665,
+            "bits": 486604799,
+            "nonce": 2573394689,
+            "merkle_root": "0e3e2357e806b6cdb1f70b54c3a3a17b6714ee1f0e68bebb44a74b1efd512098",
+            "previousblockhash": "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"
+        }"#;
+        let block1 = parse_json_header(esplora).unwrap();
+        assert_eq!(block1.raw, parse_header(BLOCK1_HEADER, 1).unwrap().raw);
+        assert_eq!(block1.height, 1);
+
+        // A hash that does not match the fields is rejected
+        let tampered = core.replace("2083236893", "2083236894");
+        assert!(parse_json_header(&tampered).is_err());
+
+        let dir = std::env::temp_dir().join(format!("ots-header-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("genesis.json"), core).unwrap();
+        std::fs::write(dir.join("genesis.hex"), format!("{GENESIS_HEADER}\n")).unwrap();
+        assert_eq!(read_header(dir.join("genesis.json").to_str().unwrap()).unwrap(), genesis);
+        assert_eq!(read_header(dir.join("genesis.hex").to_str().unwrap()).unwrap(), genesis);
+        assert_eq!(read_header(GENESIS_HEADER).unwrap(), genesis);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_validate_header_chain() {
+        let dir = std::env::temp_dir().join(format!("ots-spv-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("headers.dat");
+        let headers = [hex::decode(GENESIS_HEADER).unwrap(), hex::decode(BLOCK1_HEADER).unwrap()];
+        std::fs::write(&path, headers.concat()).unwrap();
+        let verifier = HeadersFileVerifier::new(&path).unwrap();
+
+        let block1 = parse_header(BLOCK1_HEADER, 1).unwrap();
+        let chain =
+            validate_header_chain(&verifier, &block1).await.unwrap_or_else(|e| panic!("{e}"));
+        assert_eq!((chain.checkpoint, chain.tip), (0, block1));
+        // Difficulty 1 is about 2^32 hashes
+        assert!((chain.work.log2() - 32.0).abs() < 0.01, "{}", chain.work);
+
+        // A block the chain does not lead to
+        let mut other = block1;
+        other.hash[0] ^= 1;
+        assert!(validate_header_chain(&verifier, &other).await.is_err());
+
+        // The target may only change at a difficulty adjustment
+        let genesis = parse_header(GENESIS_HEADER, 0).unwrap();
+        let mut raw = block1.raw;
+        raw[72..76].copy_from_slice(&0x1c00_ffffu32.to_le_bytes());
+        let harder = BlockHeader::from_raw(raw, 1);
+        let error = check_successor(&genesis, &harder).unwrap_err();
+        assert!(error.to_string().contains("difficulty"), "{error}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "electrum")]
+    #[tokio::test]
+    async fn test_electrum_pool() {
+        let verifier = ElectrumVerifier::new(Some("ssl://a:50002, tcp://b:50001,".into()));
+        assert_eq!(verifier.servers(), vec!["ssl://a:50002", "tcp://b:50001"]);
+        assert_eq!(ElectrumVerifier::new(None).servers(), vec![DEFAULT_ELECTRUM_SERVER]);
+
+        // Without any server, requests fail instead of hanging
+        let verifier = ElectrumVerifier::new(Some(String::new())).with_cross_check(true);
+        let error = verifier.get_block_header(0).await.unwrap_err();
+        assert!(error.to_string().contains("No Electrum server"), "{error}");
+    }
+
+    #[cfg(feature = "esplora")]
+    #[test]
+    fn test_endpoint_order() {
+        let now = Instant::now();
+        let down = EndpointHealth { failures: 1, last_failure: Some(now) };
+        let recovered = EndpointHealth {
+            failures: 3,
+            last_failure: now.checked_sub(ENDPOINT_RETRY + Duration::from_secs(1)),
+        };
+        let health = [down, EndpointHealth::default(), recovered];
+        assert_eq!(endpoint_order(&health, now), [1, 2, 0]);
+
+        let verifier =
+            EsploraVerifier::new(Some("https://a.example/api, https://b.example/api".into()))
+                .unwrap();
+        assert_eq!(verifier.health(), [("https://a.example/api", 0), ("https://b.example/api", 0)]);
+        verifier.endpoints[1].record(false);
+        assert_eq!(verifier.health()[1].1, 1);
+        verifier.endpoints[1].record(true);
+        assert_eq!(verifier.health()[1].1, 0);
+    }
+
+    /// Verifier that is always down
+    struct DownVerifier;
+
+    #[async_trait]
+    impl BlockVerifier for DownVerifier {
+        async fn get_block_header(&self, _height: u32) -> Result<BlockHeader> {
+            Err(Error::Verification("down".into()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fallback() {
+        let counting = cached(7, CacheConfig::default());
+        let verifier = FallbackVerifier::new(vec![
+            ("down", Box::new(DownVerifier)),
+            ("counting", Box::new(counting)),
+        ]);
+        assert_eq!(verifier.get_block_header(3).await.unwrap().time, 7);
+        assert_eq!(verifier.get_block_headers(3, 2).await.unwrap().len(), 2);
+
+        let verifier = FallbackVerifier::new(vec![("down", Box::new(DownVerifier))]);
+        let error = verifier.get_block_header(3).await.unwrap_err();
+        assert!(error.to_string().contains("down"), "{error}");
+    }
+
+    #[test]
+    fn test_litecoin_backend() {
+        let config = Config::default();
+        let name = |backend| litecoin_verifier(None, &config, backend).map(|(_, name)| name);
+        assert_eq!(name(None).unwrap(), LITECOIN_BACKEND_NAME);
+        assert_eq!(name(Some("https://ltc.example/api")).unwrap(), LITECOIN_ESPLORA_BACKEND_NAME);
+        let electrum = name(Some("ssl://electrum-ltc.example:50002"));
+        #[cfg(feature = "electrum")]
+        assert_eq!(electrum.unwrap(), LITECOIN_ELECTRUM_BACKEND_NAME);
+        #[cfg(not(feature = "electrum"))]
+        assert!(electrum.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_headers_file() {
+        let dir = std::env::temp_dir().join(format!("ots-headers-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("headers.dat");
+        let headers = [hex::decode(GENESIS_HEADER).unwrap(), hex::decode(BLOCK1_HEADER).unwrap()];
+        std::fs::write(&path, headers.concat()).unwrap();
+
+        let verifier = HeadersFileVerifier::new(&path).unwrap();
+        assert_eq!(verifier.tip_height(), Some(1));
+        assert_eq!(
+            verifier.get_block_header(0).await.unwrap(),
+            parse_header(GENESIS_HEADER, 0).unwrap()
+        );
+        assert_eq!(
+            verifier.get_block_header(1).await.unwrap(),
+            parse_header(BLOCK1_HEADER, 1).unwrap()
+        );
+        assert!(verifier.get_block_header(2).await.is_err());
+
+        // Headers out of order do not link up
+        std::fs::write(&path, [headers[1].clone(), headers[0].clone()].concat()).unwrap();
+        let verifier = HeadersFileVerifier::new(&path).unwrap().with_start_height(5);
+        assert!(verifier.get_block_header(5).await.is_ok());
+        assert!(verifier.get_block_header(6).await.is_err());
+        assert!(verifier.get_block_header(0).await.is_err());
+
+        std::fs::write(&path, &headers[0][..79]).unwrap();
+        assert!(HeadersFileVerifier::new(&path).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Display-order hex of a hash
+    fn display_hash(hash: &[u8; 32]) -> String {
+        let mut hash = *hash;
+        hash.reverse();
+        hex::encode(hash)
+    }
+
+    #[test]
+    fn test_transaction_id() {
+        let genesis = parse_header(GENESIS_HEADER, 0).unwrap();
+        let coinbase = hex::decode(GENESIS_COINBASE).unwrap();
+        assert_eq!(transaction_id(&coinbase), Some(genesis.merkle_root));
+
+        assert_eq!(transaction_id(&coinbase[..coinbase.len() - 1]), None);
+        assert_eq!(transaction_id(&[coinbase.as_slice(), &[0]].concat()), None);
+        assert_eq!(transaction_id(&[0xab; 32]), None);
+    }
+
+    #[test]
+    fn test_header_fields_and_linkage() {
+        let genesis = parse_header(GENESIS_HEADER, 0).unwrap();
+        let block1 = parse_header(BLOCK1_HEADER, 1).unwrap();
+        assert_eq!(
+            display_hash(&genesis.hash),
+            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"
+        );
+        assert_eq!(
+            display_hash(&block1.hash),
+            "00000000839a8e6886ab5951d76f411475428afc90947ee320161bbf18eb6048"
+        );
+        assert_eq!(genesis.prev_hash, [0u8; 32]);
+        assert_eq!(block1.time, 1_231_469_665);
+        assert_eq!(block1.bits(), 0x1d00_ffff);
+        assert_eq!(hex::encode(block1.raw), BLOCK1_HEADER);
+
+        assert!(block1.links_to(&genesis));
+        assert!(!genesis.links_to(&block1));
+        assert!(!block1.links_to(&BlockHeader { height: 5, ..genesis }));
+    }
+
+    #[test]
+    fn test_validate_pow() {
+        let block1 = parse_header(BLOCK1_HEADER, 1).unwrap();
+        block1.validate_pow().unwrap();
+
+        // A different nonce no longer meets the target
+        let mut raw = block1.raw;
+        raw[76] ^= 1;
+        assert!(BlockHeader::from_raw(raw, 1).validate_pow().is_err());
+
+        // Targets easier than the limit are rejected even if the hash meets them
+        let mut raw = block1.raw;
+        raw[72..76].copy_from_slice(&0x207f_ffffu32.to_le_bytes());
+        assert!(BlockHeader::from_raw(raw, 1).validate_pow().is_err());
+
+        // Negative or zero targets are never met
+        assert!(target_from_bits(0x1d80_ffff).is_none());
+        assert!(target_from_bits(0x0300_0000).is_none());
+        assert!(target_from_bits(0x2300_ffff).is_none());
+        let mut expected = [0u8; 32];
+        expected[26..28].copy_from_slice(&[0xff, 0xff]);
+        assert_eq!(target_from_bits(0x1d00_ffff), Some(expected));
+    }
+
+    #[tokio::test]
+    async fn test_cache_hit() {
+        let verifier = cached(0, CacheConfig::default());
+        let first = verifier.get_block_header(100).await.unwrap();
+        let second = verifier.get_block_header(100).await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(verifier.inner.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_cache_lru_eviction() {
+        let verifier = cached(0, CacheConfig { capacity: 2, ..CacheConfig::default() });
+        verifier.get_block_header(1).await.unwrap();
+        verifier.get_block_header(2).await.unwrap();
+        // Touch 1 so that 2 becomes the least recently used
+        verifier.get_block_header(1).await.unwrap();
+        verifier.get_block_header(3).await.unwrap();
+        assert_eq!(verifier.len(), 2);
+
+        verifier.get_block_header(1).await.unwrap();
+        assert_eq!(verifier.inner.calls.load(Ordering::SeqCst), 3);
+        verifier.get_block_header(2).await.unwrap();
+        assert_eq!(verifier.inner.calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_cache_ttl_near_tip() {
+        #[allow(clippy::cast_possible_truncation)]
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as u32;
+        let config = CacheConfig { ttl_secs: 0, ..CacheConfig::default() };
+
+        // Recent block: expires immediately with a zero TTL
+        let recent = cached(now, config.clone());
+        recent.get_block_header(1).await.unwrap();
+        recent.get_block_header(1).await.unwrap();
+        assert_eq!(recent.inner.calls.load(Ordering::SeqCst), 2);
+
+        // Deeply buried block: never expires
+        let buried = cached(0, config);
+        buried.get_block_header(1).await.unwrap();
+        buried.get_block_header(1).await.unwrap();
+        assert_eq!(buried.inner.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_verify_proof() {
+        use crate::ots::{Digest, DigestType};
+
+        // The synthetic header of block 7 has 7 as its merkle root prefix
+        let mut start_digest = vec![0u8; 32];
+        start_digest[..4].copy_from_slice(&7u32.to_le_bytes());
+        let attested = |attestation: Attestation| Step {
+            data: StepData::Op(Op::Append(vec![])),
+            output: start_digest.clone(),
+            next: vec![Step {
+                data: StepData::Attestation(attestation),
+                output: start_digest.clone(),
+                next: vec![],
+            }],
+        };
+        let proof = |bitcoin: usize| DetachedTimestampFile {
+            timestamp: Timestamp::new(
+                Digest::new(DigestType::Sha256, start_digest.clone()).unwrap(),
+                &Step {
+                    data: StepData::Fork,
+                    output: start_digest.clone(),
+                    next: vec![
+                        attested(Attestation::Bitcoin { height: bitcoin }),
+                        attested(Attestation::Litecoin { height: 7 }),
+                    ],
+                },
+            )
+            .unwrap(),
+        };
+        let ots = proof(7);
+        let verifier = CountingVerifier { calls: AtomicUsize::new(0), time: 1_231_006_505 };
+
+        let result = verify_proof(&ots, &verifier).await;
+        assert!(result.is_verified());
+        assert_eq!(result.attestations.len(), 2);
+        assert_eq!(result.attestations[0].attestation.path, [Op::Append(vec![])]);
+        assert!(matches!(result.attestations[1].status, AttestationStatus::Skipped));
+        let earliest = result.earliest().unwrap();
+        assert_eq!(
+            (earliest.attestation.height(), earliest.block_time()),
+            (7, Some(1_231_006_505))
+        );
 
-        Ok(BlockHeader { merkle_root: *merkle_root.as_byte_array(), time })
+        // A block at another height has another merkle root
+        let moved = proof(8);
+        let result = verify_proof(&moved, &verifier).await;
+        assert!(!result.is_verified());
+        assert!(matches!(result.attestations[0].status, AttestationStatus::MerkleRootMismatch));
+        assert!(result.earliest().is_none());
     }
 }